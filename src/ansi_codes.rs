@@ -91,11 +91,11 @@ pub enum ControlChar {
 
 impl PartialEq<char> for ControlChar {
     fn eq(&self, c: &char) -> bool {
-        (*self as u8) == (*c as u8)
+        (*self as u32) == (*c as u32)
     }
 }
 impl PartialEq<ControlChar> for char {
     fn eq(&self, c: &ControlChar) -> bool {
-        (*self as u8) == (*c as u8)
+        (*self as u32) == (*c as u32)
     }
 }
@@ -3,8 +3,10 @@ use anyhow::Result;
 use std::{collections::HashMap, io};
 use vfs::{MemoryFS, VfsPath};
 mod dev;
+mod modified;
 mod multi;
 use dev::{Device, DeviceFS};
+pub use modified::modified;
 use multi::MultiFS;
 
 // `/dev/null` implementation
@@ -43,6 +45,7 @@ impl Device for NullDevice {
 pub fn get_root() -> Result<VfsPath> {
     let mut memfs: VfsPath = MemoryFS::new().into();
     populate_rootfs(&mut memfs)?;
+    modified::boot();
 
     let mut devices: HashMap<String, Box<dyn Device>> = HashMap::new();
     devices.insert(String::from("/null"), Box::new(NullDevice {}));
@@ -0,0 +1,56 @@
+//! Modification times of files and directories, which the VFS doesn't keep itself.
+use crate::utils;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Mutex,
+    },
+};
+use vfs::VfsPath;
+
+/// When the filesystem was populated, which is when anything not changed since was modified.
+static BOOTED: AtomicI64 = AtomicI64::new(0);
+
+static MODIFIED: Mutex<BTreeMap<String, i64>> = Mutex::new(BTreeMap::new());
+
+/// Strip the trailing slash from a path, as `MultiFS` does.
+fn key(path: &str) -> &str {
+    path.strip_suffix('/').unwrap_or(path)
+}
+
+/// Note the time the filesystem was populated.
+pub(super) fn boot() {
+    BOOTED.store(utils::now(), Ordering::Relaxed);
+}
+
+/// Note that a path, and so the directory it's in, was just modified.
+pub(super) fn touch(path: &str) {
+    let path = key(path);
+    let now = utils::now();
+    let mut modified = MODIFIED.lock().expect("Lock isn't poisoned");
+    if let Some((parent, _)) = path.rsplit_once('/') {
+        modified.insert(parent.into(), now);
+    }
+    modified.insert(path.into(), now);
+}
+
+/// Forget a path that was removed, noting that its directory was modified.
+pub(super) fn forget(path: &str) {
+    let path = key(path);
+    let mut modified = MODIFIED.lock().expect("Lock isn't poisoned");
+    modified.remove(path);
+    if let Some((parent, _)) = path.rsplit_once('/') {
+        modified.insert(parent.into(), utils::now());
+    }
+}
+
+/// Get when a file or directory was last modified, in seconds since the Unix epoch.
+pub fn modified(path: &VfsPath) -> i64 {
+    MODIFIED
+        .lock()
+        .expect("Lock isn't poisoned")
+        .get(key(path.as_str()))
+        .copied()
+        .unwrap_or_else(|| BOOTED.load(Ordering::Relaxed))
+}
@@ -1,4 +1,5 @@
 //! An branching file system combining two or more filesystems.
+use super::modified;
 use std::{collections::HashSet, io::Write};
 use vfs::{
     error::VfsErrorKind,
@@ -49,7 +50,7 @@ impl MultiFS {
 
         self.layers.push((path, local_root));
         self.layers
-            .sort_by(|a, b| b.0.as_str().len().cmp(&a.0.as_str().len()));
+            .sort_by_key(|layer| std::cmp::Reverse(layer.0.as_str().len()));
         Ok(())
     }
 
@@ -104,6 +105,7 @@ impl FileSystem for MultiFS {
 
     fn create_dir(&self, path: &str) -> VfsResult<()> {
         self.get_path(path)?.create_dir_all()?;
+        modified::touch(path);
         Ok(())
     }
 
@@ -114,6 +116,7 @@ impl FileSystem for MultiFS {
     fn create_file(&self, path: &str) -> VfsResult<Box<dyn Write + Send>> {
         self.ensure_has_parent(path)?;
         let result = self.get_path(path)?.create_file()?;
+        modified::touch(path);
         Ok(result)
     }
 
@@ -123,7 +126,9 @@ impl FileSystem for MultiFS {
             self.ensure_has_parent(path)?;
             self.get_path(path)?.copy_file(&write_path)?;
         }
-        write_path.append_file()
+        let result = write_path.append_file()?;
+        modified::touch(path);
+        Ok(result)
     }
 
     fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
@@ -138,6 +143,7 @@ impl FileSystem for MultiFS {
         let write_path = self.get_path(path)?;
         if write_path.exists()? {
             write_path.remove_file()?;
+            modified::forget(path);
         }
         Ok(())
     }
@@ -146,6 +152,7 @@ impl FileSystem for MultiFS {
         let write_path = self.get_path(path)?;
         if write_path.exists()? {
             write_path.remove_dir()?;
+            modified::forget(path);
         }
         Ok(())
     }
@@ -24,6 +24,7 @@ async fn run() -> Result<()> {
     let rootfs = filesystem::get_root()?;
     let mut process = Process {
         stdin: stdin.clone(),
+        terminal: stdin.clone(),
         stdout: stdout.clone(),
         stderr: stdout.clone(),
         env: Default::default(),
@@ -7,6 +7,9 @@ use vfs::VfsPath;
 #[derive(Clone)]
 pub struct Process {
     pub stdin: InputStream,
+    /// The keyboard, even when `stdin` is redirected. Like `/dev/tty`, for programs that page
+    /// through their input.
+    pub terminal: InputStream,
     pub stdout: OutputStream,
     pub stderr: OutputStream,
     pub env: HashMap<String, String>,
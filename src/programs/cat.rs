@@ -5,7 +5,7 @@ use std::io::Write;
 
 /// Concatenate files.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// The files to concatenate.
     files: Vec<String>,
 }
@@ -8,7 +8,7 @@ use futures::io::AsyncWriteExt;
 
 /// Clear the screen
 #[derive(Parser)]
-struct Options {}
+pub(super) struct Options {}
 
 pub async fn clear(process: &mut Process) -> Result<ExitCode> {
     let _options = Options::try_parse_from(process.args.iter())?;
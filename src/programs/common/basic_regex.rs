@@ -0,0 +1,44 @@
+//! Basic regular expressions, where \( \), \{ \}, \| \+ and \? are special and the bare
+//! characters are literal, as grep and sed use by default.
+
+/// Turn a basic regular expression into an extended one, swapping what's special with what's
+/// escaped.
+pub fn to_extended(pattern: &str) -> String {
+    let mut extended = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ ('(' | ')' | '{' | '}' | '|' | '+' | '?')) => extended.push(c),
+                Some(c) => {
+                    extended.push('\\');
+                    extended.push(c);
+                }
+                None => extended.push_str("\\\\"),
+            },
+            '(' | ')' | '{' | '}' | '|' | '+' | '?' => {
+                extended.push('\\');
+                extended.push(c);
+            }
+            // A star with nothing to repeat is just a star.
+            '*' if extended.is_empty() || extended.ends_with(['(', '|', '^']) => {
+                extended.push_str("\\*")
+            }
+            c => extended.push(c),
+        }
+    }
+    extended
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn basic_regular_expressions() {
+        assert_eq!(to_extended(r"a\(b\|c\)+"), r"a(b|c)\+");
+        assert_eq!(to_extended(r"x\{2\}{3}"), r"x{2}\{3\}");
+        assert_eq!(to_extended(r"*a.*"), r"\*a.*");
+        assert_eq!(to_extended(r"\.\w"), r"\.\w");
+    }
+}
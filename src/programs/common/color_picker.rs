@@ -2,12 +2,21 @@ use anyhow::Result;
 use std::io::Write;
 const RESET: &str = "\u{001b}[0m";
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[allow(unused)]
 pub enum Color {
     Red,
     Blue,
     Green,
+    Yellow,
+    Cyan,
+    Magenta,
+    /// Faint text, for things that aren't really there yet.
+    Dim,
+    /// Swap the foreground and background colors.
+    Reverse,
+    Bold,
+    Underline,
 }
 
 impl Color {
@@ -15,7 +24,34 @@ impl Color {
         match self {
             Self::Red => "\u{001b}[31m",
             Self::Green => "\u{001b}[32m",
+            Self::Yellow => "\u{001b}[33m",
             Self::Blue => "\u{001b}[34m",
+            Self::Magenta => "\u{001b}[35m",
+            Self::Cyan => "\u{001b}[36m",
+            Self::Dim => "\u{001b}[2m",
+            Self::Reverse => "\u{001b}[7m",
+            Self::Bold => "\u{001b}[1m",
+            Self::Underline => "\u{001b}[4m",
+        }
+    }
+}
+
+/// When to color output, as given to options like `--color`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum When {
+    Always,
+    /// When writing to a terminal.
+    Auto,
+    Never,
+}
+
+impl When {
+    /// Whether to color output, given whether it's written to a terminal.
+    pub fn colors(self, terminal: bool) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Auto => terminal,
+            Self::Never => false,
         }
     }
 }
@@ -37,6 +73,7 @@ impl ColorPicker {
         self.color = Some(color)
     }
 
+    #[allow(unused)]
     pub fn reset(&mut self) {
         self.color = None
     }
@@ -0,0 +1,108 @@
+//! Lay out a list of items in columns, filling each column top to bottom like `ls`.
+use super::unicode;
+
+/// Space left between columns.
+const GUTTER: usize = 2;
+
+/// A column layout for a list of items.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Columns {
+    count: usize,
+    rows: usize,
+    widths: Vec<usize>,
+}
+
+impl Columns {
+    /// Fit items with the given display widths into as many columns as will fit within
+    /// `screen_width`. Items wider than the screen get a column to themselves.
+    pub fn new(item_widths: &[usize], screen_width: usize) -> Self {
+        for columns in (1..=item_widths.len()).rev() {
+            let rows = item_widths.len().div_ceil(columns);
+            let widths: Vec<usize> = item_widths
+                .chunks(rows)
+                .map(|column| column.iter().copied().max().unwrap_or_default())
+                .collect();
+            let total = widths.iter().sum::<usize>() + GUTTER * (widths.len() - 1);
+            if total <= screen_width || columns == 1 {
+                return Self {
+                    count: item_widths.len(),
+                    rows,
+                    widths,
+                };
+            }
+        }
+        Self {
+            count: 0,
+            rows: 0,
+            widths: Vec::new(),
+        }
+    }
+
+    /// Number of rows in the layout.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// The indices of the items on each row, in order.
+    pub fn row(&self, row: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.widths.len())
+            .map(move |column| column * self.rows + row)
+            .filter(move |&index| index < self.count)
+    }
+
+    /// How much padding should follow item `index`, which has display width `width`, to line up
+    /// the next column.
+    pub fn padding(&self, index: usize, width: usize) -> usize {
+        let column = index / self.rows;
+        if index + self.rows >= self.count {
+            // Nothing follows on this row.
+            0
+        } else {
+            self.widths[column].saturating_sub(width) + GUTTER
+        }
+    }
+
+    /// Lay out plain strings as lines of text.
+    pub fn format<S: AsRef<str>>(items: &[S], screen_width: usize) -> Vec<String> {
+        let widths: Vec<usize> = items
+            .iter()
+            .map(|item| unicode::width(item.as_ref()))
+            .collect();
+        let layout = Self::new(&widths, screen_width);
+        (0..layout.rows())
+            .map(|row| {
+                let mut line = String::new();
+                for index in layout.row(row) {
+                    line.push_str(items[index].as_ref());
+                    line.extend(std::iter::repeat_n(
+                        ' ',
+                        layout.padding(index, widths[index]),
+                    ));
+                }
+                line
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fills_columns_top_to_bottom() {
+        let items = ["a", "bb", "ccc", "d", "eeeee"];
+        assert_eq!(
+            Columns::format(&items, 12),
+            vec!["a    d", "bb   eeeee", "ccc"]
+        );
+        assert_eq!(Columns::format(&items, 80), vec!["a  bb  ccc  d  eeeee"]);
+    }
+
+    #[test]
+    fn narrow_screen() {
+        let items = ["abcdef", "ghijkl"];
+        assert_eq!(Columns::format(&items, 3), vec!["abcdef", "ghijkl"]);
+        assert!(Columns::format::<&str>(&[], 80).is_empty());
+    }
+}
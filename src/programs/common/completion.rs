@@ -0,0 +1,428 @@
+//! Tab completion for the shell.
+//!
+//! Arguments are completed according to the program's command line interface, unless the
+//! `complete` builtin says otherwise.
+use crate::{
+    process::Process,
+    programs::{self, common::shell_commands, cowsay, sh::ShellContext, theme},
+    streams,
+};
+use anyhow::{anyhow, bail, Result};
+use clap::{Arg, Command, ValueHint};
+use futures::{
+    future::{BoxFuture, FutureExt},
+    io::AsyncReadExt,
+    try_join,
+};
+
+/// Arguments whose values are the names of files in a directory, by program and argument id.
+const DIRECTORY_VALUES: [(&str, &str, &str); 2] = [
+    ("cowsay", "file", cowsay::COWS_DIR),
+    ("theme", "theme", theme::THEMES_DIR),
+];
+
+/// Words that separate one command from the next.
+const SEPARATORS: [&str; 4] = ["|", "&&", "||", ";"];
+
+/// How to complete the arguments of a command, as set up by the `complete` builtin.
+#[derive(Clone, Default)]
+pub struct CompletionSpec {
+    /// Whitespace-separated words to complete from.
+    pub words: Option<String>,
+    /// A command that prints completions, one per line.
+    pub command: Option<String>,
+}
+
+/// Complete the word starting at `start` in `section`, the part of the line before the cursor.
+pub async fn complete(
+    ctx: &ShellContext,
+    process: &Process,
+    section: &str,
+    start: usize,
+) -> Result<Vec<String>> {
+    let word = &section[start..];
+    let words: Vec<&str> = section[0..start].split_whitespace().collect();
+    let words = match words.iter().rposition(|word| SEPARATORS.contains(word)) {
+        Some(separator) => &words[separator + 1..],
+        None => &words[..],
+    };
+
+    // Commands occur at start of line, or after pipes
+    let Some((&command, args)) = words.split_first() else {
+        return complete_command(process, word);
+    };
+
+    let suggestions = if let Some(spec) = ctx.completions.get(command) {
+        complete_from_spec(process, spec, command, word, args.last().copied()).await?
+    } else if let Some(cli) = programs::program_command(command) {
+        complete_from_cli(process, cli, command, word, args)?
+    } else {
+        Vec::new()
+    };
+
+    if suggestions.is_empty() {
+        complete_path(process, word, false)
+    } else {
+        Ok(suggestions)
+    }
+}
+
+/// Complete a command name, or a path to a script.
+fn complete_command(process: &Process, word: &str) -> Result<Vec<String>> {
+    if word.contains('/') {
+        return complete_path(process, word, false);
+    }
+
+    let mut suggestions = Vec::new();
+
+    // External(as in, not part of the shell) commands.
+    let paths = process
+        .env
+        .get("PATH")
+        .ok_or_else(|| anyhow!("Could not get PATH variable"))?;
+    for path in paths.split(':') {
+        let path = process.get_path(path)?;
+        if !path.exists()? {
+            continue;
+        }
+        for command in path.read_dir()? {
+            let mut filename = command.filename();
+            if command.is_file()? && filename.starts_with(word) {
+                filename.push(' ');
+                suggestions.push(filename);
+            }
+        }
+    }
+
+    // Shell commands
+    suggestions.extend(complete_words(shell_commands::COMMANDS, word));
+    Ok(suggestions)
+}
+
+/// Complete a path to a file or directory.
+fn complete_path(process: &Process, word: &str, directories_only: bool) -> Result<Vec<String>> {
+    let (path_str, file) = if let Some(slash) = word.rfind('/') {
+        (
+            if slash == 0 { "/" } else { &word[0..slash] },
+            &word[slash + 1..],
+        )
+    } else {
+        ("", word)
+    };
+
+    let Some(home) = process.env.get("HOME") else {
+        bail!("No ${{HOME}} environmental variable");
+    };
+    let path = process.get_path(path_str.replace('~', home))?;
+
+    let mut suggestions = Vec::new();
+    if path.exists()? {
+        for entity in path.read_dir()? {
+            let filename = entity.filename();
+            if filename.starts_with(file) {
+                let mut suggestion = if path_str.is_empty() {
+                    filename
+                } else if path_str == "/" {
+                    format!("/{filename}")
+                } else {
+                    format!("{path_str}/{filename}")
+                };
+
+                if entity.is_file()? {
+                    if directories_only {
+                        continue;
+                    }
+                    suggestion.push(' ');
+                } else {
+                    suggestion.push('/');
+                }
+                suggestions.push(suggestion);
+            }
+        }
+    }
+    Ok(suggestions)
+}
+
+/// The words that start with `word`, ready to be inserted.
+fn complete_words<'a>(words: impl IntoIterator<Item = &'a str>, word: &str) -> Vec<String> {
+    words
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .map(|candidate| format!("{candidate} "))
+        .collect()
+}
+
+/// Complete according to a spec from the `complete` builtin.
+fn complete_from_spec<'a>(
+    process: &'a Process,
+    spec: &'a CompletionSpec,
+    program: &'a str,
+    word: &'a str,
+    previous: Option<&'a str>,
+) -> BoxFuture<'a, Result<Vec<String>>> {
+    async move {
+        let mut suggestions = Vec::new();
+
+        if let Some(words) = &spec.words {
+            suggestions.extend(complete_words(words.split_whitespace(), word));
+        }
+
+        // Like a Bash completion function, the command is passed the name of the program, the
+        // word being completed, and the word before it.
+        if let Some(command) = &spec.command {
+            let (mut pin, pout, mut backend) = streams::pipe();
+            let mut child_process = process.clone();
+            child_process.stdout = pout.clone();
+            child_process.args = vec![
+                command.clone(),
+                program.into(),
+                word.into(),
+                previous.unwrap_or(program).into(),
+            ];
+
+            let (_, output) = try_join! {
+                backend.run(),
+                async {
+                    let result = programs::exec_program(&mut child_process, command).await;
+                    pout.shutdown().await?;
+                    let mut output = String::new();
+                    pin.read_to_string(&mut output).await?;
+                    pin.shutdown().await?;
+                    if result?.is_none() {
+                        bail!("Cannot find {command}");
+                    }
+                    Ok(output)
+                },
+            }?;
+            suggestions.extend(output.lines().map(|line| format!("{line} ")));
+        }
+
+        Ok(suggestions)
+    }
+    .boxed()
+}
+
+/// Complete flags and their values from a program's command line interface.
+fn complete_from_cli(
+    process: &Process,
+    mut cli: Command,
+    program: &str,
+    word: &str,
+    args: &[&str],
+) -> Result<Vec<String>> {
+    cli.build();
+
+    if word.starts_with('-') {
+        let mut flags = Vec::new();
+        for arg in cli.get_arguments().filter(|arg| !arg.is_hide_set()) {
+            if let Some(long) = arg.get_long() {
+                flags.push(format!("--{long}"));
+            }
+            if let Some(short) = arg.get_short() {
+                flags.push(format!("-{short}"));
+            }
+        }
+        return Ok(complete_words(flags.iter().map(String::as_str), word));
+    }
+
+    // Work out which argument this word is a value of.
+    let mut option = None;
+    let mut positional = 0;
+    let mut only_positionals = false;
+    for arg in args {
+        if option.take().is_some() {
+            continue;
+        } else if only_positionals || !arg.starts_with('-') || *arg == "-" {
+            positional += 1;
+        } else if *arg == "--" {
+            only_positionals = true;
+        } else {
+            option = option_needing_value(&cli, arg);
+        }
+    }
+
+    let target = option.or_else(|| {
+        let mut positionals: Vec<&Arg> = cli.get_positionals().collect();
+        positionals.sort_by_key(|arg| arg.get_index());
+        positionals.into_iter().find(|arg| {
+            let count = arg
+                .get_num_args()
+                .map(|range| range.max_values())
+                .unwrap_or(1);
+            let found = positional < count;
+            positional = positional.saturating_sub(count);
+            found
+        })
+    });
+
+    match target {
+        Some(arg) => complete_value(process, program, arg, word),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The option given by `arg` if its value is expected in the next argument.
+fn option_needing_value<'a>(cli: &'a Command, arg: &str) -> Option<&'a Arg> {
+    let takes_value = |option: &&Arg| option.get_action().takes_values();
+
+    if let Some(long) = arg.strip_prefix("--") {
+        if long.contains('=') {
+            return None;
+        }
+        return cli
+            .get_arguments()
+            .filter(takes_value)
+            .find(|option| option.get_long() == Some(long));
+    }
+
+    // Short flags can be clustered, and the first that takes a value takes the rest.
+    let flags = arg.strip_prefix('-')?;
+    for (index, flag) in flags.char_indices() {
+        let option = cli
+            .get_arguments()
+            .find(|option| option.get_short() == Some(flag))?;
+        if takes_value(&option) {
+            return (index + flag.len_utf8() == flags.len()).then_some(option);
+        }
+    }
+    None
+}
+
+/// Complete the value of an argument.
+fn complete_value(process: &Process, program: &str, arg: &Arg, word: &str) -> Result<Vec<String>> {
+    let values: Vec<String> = arg
+        .get_possible_values()
+        .into_iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    if !values.is_empty() {
+        return Ok(complete_words(values.iter().map(String::as_str), word));
+    }
+
+    if let Some((_, _, directory)) = DIRECTORY_VALUES
+        .iter()
+        .find(|(name, id, _)| *name == program && arg.get_id() == id)
+    {
+        let mut names = Vec::new();
+        for entity in process.get_path(directory)?.read_dir()? {
+            names.push(entity.filename());
+        }
+        return Ok(complete_words(names.iter().map(String::as_str), word));
+    }
+
+    match arg.get_value_hint() {
+        ValueHint::CommandName => complete_command(process, word),
+        ValueHint::DirPath => complete_path(process, word, true),
+        _ => complete_path(process, word, false),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::channel::{
+        mpsc::{self, UnboundedReceiver},
+        oneshot,
+    };
+    use std::io::Write;
+    use vfs::{MemoryFS, VfsPath};
+
+    // Scripts register for signals, so the receiving end is returned to be kept alive.
+    fn make_process() -> (Process, UnboundedReceiver<oneshot::Sender<()>>) {
+        let (stdin, stdout, _) = streams::pipe();
+        let (signal_registrar, signals) = mpsc::unbounded();
+        let stderr = stdout.clone();
+        let cwd: VfsPath = MemoryFS::new().into();
+        for file in [
+            "/bin/echo",
+            "/bin/cowsay",
+            "/home/notes",
+            "/usr/share/cowsay/cows/cow",
+            "/usr/share/cowsay/cows/tux",
+        ] {
+            let path = cwd.join(file).unwrap();
+            path.parent().create_dir_all().unwrap();
+            path.create_file().unwrap();
+        }
+        cwd.join("/home/docs").unwrap().create_dir().unwrap();
+        write!(
+            cwd.join("/bin/greetings").unwrap().create_file().unwrap(),
+            "echo hello; echo ${{1}}-${{2}}-${{3}}"
+        )
+        .unwrap();
+        let process = Process {
+            terminal: stdin.clone(),
+            stdin,
+            stderr,
+            stdout,
+            signal_registrar,
+            cwd: cwd.join("/home").unwrap(),
+            args: Vec::new(),
+            env: [("PATH", "/bin"), ("HOME", "/home")]
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        };
+        (process, signals)
+    }
+
+    async fn suggestions(ctx: &ShellContext, section: &str) -> Vec<String> {
+        let start = section.rfind(' ').map(|space| space + 1).unwrap_or(0);
+        let (process, _signals) = make_process();
+        let mut suggestions = complete(ctx, &process, section, start).await.unwrap();
+        suggestions.sort();
+        suggestions
+    }
+
+    #[futures_test::test]
+    async fn commands_and_paths() {
+        let ctx = ShellContext::default();
+        assert_eq!(suggestions(&ctx, "ec").await, vec!["echo "]);
+        assert_eq!(
+            suggestions(&ctx, "echo hi | co").await,
+            vec!["complete ", "cowsay "]
+        );
+        assert_eq!(suggestions(&ctx, "echo ").await, vec!["docs/", "notes "]);
+        assert_eq!(
+            suggestions(&ctx, "which e").await,
+            vec!["echo ", "env ", "exec ", "exit ", "export "]
+        );
+        assert_eq!(suggestions(&ctx, "rmdir ").await, vec!["docs/"]);
+    }
+
+    #[futures_test::test]
+    async fn flags_and_values() {
+        let ctx = ShellContext::default();
+        assert_eq!(suggestions(&ctx, "ls --a").await, vec!["--all "]);
+        assert_eq!(
+            suggestions(&ctx, "rm -").await,
+            vec!["--force ", "--help ", "--recursive ", "-f ", "-h ", "-r "]
+        );
+        assert_eq!(suggestions(&ctx, "cowsay -f ").await, vec!["cow ", "tux "]);
+        assert_eq!(suggestions(&ctx, "cowsay -lf t").await, vec!["tux "]);
+        // Not the value of -f, so complete paths
+        assert_eq!(suggestions(&ctx, "cowsay -f tux n").await, vec!["notes "]);
+    }
+
+    #[futures_test::test]
+    async fn completion_specs() {
+        let mut ctx = ShellContext::default();
+        ctx.completions.insert(
+            "cowsay".into(),
+            CompletionSpec {
+                words: Some("moo  mooo baa".into()),
+                command: None,
+            },
+        );
+        assert_eq!(suggestions(&ctx, "cowsay m").await, vec!["moo ", "mooo "]);
+        // Nothing matched, so fall back to paths
+        assert_eq!(suggestions(&ctx, "cowsay n").await, vec!["notes "]);
+
+        ctx.completions.get_mut("cowsay").unwrap().command = Some("greetings".into());
+        assert_eq!(
+            suggestions(&ctx, "cowsay -f m").await,
+            vec!["cowsay-m--f ", "hello ", "moo ", "mooo "]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! Shell-style wildcard patterns, where `*` matches any text, `?` any one character and
+//! `[abc]` or `[a-z]` any one of a set of characters (or, as `[!abc]`, any character not in it).
+
+/// Check if all of `text` matches a pattern.
+pub fn matches(pattern: &str, text: &str, ignore_case: bool) -> bool {
+    let fold = |text: &str| -> Vec<char> {
+        if ignore_case {
+            text.to_lowercase().chars().collect()
+        } else {
+            text.chars().collect()
+        }
+    };
+    matches_chars(&fold(pattern), &fold(text))
+}
+
+/// Match a set like `[a-z]` at the start of the pattern against `c`, returning whether it
+/// matched and the rest of the pattern. Returns `None` if the set isn't closed, in which case
+/// the `[` is just a character.
+fn match_set(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let mut index = 1;
+    let negated = matches!(pattern.get(index), Some('!' | '^'));
+    if negated {
+        index += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let start = *pattern.get(index)?;
+        if start == ']' && !first {
+            return Some((matched != negated, &pattern[index + 1..]));
+        }
+        first = false;
+        if pattern.get(index + 1) == Some(&'-') && pattern.get(index + 2).is_some_and(|c| *c != ']')
+        {
+            matched |= (start..=pattern[index + 2]).contains(&c);
+            index += 3;
+        } else {
+            matched |= start == c;
+            index += 1;
+        }
+    }
+}
+
+fn matches_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => (0..=text.len()).any(|skip| matches_chars(&pattern[1..], &text[skip..])),
+        Some('?') => !text.is_empty() && matches_chars(&pattern[1..], &text[1..]),
+        Some('[') if !text.is_empty() => match match_set(pattern, text[0]) {
+            Some((matched, rest)) => matched && matches_chars(rest, &text[1..]),
+            None => text[0] == '[' && matches_chars(&pattern[1..], &text[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && matches_chars(&pattern[2..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && matches_chars(&pattern[1..], &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "main.rs", false));
+        assert!(!matches("*.rs", "main.rsx", false));
+        assert!(matches("a?c", "abc", false));
+        assert!(!matches("a?c", "ac", false));
+        assert!(matches("*", "", false));
+        assert!(matches("./*/x", "./a/b/x", false));
+        assert!(matches("README*", "readme.md", true));
+    }
+
+    #[test]
+    fn sets() {
+        assert!(matches("[abc]x", "bx", false));
+        assert!(!matches("[!abc]x", "bx", false));
+        assert!(matches("file[0-9]", "file7", false));
+        assert!(matches("[]]", "]", false));
+        assert!(matches("[a", "[a", false));
+        assert!(matches("\\*", "*", false));
+        assert!(!matches("\\*", "a", false));
+    }
+}
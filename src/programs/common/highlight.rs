@@ -0,0 +1,205 @@
+//! Syntax highlighting.
+use crate::programs::common::color_picker::{Color, ColorPicker};
+use anyhow::Result;
+use std::{io::Write, ops::Range};
+
+/// A part of some text to draw in a color.
+pub type Highlight = (Range<usize>, Color);
+
+/// Shell operators, longest first so `||` isn't mistaken for two pipes.
+const OPERATORS: [&str; 7] = ["&&", "||", ">>", "|", ";", ">", "<"];
+
+/// Operators after which a new command starts.
+const SEPARATORS: [&str; 4] = ["&&", "||", "|", ";"];
+
+/// Write `text`, colored by `highlights`.
+pub fn write_highlighted(
+    writer: &mut impl Write,
+    text: &str,
+    highlights: &[Highlight],
+) -> Result<()> {
+    let mut picker = ColorPicker::new(true);
+    let mut position = 0;
+    for (range, color) in highlights {
+        // Ignore overlapping highlights.
+        if range.start < position || range.end > text.len() {
+            continue;
+        }
+        writer.write_all(&text.as_bytes()[position..range.start])?;
+        picker.set_color(*color);
+        picker.write(writer, &text[range.clone()])?;
+        position = range.end;
+    }
+    writer.write_all(&text.as_bytes()[position..])?;
+    Ok(())
+}
+
+/// Highlight a line of shell script. Commands are green if `is_command` finds them and red if
+/// not, strings are yellow, operators are cyan and comments are blue.
+pub fn highlight_shell(line: &str, is_command: impl Fn(&str) -> bool) -> Vec<Highlight> {
+    highlight_shell_from(line, None, is_command).0
+}
+
+/// Highlight a line of shell script that starts inside a string, if `quote` is what the string
+/// was opened with. Returns the quote of a string left open at the end of the line as well.
+pub fn highlight_shell_from(
+    line: &str,
+    quote: Option<char>,
+    is_command: impl Fn(&str) -> bool,
+) -> (Vec<Highlight>, Option<char>) {
+    let mut highlights = Vec::new();
+    let mut expect_command = true;
+    let mut expect_path = false;
+    let mut open = None;
+    let operator_at = |index: usize| {
+        OPERATORS
+            .into_iter()
+            .find(|operator| line[index..].starts_with(operator))
+    };
+
+    let mut index = 0;
+    if let Some(quote) = quote {
+        let Some(end) = string_end(line, quote) else {
+            return (vec![(0..line.len(), Color::Yellow)], Some(quote));
+        };
+        highlights.push((0..end, Color::Yellow));
+        index = end;
+        expect_command = false;
+    }
+
+    while let Some(c) = line[index..].chars().next() {
+        if c.is_whitespace() {
+            index += c.len_utf8();
+            continue;
+        }
+        if c == '#' {
+            highlights.push((index..line.len(), Color::Blue));
+            break;
+        }
+
+        if let Some(operator) = operator_at(index) {
+            highlights.push((index..index + operator.len(), Color::Cyan));
+            if SEPARATORS.contains(&operator) {
+                expect_command = true;
+            } else {
+                expect_path = true;
+            }
+            index += operator.len();
+            continue;
+        }
+
+        // A word, which may contain quoted strings.
+        let start = index;
+        let mut quoted = false;
+        while let Some(c) = line[index..].chars().next() {
+            if c.is_whitespace() || operator_at(index).is_some() {
+                break;
+            }
+            index += c.len_utf8();
+            if c == '\'' || c == '"' {
+                let end = match string_end(&line[index..], c) {
+                    Some(end) => index + end,
+                    None => {
+                        open = Some(c);
+                        line.len()
+                    }
+                };
+                highlights.push((index - 1..end, Color::Yellow));
+                quoted = true;
+                index = end;
+            } else if c == '\\' {
+                index += line[index..].chars().next().map_or(0, char::len_utf8);
+            }
+        }
+
+        if expect_path {
+            expect_path = false;
+        } else {
+            if expect_command && !quoted {
+                let word = &line[start..index];
+                let color = if is_command(word) {
+                    Color::Green
+                } else {
+                    Color::Red
+                };
+                highlights.push((start..index, color));
+            }
+            expect_command = false;
+        }
+    }
+
+    highlights.sort_by_key(|(range, _)| range.start);
+    (highlights, open)
+}
+
+/// Find where a string ends, just after its closing `quote`.
+pub fn string_end(string: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in string.char_indices() {
+        if c == quote && !escaped {
+            return Some(index + c.len_utf8());
+        }
+        // Only double quoted strings have escapes.
+        escaped = quote == '"' && c == '\\' && !escaped;
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shell() {
+        let is_command = |command: &str| command == "echo";
+        assert_eq!(
+            highlight_shell("echo 'hi there' | sl -a \"x\\\"y\"", is_command),
+            vec![
+                (0..4, Color::Green),
+                (5..15, Color::Yellow),
+                (16..17, Color::Cyan),
+                (18..20, Color::Red),
+                (24..30, Color::Yellow),
+            ]
+        );
+        // Redirect targets aren't commands
+        assert_eq!(
+            highlight_shell("echo>out;ech", is_command),
+            vec![
+                (0..4, Color::Green),
+                (4..5, Color::Cyan),
+                (8..9, Color::Cyan),
+                (9..12, Color::Red),
+            ]
+        );
+        // Unterminated strings run to the end of the line
+        assert_eq!(
+            highlight_shell("\"echo", is_command),
+            vec![(0..5, Color::Yellow)]
+        );
+        // Comments start at the start of a word
+        assert_eq!(
+            highlight_shell("echo a#b # c", is_command),
+            vec![(0..4, Color::Green), (9..12, Color::Blue)]
+        );
+        // Strings can go on to the next line
+        assert_eq!(
+            highlight_shell_from("echo 'a", None, is_command),
+            (
+                vec![(0..4, Color::Green), (5..7, Color::Yellow)],
+                Some('\'')
+            )
+        );
+        assert_eq!(
+            highlight_shell_from("b' | sl", Some('\''), is_command),
+            (
+                vec![
+                    (0..2, Color::Yellow),
+                    (3..4, Color::Cyan),
+                    (5..7, Color::Red)
+                ],
+                None
+            )
+        );
+    }
+}
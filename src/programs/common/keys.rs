@@ -0,0 +1,47 @@
+//! Decoding of keypresses from a terminal in `InputMode::Char`.
+use crate::streams::InputStream;
+use anyhow::Result;
+use ascii::AsciiChar;
+
+/// A single keypress.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Key {
+    /// A printable or control character.
+    Char(char),
+    /// A character typed while holding the meta (alt) key.
+    Meta(char),
+    Up,
+    Down,
+    Right,
+    Left,
+    /// Shift+Tab.
+    BackTab,
+    /// The escape key itself.
+    Escape,
+}
+
+impl Key {
+    /// Read the next keypress, skipping escape sequences we don't understand.
+    pub async fn read(stdin: &mut InputStream) -> Result<Self> {
+        loop {
+            let c = stdin.get_char().await?;
+            if c != AsciiChar::ESC {
+                return Ok(Key::Char(c));
+            }
+
+            match stdin.get_char().await? {
+                // The escape key is sent doubled to disambiguate it from meta keys.
+                c if c == AsciiChar::ESC => return Ok(Key::Escape),
+                '[' => match stdin.get_char().await? {
+                    'A' => return Ok(Key::Up),
+                    'B' => return Ok(Key::Down),
+                    'C' => return Ok(Key::Right),
+                    'D' => return Ok(Key::Left),
+                    'Z' => return Ok(Key::BackTab),
+                    _ => continue,
+                },
+                c => return Ok(Key::Meta(c)),
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Moving along a line the way vi does, for both the editor and vi-style line editing.
+use super::unicode::{next_grapheme as next, prev_grapheme as prev};
+
+/// Where the last character starts, which is as far right as the cursor goes in normal mode.
+pub fn last_char(line: &str) -> usize {
+    prev(line, line.len())
+}
+
+/// Vi considers runs of word characters, runs of other non-blanks and runs of blanks to be
+/// separate words. A WORD is any run of non-blanks.
+pub fn class(c: char, big: bool) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if big || c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+/// Where `f` goes from `from`, which is the `count`th `c` after it, or before it for `F`. `t`
+/// and `T` (`till`) stop just short of it.
+pub fn find(
+    line: &str,
+    from: usize,
+    c: char,
+    forward: bool,
+    till: bool,
+    count: usize,
+) -> Option<usize> {
+    let mut target = from;
+    for _ in 0..count {
+        target = if forward {
+            let start = next(line, target);
+            start + line[start..].find(c)?
+        } else {
+            line[0..target].rfind(c)?
+        };
+    }
+    if till {
+        target = if forward {
+            prev(line, target)
+        } else {
+            next(line, target)
+        };
+    }
+    Some(target)
+}
+
+/// Where repeating a find with `;` or `,` looks from. A `t` or `T` starts a character along, so
+/// it doesn't get stuck next to the match it stopped at last time.
+pub fn repeat_from(line: &str, cursor: usize, forward: bool, till: bool) -> usize {
+    match (till, forward) {
+        (false, _) => cursor,
+        (true, true) => next(line, cursor),
+        (true, false) => prev(line, cursor),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finding() {
+        let line = "a,b,c,d";
+        assert_eq!(find(line, 0, ',', true, false, 2), Some(3));
+        assert_eq!(find(line, 0, ',', true, true, 2), Some(2));
+        assert_eq!(find(line, 6, ',', false, true, 1), Some(6));
+        assert_eq!(find(line, 6, ',', false, false, 4), None);
+        // Repeating a `t` from next to its match goes on to the one after.
+        let from = repeat_from(line, 2, true, true);
+        assert_eq!(find(line, from, ',', true, true, 1), Some(4));
+        assert_eq!(last_char("ab"), 1);
+        assert_eq!(last_char(""), 0);
+    }
+}
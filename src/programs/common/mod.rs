@@ -1,4 +1,14 @@
+pub mod basic_regex;
 pub mod color_picker;
+pub mod columns;
+pub mod completion;
 pub mod extendable_iterator;
+pub mod glob;
+pub mod highlight;
+pub mod keys;
+pub mod line_motions;
 pub mod readline;
 pub mod shell_commands;
+#[cfg(test)]
+pub mod testing;
+pub mod unicode;
@@ -0,0 +1,1372 @@
+use crate::{
+    programs::common::{
+        color_picker::{Color, ColorPicker},
+        columns::Columns,
+        highlight::{self, Highlight},
+        keys::Key,
+        line_motions,
+        unicode::{self, next_grapheme, prev_grapheme},
+    },
+    streams::{InputMode, InputStream, OutputStream},
+    utils, AnsiCode, ControlChar,
+};
+use anyhow::Result;
+use ascii::AsciiChar;
+use futures::io::AsyncWriteExt;
+use std::{collections::VecDeque, future::Future, io::Read};
+use vfs::VfsPath;
+use vi::{Action, ViState};
+
+mod vi;
+
+async fn move_cursor_left(stdout: &mut OutputStream, n: usize) -> Result<()> {
+    for _ in 0..n {
+        stdout.write_all(&AnsiCode::CursorLeft.to_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Redraw the line being edited, with a suggestion for the rest of it. The terminal's cursor
+/// is moved from `drawn_cursor`, a column, to `cursor`, a byte of the line.
+async fn draw_line(
+    stdout: &mut OutputStream,
+    buffer: &str,
+    highlights: &[Highlight],
+    suggestion: &str,
+    drawn_cursor: usize,
+    cursor: usize,
+) -> Result<()> {
+    move_cursor_left(stdout, drawn_cursor).await?;
+    stdout
+        .write_all(&AnsiCode::ClearToEndOfLine.to_bytes())
+        .await?;
+    highlight::write_highlighted(stdout, buffer, highlights)?;
+    if !suggestion.is_empty() {
+        let mut picker = ColorPicker::new(true);
+        picker.set_color(Color::Dim);
+        picker.write(stdout, suggestion)?;
+    }
+    let after = unicode::width(&buffer[cursor..]) + unicode::width(suggestion);
+    move_cursor_left(stdout, after).await
+}
+
+/// Find the most recent history entry that starts with `line`, and return the rest of it.
+fn suggest<'a>(history: &'a [String], line: &str) -> &'a str {
+    if line.is_empty() {
+        return "";
+    }
+    history
+        .iter()
+        .rev()
+        .find(|record| record.len() > line.len() && record.starts_with(line))
+        .map(|record| &record[line.len()..])
+        .unwrap_or_default()
+}
+
+/// Go back to the start of the line, after drawing something else over it, and write the
+/// prompt again.
+async fn restart_line(stdout: &mut OutputStream, prompt: &str) -> Result<()> {
+    // We can only return to the start of the prompt's last line.
+    let prompt = prompt.rsplit('\n').next().unwrap_or_default();
+    stdout
+        .write_all(&AnsiCode::CursorResetColumn.to_bytes())
+        .await?;
+    stdout.write_all(prompt.as_bytes()).await?;
+    Ok(())
+}
+
+/// Prompt for a vi-style history search pattern at the start of the line. Returns `None` if
+/// the search was cancelled.
+async fn read_search_pattern(
+    stdin: &mut InputStream,
+    stdout: &mut OutputStream,
+    prefix: char,
+) -> Result<Option<String>> {
+    let mut pattern = String::new();
+    restart_line(stdout, &prefix.to_string()).await?;
+    loop {
+        stdout
+            .write_all(&AnsiCode::ClearToEndOfLine.to_bytes())
+            .await?;
+        stdout.flush().await?;
+        match Key::read(stdin).await? {
+            Key::Char('\n' | '\r') => return Ok(Some(pattern)),
+            Key::Char(c) if c == AsciiChar::BackSpace => {
+                if pattern.is_empty() {
+                    return Ok(None);
+                }
+                let start = prev_grapheme(&pattern, pattern.len());
+                move_cursor_left(stdout, unicode::width(&pattern[start..])).await?;
+                pattern.truncate(start);
+            }
+            Key::Char(c) if !c.is_control() => {
+                pattern.push(c);
+                stdout.write_all(c.to_string().as_bytes()).await?;
+            }
+            Key::Escape => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+/// How an incremental history search ended.
+enum SearchOutcome {
+    /// Enter was pressed on a match.
+    Accept(String),
+    /// The search was left to edit history entry `index`. The key that ended the search, if
+    /// any, should be handled as a normal keypress.
+    Edit {
+        index: usize,
+        cursor: usize,
+        key: Option<Key>,
+    },
+    /// The search was cancelled with ^G, or left before anything matched. As with `Edit`, a
+    /// key may be left over.
+    Abort(Option<Key>),
+}
+
+/// Find the closest history entry containing `query`, starting at entry `start` and moving
+/// towards older entries (or newer ones if `forward` is set).
+///
+/// Returns the index of the entry and the position of the match within it.
+fn find_in_history(
+    history: &[String],
+    query: &str,
+    start: usize,
+    forward: bool,
+) -> Option<(usize, usize)> {
+    let mut indices: Box<dyn Iterator<Item = usize>> = if forward {
+        Box::new(start..history.len())
+    } else {
+        Box::new((0..=start.min(history.len().checked_sub(1)?)).rev())
+    };
+    indices.find_map(|index| {
+        let record = &history[index];
+        let position = if forward {
+            record.find(query)
+        } else {
+            record.rfind(query)
+        };
+        position.map(|position| (index, position))
+    })
+}
+
+/// Bash-style `(reverse-i-search)`.
+///
+/// Typing refines the query, ^R/^S jump to the next older/newer match, enter runs the match,
+/// ^G cancels, and any other key leaves the search to edit the match.
+async fn search_history(
+    history: &[String],
+    start: usize,
+    mut forward: bool,
+    stdin: &mut InputStream,
+    stdout: &mut OutputStream,
+) -> Result<SearchOutcome> {
+    let mut picker = ColorPicker::new(true);
+    picker.set_color(Color::Reverse);
+
+    let mut query = String::new();
+    // The current entry, and the position and length of the match within it.
+    let mut index = start;
+    let mut matched: Option<(usize, usize)> = None;
+    let mut failed = false;
+
+    loop {
+        let record = history.get(index).map(String::as_str).unwrap_or_default();
+
+        let label = match (failed, forward) {
+            (false, false) => "(reverse-i-search)",
+            (false, true) => "(i-search)",
+            (true, false) => "(failed reverse-i-search)",
+            (true, true) => "(failed i-search)",
+        };
+        stdout
+            .write_all(&AnsiCode::CursorResetColumn.to_bytes())
+            .await?;
+        stdout
+            .write_all(format!("{label}`{query}': ").as_bytes())
+            .await?;
+        let cursor = match matched {
+            Some((position, len)) => {
+                let end = position + len;
+                stdout.write_all(&record.as_bytes()[0..position]).await?;
+                picker.write(stdout, &record[position..end])?;
+                stdout.write_all(&record.as_bytes()[end..]).await?;
+                position
+            }
+            None => {
+                stdout.write_all(record.as_bytes()).await?;
+                record.len()
+            }
+        };
+        stdout
+            .write_all(&AnsiCode::ClearToEndOfLine.to_bytes())
+            .await?;
+        move_cursor_left(stdout, unicode::width(&record[cursor..])).await?;
+        stdout.flush().await?;
+
+        let key = Key::read(stdin).await?;
+        let leave = |key| {
+            if matched.is_some() {
+                SearchOutcome::Edit { index, cursor, key }
+            } else {
+                SearchOutcome::Abort(key)
+            }
+        };
+        let c = match key {
+            Key::Char(c) => c,
+            Key::Escape => return Ok(leave(None)),
+            key => return Ok(leave(Some(key))),
+        };
+
+        if c == ControlChar::R || c == ControlChar::S {
+            forward = c == ControlChar::S;
+            if query.is_empty() {
+                continue;
+            }
+            let next = if forward {
+                index.checked_add(1)
+            } else {
+                index.checked_sub(1)
+            };
+            // Skip over entries identical to the current match.
+            let found = next.and_then(|next| {
+                let mut next = next;
+                loop {
+                    let (found, position) = find_in_history(history, &query, next, forward)?;
+                    if history[found] != record {
+                        return Some((found, position));
+                    }
+                    next = if forward {
+                        found.checked_add(1)?
+                    } else {
+                        found.checked_sub(1)?
+                    };
+                }
+            });
+            match found {
+                Some((found, position)) => {
+                    index = found;
+                    matched = Some((position, query.len()));
+                    failed = false;
+                }
+                None => failed = true,
+            }
+        } else if c == ControlChar::G {
+            return Ok(SearchOutcome::Abort(None));
+        } else if (c == '\n' || c == '\r') && matched.is_some() {
+            return Ok(SearchOutcome::Accept(record.into()));
+        } else if c == AsciiChar::BackSpace {
+            query.pop();
+            // Search again from where we started.
+            index = start;
+            matched = None;
+            failed = false;
+            if !query.is_empty() {
+                match find_in_history(history, &query, start, forward) {
+                    Some((found, position)) => {
+                        index = found;
+                        matched = Some((position, query.len()));
+                    }
+                    None => failed = true,
+                }
+            }
+        } else if (c as u32) < 0x20 {
+            return Ok(leave(Some(key)));
+        } else {
+            query.push(c);
+            // Stay on the current entry if it still matches.
+            match find_in_history(history, &query, index, forward) {
+                Some((found, position)) => {
+                    index = found;
+                    matched = Some((position, query.len()));
+                }
+                None => failed = true,
+            }
+        }
+    }
+}
+
+/// How many kills are remembered for yanking.
+const KILL_RING_SIZE: usize = 16;
+
+/// Text removed by kill commands, which can be yanked back.
+#[derive(Default)]
+struct KillRing {
+    entries: VecDeque<String>,
+    // How far back from the most recent kill the last yank was.
+    offset: usize,
+}
+
+impl KillRing {
+    /// Save killed text. A kill that immediately follows another is merged with it, in front if
+    /// it was killed `backward`, so the two can be yanked together.
+    fn kill(&mut self, text: &str, merge: bool, backward: bool) {
+        match self.entries.back_mut() {
+            Some(last) if merge => {
+                if backward {
+                    last.insert_str(0, text);
+                } else {
+                    last.push_str(text);
+                }
+            }
+            _ => {
+                self.entries.push_back(text.into());
+                if self.entries.len() > KILL_RING_SIZE {
+                    self.entries.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Get the most recent kill.
+    fn yank(&mut self) -> Option<&str> {
+        self.offset = 0;
+        self.entries.back().map(String::as_str)
+    }
+
+    /// Get the kill before the one last yanked, cycling back around to the most recent.
+    fn yank_pop(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.offset = (self.offset + 1) % self.entries.len();
+        self.entries
+            .get(self.entries.len() - 1 - self.offset)
+            .map(String::as_str)
+    }
+}
+
+/// The previous command, for commands that act differently when repeated.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum LastCommand {
+    Other,
+    /// Typed a character. A run of typing is undone all at once.
+    Insert,
+    /// Deleted a character. A run of deletions is undone all at once.
+    Delete,
+    /// Killed text, which the next kill is merged with.
+    Kill,
+    /// Yanked text into this range, which yank-pop can replace.
+    Yank(usize, usize),
+    /// Tried to complete a word. Completing twice in a row lists the candidates.
+    Complete,
+}
+
+/// Number of completion candidates above which we ask before listing them all.
+const COMPLETION_QUERY_ITEMS: usize = 100;
+
+/// Completion candidates that have been listed, which further completions cycle through in
+/// place.
+struct Menu {
+    candidates: Vec<String>,
+    selected: Option<usize>,
+    /// The part of the line the selected candidate occupies.
+    start: usize,
+    end: usize,
+}
+
+impl Menu {
+    /// Replace the selected candidate in `buffer` with the next one, or the previous one if
+    /// going `backward`. Returns where the new candidate ends.
+    fn select(&mut self, buffer: &mut String, backward: bool) -> usize {
+        let count = self.candidates.len();
+        let selected = match (self.selected, backward) {
+            (None, false) => 0,
+            (None, true) => count - 1,
+            (Some(index), false) => (index + 1) % count,
+            (Some(index), true) => (index + count - 1) % count,
+        };
+        self.selected = Some(selected);
+        let candidate = &self.candidates[selected];
+        buffer.replace_range(self.start..self.end, candidate);
+        self.end = self.start + candidate.len();
+        self.end
+    }
+}
+
+/// The longest string every word starts with.
+fn common_prefix(words: &[String]) -> &str {
+    let Some((first, rest)) = words.split_first() else {
+        return "";
+    };
+    let mut prefix = first.as_str();
+    for word in rest {
+        while !word.starts_with(prefix) {
+            let mut chars = prefix.chars();
+            chars.next_back();
+            prefix = chars.as_str();
+        }
+    }
+    prefix
+}
+
+/// The part of a completion candidate worth showing in a list, which for paths is just the
+/// last component.
+fn display_name(candidate: &str) -> &str {
+    let candidate = candidate.trim_end_matches(' ');
+    let start = candidate
+        .trim_end_matches('/')
+        .rfind('/')
+        .map(|slash| slash + 1)
+        .unwrap_or(0);
+    &candidate[start..]
+}
+
+/// List completion candidates in columns below the line being edited, first asking if there
+/// are a lot of them. Returns whether they were listed.
+async fn list_candidates(
+    stdin: &mut InputStream,
+    stdout: &mut OutputStream,
+    candidates: &[String],
+) -> Result<bool> {
+    stdout.write_all(b"\n").await?;
+    if candidates.len() >= COMPLETION_QUERY_ITEMS {
+        stdout
+            .write_all(
+                format!("Display all {} possibilities? (y or n)", candidates.len()).as_bytes(),
+            )
+            .await?;
+        stdout.flush().await?;
+        let answer = loop {
+            match Key::read(stdin).await? {
+                Key::Char('y' | 'Y' | ' ') => break true,
+                Key::Char('n' | 'N') | Key::Escape => break false,
+                Key::Char(c) if c == AsciiChar::BackSpace || c == ControlChar::G => break false,
+                _ => {}
+            }
+        };
+        stdout.write_all(b"\n").await?;
+        if !answer {
+            return Ok(false);
+        }
+    }
+
+    let names: Vec<&str> = candidates.iter().map(|c| display_name(c)).collect();
+    for line in Columns::format(&names, utils::get_screen_width()) {
+        stdout.write_all(line.as_bytes()).await?;
+        stdout.write_all(b"\n").await?;
+    }
+    Ok(true)
+}
+
+/// Find the start of the word before `cursor`.
+fn word_start(buffer: &str, cursor: usize, is_word: fn(char) -> bool) -> usize {
+    buffer[0..cursor]
+        .trim_end_matches(|c| !is_word(c))
+        .trim_end_matches(is_word)
+        .len()
+}
+
+/// Find the end of the word after `cursor`.
+fn word_end(buffer: &str, cursor: usize, is_word: fn(char) -> bool) -> usize {
+    let rest = buffer[cursor..]
+        .trim_start_matches(|c| !is_word(c))
+        .trim_start_matches(is_word);
+    buffer.len() - rest.len()
+}
+
+/// Uppercase the first letter of a word, and lowercase the rest.
+fn capitalize(word: &str) -> String {
+    let mut capitalized = String::with_capacity(word.len());
+    let mut seen_letter = false;
+    for c in word.chars() {
+        if seen_letter {
+            capitalized.extend(c.to_lowercase());
+        } else {
+            seen_letter = c.is_alphanumeric();
+            capitalized.extend(c.to_uppercase());
+        }
+    }
+    capitalized
+}
+
+/// This trait indicates that a struct can record or retrieve command history.
+pub trait History {
+    fn get_records(&self) -> Result<Vec<String>>;
+    fn add_record(&self, record: &str) -> Result<()>;
+}
+
+/// Read and write history to/from a file.
+pub struct FileBasedHistory {
+    file: VfsPath,
+}
+
+impl FileBasedHistory {
+    pub fn new(file: VfsPath) -> Self {
+        Self { file }
+    }
+}
+
+impl History for FileBasedHistory {
+    fn get_records(&self) -> Result<Vec<String>> {
+        if !self.file.exists()? {
+            self.file.create_file()?;
+        }
+        let mut file = self.file.open_file()?;
+        let mut records = String::new();
+        file.read_to_string(&mut records)?;
+        Ok(records.trim().split('\n').map(String::from).collect())
+    }
+
+    fn add_record(&self, record: &str) -> Result<()> {
+        let mut file = if self.file.exists()? {
+            self.file.append_file()?
+        } else {
+            self.file.create_file()?
+        };
+        std::io::Write::write_all(&mut file, record.as_bytes())?;
+        std::io::Write::write_all(&mut file, b"\n")?;
+        Ok(())
+    }
+}
+
+/// "History" that records nothing
+#[derive(Default)]
+pub struct NullHistory;
+
+impl History for NullHistory {
+    fn get_records(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    fn add_record(&self, _record: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Colors a line as it's typed.
+type Highlighter = Box<dyn Fn(&str) -> Vec<Highlight> + Send + Sync>;
+
+/// Which set of key bindings to edit lines with.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum EditingMode {
+    #[default]
+    Emacs,
+    Vi,
+}
+
+/// A GNU Readline-like implementation.
+pub struct Readline<T: History> {
+    history: T,
+    kill_ring: KillRing,
+    highlighter: Option<Highlighter>,
+    /// Vi mode state, if in vi mode.
+    vi: Option<ViState>,
+}
+
+impl<T: History> Readline<T> {
+    pub fn new(history: T) -> Self {
+        Self {
+            history,
+            kill_ring: Default::default(),
+            highlighter: None,
+            vi: None,
+        }
+    }
+
+    /// Switch between emacs and vi key bindings.
+    pub fn set_editing_mode(&mut self, mode: EditingMode) {
+        match mode {
+            EditingMode::Emacs => self.vi = None,
+            EditingMode::Vi => {
+                self.vi.get_or_insert_with(Default::default);
+            }
+        }
+    }
+
+    /// Color lines as they're typed.
+    pub fn set_highlighter(
+        &mut self,
+        highlighter: impl Fn(&str) -> Vec<Highlight> + Send + Sync + 'static,
+    ) {
+        self.highlighter = Some(Box::new(highlighter));
+    }
+
+    /// Get next line.
+    pub async fn get_line<F, Fut>(
+        &mut self,
+        prompt: &str,
+        stdin: &mut InputStream,
+        stdout: &mut OutputStream,
+        completer: F,
+    ) -> Result<String>
+    where
+        F: Fn(String, usize) -> Fut,
+        Fut: Future<Output = Result<Vec<String>>>,
+    {
+        stdin.set_mode(InputMode::Char).await?;
+
+        let result = self.get_line_inner(prompt, stdin, stdout, completer).await;
+
+        stdin.set_mode(InputMode::Line).await?;
+
+        if let Ok(result) = result.as_ref() {
+            self.history.add_record(result)?;
+        }
+
+        result
+    }
+
+    async fn get_line_inner<F, Fut>(
+        &mut self,
+        prompt: &str,
+        stdin: &mut InputStream,
+        stdout: &mut OutputStream,
+        completer: F,
+    ) -> Result<String>
+    where
+        F: Fn(String, usize) -> Fut,
+        Fut: Future<Output = Result<Vec<String>>>,
+    {
+        let mut cursor = 0;
+        // Which column the terminal's cursor is in, relative to the start of the line.
+        let mut drawn_cursor = 0;
+        let mut skip_refresh = false;
+        // Typed a character at the end of the line, which is all that needs drawing.
+        let mut typed_at_end = false;
+        // How long the suggestion on screen is.
+        let mut drawn_suggestion = 0;
+        let history = self.history.get_records()?;
+        let mut buffers = history.clone();
+        buffers.push(String::new());
+        let mut buffer_index = buffers.len() - 1;
+        // Each line has its own undo stack of (contents, cursor) snapshots.
+        let mut undo_stacks: Vec<Vec<(String, usize)>> = vec![Vec::new(); buffers.len()];
+        let mut last_command = LastCommand::Other;
+        // Keys to handle before reading any more, such as the key that ended a history search.
+        let mut pending_keys = VecDeque::new();
+        let mut menu: Option<Menu> = None;
+        if let Some(vi) = self.vi.as_mut() {
+            vi.reset();
+        }
+
+        stdout.write_all(prompt.as_bytes()).await?;
+        loop {
+            let buffer = buffers
+                .get_mut(buffer_index)
+                .expect("History out of bounds");
+            let undo_stack = &mut undo_stacks[buffer_index];
+
+            // Suggest the rest of a line from history, fish-style.
+            let suggestion = if cursor == buffer.len() {
+                suggest(&history, buffer)
+            } else {
+                ""
+            };
+
+            if typed_at_end
+                && self.highlighter.is_none()
+                && suggestion.is_empty()
+                && drawn_suggestion == 0
+            {
+                // Only the character just typed needs writing.
+                let start = buffer
+                    .char_indices()
+                    .next_back()
+                    .map_or(0, |(index, _)| index);
+                stdout.write_all(&buffer.as_bytes()[start..]).await?;
+            } else if !skip_refresh {
+                let highlights = match &self.highlighter {
+                    Some(highlighter) => highlighter(buffer),
+                    None => Vec::new(),
+                };
+                draw_line(
+                    stdout,
+                    buffer,
+                    &highlights,
+                    suggestion,
+                    drawn_cursor,
+                    cursor,
+                )
+                .await?;
+                drawn_suggestion = suggestion.len();
+            }
+            drawn_cursor = unicode::width(&buffer[..cursor]);
+            skip_refresh = false;
+            typed_at_end = false;
+            stdout.flush().await?;
+
+            let key = match pending_keys.pop_front() {
+                Some(key) => key,
+                None => Key::read(stdin).await?,
+            };
+            let previous_command = std::mem::replace(&mut last_command, LastCommand::Other);
+
+            if let Some(vi) = self.vi.as_mut() {
+                let enter = key == Key::Char('\n') || key == Key::Char('\r');
+                if vi.normal && !enter {
+                    let snapshot = (buffer.clone(), cursor);
+                    let Some(action) = vi.handle_key(key, buffer, &mut cursor, &mut self.kill_ring)
+                    else {
+                        skip_refresh = true;
+                        continue;
+                    };
+                    let changed = *buffer != snapshot.0;
+                    match action {
+                        Action::Edit => {
+                            if changed {
+                                undo_stack.push(snapshot);
+                            }
+                        }
+                        // Typing continues the change, to be undone all at once.
+                        Action::Insert => {
+                            if changed {
+                                undo_stack.push(snapshot);
+                                last_command = LastCommand::Insert;
+                            }
+                        }
+                        Action::Undo => {
+                            if let Some((contents, position)) = undo_stack.pop() {
+                                *buffer = contents;
+                                cursor = std::cmp::min(position, line_motions::last_char(buffer));
+                            }
+                        }
+                        Action::Repeat => {
+                            for key in vi.last_change().iter().rev() {
+                                pending_keys.push_front(*key);
+                            }
+                        }
+                        Action::History { forward, count } => {
+                            buffer_index = if forward {
+                                std::cmp::min(buffer_index + count, buffers.len() - 1)
+                            } else {
+                                buffer_index.saturating_sub(count)
+                            };
+                            cursor = 0;
+                        }
+                        Action::Search { .. } | Action::SearchAgain { .. } => {
+                            let (pattern, forward) = match action {
+                                Action::Search { forward } => {
+                                    let prefix = if forward { '?' } else { '/' };
+                                    let pattern =
+                                        read_search_pattern(stdin, stdout, prefix).await?;
+                                    restart_line(stdout, prompt).await?;
+                                    drawn_cursor = 0;
+                                    match pattern {
+                                        Some(pattern) if !pattern.is_empty() => (pattern, forward),
+                                        // Search for the last pattern again
+                                        Some(_) => match vi.last_search.clone() {
+                                            Some((pattern, _)) => (pattern, forward),
+                                            None => continue,
+                                        },
+                                        None => continue,
+                                    }
+                                }
+                                _ => match vi.last_search.clone() {
+                                    Some((pattern, forward)) => (
+                                        pattern,
+                                        forward
+                                            != (action == Action::SearchAgain { reverse: true }),
+                                    ),
+                                    None => continue,
+                                },
+                            };
+                            vi.last_search = Some((pattern.clone(), forward));
+
+                            // The line being edited isn't part of the history.
+                            let history = &buffers[0..buffers.len() - 1];
+                            let found = if forward {
+                                find_in_history(history, &pattern, buffer_index + 1, true)
+                            } else {
+                                buffer_index.checked_sub(1).and_then(|start| {
+                                    find_in_history(history, &pattern, start, false)
+                                })
+                            };
+                            if let Some((index, _)) = found {
+                                buffer_index = index;
+                                cursor = 0;
+                            }
+                        }
+                    }
+                    continue;
+                } else if key == Key::Escape {
+                    vi.escape(buffer, &mut cursor);
+                    continue;
+                }
+                vi.record(key);
+            }
+            let c = match key {
+                // Up/Down arrow - Move up/down in history
+                Key::Up | Key::Down => {
+                    if key == Key::Up && buffer_index > 0 {
+                        buffer_index -= 1;
+                    } else if key == Key::Down && buffer_index < buffers.len() - 1 {
+                        buffer_index += 1
+                    } else {
+                        continue;
+                    }
+                    cursor = buffers[buffer_index].len();
+                    continue;
+                }
+                // Right arrow - move right, or accept the suggestion
+                Key::Right => {
+                    if cursor < buffer.len() {
+                        cursor = next_grapheme(buffer, cursor);
+                    } else if !suggestion.is_empty() {
+                        undo_stack.push((buffer.clone(), cursor));
+                        buffer.push_str(suggestion);
+                        cursor = buffer.len();
+                    }
+                    continue;
+                }
+                // Left arrow - move left
+                Key::Left => {
+                    cursor = prev_grapheme(buffer, cursor);
+                    continue;
+                }
+                // Move left one word
+                Key::Meta('b') => {
+                    cursor = word_start(buffer, cursor, char::is_alphanumeric);
+                    continue;
+                }
+                // Move right one word, or accept a word of the suggestion
+                Key::Meta('f') => {
+                    if cursor == buffer.len() && !suggestion.is_empty() {
+                        undo_stack.push((buffer.clone(), cursor));
+                        let end = word_end(suggestion, 0, char::is_alphanumeric);
+                        buffer.push_str(&suggestion[0..end]);
+                        cursor = buffer.len();
+                    } else {
+                        cursor = word_end(buffer, cursor, char::is_alphanumeric);
+                    }
+                    continue;
+                }
+                // Kill word forward/backward
+                Key::Meta('d') | Key::Meta('\x08') => {
+                    let (start, end) = if key == Key::Meta('d') {
+                        (cursor, word_end(buffer, cursor, char::is_alphanumeric))
+                    } else {
+                        (word_start(buffer, cursor, char::is_alphanumeric), cursor)
+                    };
+                    if start != end {
+                        undo_stack.push((buffer.clone(), cursor));
+                        let killed: String = buffer.drain(start..end).collect();
+                        self.kill_ring.kill(
+                            &killed,
+                            previous_command == LastCommand::Kill,
+                            key != Key::Meta('d'),
+                        );
+                        cursor = start;
+                    }
+                    last_command = LastCommand::Kill;
+                    continue;
+                }
+                // Yank-pop - replace what was just yanked with an older kill
+                Key::Meta('y') => {
+                    if let LastCommand::Yank(start, end) = previous_command {
+                        if let Some(text) = self.kill_ring.yank_pop() {
+                            buffer.replace_range(start..end, text);
+                            cursor = start + text.len();
+                            last_command = LastCommand::Yank(start, cursor);
+                        }
+                    }
+                    continue;
+                }
+                // Upcase, downcase or capitalize word
+                Key::Meta(mode @ ('u' | 'l' | 'c')) => {
+                    let end = word_end(buffer, cursor, char::is_alphanumeric);
+                    if cursor != end {
+                        undo_stack.push((buffer.clone(), cursor));
+                        let word = &buffer[cursor..end];
+                        let word = match mode {
+                            'u' => word.to_uppercase(),
+                            'l' => word.to_lowercase(),
+                            _ => capitalize(word),
+                        };
+                        buffer.replace_range(cursor..end, &word);
+                        cursor = end;
+                    }
+                    continue;
+                }
+                Key::Meta(_) | Key::Escape => continue,
+                Key::BackTab => '\t',
+                Key::Char(c) => c,
+            };
+
+            // ^R/^S - search backward/forward through history
+            if c == ControlChar::R || c == ControlChar::S {
+                let forward = c == ControlChar::S;
+                // The line being edited isn't part of the history.
+                let history = &buffers[0..buffers.len() - 1];
+                let start = std::cmp::min(buffer_index, history.len().saturating_sub(1));
+                let outcome = search_history(history, start, forward, stdin, stdout).await?;
+                match outcome {
+                    SearchOutcome::Accept(line) => {
+                        stdout
+                            .write_all(&AnsiCode::CursorResetColumn.to_bytes())
+                            .await?;
+                        stdout.write_all(b"\n").await?;
+                        return Ok(line);
+                    }
+                    SearchOutcome::Edit {
+                        index,
+                        cursor: position,
+                        key,
+                    } => {
+                        buffer_index = index;
+                        cursor = position;
+                        pending_keys.extend(key);
+                    }
+                    SearchOutcome::Abort(key) => {
+                        pending_keys.extend(key);
+                    }
+                }
+                restart_line(stdout, prompt).await?;
+                drawn_cursor = 0;
+            // ^A - move cusor to beginning of line
+            } else if c == ControlChar::A {
+                cursor = 0;
+            // ^B - move cursor back one char
+            } else if c == ControlChar::B {
+                cursor = prev_grapheme(buffer, cursor);
+            // ^D - delete character under cursor
+            } else if c == ControlChar::D {
+                if cursor < buffer.len() {
+                    if previous_command != LastCommand::Delete {
+                        undo_stack.push((buffer.clone(), cursor));
+                    }
+                    buffer.replace_range(cursor..next_grapheme(buffer, cursor), "");
+                }
+                last_command = LastCommand::Delete;
+            // ^E - move cursor to end of line
+            // ^F - move cursor forward one char
+            // Either accepts the suggestion at the end of the line
+            } else if c == ControlChar::E || c == ControlChar::F {
+                if cursor == buffer.len() && !suggestion.is_empty() {
+                    undo_stack.push((buffer.clone(), cursor));
+                    buffer.push_str(suggestion);
+                    cursor = buffer.len();
+                } else if c == ControlChar::E {
+                    cursor = buffer.len();
+                } else {
+                    cursor = next_grapheme(buffer, cursor);
+                }
+            // ^K/^U - kill after/before cursor
+            // ^W - kill the whitespace-delimited word before the cursor
+            } else if c == ControlChar::K || c == ControlChar::U || c == ControlChar::W {
+                let (start, end) = if c == ControlChar::K {
+                    (cursor, buffer.len())
+                } else if c == ControlChar::U {
+                    (0, cursor)
+                } else {
+                    (word_start(buffer, cursor, |c| !c.is_whitespace()), cursor)
+                };
+                if start != end {
+                    undo_stack.push((buffer.clone(), cursor));
+                    let killed: String = buffer.drain(start..end).collect();
+                    self.kill_ring.kill(
+                        &killed,
+                        previous_command == LastCommand::Kill,
+                        c != ControlChar::K,
+                    );
+                    cursor = start;
+                }
+                last_command = LastCommand::Kill;
+            // ^L - clear screen
+            } else if c == ControlChar::L {
+                stdout.write_all(&AnsiCode::Clear.to_bytes()).await?;
+                stdout.write_all(prompt.as_bytes()).await?;
+                drawn_cursor = 0;
+            // ^T - transpose characters
+            } else if c == ControlChar::T {
+                // At the end of the line, the last two are swapped.
+                let position = if cursor == buffer.len() {
+                    prev_grapheme(buffer, cursor)
+                } else {
+                    cursor
+                };
+                if position > 0 && position < buffer.len() {
+                    undo_stack.push((buffer.clone(), cursor));
+                    let start = prev_grapheme(buffer, position);
+                    let end = next_grapheme(buffer, position);
+                    let swapped = format!("{}{}", &buffer[position..end], &buffer[start..position]);
+                    buffer.replace_range(start..end, &swapped);
+                    cursor = end;
+                }
+            // ^Y - yank the last kill
+            } else if c == ControlChar::Y {
+                if let Some(text) = self.kill_ring.yank() {
+                    undo_stack.push((buffer.clone(), cursor));
+                    buffer.insert_str(cursor, text);
+                    last_command = LastCommand::Yank(cursor, cursor + text.len());
+                    cursor += text.len();
+                }
+            // ^_ - undo
+            } else if c == AsciiChar::US {
+                if let Some((contents, position)) = undo_stack.pop() {
+                    *buffer = contents;
+                    cursor = position;
+                }
+            // Tab completions
+            } else if c == '\t' {
+                // Once the candidates have been listed, completing again cycles through them.
+                if let (LastCommand::Complete, Some(menu)) = (previous_command, menu.as_mut()) {
+                    if menu.selected.is_none() {
+                        undo_stack.push((buffer.clone(), cursor));
+                    }
+                    cursor = menu.select(buffer, key == Key::BackTab);
+                    last_command = LastCommand::Complete;
+                    continue;
+                }
+                menu = None;
+
+                let start = buffer[0..cursor].rfind(' ').map(|x| x + 1).unwrap_or(0);
+                let section = &buffer[0..cursor];
+                let word = &section[start..];
+                let mut suggestions = completer(section.into(), start).await?;
+                suggestions.sort();
+                suggestions.dedup();
+                last_command = LastCommand::Complete;
+
+                // Fill in as much as all the suggestions have in common.
+                let prefix = common_prefix(&suggestions);
+                if prefix.len() > word.len() {
+                    undo_stack.push((buffer.clone(), cursor));
+                    buffer.replace_range(start..cursor, prefix);
+                    cursor = start + prefix.len();
+                // Otherwise, list them if asked twice.
+                } else if suggestions.len() > 1 && previous_command == LastCommand::Complete {
+                    if list_candidates(stdin, stdout, &suggestions).await? {
+                        menu = Some(Menu {
+                            candidates: suggestions,
+                            selected: None,
+                            start,
+                            end: cursor,
+                        });
+                    }
+                    stdout.write_all(prompt.as_bytes()).await?;
+                    drawn_cursor = 0;
+                } else {
+                    skip_refresh = true;
+                }
+
+            // Newline(^L) or carriage return (^M)
+            } else if c == '\n' || c == '\r' {
+                // Don't leave the suggestion behind.
+                if drawn_suggestion > 0 {
+                    let highlights = match &self.highlighter {
+                        Some(highlighter) => highlighter(buffer),
+                        None => Vec::new(),
+                    };
+                    draw_line(stdout, buffer, &highlights, "", drawn_cursor, cursor).await?;
+                }
+                // An interesting bug appears without this next line.
+                // The character behind the cursor will be deleted!
+                // The bug probably lies in term.js
+                stdout
+                    .write_all(&AnsiCode::CursorResetColumn.to_bytes())
+                    .await?;
+                stdout.write_all(b"\n").await?;
+                // Todo: I think there's a way to move out of the vector instead of cloning.
+                return Ok(buffer.clone());
+            // Backspace
+            } else if c == AsciiChar::BackSpace {
+                if cursor > 0 {
+                    if previous_command != LastCommand::Delete {
+                        undo_stack.push((buffer.clone(), cursor));
+                    }
+                    let start = prev_grapheme(buffer, cursor);
+                    buffer.replace_range(start..cursor, "");
+                    cursor = start;
+                }
+                last_command = LastCommand::Delete;
+            // Ignore unknown commands
+            } else if (c as u32) < 0x20 {
+                // Do nothing
+            } else {
+                // Typing is undone a whole run at a time.
+                if previous_command != LastCommand::Insert {
+                    undo_stack.push((buffer.clone(), cursor));
+                }
+                last_command = LastCommand::Insert;
+                buffer.insert(cursor, c);
+                cursor += c.len_utf8();
+                typed_at_end = cursor == buffer.len();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::streams;
+    use futures::try_join;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct MemoryHistory(RefCell<Vec<String>>);
+
+    impl History for MemoryHistory {
+        fn get_records(&self) -> Result<Vec<String>> {
+            Ok(self.0.borrow().clone())
+        }
+
+        fn add_record(&self, record: &str) -> Result<()> {
+            self.0.borrow_mut().push(record.into());
+            Ok(())
+        }
+    }
+
+    // Feed `input` to a Readline with the given history and return the resulting line.
+    async fn type_line(records: &[&str], input: &str) -> String {
+        type_line_with_completer(records, input, |_, _| async { Ok(Vec::new()) }).await
+    }
+
+    async fn type_line_vi(records: &[&str], input: &str) -> String {
+        type_line_in_mode(EditingMode::Vi, records, input, |_, _| async {
+            Ok(Vec::new())
+        })
+        .await
+    }
+
+    async fn type_line_with_completer<F, Fut>(records: &[&str], input: &str, completer: F) -> String
+    where
+        F: Fn(String, usize) -> Fut,
+        Fut: Future<Output = Result<Vec<String>>>,
+    {
+        type_line_in_mode(EditingMode::Emacs, records, input, completer).await
+    }
+
+    async fn type_line_in_mode<F, Fut>(
+        mode: EditingMode,
+        records: &[&str],
+        input: &str,
+        completer: F,
+    ) -> String
+    where
+        F: Fn(String, usize) -> Fut,
+        Fut: Future<Output = Result<Vec<String>>>,
+    {
+        let history = MemoryHistory(RefCell::new(
+            records.iter().map(|record| record.to_string()).collect(),
+        ));
+        let mut readline = Readline::new(history);
+        readline.set_editing_mode(mode);
+        let (mut stdin, mut keyboard, mut backend) = streams::pipe();
+        let (_, mut stdout, _stdout_backend) = streams::pipe();
+
+        let (_, line) = try_join! {
+            backend.run(),
+            async {
+                keyboard.write_all(input.as_bytes()).await?;
+                keyboard.flush().await?;
+                let line = readline
+                    .get_line("$ ", &mut stdin, &mut stdout, completer)
+                    .await?;
+                keyboard.shutdown().await?;
+                stdin.shutdown().await?;
+                Ok(line)
+            }
+        }
+        .unwrap();
+        line
+    }
+
+    #[test]
+    fn find_in_history_both_directions() {
+        let history: Vec<String> = ["echo one", "cowsay moo", "echo two"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(find_in_history(&history, "echo", 2, false), Some((2, 0)));
+        assert_eq!(find_in_history(&history, "echo", 1, false), Some((0, 0)));
+        assert_eq!(find_in_history(&history, "moo", 0, true), Some((1, 7)));
+        assert_eq!(find_in_history(&history, "moo", 2, true), None);
+        assert_eq!(find_in_history(&[], "moo", 0, false), None);
+    }
+
+    #[futures_test::test]
+    async fn reverse_search() {
+        let history = ["echo one", "cowsay moo", "echo two"];
+        // Most recent match
+        assert_eq!(type_line(&history, "\x12echo\n").await, "echo two");
+        // ^R again to go further back
+        assert_eq!(type_line(&history, "\x12echo\x12\n").await, "echo one");
+        // ^S to come back
+        assert_eq!(type_line(&history, "\x12echo\x12\x13\n").await, "echo two");
+        // Leave the search to edit the match
+        assert_eq!(type_line(&history, "\x12cow\x05!\n").await, "cowsay moo!");
+        // ^G cancels
+        assert_eq!(type_line(&history, "ls\x12cow\x07\n").await, "ls");
+        // Failed searches keep the last match
+        assert_eq!(type_line(&history, "\x12twox\n").await, "echo two");
+        // Nothing matched, so keep the line being edited
+        assert_eq!(type_line(&history, "ls\x12xyz\n").await, "ls");
+    }
+
+    #[futures_test::test]
+    async fn kill_and_yank() {
+        // ^W kills a word, ^Y yanks it back
+        assert_eq!(
+            type_line(&[], "echo hi there\x17\x01\x19 \n").await,
+            "there echo hi "
+        );
+        // Consecutive kills are yanked together
+        assert_eq!(
+            type_line(&[], "one two three\x17\x17\x05\x19\n").await,
+            "one two three"
+        );
+        // ^[y replaces the yank with an older kill
+        assert_eq!(
+            type_line(&[], "aaa bbb\x17\x01\x0b\x19\x1by\n").await,
+            "bbb"
+        );
+        // ^U and ^K
+        assert_eq!(
+            type_line(&[], "abcdef\x1b[D\x1b[D\x15\x05\x19\n").await,
+            "efabcd"
+        );
+        assert_eq!(
+            type_line(&[], "abcdef\x01\x06\x0b\x19\x19\n").await,
+            "abcdefbcdef"
+        );
+        // ^[d and ^[<backspace>
+        assert_eq!(
+            type_line(&[], "cat /etc/profile\x1b\x08\n").await,
+            "cat /etc/"
+        );
+        assert_eq!(
+            type_line(&[], "cat /etc/profile\x01\x1bd\n").await,
+            " /etc/profile"
+        );
+    }
+
+    #[futures_test::test]
+    async fn word_editing() {
+        // ^T
+        assert_eq!(type_line(&[], "ab\x14\n").await, "ba");
+        assert_eq!(type_line(&[], "abc\x01\x06\x14\n").await, "bac");
+        // ^[u, ^[l and ^[c
+        assert_eq!(type_line(&[], "foo bar\x01\x1bu\n").await, "FOO bar");
+        assert_eq!(type_line(&[], "FOO BAR\x01\x1bf\x1bl\n").await, "FOO bar");
+        assert_eq!(type_line(&[], "foo bAR\x01\x1bc\x1bc\n").await, "Foo Bar");
+    }
+
+    #[futures_test::test]
+    async fn unicode() {
+        // The cursor moves over a whole character, however many bytes it takes.
+        assert_eq!(type_line(&[], "a🐮b\x1b[D\x1b[D\x08\n").await, "🐮b");
+        assert_eq!(type_line(&[], "é🐮\x02\x02\x04\n").await, "🐮");
+        assert_eq!(type_line(&[], "漢字\x02x\n").await, "漢x字");
+        // An accent typed after a letter is part of it.
+        assert_eq!(type_line(&[], "ae\u{301}\x08\n").await, "a");
+        assert_eq!(type_line(&[], "ae\u{301}\x14\n").await, "e\u{301}a");
+        assert_eq!(type_line(&[], "🐮🐄\x14\n").await, "🐄🐮");
+        // Characters outside ASCII aren't mistaken for control characters.
+        assert_eq!(type_line(&[], "Ā\n").await, "Ā");
+    }
+
+    #[futures_test::test]
+    async fn undo() {
+        // A run of typing is undone at once
+        assert_eq!(type_line(&[], "echo\x1f\n").await, "");
+        assert_eq!(type_line(&[], "echo\x01hi \x1f\n").await, "echo");
+        assert_eq!(type_line(&[], "echo hi\x17\x1f\n").await, "echo hi");
+        assert_eq!(type_line(&[], "echo hi\x08\x08\x1f\n").await, "echo hi");
+        assert_eq!(type_line(&[], "echo hi\x17\x19\x1f\x1f\n").await, "echo hi");
+    }
+
+    // Complete words from a fixed list.
+    async fn complete_line(input: &str) -> String {
+        let words = ["cat ", "cowsay ", "cowthink ", "echo "];
+        type_line_with_completer(&[], input, |section, start| async move {
+            let word = &section[start..];
+            Ok(words
+                .iter()
+                .filter(|candidate| candidate.starts_with(word))
+                .map(|candidate| candidate.to_string())
+                .collect())
+        })
+        .await
+    }
+
+    #[test]
+    fn completion_helpers() {
+        let words = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        assert_eq!(common_prefix(&words(&["cowsay", "cowthink"])), "cow");
+        assert_eq!(common_prefix(&words(&["cat", "cowsay"])), "c");
+        assert_eq!(common_prefix(&words(&["λx", "λy"])), "λ");
+        assert_eq!(common_prefix(&[]), "");
+        assert_eq!(display_name("/usr/bin/ls "), "ls");
+        assert_eq!(display_name("./src/"), "src/");
+        assert_eq!(display_name("echo "), "echo");
+    }
+
+    #[futures_test::test]
+    async fn tab_completion() {
+        // A single candidate is completed
+        assert_eq!(complete_line("ec\t\n").await, "echo ");
+        // Otherwise the common prefix is
+        assert_eq!(complete_line("co\t\n").await, "cow");
+        // Listing them changes nothing
+        assert_eq!(complete_line("co\t\t\n").await, "cow");
+        // After that, each Tab selects the next candidate
+        assert_eq!(complete_line("co\t\t\t\n").await, "cowsay ");
+        assert_eq!(complete_line("co\t\t\t\t\n").await, "cowthink ");
+        assert_eq!(complete_line("co\t\t\t\t\t\n").await, "cowsay ");
+        // Shift-Tab selects the previous one
+        assert_eq!(complete_line("c\t\t\x1b[Z\n").await, "cowthink ");
+        // Any other key leaves the menu
+        assert_eq!(complete_line("co\t\t\tx\t\n").await, "cowsay x");
+    }
+
+    #[futures_test::test]
+    async fn autosuggestion() {
+        let history = ["echo hello", "echo world", "ls"];
+        // Suggestions aren't used unless accepted
+        assert_eq!(type_line(&history, "ech\n").await, "ech");
+        // Right arrow, ^E or ^F accepts the most recent match
+        assert_eq!(type_line(&history, "ech\x1b[C\n").await, "echo world");
+        assert_eq!(type_line(&history, "echo h\x05\n").await, "echo hello");
+        assert_eq!(type_line(&history, "echo h\x06!\n").await, "echo hello!");
+        // Meta-f accepts a word
+        assert_eq!(type_line(&history, "e\x1bf\n").await, "echo");
+        // Only at the end of the line
+        assert_eq!(type_line(&history, "ech\x02\x05\x05\n").await, "echo world");
+        assert_eq!(suggest(&[], "ls"), "");
+        assert_eq!(suggest(&["ls".into()], ""), "");
+    }
+
+    #[futures_test::test]
+    async fn vi_mode() {
+        const ESC: &str = "\x1b\x1b";
+        let history = ["echo one", "ls -l", "echo two"];
+        // Inserting, then editing in normal mode
+        assert_eq!(type_line_vi(&[], "hello world\n").await, "hello world");
+        assert_eq!(
+            type_line_vi(&[], &format!("hello world{ESC}bdw\n")).await,
+            "hello "
+        );
+        assert_eq!(
+            type_line_vi(&[], &format!("one two three{ESC}0wcwfour{ESC}\n")).await,
+            "one four three"
+        );
+        assert_eq!(type_line_vi(&[], &format!("abc{ESC}0xp\n")).await, "bac");
+        assert_eq!(type_line_vi(&[], &format!("a b c{ESC}0dw.\n")).await, "c");
+        // Undo takes back a whole insertion
+        assert_eq!(
+            type_line_vi(&[], &format!("abc{ESC}adef{ESC}u\n")).await,
+            "abc"
+        );
+        // Walking and searching history
+        assert_eq!(
+            type_line_vi(&history, &format!("{ESC}k\n")).await,
+            "echo two"
+        );
+        assert_eq!(
+            type_line_vi(&history, &format!("{ESC}kkj\n")).await,
+            "echo two"
+        );
+        assert_eq!(type_line_vi(&history, &format!("{ESC}2k\n")).await, "ls -l");
+        assert_eq!(
+            type_line_vi(&history, &format!("{ESC}/echo\nn\n")).await,
+            "echo one"
+        );
+        assert_eq!(
+            type_line_vi(&history, &format!("{ESC}/echo\nnN\n")).await,
+            "echo two"
+        );
+    }
+}
@@ -0,0 +1,686 @@
+//! Vi-style line editing.
+//!
+//! Lines start in insert mode, which works much like the emacs-style mode. Escape switches to
+//! normal mode, where keys are commands.
+use super::KillRing;
+use crate::programs::common::{
+    keys::Key,
+    line_motions::{self, class, last_char},
+    unicode::{next_grapheme as next, prev_grapheme as prev},
+};
+use ascii::AsciiChar;
+
+/// Where the cursor goes, or what an operator acts on.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Motion {
+    Left,
+    Right,
+    Start,
+    FirstNonBlank,
+    End,
+    /// Start of the next word, or WORD if `big`.
+    WordForward(bool),
+    /// Start of the previous word.
+    WordBackward(bool),
+    /// End of the word.
+    WordEnd(bool),
+    /// Find a character with f, F, t or T.
+    Find {
+        c: char,
+        forward: bool,
+        till: bool,
+    },
+    /// Repeat the last find, in the opposite direction if `reverse`.
+    RepeatFind {
+        reverse: bool,
+    },
+    /// The whole line, for `dd` and friends.
+    Line,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Command {
+    Move(Motion),
+    Operate(Operator, Motion),
+    /// Insert before the cursor, or after if `append`, or at the start/end of the line if
+    /// `line`.
+    Insert {
+        append: bool,
+        line: bool,
+    },
+    Put {
+        before: bool,
+    },
+    Replace(char),
+    ToggleCase,
+    Undo,
+    Repeat,
+    /// Move to an older history entry, or a newer one if `forward`.
+    History {
+        forward: bool,
+    },
+    /// Prompt for a pattern and search history.
+    Search {
+        forward: bool,
+    },
+    SearchAgain {
+        reverse: bool,
+    },
+}
+
+impl Command {
+    /// If this command can change the line, so `.` should repeat it.
+    fn is_change(&self) -> bool {
+        matches!(
+            self,
+            Self::Operate(Operator::Delete | Operator::Change, _)
+                | Self::Insert { .. }
+                | Self::Put { .. }
+                | Self::Replace(_)
+                | Self::ToggleCase
+        )
+    }
+
+    fn enters_insert_mode(&self) -> bool {
+        matches!(
+            self,
+            Self::Operate(Operator::Change, _) | Self::Insert { .. }
+        )
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct ViCommand {
+    count: usize,
+    command: Command,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+enum Parse {
+    /// More keys are needed.
+    Incomplete,
+    Invalid,
+    Done(ViCommand),
+}
+
+/// What the line editor needs to do after a command.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(super) enum Action {
+    /// The line was edited or the cursor moved.
+    Edit,
+    /// The line was edited, and we're now in insert mode.
+    Insert,
+    Undo,
+    /// Replay these keys, which made the last change.
+    Repeat,
+    /// Move this many entries through history, forward or backward.
+    History {
+        forward: bool,
+        count: usize,
+    },
+    Search {
+        forward: bool,
+    },
+    SearchAgain {
+        reverse: bool,
+    },
+}
+
+/// The character a key stands for in normal mode.
+fn normal_char(key: Key) -> Option<char> {
+    Some(match key {
+        Key::Char(c) if c == AsciiChar::BackSpace => 'h',
+        Key::Char(' ') => 'l',
+        Key::Char(c) => c,
+        Key::Left => 'h',
+        Key::Right => 'l',
+        Key::Up => 'k',
+        Key::Down => 'j',
+        Key::Meta(_) | Key::Escape | Key::BackTab => return None,
+    })
+}
+
+/// Read a count, if there is one.
+fn parse_count(chars: &[char], position: &mut usize) -> Option<usize> {
+    let start = *position;
+    while let Some(c) = chars.get(*position) {
+        // Zero on its own is a motion.
+        if !c.is_ascii_digit() || (*c == '0' && *position == start) {
+            break;
+        }
+        *position += 1;
+    }
+    chars[start..*position]
+        .iter()
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+/// Read a motion. Returns `Err(true)` if more keys are needed, or `Err(false)` if invalid.
+fn parse_motion(chars: &[char], position: &mut usize) -> Result<Motion, bool> {
+    let c = *chars.get(*position).ok_or(true)?;
+    *position += 1;
+    Ok(match c {
+        'h' => Motion::Left,
+        'l' => Motion::Right,
+        '0' => Motion::Start,
+        '^' => Motion::FirstNonBlank,
+        '$' => Motion::End,
+        'w' | 'W' => Motion::WordForward(c == 'W'),
+        'b' | 'B' => Motion::WordBackward(c == 'B'),
+        'e' | 'E' => Motion::WordEnd(c == 'E'),
+        'f' | 'F' | 't' | 'T' => {
+            let target = *chars.get(*position).ok_or(true)?;
+            *position += 1;
+            Motion::Find {
+                c: target,
+                forward: c.is_lowercase(),
+                till: c.eq_ignore_ascii_case(&'t'),
+            }
+        }
+        ';' | ',' => Motion::RepeatFind { reverse: c == ',' },
+        _ => return Err(false),
+    })
+}
+
+/// Parse a normal mode command from the keys typed so far.
+fn parse(keys: &[Key]) -> Parse {
+    let Some(chars) = keys
+        .iter()
+        .map(|key| normal_char(*key))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Parse::Invalid;
+    };
+
+    let mut position = 0;
+    let mut count = parse_count(&chars, &mut position);
+    let Some(&c) = chars.get(position) else {
+        return Parse::Incomplete;
+    };
+
+    let operator = match c {
+        'd' => Some(Operator::Delete),
+        'c' => Some(Operator::Change),
+        'y' => Some(Operator::Yank),
+        _ => None,
+    };
+
+    let command = if let Some(operator) = operator {
+        position += 1;
+        if let Some(motion_count) = parse_count(&chars, &mut position) {
+            count = Some(count.unwrap_or(1) * motion_count);
+        }
+        if chars.get(position) == Some(&c) {
+            Command::Operate(operator, Motion::Line)
+        } else {
+            match parse_motion(&chars, &mut position) {
+                Ok(motion) => Command::Operate(operator, motion),
+                Err(true) => return Parse::Incomplete,
+                Err(false) => return Parse::Invalid,
+            }
+        }
+    } else {
+        match parse_motion(&chars, &mut position) {
+            Ok(motion) => Command::Move(motion),
+            Err(true) => return Parse::Incomplete,
+            Err(false) => match c {
+                'i' | 'a' | 'I' | 'A' => Command::Insert {
+                    append: c.eq_ignore_ascii_case(&'a'),
+                    line: c.is_uppercase(),
+                },
+                'x' => Command::Operate(Operator::Delete, Motion::Right),
+                'X' => Command::Operate(Operator::Delete, Motion::Left),
+                'D' => Command::Operate(Operator::Delete, Motion::End),
+                'C' => Command::Operate(Operator::Change, Motion::End),
+                's' => Command::Operate(Operator::Change, Motion::Right),
+                'S' => Command::Operate(Operator::Change, Motion::Line),
+                'Y' => Command::Operate(Operator::Yank, Motion::Line),
+                'p' | 'P' => Command::Put { before: c == 'P' },
+                'r' => match chars.get(position) {
+                    Some(&c) => Command::Replace(c),
+                    None => return Parse::Incomplete,
+                },
+                '~' => Command::ToggleCase,
+                'u' => Command::Undo,
+                '.' => Command::Repeat,
+                'k' | 'j' => Command::History { forward: c == 'j' },
+                '/' | '?' => Command::Search { forward: c == '?' },
+                'n' | 'N' => Command::SearchAgain { reverse: c == 'N' },
+                _ => return Parse::Invalid,
+            },
+        }
+    };
+
+    Parse::Done(ViCommand {
+        count: count.unwrap_or(1),
+        command,
+    })
+}
+
+fn class_at(buffer: &str, index: usize, big: bool) -> Option<u8> {
+    buffer[index..].chars().next().map(|c| class(c, big))
+}
+
+fn class_before(buffer: &str, index: usize, big: bool) -> Option<u8> {
+    buffer[0..index].chars().next_back().map(|c| class(c, big))
+}
+
+/// Keeps track of vi mode.
+#[derive(Default)]
+pub(super) struct ViState {
+    /// In normal mode rather than insert mode.
+    pub(super) normal: bool,
+    /// Keys of a command still being typed.
+    keys: Vec<Key>,
+    last_find: Option<Motion>,
+    /// Keys of a change still being made in insert mode.
+    recording: Option<Vec<Key>>,
+    last_change: Vec<Key>,
+    /// The pattern last searched for.
+    pub(super) last_search: Option<(String, bool)>,
+}
+
+impl ViState {
+    /// Go back to insert mode for a new line.
+    pub(super) fn reset(&mut self) {
+        self.normal = false;
+        self.keys.clear();
+        self.recording = None;
+    }
+
+    /// Record a key typed in insert mode, in case it's part of a change.
+    pub(super) fn record(&mut self, key: Key) {
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(key);
+        }
+    }
+
+    /// Leave insert mode, moving the cursor back onto the last character typed.
+    pub(super) fn escape(&mut self, buffer: &str, cursor: &mut usize) {
+        if let Some(mut recording) = self.recording.take() {
+            recording.push(Key::Escape);
+            self.last_change = recording;
+        }
+        self.normal = true;
+        *cursor = prev(buffer, *cursor);
+    }
+
+    /// The keys that made the last change, for `.` to repeat.
+    pub(super) fn last_change(&self) -> &[Key] {
+        &self.last_change
+    }
+
+    /// Handle a key typed in normal mode, running the command if it's complete.
+    pub(super) fn handle_key(
+        &mut self,
+        key: Key,
+        buffer: &mut String,
+        cursor: &mut usize,
+        kill_ring: &mut KillRing,
+    ) -> Option<Action> {
+        self.keys.push(key);
+        let command = match parse(&self.keys) {
+            Parse::Incomplete => return None,
+            Parse::Invalid => {
+                self.keys.clear();
+                return None;
+            }
+            Parse::Done(command) => command,
+        };
+
+        let keys = std::mem::take(&mut self.keys);
+        if command.command.enters_insert_mode() {
+            self.recording = Some(keys);
+        } else if command.command.is_change() {
+            self.last_change = keys;
+        }
+
+        let action = self.execute(command, buffer, cursor, kill_ring);
+        if action == Action::Insert {
+            self.normal = false;
+        } else if !buffer.is_empty() {
+            *cursor = std::cmp::min(*cursor, last_char(buffer));
+        }
+        Some(action)
+    }
+
+    /// Find where a motion goes from `cursor`, and whether an operator should include the
+    /// character there.
+    fn motion_target(
+        &mut self,
+        motion: Motion,
+        count: usize,
+        buffer: &str,
+        cursor: usize,
+    ) -> Option<(usize, bool)> {
+        let mut target = cursor;
+        let inclusive = match motion {
+            Motion::Left => {
+                for _ in 0..count {
+                    target = prev(buffer, target);
+                }
+                false
+            }
+            Motion::Right => {
+                for _ in 0..count {
+                    target = next(buffer, target);
+                }
+                false
+            }
+            Motion::Start => {
+                target = 0;
+                false
+            }
+            Motion::FirstNonBlank => {
+                target = buffer.len() - buffer.trim_start().len();
+                false
+            }
+            Motion::End => {
+                target = last_char(buffer);
+                true
+            }
+            Motion::Line => {
+                target = buffer.len();
+                false
+            }
+            Motion::WordForward(big) => {
+                for _ in 0..count {
+                    if let Some(class) = class_at(buffer, target, big).filter(|class| *class != 0) {
+                        while class_at(buffer, target, big) == Some(class) {
+                            target = next(buffer, target);
+                        }
+                    }
+                    while class_at(buffer, target, big) == Some(0) {
+                        target = next(buffer, target);
+                    }
+                }
+                false
+            }
+            Motion::WordBackward(big) => {
+                for _ in 0..count {
+                    while class_before(buffer, target, big) == Some(0) {
+                        target = prev(buffer, target);
+                    }
+                    if let Some(class) = class_before(buffer, target, big) {
+                        while class_before(buffer, target, big) == Some(class) {
+                            target = prev(buffer, target);
+                        }
+                    }
+                }
+                false
+            }
+            Motion::WordEnd(big) => {
+                for _ in 0..count {
+                    target = next(buffer, target);
+                    while class_at(buffer, target, big) == Some(0) {
+                        target = next(buffer, target);
+                    }
+                    let class = class_at(buffer, target, big)?;
+                    while class_at(buffer, next(buffer, target), big) == Some(class) {
+                        target = next(buffer, target);
+                    }
+                }
+                true
+            }
+            Motion::Find { c, forward, till } => {
+                self.last_find = Some(motion);
+                target = line_motions::find(buffer, target, c, forward, till, count)?;
+                forward
+            }
+            Motion::RepeatFind { reverse } => {
+                let Some(Motion::Find { c, forward, till }) = self.last_find else {
+                    return None;
+                };
+                let forward = forward != reverse;
+                let from = line_motions::repeat_from(buffer, cursor, forward, till);
+                target = line_motions::find(buffer, from, c, forward, till, count)?;
+                forward
+            }
+        };
+        Some((target, inclusive))
+    }
+
+    fn execute(
+        &mut self,
+        command: ViCommand,
+        buffer: &mut String,
+        cursor: &mut usize,
+        kill_ring: &mut KillRing,
+    ) -> Action {
+        let count = command.count;
+        match command.command {
+            Command::Move(motion) => {
+                if let Some((target, _)) = self.motion_target(motion, count, buffer, *cursor) {
+                    *cursor = target;
+                }
+            }
+            Command::Operate(operator, motion) => {
+                // `cw` changes to the end of the word, leaving the space after it.
+                let motion = match (operator, motion) {
+                    (Operator::Change, Motion::WordForward(big))
+                        if class_at(buffer, *cursor, big).is_some_and(|class| class != 0) =>
+                    {
+                        Motion::WordEnd(big)
+                    }
+                    _ => motion,
+                };
+                let Some((target, inclusive)) = self.motion_target(motion, count, buffer, *cursor)
+                else {
+                    return Action::Edit;
+                };
+                let (start, end) = if motion == Motion::Line {
+                    (0, buffer.len())
+                } else if target >= *cursor {
+                    let end = if inclusive {
+                        next(buffer, target)
+                    } else {
+                        target
+                    };
+                    (*cursor, end)
+                } else {
+                    (target, *cursor)
+                };
+
+                if start != end {
+                    kill_ring.kill(&buffer[start..end], false, false);
+                }
+                match operator {
+                    Operator::Yank => {
+                        if motion != Motion::Line {
+                            *cursor = start;
+                        }
+                    }
+                    Operator::Delete | Operator::Change => {
+                        buffer.replace_range(start..end, "");
+                        *cursor = start;
+                        if operator == Operator::Change {
+                            return Action::Insert;
+                        }
+                    }
+                }
+            }
+            Command::Insert { append, line } => {
+                *cursor = match (append, line) {
+                    (false, false) => *cursor,
+                    (true, false) => next(buffer, *cursor),
+                    (false, true) => buffer.len() - buffer.trim_start().len(),
+                    (true, true) => buffer.len(),
+                };
+                return Action::Insert;
+            }
+            Command::Put { before } => {
+                if let Some(text) = kill_ring.yank() {
+                    let text = text.repeat(count);
+                    let position = if before || buffer.is_empty() {
+                        *cursor
+                    } else {
+                        next(buffer, *cursor)
+                    };
+                    buffer.insert_str(position, &text);
+                    *cursor = prev(buffer, position + text.len());
+                }
+            }
+            Command::Replace(c) => {
+                let end = (0..count).try_fold(*cursor, |end, _| {
+                    (end < buffer.len()).then(|| next(buffer, end))
+                });
+                if let Some(end) = end {
+                    let replacement = c.to_string().repeat(count);
+                    buffer.replace_range(*cursor..end, &replacement);
+                    *cursor = prev(buffer, *cursor + replacement.len());
+                }
+            }
+            Command::ToggleCase => {
+                for _ in 0..count {
+                    if *cursor == buffer.len() {
+                        break;
+                    }
+                    let end = next(buffer, *cursor);
+                    let toggled: String = (buffer[*cursor..end].chars())
+                        .flat_map(|c| -> Vec<char> {
+                            if c.is_uppercase() {
+                                c.to_lowercase().collect()
+                            } else {
+                                c.to_uppercase().collect()
+                            }
+                        })
+                        .collect();
+                    buffer.replace_range(*cursor..end, &toggled);
+                    *cursor += toggled.len();
+                }
+            }
+            Command::Undo => return Action::Undo,
+            Command::Repeat => return Action::Repeat,
+            Command::History { forward } => return Action::History { forward, count },
+            Command::Search { forward } => return Action::Search { forward },
+            Command::SearchAgain { reverse } => return Action::SearchAgain { reverse },
+        }
+        Action::Edit
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keys(input: &str) -> Vec<Key> {
+        input.chars().map(Key::Char).collect()
+    }
+
+    #[test]
+    fn parsing() {
+        let done = |count, command| Parse::Done(ViCommand { count, command });
+        assert_eq!(
+            parse(&keys("w")),
+            done(1, Command::Move(Motion::WordForward(false)))
+        );
+        assert_eq!(parse(&keys("0")), done(1, Command::Move(Motion::Start)));
+        assert_eq!(
+            parse(&keys("2d3w")),
+            done(
+                6,
+                Command::Operate(Operator::Delete, Motion::WordForward(false))
+            )
+        );
+        assert_eq!(
+            parse(&keys("cc")),
+            done(1, Command::Operate(Operator::Change, Motion::Line))
+        );
+        assert_eq!(parse(&keys("d")), Parse::Incomplete);
+        assert_eq!(parse(&keys("10")), Parse::Incomplete);
+        assert_eq!(parse(&keys("yt")), Parse::Incomplete);
+        assert_eq!(
+            parse(&keys("yT(")),
+            done(
+                1,
+                Command::Operate(
+                    Operator::Yank,
+                    Motion::Find {
+                        c: '(',
+                        forward: false,
+                        till: true
+                    }
+                )
+            )
+        );
+        assert_eq!(parse(&keys("rx")), done(1, Command::Replace('x')));
+        assert_eq!(parse(&keys("dz")), Parse::Invalid);
+        assert_eq!(parse(&[Key::Char('d'), Key::Escape]), Parse::Invalid);
+    }
+
+    // Run normal mode commands on a line, starting at `cursor`.
+    fn run(line: &str, cursor: usize, input: &str) -> (String, usize) {
+        let mut vi = ViState::default();
+        let mut kill_ring = KillRing::default();
+        let mut buffer = String::from(line);
+        let mut cursor = cursor;
+        for key in keys(input) {
+            vi.handle_key(key, &mut buffer, &mut cursor, &mut kill_ring);
+        }
+        (buffer, cursor)
+    }
+
+    #[test]
+    fn motions() {
+        let line = "echo foo.bar  baz";
+        assert_eq!(run(line, 0, "w").1, 5);
+        assert_eq!(run(line, 0, "2w").1, 8);
+        assert_eq!(run(line, 0, "2W").1, 14);
+        assert_eq!(run(line, 16, "b").1, 14);
+        assert_eq!(run(line, 14, "3b").1, 5);
+        assert_eq!(run(line, 0, "e").1, 3);
+        assert_eq!(run(line, 3, "e").1, 7);
+        assert_eq!(run(line, 0, "$").1, 16);
+        assert_eq!(run(line, 16, "0").1, 0);
+        assert_eq!(run("  ls", 3, "^").1, 2);
+        assert_eq!(run(line, 0, "fo").1, 3);
+        assert_eq!(run(line, 0, "fo;").1, 6);
+        assert_eq!(run(line, 0, "to").1, 2);
+        assert_eq!(run(line, 0, "2to").1, 5);
+        assert_eq!(run(line, 0, "to;").1, 5);
+        assert_eq!(run(line, 16, "Fo").1, 7);
+        assert_eq!(run(line, 16, "To").1, 8);
+        assert_eq!(run(line, 16, "Fo,").1, 7);
+        // Motions that fail don't move
+        assert_eq!(run(line, 0, "fq").1, 0);
+        // Characters are whole graphemes
+        let line = "a🐮e\u{301}b";
+        assert_eq!(run(line, 0, "l").1, 1);
+        assert_eq!(run(line, 0, "2l").1, 5);
+        assert_eq!(run(line, 0, "$").1, 8);
+        assert_eq!(run(line, 8, "h").1, 5);
+    }
+
+    #[test]
+    fn operators() {
+        let line = "echo foo bar";
+        assert_eq!(run(line, 0, "dw"), ("foo bar".into(), 0));
+        assert_eq!(run(line, 5, "d$"), ("echo ".into(), 4));
+        assert_eq!(run(line, 5, "D"), ("echo ".into(), 4));
+        assert_eq!(run(line, 9, "db"), ("echo bar".into(), 5));
+        assert_eq!(run(line, 0, "dtb"), ("bar".into(), 0));
+        assert_eq!(run(line, 0, "dfo"), (" foo bar".into(), 0));
+        assert_eq!(run(line, 0, "dd"), ("".into(), 0));
+        assert_eq!(run(line, 0, "x"), ("cho foo bar".into(), 0));
+        assert_eq!(run(line, 11, "x"), ("echo foo ba".into(), 10));
+        assert_eq!(run(line, 1, "3x"), ("e foo bar".into(), 1));
+        assert_eq!(run(line, 0, "ywP"), ("echo echo foo bar".into(), 4));
+        assert_eq!(run(line, 0, "xp"), ("ceho foo bar".into(), 1));
+        assert_eq!(run(line, 0, "3rz"), ("zzzo foo bar".into(), 2));
+        assert_eq!(run(line, 0, "2~"), ("ECho foo bar".into(), 2));
+        let line = "🐮e\u{301}b";
+        assert_eq!(run(line, 0, "x"), ("e\u{301}b".into(), 0));
+        assert_eq!(run(line, 4, "x"), ("🐮b".into(), 4));
+        assert_eq!(run(line, 4, "~"), ("🐮E\u{301}b".into(), 7));
+        assert_eq!(run(line, 0, "2rz"), ("zzb".into(), 1));
+    }
+}
@@ -8,7 +8,10 @@ use crate::{
     process::{ExitCode, Process},
     programs::{
         self,
-        common::readline::{NullHistory, Readline},
+        common::{
+            completion::CompletionSpec,
+            readline::{EditingMode, NullHistory, Readline},
+        },
         sh::ShellContext,
     },
 };
@@ -17,7 +20,14 @@ use clap::Parser;
 use futures::AsyncWriteExt;
 
 /// List of all internal shell commands.
-pub const COMMANDS: [&str; 7] = ["cd", "env", "export", "read", "exit", "exec", "source"];
+pub const COMMANDS: [&str; 9] = [
+    "cd", "complete", "env", "export", "read", "exit", "exec", "set", "source",
+];
+
+/// Whether a command is built into the shell, rather than being a program.
+pub fn is_builtin(command: &str) -> bool {
+    ["true", "false", "."].contains(&command) || COMMANDS.contains(&command)
+}
 
 /// Exit shell.
 pub async fn exit(
@@ -227,7 +237,7 @@ pub async fn read(
             &options.prompt.unwrap_or_default(),
             &mut stdin,
             &mut stdout,
-            |_, _| Ok(Vec::new()),
+            |_, _| async { Ok(Vec::new()) },
         )
         .await?;
     ctx.variables.insert(options.variable.clone(), line.clone());
@@ -237,3 +247,120 @@ pub async fn read(
 
     Ok(ExitCode::SUCCESS)
 }
+
+/// Specify how arguments to a command are completed.
+pub async fn complete(
+    ctx: &mut ShellContext,
+    process: &mut Process,
+    args: Vec<String>,
+) -> Result<ExitCode> {
+    /// Specify how arguments to a command are completed.
+    ///
+    /// Without -W or -F, print how the commands are completed.
+    #[derive(Parser)]
+    #[command(verbatim_doc_comment)]
+    struct Options {
+        /// Complete words from this whitespace-separated list.
+        #[arg(short = 'W', value_name = "WORDLIST")]
+        words: Option<String>,
+        /// Complete with the lines printed by this command, which is passed the name of the
+        /// command, the word being completed, and the word before it.
+        #[arg(short = 'F', value_name = "COMMAND")]
+        command: Option<String>,
+        /// Print completion specifications.
+        #[arg(short)]
+        print: bool,
+        /// Remove completion specifications.
+        #[arg(short, conflicts_with = "print")]
+        remove: bool,
+        /// The commands to complete.
+        names: Vec<String>,
+    }
+    let options = Options::try_parse_from(args.iter())?;
+
+    if options.remove {
+        if options.names.is_empty() {
+            ctx.completions.clear();
+        }
+        for name in options.names {
+            ctx.completions.remove(&name);
+        }
+    } else if options.print || (options.words.is_none() && options.command.is_none()) {
+        let mut names = options.names;
+        if names.is_empty() {
+            names = ctx.completions.keys().cloned().collect();
+            names.sort();
+        }
+        for name in names {
+            let Some(spec) = ctx.completions.get(&name) else {
+                bail!("{name}: no completion specification");
+            };
+            let mut line = String::from("complete");
+            if let Some(words) = &spec.words {
+                line.push_str(&format!(" -W '{words}'"));
+            }
+            if let Some(command) = &spec.command {
+                line.push_str(&format!(" -F {command}"));
+            }
+            process
+                .stdout
+                .write_all(format!("{line} {name}\n").as_bytes())
+                .await?;
+        }
+    } else {
+        if options.names.is_empty() {
+            bail!("No commands given");
+        }
+        let spec = CompletionSpec {
+            words: options.words,
+            command: options.command,
+        };
+        for name in options.names {
+            ctx.completions.insert(name, spec.clone());
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Set shell options.
+pub async fn set(
+    ctx: &mut ShellContext,
+    process: &mut Process,
+    args: Vec<String>,
+) -> Result<ExitCode> {
+    /// Set shell options.
+    #[derive(Parser)]
+    struct Options {
+        /// Turn on an option (emacs or vi), or print the options if none is given.
+        #[arg(short = 'o', num_args = 0..=1, value_parser = ["emacs", "vi"])]
+        option: Option<Option<String>>,
+    }
+    let options = Options::try_parse_from(args.iter())?;
+
+    match options.option {
+        Some(Some(option)) => {
+            ctx.editing_mode = if option == "vi" {
+                EditingMode::Vi
+            } else {
+                EditingMode::Emacs
+            };
+        }
+        Some(None) => {
+            for (name, mode) in [("emacs", EditingMode::Emacs), ("vi", EditingMode::Vi)] {
+                let state = if ctx.editing_mode == mode {
+                    "on"
+                } else {
+                    "off"
+                };
+                process
+                    .stdout
+                    .write_all(format!("{name:<16}{state}\n").as_bytes())
+                    .await?;
+            }
+        }
+        None => {}
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
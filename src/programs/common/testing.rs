@@ -0,0 +1,66 @@
+//! Running programs in tests, on a file system in memory.
+use crate::{process::Process, streams};
+use anyhow::Result;
+use futures::{channel::mpsc, try_join, AsyncReadExt};
+use std::io::{Read, Write};
+use vfs::{MemoryFS, VfsPath};
+
+/// A file system holding files with some contents.
+pub fn file_system(files: &[(&str, &str)]) -> VfsPath {
+    let cwd: VfsPath = MemoryFS::new().into();
+    for (file, contents) in files {
+        write!(cwd.join(file).unwrap().create_file().unwrap(), "{contents}").unwrap();
+    }
+    cwd
+}
+
+/// The contents of a file, if it's there.
+pub fn contents(cwd: &VfsPath, file: &str) -> Option<String> {
+    let path = cwd.join(file).unwrap();
+    if !path.exists().unwrap() {
+        return None;
+    }
+    let mut contents = String::new();
+    path.open_file()
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    Some(contents)
+}
+
+/// Run a program in `cwd` with `args`, typing `input` on the terminal before it starts. Returns
+/// what the program ended with, and everything it wrote to stdout and stderr.
+pub async fn run<T>(
+    cwd: &VfsPath,
+    args: &[&str],
+    input: &str,
+    program: impl AsyncFnOnce(&mut Process) -> Result<T>,
+) -> (Result<T>, String) {
+    let (mut stdin, mut keyboard, mut keyboard_backend) = streams::pipe();
+    let (mut screen, stdout, mut screen_backend) = streams::pipe();
+    let (signal_registrar, _) = mpsc::unbounded();
+    let mut process = Process {
+        terminal: stdin.clone(),
+        stdin: stdin.clone(),
+        stderr: stdout.clone(),
+        stdout,
+        signal_registrar,
+        cwd: cwd.clone(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        env: Default::default(),
+    };
+    let mut result = None;
+    let mut output = String::new();
+    try_join!(keyboard_backend.run(), screen_backend.run(), async {
+        keyboard.write_all(input.as_bytes())?;
+        keyboard.shutdown().await?;
+        result = Some(program(&mut process).await);
+        process.stdout.shutdown().await?;
+        screen.read_to_string(&mut output).await?;
+        screen.shutdown().await?;
+        stdin.shutdown().await?;
+        Ok(())
+    })
+    .unwrap();
+    (result.expect("The program ran"), output)
+}
@@ -0,0 +1,182 @@
+//! Moving through text a grapheme cluster at a time, which is what someone reading it would
+//! call a character, and how many columns of the terminal it takes up. Most characters take
+//! one column, while wide ones like emoji and CJK take two, and tabs take up to the next tab stop.
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
+use unicode_width::UnicodeWidthStr;
+
+/// Where the grapheme after the one at `index` starts, or the end of the text.
+pub fn next_grapheme(text: &str, index: usize) -> usize {
+    let mut cursor = GraphemeCursor::new(index, text.len(), true);
+    match cursor.next_boundary(text, 0) {
+        Ok(Some(next)) => next,
+        _ => text.len(),
+    }
+}
+
+/// Where the grapheme before `index` starts, or the start of the text.
+pub fn prev_grapheme(text: &str, index: usize) -> usize {
+    let mut cursor = GraphemeCursor::new(index, text.len(), true);
+    match cursor.prev_boundary(text, 0) {
+        Ok(Some(prev)) => prev,
+        _ => 0,
+    }
+}
+
+/// Where the grapheme that `index` is part of starts, for when an index might have been left in
+/// the middle of one.
+pub fn grapheme_start(text: &str, index: usize) -> usize {
+    if index >= text.len() {
+        return text.len();
+    }
+    let index = (0..=index)
+        .rev()
+        .find(|&index| text.is_char_boundary(index))
+        .unwrap_or(0);
+    let mut cursor = GraphemeCursor::new(index, text.len(), true);
+    match cursor.is_boundary(text, 0) {
+        Ok(false) => prev_grapheme(text, index),
+        _ => index,
+    }
+}
+
+/// How many columns a grapheme takes up. Anything shown at all takes at least one, so that the
+/// cursor can be put on it.
+pub fn grapheme_width(grapheme: &str) -> usize {
+    std::cmp::max(1, grapheme.width())
+}
+
+/// How many columns text takes up on the terminal.
+pub fn width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// How many columns apart tab stops are, unless an editor is told otherwise.
+pub const TAB_SIZE: usize = 8;
+
+/// How many columns a grapheme takes up when it's shown at `column`, which for a tab is up to
+/// the next tab stop.
+pub fn cell_width(grapheme: &str, column: usize, tabstop: usize) -> usize {
+    if grapheme == "\t" {
+        tabstop - column % tabstop
+    } else {
+        grapheme_width(grapheme)
+    }
+}
+
+/// The column a byte of a line is shown at, with tabs expanded.
+pub fn column(line: &str, index: usize, tabstop: usize) -> usize {
+    let mut column = 0;
+    for (start, grapheme) in line.grapheme_indices(true) {
+        if start >= index {
+            break;
+        }
+        column += cell_width(grapheme, column, tabstop);
+    }
+    column
+}
+
+/// The grapheme of a line shown at a column, or the end of the line if it's shorter.
+pub fn index_at(line: &str, wanted: usize, tabstop: usize) -> usize {
+    let mut column = 0;
+    for (start, grapheme) in line.grapheme_indices(true) {
+        column += cell_width(grapheme, column, tabstop);
+        if column > wanted {
+            return start;
+        }
+    }
+    line.len()
+}
+
+/// The part of a line that fits in `width` columns from column `start`, with tabs expanded and
+/// `<` and `>` at the edges if there's more of it to either side.
+pub fn visible(line: &str, start: usize, width: usize, tabstop: usize) -> String {
+    let mut shown = String::new();
+    let mut column = 0;
+    let mut more = false;
+    for grapheme in line.graphemes(true) {
+        let size = cell_width(grapheme, column, tabstop);
+        let end = column + size;
+        if end > start + width {
+            more = true;
+            break;
+        }
+        if column >= start {
+            if grapheme == "\t" {
+                shown.push_str(&" ".repeat(size));
+            } else {
+                shown.push_str(grapheme);
+            }
+        } else if end > start {
+            // Only part of a tab or wide character is shown.
+            shown.push_str(&" ".repeat(end - start));
+        }
+        column = end;
+    }
+    if more {
+        // Leave room for the marker saying there's more.
+        while self::width(&shown) > width.saturating_sub(1) {
+            shown.truncate(prev_grapheme(&shown, shown.len()));
+        }
+        shown.push_str(&" ".repeat(width - 1 - self::width(&shown)));
+        shown.push('>');
+    }
+    if start > 0 && !shown.is_empty() {
+        let first = next_grapheme(&shown, 0);
+        let padding = " ".repeat(self::width(&shown[..first]) - 1);
+        shown.replace_range(..first, &format!("<{padding}"));
+    }
+    shown
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn graphemes() {
+        // A cow, an e with a combining accent, and a family joined into one emoji.
+        let text = "a🐮e\u{301}👨\u{200d}👩\u{200d}👧b";
+        let mut boundaries = vec![0];
+        while *boundaries.last().unwrap() < text.len() {
+            boundaries.push(next_grapheme(text, *boundaries.last().unwrap()));
+        }
+        assert_eq!(boundaries, [0, 1, 5, 8, 26, 27]);
+        for pair in boundaries.windows(2) {
+            assert_eq!(prev_grapheme(text, pair[1]), pair[0]);
+        }
+        assert_eq!(next_grapheme(text, text.len()), text.len());
+        assert_eq!(prev_grapheme(text, 0), 0);
+
+        assert_eq!(grapheme_start(text, 3), 1);
+        assert_eq!(grapheme_start(text, 6), 5);
+        assert_eq!(grapheme_start(text, 8), 8);
+        assert_eq!(grapheme_start(text, 100), text.len());
+    }
+
+    #[test]
+    fn widths() {
+        assert_eq!(width("abc"), 3);
+        assert_eq!(width("é"), 1);
+        assert_eq!(width("e\u{301}"), 1);
+        assert_eq!(width("🐮"), 2);
+        assert_eq!(width("漢字"), 4);
+        assert_eq!(width("\u{301}"), 1);
+    }
+
+    #[test]
+    fn tabs() {
+        assert_eq!(cell_width("\t", 3, 8), 5);
+        assert_eq!(cell_width("\t", 4, 4), 4);
+        assert_eq!(column("a\tb", 2, 8), 8);
+        assert_eq!(column("a\tb", 2, 4), 4);
+        assert_eq!(column("🐮x", 4, 8), 2);
+        assert_eq!(index_at("a\tb", 3, 8), 1);
+        assert_eq!(index_at("a\tb", 8, 8), 2);
+        assert_eq!(index_at("a\tb", 20, 8), 3);
+        assert_eq!(visible("a\tb", 0, 20, 8), "a       b");
+        assert_eq!(visible("abcdefghij", 0, 5, 8), "abcd>");
+        assert_eq!(visible("abcdefghij", 4, 5, 8), "<fgh>");
+        assert_eq!(visible("abcdefghij", 8, 5, 8), "<j");
+        assert_eq!(visible("a漢字", 2, 5, 8), "<字");
+    }
+}
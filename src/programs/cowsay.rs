@@ -5,12 +5,12 @@ use futures::io::AsyncReadExt;
 use std::io::Write;
 
 const MAX_WIDTH: usize = 40;
-const COWS_DIR: &str = "/usr/share/cowsay/cows";
+pub(super) const COWS_DIR: &str = "/usr/share/cowsay/cows";
 const DEFAULT_COW: &str = "cow";
 
 /// Have a cow say things
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// The things to say.
     args: Vec<String>,
     /// List cow files.
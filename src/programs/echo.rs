@@ -6,7 +6,7 @@ use futures::io::AsyncWriteExt;
 
 /// Echo args to standard out.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// Do not append a newline.
     #[arg(short)]
     no_newline: bool,
@@ -56,8 +56,7 @@ pub async fn echo(process: &mut Process) -> Result<ExitCode> {
 fn unescape(escaped: &str) -> String {
     let mut escaped = escaped.chars();
     let mut unescaped = String::new();
-    loop {
-        let Some(c) = escaped.next() else { break };
+    while let Some(c) = escaped.next() {
         if c == '\\' {
             let c = match escaped.next().unwrap_or('\\') {
                 'e' => AsciiChar::ESC.as_char(),
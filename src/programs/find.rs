@@ -1,49 +1,518 @@
-use crate::process::{ExitCode, Process};
-use anyhow::{bail, Result};
+use crate::{
+    filesystem,
+    process::{ExitCode, Process},
+    programs::{self, common::glob},
+};
+use anyhow::{anyhow, bail, Result};
 use clap::Parser;
-use futures::AsyncWriteExt;
-use vfs::VfsPath;
+use std::{cmp::Ordering, io::Write};
+use vfs::{VfsFileType, VfsPath};
 
 /// Search for files/directories
+///
+/// Starting points are searched recursively, and each file is checked against an expression:
+///
+///   -name PATTERN, -iname PATTERN   The file's name matches a wildcard pattern
+///   -path PATTERN                   Its whole path matches
+///   -type f, -type d                It's a file or a directory
+///   -size [+-]N[ckMG]               It's N, more than N or less than N blocks in size
+///   -newer FILE                     It was modified more recently than FILE
+///   -maxdepth N, -mindepth N        Only check files at most or at least N levels down
+///   -print, -print0                 Print its path, after a newline or NUL character
+///   -exec COMMAND {} ;              Run a command on the file, true if it succeeds
+///   -exec COMMAND {} +              Run a command on all matching files at once
+///   -delete                         Delete it
+///
+/// Combine them with ( ), ! (not), -a (and, the default) and -o (or). Paths are printed if no
+/// other action is given.
 #[derive(Parser)]
-struct Options {
-    /// The directories to search.
-    directories: Vec<String>,
+#[command(verbatim_doc_comment)]
+pub(super) struct Options {
+    /// The directories to search, followed by the expression.
+    #[arg(allow_hyphen_values = true, trailing_var_arg = true)]
+    arguments: Vec<String>,
 }
 
-pub async fn find(process: &mut Process) -> Result<ExitCode> {
-    let mut options = Options::try_parse_from(process.args.iter())?;
+/// A test or action, or some of them combined.
+enum Expression {
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Name {
+        pattern: String,
+        ignore_case: bool,
+    },
+    Path(String),
+    Type(VfsFileType),
+    /// Compare the size of a file, in units of `unit` bytes rounded up, to `size`.
+    Size {
+        ordering: Ordering,
+        size: u64,
+        unit: u64,
+    },
+    /// Modified after this time.
+    Newer(i64),
+    Print {
+        terminator: char,
+    },
+    /// Run a command, either for each file or, with a batch, for all of them once the search is
+    /// done.
+    Exec {
+        command: Vec<String>,
+        batch: Option<usize>,
+    },
+    Delete,
+}
+
+/// Parses expressions from the arguments of `find`.
+struct ExpressionParser<'a> {
+    process: &'a Process,
+    arguments: &'a [String],
+    index: usize,
+    max_depth: Option<usize>,
+    min_depth: usize,
+    /// There's an action, so paths aren't printed by default.
+    has_action: bool,
+    /// How many `-exec ... +` batches there are.
+    batches: usize,
+}
+
+impl ExpressionParser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.arguments.get(self.index).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<&str> {
+        let argument = self.arguments.get(self.index)?;
+        self.index += 1;
+        Some(argument)
+    }
+
+    fn value(&mut self, test: &str) -> Result<String> {
+        self.next()
+            .map(String::from)
+            .ok_or_else(|| anyhow!("missing argument to `{test}'"))
+    }
 
-    if options.directories.is_empty() {
-        options.directories.push(String::from("."));
+    fn number(&mut self, test: &str) -> Result<usize> {
+        let value = self.value(test)?;
+        value
+            .parse()
+            .map_err(|_| anyhow!("invalid argument `{value}' to `{test}'"))
     }
 
-    let paths: Result<Vec<(VfsPath, String)>> = options
-        .directories
-        .into_iter()
-        .map(|mut path_expression| {
-            let dir = process.get_path(&path_expression)?;
-            if !path_expression.ends_with('/') {
-                path_expression.push('/');
+    /// `a -o b`
+    fn or(&mut self) -> Result<Option<Expression>> {
+        let Some(mut expression) = self.and()? else {
+            return Ok(None);
+        };
+        while matches!(self.peek(), Some("-o" | "-or")) {
+            self.next();
+            let Some(right) = self.and()? else {
+                bail!("expected an expression after -o");
+            };
+            expression = Expression::Or(Box::new(expression), Box::new(right));
+        }
+        Ok(Some(expression))
+    }
+
+    /// `a -a b`, or just `a b`
+    fn and(&mut self) -> Result<Option<Expression>> {
+        let Some(mut expression) = self.not()? else {
+            return Ok(None);
+        };
+        loop {
+            let explicit = matches!(self.peek(), Some("-a" | "-and"));
+            if explicit {
+                self.next();
             }
-            if !dir.exists()? {
-                bail!("No such file or directory: {}", dir.as_str());
+            let Some(right) = self.not()? else {
+                if explicit {
+                    bail!("expected an expression after -a");
+                }
+                return Ok(Some(expression));
+            };
+            expression = Expression::And(Box::new(expression), Box::new(right));
+        }
+    }
+
+    /// `! a`
+    fn not(&mut self) -> Result<Option<Expression>> {
+        self.options()?;
+        if matches!(self.peek(), Some("!" | "-not")) {
+            self.next();
+            let Some(expression) = self.not()? else {
+                bail!("expected an expression after !");
+            };
+            return Ok(Some(Expression::Not(Box::new(expression))));
+        }
+        let expression = self.primary()?;
+        self.options()?;
+        Ok(expression)
+    }
+
+    /// A test, action or parenthesized expression.
+    fn primary(&mut self) -> Result<Option<Expression>> {
+        let expression = match self.peek() {
+            None | Some(")" | "-o" | "-or" | "-a" | "-and") => return Ok(None),
+            Some("(") => {
+                self.next();
+                let expression = self.or()?;
+                if self.next() != Some(")") {
+                    bail!("expected a `)'");
+                }
+                let Some(expression) = expression else {
+                    bail!("empty parentheses are not allowed");
+                };
+                expression
+            }
+            Some(_) => {
+                let test = self.next().expect("Peeked already").to_string();
+                self.test(&test)?
+            }
+        };
+        Ok(Some(expression))
+    }
+
+    /// Take in options like `-maxdepth`, which aren't expressions, wherever they are.
+    fn options(&mut self) -> Result<()> {
+        while let Some(option @ ("-maxdepth" | "-mindepth")) = self.peek() {
+            let option = option.to_string();
+            self.next();
+            let depth = self.number(&option)?;
+            if option == "-maxdepth" {
+                self.max_depth = Some(depth);
+            } else {
+                self.min_depth = depth;
+            }
+        }
+        Ok(())
+    }
+
+    /// A test or action.
+    fn test(&mut self, test: &str) -> Result<Expression> {
+        let expression = match test {
+            "-name" | "-iname" => Expression::Name {
+                pattern: self.value(test)?,
+                ignore_case: test == "-iname",
+            },
+            "-path" => Expression::Path(self.value(test)?),
+            "-type" => match self.value(test)?.as_str() {
+                "f" => Expression::Type(VfsFileType::File),
+                "d" => Expression::Type(VfsFileType::Directory),
+                kind => bail!("unknown argument to -type: {kind}"),
+            },
+            "-size" => {
+                let value = self.value(test)?;
+                let (ordering, size) = if let Some(size) = value.strip_prefix('+') {
+                    (Ordering::Greater, size)
+                } else if let Some(size) = value.strip_prefix('-') {
+                    (Ordering::Less, size)
+                } else {
+                    (Ordering::Equal, value.as_str())
+                };
+                let (size, unit) = match size.char_indices().last() {
+                    Some((index, unit)) if unit.is_ascii_alphabetic() => (&size[..index], unit),
+                    _ => (size, 'b'),
+                };
+                let unit = match unit {
+                    'b' => 512,
+                    'c' => 1,
+                    'k' => 1024,
+                    'M' => 1024 * 1024,
+                    'G' => 1024 * 1024 * 1024,
+                    _ => bail!("invalid -size type `{unit}'"),
+                };
+                let Ok(size) = size.parse() else {
+                    bail!("invalid argument `{value}' to `-size'");
+                };
+                Expression::Size {
+                    ordering,
+                    size,
+                    unit,
+                }
             }
-            if !dir.is_dir()? {
-                bail!("Not a directory: {}", dir.as_str());
+            "-newer" => {
+                let file = self.value(test)?;
+                let path = self.process.get_path(&file)?;
+                if !path.exists()? {
+                    bail!("'{file}': No such file or directory");
+                }
+                Expression::Newer(filesystem::modified(&path))
+            }
+            "-print" | "-print0" => {
+                self.has_action = true;
+                Expression::Print {
+                    terminator: if test == "-print" { '\n' } else { '\0' },
+                }
+            }
+            "-exec" => {
+                self.has_action = true;
+                let mut command = Vec::new();
+                let batch = loop {
+                    match self.next() {
+                        None => bail!("missing argument to `-exec'"),
+                        Some(";") => break None,
+                        Some("+") if command.last().is_some_and(|arg| arg == "{}") => {
+                            self.batches += 1;
+                            break Some(self.batches - 1);
+                        }
+                        Some(argument) => command.push(argument.to_string()),
+                    }
+                };
+                if command.is_empty() {
+                    bail!("missing argument to `-exec'");
+                }
+                Expression::Exec { command, batch }
+            }
+            "-delete" => {
+                self.has_action = true;
+                Expression::Delete
+            }
+            _ => bail!("unknown predicate `{test}'"),
+        };
+        Ok(expression)
+    }
+}
+
+/// A file found by the search.
+struct Found {
+    path: VfsPath,
+    /// The path as it's printed, which starts with the starting point as it was given.
+    display: String,
+    depth: usize,
+}
+
+/// Searches for files and runs the expression on them.
+struct Finder<'a> {
+    process: &'a mut Process,
+    /// The files found by each `-exec ... +`, to run the command on at the end.
+    batches: Vec<Vec<String>>,
+    code: ExitCode,
+}
+
+impl Finder<'_> {
+    /// Run a command, returning whether it succeeded.
+    async fn exec(&mut self, command: &[String]) -> Result<bool> {
+        let mut process = self.process.clone();
+        process.args = command.to_vec();
+        // Boxed, because the command could be find again.
+        match Box::pin(programs::exec_program(&mut process, &command[0])).await? {
+            Some(code) => Ok(code.is_success()),
+            None => bail!("{}: No such file or directory", command[0]),
+        }
+    }
+
+    async fn evaluate(&mut self, expression: &Expression, found: &Found) -> Result<bool> {
+        Ok(match expression {
+            Expression::Not(expression) => !Box::pin(self.evaluate(expression, found)).await?,
+            Expression::And(left, right) => {
+                Box::pin(self.evaluate(left, found)).await?
+                    && Box::pin(self.evaluate(right, found)).await?
+            }
+            Expression::Or(left, right) => {
+                Box::pin(self.evaluate(left, found)).await?
+                    || Box::pin(self.evaluate(right, found)).await?
+            }
+            Expression::Name {
+                pattern,
+                ignore_case,
+            } => {
+                let name = found.display.trim_end_matches('/');
+                let name = name.rsplit('/').next().unwrap_or(name);
+                glob::matches(pattern, name, *ignore_case)
+            }
+            Expression::Path(pattern) => glob::matches(pattern, &found.display, false),
+            Expression::Type(file_type) => found.path.metadata()?.file_type == *file_type,
+            Expression::Size {
+                ordering,
+                size,
+                unit,
+            } => found.path.metadata()?.len.div_ceil(*unit).cmp(size) == *ordering,
+            Expression::Newer(time) => filesystem::modified(&found.path) > *time,
+            Expression::Print { terminator } => {
+                write!(self.process.stdout, "{}{terminator}", found.display)?;
+                true
+            }
+            Expression::Exec {
+                command,
+                batch: None,
+            } => {
+                let command: Vec<String> = command
+                    .iter()
+                    .map(|argument| argument.replace("{}", &found.display))
+                    .collect();
+                self.exec(&command).await?
+            }
+            Expression::Exec {
+                batch: Some(batch), ..
+            } => {
+                self.batches[*batch].push(found.display.clone());
+                true
+            }
+            Expression::Delete => {
+                let result = if found.path.is_dir()? {
+                    found.path.remove_dir()
+                } else {
+                    found.path.remove_file()
+                };
+                if let Err(err) = result {
+                    writeln!(
+                        self.process.stderr,
+                        "find: cannot delete '{}': {err}",
+                        found.display
+                    )?;
+                    self.code = ExitCode::FAILURE;
+                }
+                true
             }
-            Ok((dir, path_expression))
         })
-        .collect();
+    }
+
+    /// Run each `-exec ... +` on the files it found.
+    async fn run_batches(&mut self, expression: &Expression) -> Result<()> {
+        match expression {
+            Expression::Not(expression) => Box::pin(self.run_batches(expression)).await,
+            Expression::And(left, right) | Expression::Or(left, right) => {
+                Box::pin(self.run_batches(left)).await?;
+                Box::pin(self.run_batches(right)).await
+            }
+            Expression::Exec {
+                command,
+                batch: Some(batch),
+            } => {
+                let files = std::mem::take(&mut self.batches[*batch]);
+                if !files.is_empty() {
+                    let mut command = command.clone();
+                    command.pop();
+                    command.extend(files);
+                    if !self.exec(&command).await? {
+                        self.code = ExitCode::FAILURE;
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
 
-    for (path, expressed_as) in paths? {
-        for entity in path.walk_dir()? {
-            let dis = entity?.as_str().to_string();
-            let path_str = format!("{}/", path.as_str());
-            let mut dis = dis.replacen(path_str.as_str(), expressed_as.as_str(), 1);
-            dis.push('\n');
-            process.stdout.write_all(dis.as_bytes()).await?;
+/// Collect a starting point and everything under it, up to `max_depth` levels down. Directories
+/// come before what's in them, unless `contents_first` is set.
+fn walk(
+    path: VfsPath,
+    display: String,
+    depth: usize,
+    max_depth: usize,
+    contents_first: bool,
+    found: &mut Vec<Found>,
+) -> Result<()> {
+    let is_dir = path.is_dir()?;
+    let this = Found {
+        path: path.clone(),
+        display: display.clone(),
+        depth,
+    };
+    if !contents_first {
+        found.push(this);
+    }
+    if is_dir && depth < max_depth {
+        let mut children: Vec<VfsPath> = path.read_dir()?.collect();
+        children.sort_by_key(VfsPath::filename);
+        for child in children {
+            let display = if display.ends_with('/') {
+                format!("{display}{}", child.filename())
+            } else {
+                format!("{display}/{}", child.filename())
+            };
+            walk(child, display, depth + 1, max_depth, contents_first, found)?;
+        }
+    }
+    if contents_first {
+        found.push(Found {
+            path,
+            display,
+            depth,
+        });
+    }
+    Ok(())
+}
+
+pub async fn find(process: &mut Process) -> Result<ExitCode> {
+    let options = Options::try_parse_from(process.args.iter())?;
+
+    let start = options
+        .arguments
+        .iter()
+        .position(|argument| argument.starts_with('-') || argument == "(" || argument == "!")
+        .unwrap_or(options.arguments.len());
+    let (mut starting_points, arguments) = options.arguments.split_at(start);
+    let default = [String::from(".")];
+    if starting_points.is_empty() {
+        starting_points = &default;
+    }
+
+    let mut parser = ExpressionParser {
+        process,
+        arguments,
+        index: 0,
+        max_depth: None,
+        min_depth: 0,
+        has_action: false,
+        batches: 0,
+    };
+    let expression = parser.or()?;
+    if let Some(argument) = parser.peek() {
+        bail!("unexpected `{argument}'");
+    }
+    let print = Expression::Print { terminator: '\n' };
+    let expression = match expression {
+        None => print,
+        Some(expression) if !parser.has_action => {
+            Expression::And(Box::new(expression), Box::new(print))
+        }
+        Some(expression) => expression,
+    };
+    let ExpressionParser {
+        max_depth,
+        min_depth,
+        batches,
+        ..
+    } = parser;
+    // Deleting a directory needs what's in it to be deleted first.
+    let contents_first = arguments.iter().any(|argument| argument == "-delete");
+
+    let mut finder = Finder {
+        process,
+        batches: vec![Vec::new(); batches],
+        code: ExitCode::SUCCESS,
+    };
+    for starting_point in starting_points {
+        let path = finder.process.get_path(starting_point)?;
+        if !path.exists()? {
+            writeln!(
+                finder.process.stderr,
+                "find: '{starting_point}': No such file or directory"
+            )?;
+            finder.code = ExitCode::FAILURE;
+            continue;
+        }
+        let mut found = Vec::new();
+        walk(
+            path,
+            starting_point.clone(),
+            0,
+            max_depth.unwrap_or(usize::MAX),
+            contents_first,
+            &mut found,
+        )?;
+        for found in found {
+            // Commands run on earlier files may have removed this one.
+            if found.depth >= min_depth && found.path.exists()? {
+                finder.evaluate(&expression, &found).await?;
+            }
         }
     }
-    Ok(ExitCode::SUCCESS)
+    finder.run_batches(&expression).await?;
+    Ok(finder.code)
 }
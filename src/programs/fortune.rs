@@ -10,7 +10,7 @@ const REPEAT_FILE: &str = "/run/fortunes.history";
 
 /// Generate a fortune, quote, or wise adage.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// Only show short fortunes.
     #[arg(short)]
     short: bool,
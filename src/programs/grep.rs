@@ -1,75 +1,469 @@
 use crate::{
     process::{ExitCode, Process},
-    streams::{file_redirect_in, InputStream, OutputStream},
+    programs::common::{
+        basic_regex,
+        color_picker::{Color, ColorPicker, When},
+    },
+    streams::{InputStream, OutputStream},
 };
-use anyhow::Result;
-use clap::Parser;
-use futures::try_join;
-use regex::Regex;
-use std::io::Write;
+use anyhow::{bail, Result};
+use clap::{ArgAction, Parser};
+use regex::{Match, Regex, RegexBuilder};
+use std::{collections::VecDeque, io::Write};
+use vfs::VfsPath;
+
+/// What standard input is called when filenames are shown.
+const STDIN_NAME: &str = "(standard input)";
 
 /// Filter files by regex.
+///
+/// Patterns are basic regular expressions, where \( \), \{ \}, \| \+ and \? are special, unless
+/// -E or -F is given. Exits with 0 if a line is selected, 1 if none are, and 2 if there's an
+/// error.
 #[derive(Parser)]
-struct Options {
-    /// The regex pattern to use.
-    pattern: String,
-    /// The files to filter.
-    files: Vec<String>,
+#[command(disable_help_flag = true)]
+pub(super) struct Options {
+    /// Use the pattern, which can be given more than once.
+    #[arg(short = 'e', long = "regexp", value_name = "PATTERNS")]
+    patterns: Vec<String>,
+    /// Patterns are extended regular expressions, where ( ), { }, |, + and ? are special.
+    #[arg(short = 'E', long)]
+    extended_regexp: bool,
+    /// Patterns are fixed strings, rather than regular expressions.
+    #[arg(short = 'F', long)]
+    fixed_strings: bool,
+    /// Ignore case.
+    #[arg(short, long)]
+    ignore_case: bool,
     /// Select non-matching lines.
     #[arg(short = 'v', long)]
     invert_match: bool,
-    /// Ignore case.
+    /// Only match whole words.
+    #[arg(short = 'w', long)]
+    word_regexp: bool,
+    /// Only match whole lines.
+    #[arg(short = 'x', long)]
+    line_regexp: bool,
+    /// Print how many lines are selected in each file, rather than the lines.
     #[arg(short, long)]
-    ignore_case: bool,
+    count: bool,
+    /// Print the names of files with selected lines.
+    #[arg(short = 'l', long)]
+    files_with_matches: bool,
+    /// Print the names of files without selected lines.
+    #[arg(short = 'L', long)]
+    files_without_match: bool,
+    /// Print only the parts of lines that match, each on a line of its own.
+    #[arg(short = 'o', long)]
+    only_matching: bool,
+    /// Print nothing, and exit as soon as a line is selected.
+    #[arg(short, long)]
+    quiet: bool,
+    /// Don't complain about files that don't exist.
+    #[arg(short = 's', long)]
+    no_messages: bool,
+    /// Print line numbers.
+    #[arg(short = 'n', long)]
+    line_number: bool,
+    /// Print the name of the file before each line. This is the default with several files.
+    #[arg(short = 'H', long)]
+    with_filename: bool,
+    /// Don't print the names of files.
+    #[arg(short = 'h', long)]
+    no_filename: bool,
+    /// Search directories and everything in them.
+    #[arg(short, long)]
+    recursive: bool,
+    /// Print this many lines after selected lines.
+    #[arg(short = 'A', long, value_name = "NUM")]
+    after_context: Option<usize>,
+    /// Print this many lines before selected lines.
+    #[arg(short = 'B', long, value_name = "NUM")]
+    before_context: Option<usize>,
+    /// Print this many lines around selected lines.
+    #[arg(short = 'C', long, value_name = "NUM")]
+    context: Option<usize>,
+    /// Highlight matches: always, auto (when writing to a terminal) or never.
+    #[arg(
+        long,
+        value_name = "WHEN",
+        value_enum,
+        default_value = "auto",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always"
+    )]
+    color: When,
+    /// Print help.
+    #[arg(long, action = ArgAction::Help)]
+    help: Option<bool>,
+    /// The pattern, unless -e is given, and the files to filter. Standard input is filtered if
+    /// there aren't any.
+    args: Vec<String>,
+}
+
+/// Where lines are read from.
+enum Input<'a> {
+    Stream(&'a mut InputStream),
+    Text(std::str::Lines<'a>),
+}
+
+impl Input<'_> {
+    async fn next_line(&mut self) -> Option<String> {
+        match self {
+            Self::Stream(stream) => stream.get_line().await.ok(),
+            Self::Text(lines) => lines.next().map(String::from),
+        }
+    }
+}
+
+/// Searches files for lines, as the options say to.
+struct Grep {
+    options: Options,
+    regex: Regex,
+    stdout: OutputStream,
+    colors: bool,
+    with_filename: bool,
+    /// Lines to print before and after selected lines.
+    before: usize,
+    after: usize,
+    /// Lines have been printed, so the next lines not next to them are set apart by `--`.
+    printed: bool,
 }
 
-async fn grep_inner(
-    stream: &mut InputStream,
-    out: &mut OutputStream,
-    pattern: &Regex,
-    invert: bool,
-) -> Result<()> {
-    while let Ok(line) = stream.get_line().await {
-        if pattern.is_match(&line) != invert {
-            out.write_all(line.as_bytes())?;
-            out.write_all(b"\n")?;
+impl Grep {
+    fn write(&mut self, text: &str, color: Option<Color>) -> Result<()> {
+        let mut picker = ColorPicker::new(self.colors && color.is_some());
+        if let Some(color) = color {
+            picker.set_color(color);
+        }
+        picker.write(&mut self.stdout, text)
+    }
+
+    /// Write the filename and line number, if they're shown, before a line. `separator` is `:`
+    /// for selected lines and `-` for context.
+    fn write_prefix(&mut self, name: &str, number: usize, separator: char) -> Result<()> {
+        let separator = separator.to_string();
+        if self.with_filename {
+            self.write(name, Some(Color::Magenta))?;
+            self.write(&separator, Some(Color::Cyan))?;
+        }
+        if self.options.line_number {
+            self.write(&number.to_string(), Some(Color::Green))?;
+            self.write(&separator, Some(Color::Cyan))?;
+        }
+        Ok(())
+    }
+
+    fn write_context(&mut self, name: &str, number: usize, line: &str) -> Result<()> {
+        self.write_prefix(name, number, '-')?;
+        writeln!(self.stdout, "{line}")?;
+        Ok(())
+    }
+
+    fn write_selected(&mut self, name: &str, number: usize, line: &str) -> Result<()> {
+        let regex = self.regex.clone();
+        let matches = matches(&regex, line, self.options.word_regexp);
+        let matches = matches.filter(|found| !found.is_empty());
+        if self.options.only_matching {
+            if !self.options.invert_match {
+                for found in matches {
+                    self.write_prefix(name, number, ':')?;
+                    self.write(found.as_str(), Some(Color::Red))?;
+                    writeln!(self.stdout)?;
+                }
+            }
+            return Ok(());
+        }
+
+        self.write_prefix(name, number, ':')?;
+        let mut end = 0;
+        if !self.options.invert_match {
+            for found in matches {
+                self.write(&line[end..found.start()], None)?;
+                self.write(found.as_str(), Some(Color::Red))?;
+                end = found.end();
+            }
+        }
+        writeln!(self.stdout, "{}", &line[end..])?;
+        Ok(())
+    }
+
+    /// Search lines, printing what the options say to. Returns whether any were selected.
+    async fn search(&mut self, name: &str, mut input: Input<'_>) -> Result<bool> {
+        let print_lines = !(self.options.count
+            || self.options.files_with_matches
+            || self.options.files_without_match
+            || self.options.quiet);
+        let mut count = 0;
+        let mut number = 0;
+        // Lines that might be printed before the next selected line.
+        let mut before: VecDeque<(usize, String)> = VecDeque::new();
+        let mut after = 0;
+        let mut last_printed = None;
+        while let Some(line) = input.next_line().await {
+            number += 1;
+            let matched = matches(&self.regex, &line, self.options.word_regexp)
+                .next()
+                .is_some();
+            let selected = matched != self.options.invert_match;
+            if !selected {
+                if print_lines && after > 0 {
+                    self.write_context(name, number, &line)?;
+                    after -= 1;
+                    last_printed = Some(number);
+                } else if print_lines && self.before > 0 {
+                    before.push_back((number, line));
+                    if before.len() > self.before {
+                        before.pop_front();
+                    }
+                }
+                continue;
+            }
+
+            count += 1;
+            if self.options.quiet
+                || self.options.files_with_matches
+                || self.options.files_without_match
+            {
+                break;
+            }
+            if !print_lines {
+                continue;
+            }
+            let first = before.front().map_or(number, |(first, _)| *first);
+            let adjacent = last_printed.is_some_and(|last| last + 1 >= first);
+            if self.printed && !adjacent && (self.before > 0 || self.after > 0) {
+                self.write("--", Some(Color::Cyan))?;
+                writeln!(self.stdout)?;
+            }
+            for (number, line) in std::mem::take(&mut before) {
+                self.write_context(name, number, &line)?;
+            }
+            self.write_selected(name, number, &line)?;
+            self.printed = true;
+            last_printed = Some(number);
+            after = self.after;
+        }
+
+        if self.options.count {
+            if self.with_filename {
+                self.write(name, Some(Color::Magenta))?;
+                self.write(":", Some(Color::Cyan))?;
+            }
+            writeln!(self.stdout, "{count}")?;
+        } else if self.options.files_with_matches && count > 0
+            || self.options.files_without_match && count == 0
+        {
+            self.write(name, Some(Color::Magenta))?;
+            writeln!(self.stdout)?;
         }
+        Ok(count > 0)
     }
+}
+
+/// Matches of a regex in a line. With `words`, only those that don't have word characters
+/// either side of them count.
+fn matches<'a>(regex: &'a Regex, line: &'a str, words: bool) -> impl Iterator<Item = Match<'a>> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    regex.find_iter(line).filter(move |found| {
+        !words
+            || !(line[..found.start()].chars().next_back().is_some_and(is_word)
+                || line[found.end()..].chars().next().is_some_and(is_word))
+    })
+}
 
+/// The files in a directory and everything in it, by the names they're shown with, which are
+/// their paths from the directory after `prefix`.
+fn walk(directory: &VfsPath, prefix: &str, files: &mut Vec<String>) -> Result<()> {
+    let mut children: Vec<_> = directory.read_dir()?.collect();
+    children.sort_by_key(|child| child.filename());
+    for child in children {
+        let name = format!("{prefix}{}", child.filename());
+        if child.is_dir()? {
+            walk(&child, &format!("{name}/"), files)?;
+        } else {
+            files.push(name);
+        }
+    }
     Ok(())
 }
 
 pub async fn grep(process: &mut Process) -> Result<ExitCode> {
-    let options = Options::try_parse_from(process.args.iter())?;
-    let pattern = if options.ignore_case {
-        format!("(?i){}", options.pattern)
-    } else {
-        options.pattern
+    let options = match Options::try_parse_from(process.args.iter()) {
+        Ok(options) => options,
+        // Help isn't an error.
+        Err(err) if !err.use_stderr() => return Err(err.into()),
+        Err(err) => {
+            write!(process.stderr, "grep: {err}")?;
+            return Ok(ExitCode::from(2));
+        }
     };
-    let pattern = Regex::new(&pattern)?;
-
-    if options.files.is_empty() {
-        let mut stdin = process.stdin.clone();
-        grep_inner(
-            &mut stdin,
-            &mut process.stdout,
-            &pattern,
-            options.invert_match,
-        )
-        .await?;
+    match grep_inner(process, options).await {
+        Ok(code) => Ok(code),
+        Err(err) => {
+            writeln!(process.stderr, "grep: {err}")?;
+            Ok(ExitCode::from(2))
+        }
+    }
+}
+
+async fn grep_inner(process: &mut Process, mut options: Options) -> Result<ExitCode> {
+    let mut files = std::mem::take(&mut options.args);
+    let patterns = if options.patterns.is_empty() {
+        if files.is_empty() {
+            bail!("no pattern given");
+        }
+        vec![files.remove(0)]
     } else {
-        for file in options.files {
-            let fp = process.get_path(file)?.open_file()?;
-            let (mut fp, mut backend) = file_redirect_in(Box::new(fp));
-            try_join! {
-                async {
-                    grep_inner(&mut fp, &mut process.stdout, &pattern, options.invert_match).await?;
-                    fp.shutdown().await?;
-                    Ok(())
-                },
-                backend.run()
-            }?;
+        std::mem::take(&mut options.patterns)
+    };
+    let patterns: Vec<String> = patterns
+        .iter()
+        .flat_map(|pattern| pattern.split('\n'))
+        .map(|pattern| {
+            let translated = if options.fixed_strings {
+                regex::escape(pattern)
+            } else if options.extended_regexp {
+                pattern.into()
+            } else {
+                basic_regex::to_extended(pattern)
+            };
+            // Check each pattern on its own, so errors are about the pattern as it was typed.
+            if let Err(err) = Regex::new(&translated) {
+                let err = err.to_string();
+                let reason = err.lines().last().unwrap_or_default();
+                bail!("{pattern}: {}", reason.trim_start_matches("error: "));
+            }
+            Ok(format!("(?:{translated})"))
+        })
+        .collect::<Result<_>>()?;
+    let mut pattern = patterns.join("|");
+    if options.line_regexp {
+        pattern = format!("^(?:{pattern})$");
+    }
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(options.ignore_case)
+        .build()?;
+
+    let recursive = options.recursive;
+    // Without any files, -r searches the working directory, naming files from there.
+    let implicit = recursive && files.is_empty();
+    if files.is_empty() {
+        files.push(if recursive { ".".into() } else { "-".into() });
+    }
+    let with_filename =
+        !options.no_filename && (options.with_filename || files.len() > 1 || recursive);
+    let mut grep = Grep {
+        regex,
+        stdout: process.stdout.clone(),
+        colors: options.color.colors(process.stdout.to_terminal().await?),
+        with_filename,
+        before: options.before_context.or(options.context).unwrap_or(0),
+        after: options.after_context.or(options.context).unwrap_or(0),
+        printed: false,
+        options,
+    };
+
+    let mut matched = false;
+    let mut error = false;
+    for file in files {
+        if file == "-" {
+            let mut stdin = process.stdin.clone();
+            matched |= grep.search(STDIN_NAME, Input::Stream(&mut stdin)).await?;
+        } else {
+            let path = process.get_path(&file)?;
+            let names = if !path.exists()? {
+                if !grep.options.no_messages {
+                    writeln!(process.stderr, "grep: {file}: No such file or directory")?;
+                }
+                error = true;
+                continue;
+            } else if !path.is_dir()? {
+                vec![file]
+            } else if recursive {
+                let prefix = match implicit {
+                    true => String::new(),
+                    false => format!("{}/", file.trim_end_matches('/')),
+                };
+                let mut names = Vec::new();
+                walk(&path, &prefix, &mut names)?;
+                names
+            } else {
+                writeln!(process.stderr, "grep: {file}: Is a directory")?;
+                error = true;
+                continue;
+            };
+            for name in names {
+                let mut contents = String::new();
+                process
+                    .get_path(&name)?
+                    .open_file()?
+                    .read_to_string(&mut contents)?;
+                matched |= grep.search(&name, Input::Text(contents.lines())).await?;
+                if matched && grep.options.quiet {
+                    break;
+                }
+            }
+        }
+        if matched && grep.options.quiet {
+            break;
         }
     }
-    Ok(ExitCode::SUCCESS)
+
+    Ok(if matched && (grep.options.quiet || !error) {
+        ExitCode::SUCCESS
+    } else if error {
+        ExitCode::from(2)
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::programs::common::testing::{self, file_system};
+
+    /// Run grep on a file system holding a few files, reading `input`. Returns its exit code,
+    /// and what it wrote.
+    async fn run(args: &[&str], input: &str) -> (u8, String) {
+        let cwd = file_system(&[("words", "hello\n")]);
+        cwd.join("dir/sub").unwrap().create_dir_all().unwrap();
+        write!(cwd.join("dir/a").unwrap().create_file().unwrap(), "hello").unwrap();
+        write!(cwd.join("dir/sub/b").unwrap().create_file().unwrap(), "hello").unwrap();
+        let (code, output) = testing::run(&cwd, args, input, grep).await;
+        (code.unwrap().into(), output)
+    }
+
+    #[futures_test::test]
+    async fn exit_codes() {
+        assert_eq!(run(&["grep", "hello", "words"], "").await.0, 0);
+        assert_eq!(run(&["grep", "goodbye", "words"], "").await.0, 1);
+        assert_eq!(run(&["grep", "hello", "missing"], "").await.0, 2);
+        assert_eq!(run(&["grep", "--bogus", "hello", "words"], "").await.0, 2);
+        assert_eq!(run(&["grep", "-e"], "").await.0, 2);
+        assert_eq!(
+            run(&["grep", "-E", "(", "words"], "").await,
+            (2, "grep: (: unclosed group\n".into())
+        );
+    }
+
+    #[futures_test::test]
+    async fn words() {
+        let input = "a @x b\nb@x\n@xy\n";
+        assert_eq!(run(&["grep", "-w", "@x"], input).await.1, "a @x b\n");
+        assert_eq!(run(&["grep", "-wo", "b"], input).await.1, "b\nb\n");
+        assert_eq!(run(&["grep", "-w", "x"], input).await.1, "a @x b\nb@x\n");
+    }
+
+    #[futures_test::test]
+    async fn recursion() {
+        let (_, output) = run(&["grep", "-r", "hello", "."], "").await;
+        assert_eq!(output, "./dir/a:hello\n./dir/sub/b:hello\n./words:hello\n");
+        let (_, output) = run(&["grep", "-r", "hello", "dir/"], "").await;
+        assert_eq!(output, "dir/a:hello\ndir/sub/b:hello\n");
+        let (_, output) = run(&["grep", "-rl", "hello"], "").await;
+        assert_eq!(output, "dir/a\ndir/sub/b\nwords\n");
+    }
 }
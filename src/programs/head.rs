@@ -9,7 +9,7 @@ use std::io::Write;
 
 /// Show first n lines.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// How many lines to show.
     #[arg(short, default_value = "10")]
     n: usize,
@@ -0,0 +1,602 @@
+use crate::{
+    process::{ExitCode, Process},
+    programs::common::{
+        color_picker::{Color, ColorPicker},
+        keys::Key,
+        unicode::{grapheme_width, prev_grapheme},
+    },
+    streams::{InputMode, InputStream, OutputStream},
+    utils, AnsiCode, ControlChar,
+};
+use anyhow::Result;
+use ascii::AsciiChar;
+use clap::Parser;
+use futures::AsyncReadExt;
+use regex::Regex;
+use std::{io::Write, ops::Range};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// How many columns a tab takes up.
+const TAB_SIZE: usize = 8;
+
+/// How many columns line numbers take up, with the space after them.
+const NUMBER_WIDTH: usize = 8;
+
+/// Show files, or what's piped in, a screenful at a time.
+///
+/// j, k        Scroll down or up a line
+/// space, b    Scroll down or up a screenful
+/// d, u        Scroll down or up half a screenful
+/// g, G        Go to the first or last line
+/// /pattern    Search forward for a regular expression
+/// ?pattern    Search backward
+/// n, N        Go to the next or previous match
+/// :n, :p      Show the next or previous file
+/// q           Quit
+#[derive(Parser)]
+#[command(verbatim_doc_comment)]
+pub(super) struct Options {
+    /// Number each line.
+    #[arg(short = 'N', long)]
+    line_numbers: bool,
+    /// Show colors, rather than the escape codes for them.
+    #[arg(short = 'R', long)]
+    raw_control_chars: bool,
+    /// The files to show. Standard input is shown if there aren't any.
+    files: Vec<String>,
+}
+
+/// A piece of a line as it's shown.
+#[derive(PartialEq, Eq, Debug)]
+struct Piece {
+    text: String,
+    width: usize,
+    /// Shown in reverse video, for search matches and control characters.
+    reverse: bool,
+}
+
+/// Match a color escape code at the start of some text.
+fn color_code() -> Regex {
+    Regex::new("^\x1b\\[[0-9;]*m").expect("Valid regex")
+}
+
+/// Split a line into the pieces it's shown as: characters, tabs as spaces, control characters as
+/// `^X`, and with `color`, color escape codes passed through as they are.
+fn pieces(line: &str, color: Option<&Regex>, matches: &[Range<usize>]) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut column = 0;
+    let mut index = 0;
+    for (start, grapheme) in line.grapheme_indices(true) {
+        if start < index {
+            // Part of an escape code already passed through.
+            continue;
+        }
+        let matched = matches.iter().any(|range| range.contains(&start));
+        let code = color
+            .filter(|_| grapheme == "\x1b")
+            .and_then(|color| color.find(&line[start..]));
+        let piece = if let Some(code) = code {
+            index = start + code.end();
+            Piece {
+                text: code.as_str().into(),
+                width: 0,
+                reverse: false,
+            }
+        } else if grapheme == "\t" {
+            let width = TAB_SIZE - column % TAB_SIZE;
+            Piece {
+                text: " ".repeat(width),
+                width,
+                reverse: matched,
+            }
+        } else if let Some(c) = grapheme.chars().next().filter(|c| c.is_control()) {
+            let c = char::from_u32((c as u32 + 0x40) % 0x80).unwrap_or('?');
+            Piece {
+                text: format!("^{c}"),
+                width: 2,
+                reverse: true,
+            }
+        } else {
+            Piece {
+                text: grapheme.into(),
+                width: grapheme_width(grapheme),
+                reverse: matched,
+            }
+        };
+        column += piece.width;
+        pieces.push(piece);
+    }
+    pieces
+}
+
+/// The rows of the screen a line takes up, folding it where it's wider than `width`.
+fn fold(pieces: Vec<Piece>, width: usize) -> Vec<Vec<Piece>> {
+    let mut rows = vec![Vec::new()];
+    let mut column = 0;
+    for piece in pieces {
+        if column + piece.width > width && column > 0 {
+            rows.push(Vec::new());
+            column = 0;
+        }
+        column += piece.width;
+        rows.last_mut().expect("There's always a row").push(piece);
+    }
+    rows
+}
+
+/// Write a row of pieces.
+fn write_row(stdout: &mut impl Write, row: &[Piece]) -> Result<()> {
+    let mut picker = ColorPicker::new(true);
+    picker.set_color(Color::Reverse);
+    let mut reversed = String::new();
+    for piece in row {
+        if piece.reverse {
+            reversed.push_str(&piece.text);
+            continue;
+        }
+        if !reversed.is_empty() {
+            picker.write(stdout, &reversed)?;
+            reversed.clear();
+        }
+        stdout.write_all(piece.text.as_bytes())?;
+    }
+    // This always ends with a reset, which also stops any colors passed through.
+    picker.write(stdout, &reversed)?;
+    Ok(())
+}
+
+/// A file being shown.
+struct Document {
+    name: Option<String>,
+    lines: Vec<String>,
+}
+
+impl Document {
+    fn new(name: Option<String>, contents: &str) -> Self {
+        let contents = contents.strip_suffix('\n').unwrap_or(contents);
+        Self {
+            name,
+            lines: contents.split('\n').map(String::from).collect(),
+        }
+    }
+}
+
+/// Where a line is shown: which line, and which of the rows it's folded into.
+type Row = (usize, usize);
+
+struct Less {
+    terminal: InputStream,
+    stdout: OutputStream,
+    options: Options,
+    documents: Vec<Document>,
+    /// The document being shown.
+    document: usize,
+    /// The row at the top of the screen.
+    top: Row,
+    /// How many rows of text fit above the prompt.
+    height: usize,
+    width: usize,
+    /// Color escape codes, with -R.
+    color: Option<Regex>,
+    search: Option<Regex>,
+    backward: bool,
+    /// The line of the last match, and the top row the screen was left at to show it. Matches
+    /// near the end can't be scrolled to the top, so searching again carries on from the match
+    /// while the screen hasn't moved.
+    found: Option<(usize, Row)>,
+    /// A message to show in the prompt instead of the usual one.
+    status: Option<String>,
+}
+
+impl Less {
+    fn lines(&self) -> &[String] {
+        &self.documents[self.document].lines
+    }
+
+    /// How many columns the text of a line gets, after its number.
+    fn text_width(&self) -> usize {
+        if self.options.line_numbers {
+            std::cmp::max(1, self.width.saturating_sub(NUMBER_WIDTH))
+        } else {
+            self.width
+        }
+    }
+
+    /// The rows a line is shown as.
+    fn rows(&self, line: usize) -> Vec<Vec<Piece>> {
+        let text = &self.lines()[line];
+        let matches: Vec<_> = match &self.search {
+            Some(regex) => regex.find_iter(text).map(|found| found.range()).collect(),
+            None => Vec::new(),
+        };
+        let pieces = pieces(text, self.color.as_ref(), &matches);
+        fold(pieces, self.text_width())
+    }
+
+    fn row_count(&self, line: usize) -> usize {
+        self.rows(line).len()
+    }
+
+    /// The row after another one, if there is one.
+    fn next_row(&self, (line, row): Row) -> Option<Row> {
+        if row + 1 < self.row_count(line) {
+            Some((line, row + 1))
+        } else if line + 1 < self.lines().len() {
+            Some((line + 1, 0))
+        } else {
+            None
+        }
+    }
+
+    /// The row before another one, if there is one.
+    fn prev_row(&self, (line, row): Row) -> Option<Row> {
+        if row > 0 {
+            Some((line, row - 1))
+        } else if line > 0 {
+            Some((line - 1, self.row_count(line - 1) - 1))
+        } else {
+            None
+        }
+    }
+
+    /// The top row that shows the end of the document at the bottom of the screen.
+    fn last_top(&self) -> Row {
+        let last = self.lines().len() - 1;
+        let mut top = (last, self.row_count(last) - 1);
+        for _ in 1..self.height {
+            match self.prev_row(top) {
+                Some(row) => top = row,
+                None => break,
+            }
+        }
+        top
+    }
+
+    /// Scroll down some rows, stopping when the end of the document is on the screen.
+    fn scroll_down(&mut self, rows: usize) {
+        let last_top = self.last_top();
+        for _ in 0..rows {
+            if self.top >= last_top {
+                break;
+            }
+            match self.next_row(self.top) {
+                Some(row) => self.top = row,
+                None => break,
+            }
+        }
+    }
+
+    fn scroll_up(&mut self, rows: usize) {
+        for _ in 0..rows {
+            match self.prev_row(self.top) {
+                Some(row) => self.top = row,
+                None => break,
+            }
+        }
+    }
+
+    /// Is the end of the document on the screen?
+    fn at_end(&self) -> bool {
+        self.top >= self.last_top()
+    }
+
+    fn draw(&mut self) -> Result<()> {
+        let mut screen = Vec::new();
+        screen.extend_from_slice(&AnsiCode::Clear.to_bytes());
+        let mut position = Some(self.top);
+        let mut screen_row = 0;
+        while let Some((line, row)) = position {
+            if screen_row >= self.height {
+                break;
+            }
+            let rows = self.rows(line);
+            for (index, pieces) in rows.iter().enumerate().skip(row) {
+                if screen_row >= self.height {
+                    break;
+                }
+                screen.extend_from_slice(&AnsiCode::AbsolutePosition(screen_row, 0).to_bytes());
+                if self.options.line_numbers {
+                    let number = if index == 0 {
+                        format!("{:>width$} ", line + 1, width = NUMBER_WIDTH - 1)
+                    } else {
+                        " ".repeat(NUMBER_WIDTH)
+                    };
+                    screen.extend_from_slice(number.as_bytes());
+                }
+                write_row(&mut screen, pieces)?;
+                screen_row += 1;
+            }
+            position = (line + 1 < self.lines().len()).then_some((line + 1, 0));
+        }
+        for screen_row in screen_row..self.height {
+            screen.extend_from_slice(&AnsiCode::AbsolutePosition(screen_row, 0).to_bytes());
+            screen.push(b'~');
+        }
+
+        let prompt = match self.status.take() {
+            Some(status) => status,
+            None if self.at_end() && self.document + 1 < self.documents.len() => {
+                let next = self.documents[self.document + 1].name.as_deref();
+                format!("(END) - Next: {}", next.unwrap_or_default())
+            }
+            None if self.at_end() => "(END)".into(),
+            None => match &self.documents[self.document].name {
+                Some(name) if self.top == (0, 0) => name.clone(),
+                _ => ":".into(),
+            },
+        };
+        screen.extend_from_slice(&AnsiCode::AbsolutePosition(self.height, 0).to_bytes());
+        let mut picker = ColorPicker::new(true);
+        picker.set_color(Color::Reverse);
+        picker.write(&mut screen, &prompt)?;
+        self.stdout.write_all(&screen)?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    /// Read a line typed after `prompt` at the bottom of the screen. Returns `None` if it's
+    /// cancelled, by escape or by backspacing past the start.
+    async fn read_line(&mut self, prompt: char) -> Result<Option<String>> {
+        let mut text = String::new();
+        loop {
+            let line = format!("{prompt}{text}");
+            self.stdout
+                .write_all(&AnsiCode::AbsolutePosition(self.height, 0).to_bytes())?;
+            self.stdout.write_all(&AnsiCode::ClearLine.to_bytes())?;
+            self.stdout.write_all(line.as_bytes())?;
+            self.stdout.flush()?;
+            match Key::read(&mut self.terminal).await? {
+                Key::Char('\n' | '\r') => return Ok(Some(text)),
+                Key::Escape => return Ok(None),
+                Key::Char(c) if c == AsciiChar::BackSpace || c == AsciiChar::DEL => {
+                    if text.is_empty() {
+                        return Ok(None);
+                    }
+                    text.truncate(prev_grapheme(&text, text.len()));
+                }
+                Key::Char(c) if !c.is_control() => text.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    /// Go to the next line matching the search, after the top of the screen or the last match,
+    /// or before it if `backward`.
+    fn find(&mut self, backward: bool) {
+        let Some(regex) = &self.search else {
+            self.status = Some("No previous regular expression".into());
+            return;
+        };
+        let start = match self.found {
+            Some((line, top)) if top == self.top => line,
+            _ => self.top.0,
+        };
+        let found = if backward {
+            (0..start)
+                .rev()
+                .find(|&line| regex.is_match(&self.lines()[line]))
+        } else {
+            (start + 1..self.lines().len()).find(|&line| regex.is_match(&self.lines()[line]))
+        };
+        match found {
+            Some(line) => {
+                self.top = (line, 0);
+                // Don't scroll past the end.
+                self.top = std::cmp::min(self.top, self.last_top());
+                self.found = Some((line, self.top));
+            }
+            None => self.status = Some("Pattern not found".into()),
+        }
+    }
+
+    /// Ask for a pattern and search for it.
+    async fn search(&mut self, backward: bool) -> Result<()> {
+        let Some(pattern) = self.read_line(if backward { '?' } else { '/' }).await? else {
+            return Ok(());
+        };
+        if !pattern.is_empty() {
+            match Regex::new(&pattern) {
+                Ok(regex) => {
+                    self.search = Some(regex);
+                    self.found = None;
+                }
+                Err(_) => {
+                    self.status = Some(format!("Invalid pattern: {pattern}"));
+                    return Ok(());
+                }
+            }
+        }
+        self.backward = backward;
+        self.find(backward);
+        Ok(())
+    }
+
+    /// Show another document.
+    fn show(&mut self, document: usize) {
+        self.document = document;
+        self.top = (0, 0);
+        self.found = None;
+    }
+
+    /// Handle a key. Returns true to quit.
+    async fn handle_key(&mut self, key: Key) -> Result<bool> {
+        let page = std::cmp::max(1, self.height);
+        let half = std::cmp::max(1, self.height / 2);
+        match key {
+            Key::Char('q' | 'Q') => return Ok(true),
+            Key::Char('j' | 'e' | '\n' | '\r') | Key::Down => self.scroll_down(1),
+            Key::Char(c) if c == ControlChar::N || c == ControlChar::E => self.scroll_down(1),
+            Key::Char('k' | 'y') | Key::Up => self.scroll_up(1),
+            Key::Char(c) if c == ControlChar::P || c == ControlChar::Y => self.scroll_up(1),
+            Key::Char(' ' | 'f') => self.scroll_down(page),
+            Key::Char(c) if c == ControlChar::F || c == ControlChar::V => self.scroll_down(page),
+            Key::Char('b') | Key::Meta('v') => self.scroll_up(page),
+            Key::Char(c) if c == ControlChar::B => self.scroll_up(page),
+            Key::Char('d') => self.scroll_down(half),
+            Key::Char(c) if c == ControlChar::D => self.scroll_down(half),
+            Key::Char('u') => self.scroll_up(half),
+            Key::Char(c) if c == ControlChar::U => self.scroll_up(half),
+            Key::Char('g' | '<') => self.top = (0, 0),
+            Key::Char('G' | '>') => self.top = self.last_top(),
+            Key::Char('/') => self.search(false).await?,
+            Key::Char('?') => self.search(true).await?,
+            Key::Char('n') => self.find(self.backward),
+            Key::Char('N') => self.find(!self.backward),
+            Key::Char(':') => match Key::read(&mut self.terminal).await? {
+                Key::Char('n') if self.document + 1 < self.documents.len() => {
+                    self.show(self.document + 1)
+                }
+                Key::Char('p') if self.document > 0 => self.show(self.document - 1),
+                Key::Char('n' | 'p') => self.status = Some("No more files".into()),
+                Key::Char('q' | 'Q') => return Ok(true),
+                _ => {}
+            },
+            _ => {}
+        }
+        Ok(false)
+    }
+}
+
+pub async fn less(process: &mut Process) -> Result<ExitCode> {
+    let options = Options::try_parse_from(process.args.iter())?;
+
+    let mut documents = Vec::new();
+    let mut code = ExitCode::SUCCESS;
+    if options.files.is_empty() {
+        let mut contents = String::new();
+        process.stdin.read_to_string(&mut contents).await?;
+        documents.push(Document::new(None, &contents));
+    }
+    for file in &options.files {
+        let mut contents = String::new();
+        let read = process
+            .get_path(file)
+            .and_then(|path| Ok(path.open_file()?.read_to_string(&mut contents)?));
+        match read {
+            Ok(_) => documents.push(Document::new(Some(file.clone()), &contents)),
+            Err(err) => {
+                writeln!(process.stderr, "less: {file}: {err}")?;
+                code = ExitCode::FAILURE;
+            }
+        }
+    }
+    if documents.is_empty() {
+        return Ok(code);
+    }
+
+    // There's nobody to page for, so just pass everything through.
+    if !process.stdout.to_terminal().await? {
+        for document in documents {
+            for line in document.lines {
+                writeln!(process.stdout, "{line}")?;
+            }
+        }
+        return Ok(code);
+    }
+
+    let mut terminal = process.terminal.clone();
+    terminal.set_mode(InputMode::Char).await?;
+    let mut less = Less {
+        terminal,
+        stdout: process.stdout.clone(),
+        color: options.raw_control_chars.then(color_code),
+        options,
+        documents,
+        document: 0,
+        top: (0, 0),
+        height: std::cmp::max(1, utils::js_term_get_screen_height()),
+        width: std::cmp::max(NUMBER_WIDTH + 1, utils::get_screen_width()),
+        search: None,
+        backward: false,
+        found: None,
+        status: None,
+    };
+
+    loop {
+        less.draw()?;
+        let key = Key::read(&mut less.terminal).await?;
+        if less.handle_key(key).await? {
+            break;
+        }
+    }
+
+    less.stdout.write_all(&AnsiCode::Clear.to_bytes())?;
+    less.terminal.set_mode(InputMode::Line).await?;
+    Ok(code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The text of each row a line is shown as, with reversed pieces in brackets.
+    fn show(line: &str, width: usize, raw: bool, matches: &[Range<usize>]) -> Vec<String> {
+        let color = color_code();
+        let rows = fold(pieces(line, raw.then_some(&color), matches), width);
+        rows.into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|piece| match piece.reverse {
+                        true => format!("[{}]", piece.text),
+                        false => piece.text,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pieces_shown() {
+        assert_eq!(show("a\tb", 80, false, &[]), ["a       b"]);
+        assert_eq!(show("a\x08b", 80, false, &[]), ["a[^H]b"]);
+        assert_eq!(show("\x1b[31mred", 80, false, &[]), ["[^[][31mred"]);
+        assert_eq!(show("\x1b[31mred", 80, true, &[]), ["\x1b[31mred"]);
+        assert_eq!(
+            show("one two two", 80, false, &[4..7, 8..11]),
+            ["one [t][w][o] [t][w][o]"]
+        );
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(show("abcdefg", 3, false, &[]), ["abc", "def", "g"]);
+        assert_eq!(show("ab漢字", 3, false, &[]), ["ab", "漢", "字"]);
+        // Escape codes don't take up any room.
+        assert_eq!(show("\x1b[1mab\x1b[0m", 2, true, &[]), ["\x1b[1mab\x1b[0m"]);
+    }
+
+    #[test]
+    fn searching() {
+        let (terminal, stdout, _) = crate::streams::pipe();
+        let lines: Vec<_> = (0..10).map(|line| format!("line {line}")).collect();
+        let mut less = Less {
+            terminal,
+            stdout,
+            options: Options::try_parse_from(["less"]).unwrap(),
+            documents: vec![Document::new(None, &lines.join("\n"))],
+            document: 0,
+            top: (0, 0),
+            height: 5,
+            width: 80,
+            color: None,
+            search: Regex::new("[79]").ok(),
+            backward: false,
+            found: None,
+            status: None,
+        };
+        // Both matches are in the last screenful, so the screen stops at the end.
+        less.find(false);
+        assert_eq!((less.top, less.found), ((5, 0), Some((7, (5, 0)))));
+        less.find(false);
+        assert_eq!((less.top, less.found), ((5, 0), Some((9, (5, 0)))));
+        less.find(false);
+        assert_eq!(less.status.take().as_deref(), Some("Pattern not found"));
+        less.find(true);
+        assert_eq!(less.found, Some((7, (5, 0))));
+
+        // After scrolling, searches start from the screen again.
+        less.top = (0, 0);
+        less.find(true);
+        assert_eq!(less.status.as_deref(), Some("Pattern not found"));
+    }
+}
@@ -1,51 +1,460 @@
-use crate::process::{ExitCode, Process};
-use crate::programs::common::color_picker::{Color, ColorPicker};
-use anyhow::{bail, Result};
-use clap::Parser;
+use crate::{
+    filesystem,
+    process::{ExitCode, Process},
+    programs::common::{color_picker::When, columns::Columns, unicode},
+    utils,
+};
+use anyhow::Result;
+use clap::{ArgAction, Parser};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    io::{Read, Write},
+};
+use vfs::{VfsFileType, VfsPath};
 
-const DIR_COLOR: Color = Color::Blue;
+/// Colors used when `LS_COLORS` isn't set.
+const DEFAULT_COLORS: &str = "di=34:ex=32";
+
+/// How many seconds old a file can be before its year is shown instead of the time of day.
+const RECENT: i64 = 60 * 60 * 24 * 365 / 2;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
 
 /// List files/directories.
+///
+/// Directories are colored blue and programs green, unless LS_COLORS says otherwise.
 #[derive(Parser)]
-struct Options {
+#[command(disable_help_flag = true)]
+pub(super) struct Options {
     /// Do not ignore hidden files.
     #[arg(short, long)]
     all: bool,
-    /// The directory to list.
-    target: Option<String>,
+    /// Show the type, permissions, size and modification time of each file.
+    #[arg(short = 'l')]
+    long: bool,
+    /// List the contents of directories within directories.
+    #[arg(short = 'R', long)]
+    recursive: bool,
+    /// List one file per line.
+    #[arg(short = '1')]
+    one_per_line: bool,
+    /// List directories themselves, rather than what's in them.
+    #[arg(short, long)]
+    directory: bool,
+    /// With -l, show sizes like 1.5K and 12M.
+    #[arg(short = 'h', long)]
+    human_readable: bool,
+    /// Sort by modification time, newest first.
+    #[arg(short = 't')]
+    time: bool,
+    /// Sort by size, largest first.
+    #[arg(short = 'S')]
+    size: bool,
+    /// Reverse the order files are sorted in.
+    #[arg(short, long)]
+    reverse: bool,
+    /// Color file names: always, auto (when writing to a terminal) or never.
+    #[arg(
+        long,
+        value_name = "WHEN",
+        value_enum,
+        default_value = "auto",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always"
+    )]
+    color: When,
+    /// Print help.
+    #[arg(long, action = ArgAction::Help)]
+    help: Option<bool>,
+    /// The files and directories to list.
+    targets: Vec<String>,
 }
 
-pub async fn ls(process: &mut Process) -> Result<ExitCode> {
-    let options = Options::try_parse_from(process.args.iter())?;
-    let path = {
-        let dir = process.get_path(options.target.unwrap_or_else(|| ".".into()))?;
-        if !dir.exists()? {
-            bail!("No such file or directory: {}", dir.as_str());
+/// A file or directory to list.
+struct Entry {
+    /// The name it's listed under, which is the path given for operands.
+    name: String,
+    path: VfsPath,
+    is_dir: bool,
+    len: u64,
+    modified: i64,
+    /// A script, which can be run like a program.
+    executable: bool,
+}
+
+impl Entry {
+    fn new(name: String, path: VfsPath) -> Result<Self> {
+        let metadata = path.metadata()?;
+        let is_dir = metadata.file_type == VfsFileType::Directory;
+        let mut executable = false;
+        if !is_dir && metadata.len >= 2 {
+            let mut magic = [0; 2];
+            executable = path.open_file()?.read_exact(&mut magic).is_ok() && &magic == b"#!";
         }
-        if !dir.is_dir()? {
-            bail!("Not a directory: {}", dir.as_str());
+        Ok(Self {
+            modified: filesystem::modified(&path),
+            name,
+            path,
+            is_dir,
+            len: metadata.len,
+            executable,
+        })
+    }
+
+    fn is_hidden(&self) -> bool {
+        self.name.starts_with('.')
+    }
+
+    /// The file's type and permissions, like `drwxr-xr-x`. Everything can be read and written by
+    /// everyone, so they depend only on the type.
+    fn mode(&self) -> &'static str {
+        if self.is_dir {
+            "drwxr-xr-x"
+        } else if self.executable {
+            "-rwxr-xr-x"
+        } else {
+            "-rw-r--r--"
+        }
+    }
+}
+
+/// Colors for file names, from `LS_COLORS`, by kind (like `di` for directories) or by pattern
+/// (like `*.rs`).
+struct Colors(HashMap<String, String>);
+
+impl Colors {
+    fn new(spec: &str) -> Self {
+        Self(
+            spec.split(':')
+                .filter_map(|item| item.split_once('='))
+                .map(|(key, codes)| (key.into(), codes.into()))
+                .collect(),
+        )
+    }
+
+    /// The escape codes that color an entry.
+    fn codes(&self, entry: &Entry) -> Option<String> {
+        let codes = if entry.is_dir {
+            self.0.get("di")
+        } else {
+            self.0
+                .iter()
+                .filter_map(|(key, codes)| Some((key.strip_prefix('*')?, codes)))
+                .filter(|(suffix, _)| entry.name.ends_with(suffix))
+                .max_by_key(|(suffix, _)| suffix.len())
+                .map(|(_, codes)| codes)
+                .or_else(|| self.0.get("ex").filter(|_| entry.executable))
+                .or_else(|| self.0.get("fi"))
+        }?;
+        // The terminal takes one code at a time.
+        let codes: String = codes
+            .split(';')
+            .filter_map(|code| code.parse::<u8>().ok())
+            .map(|code| format!("\x1b[{code}m"))
+            .collect();
+        Some(codes).filter(|codes| !codes.is_empty())
+    }
+}
+
+/// A size, like 1.5K or 12M.
+fn human_size(len: u64) -> String {
+    if len < 1024 {
+        return len.to_string();
+    }
+    let mut size = len as f64;
+    for unit in ["K", "M", "G", "T"] {
+        size /= 1024.0;
+        if size < 1024.0 || unit == "T" {
+            // Round up, like the GNU version.
+            let tenths = (size * 10.0).ceil() / 10.0;
+            return if tenths < 10.0 {
+                format!("{tenths:.1}{unit}")
+            } else {
+                format!("{}{unit}", size.ceil())
+            };
         }
-        dir
+    }
+    unreachable!("Sizes stop at terabytes")
+}
+
+/// The year, month and day of a number of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, usize, i64) {
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
     };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month as usize, day)
+}
 
-    let mut picker = ColorPicker::new(process.stdout.to_terminal().await?);
+/// A time like `Oct 18 14:03`, or `Oct 18  2025` if it's not recent. Both times are in seconds
+/// since the Unix epoch, in the local time zone.
+fn format_time(time: i64, now: i64) -> String {
+    let (year, month, day) = civil_from_days(time.div_euclid(86400));
+    let month = MONTHS[month - 1];
+    if (now - time).abs() < RECENT {
+        let seconds = time.rem_euclid(86400);
+        format!(
+            "{month} {day:>2} {:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60
+        )
+    } else {
+        format!("{month} {day:>2}  {year}")
+    }
+}
+
+/// Lists files as the options say to.
+struct Lister {
+    options: Options,
+    colors: Option<Colors>,
+    /// The width of the terminal, if files are listed in columns on one.
+    width: Option<usize>,
+    /// Something has been listed, so the next directory is set apart by a blank line.
+    listed: bool,
+    code: ExitCode,
+}
 
-    // Show '.' and '..', because those don't appear in read_dir()
-    if options.all {
-        picker.set_color(DIR_COLOR);
-        picker.write(&mut process.stdout, ".\n..\n")?;
+impl Lister {
+    fn sort(&self, entries: &mut [Entry]) {
+        entries.sort_by(|a, b| {
+            let order = if self.options.time {
+                b.modified.cmp(&a.modified)
+            } else if self.options.size {
+                b.len.cmp(&a.len)
+            } else {
+                Ordering::Equal
+            };
+            order.then_with(|| a.name.cmp(&b.name))
+        });
+        if self.options.reverse {
+            entries.reverse();
+        }
     }
 
-    for entity in path.read_dir()? {
-        if entity.is_dir()? {
-            picker.set_color(DIR_COLOR);
+    /// An entry's name, colored if colors are on.
+    fn name(&self, entry: &Entry) -> String {
+        match self.colors.as_ref().and_then(|colors| colors.codes(entry)) {
+            Some(codes) => format!("{codes}{}\x1b[0m", entry.name),
+            None => entry.name.clone(),
+        }
+    }
+
+    fn size(&self, entry: &Entry) -> String {
+        if self.options.human_readable {
+            human_size(entry.len)
         } else {
-            picker.reset();
+            entry.len.to_string()
         }
-        let display = format!("{}\n", entity.filename());
-        if options.all || !display.starts_with('.') {
-            picker.write(&mut process.stdout, &display)?;
+    }
+
+    fn write_long(&self, stdout: &mut impl Write, entries: &[Entry]) -> Result<()> {
+        let now = utils::now() + utils::utc_offset();
+        let sizes: Vec<String> = entries.iter().map(|entry| self.size(entry)).collect();
+        let size_width = sizes.iter().map(String::len).max().unwrap_or_default();
+        for (entry, size) in entries.iter().zip(sizes) {
+            writeln!(
+                stdout,
+                "{} 1 root root {size:>size_width$} {} {}",
+                entry.mode(),
+                format_time(entry.modified + utils::utc_offset(), now),
+                self.name(entry)
+            )?;
         }
+        Ok(())
+    }
+
+    fn write_entries(&self, stdout: &mut impl Write, entries: &[Entry]) -> Result<()> {
+        if self.options.long {
+            return self.write_long(stdout, entries);
+        }
+        let Some(width) = self.width else {
+            for entry in entries {
+                writeln!(stdout, "{}", self.name(entry))?;
+            }
+            return Ok(());
+        };
+        let widths: Vec<usize> = entries
+            .iter()
+            .map(|entry| unicode::width(&entry.name))
+            .collect();
+        let layout = Columns::new(&widths, width);
+        for row in 0..layout.rows() {
+            for index in layout.row(row) {
+                let padding = layout.padding(index, widths[index]);
+                write!(
+                    stdout,
+                    "{}{}",
+                    self.name(&entries[index]),
+                    " ".repeat(padding)
+                )?;
+            }
+            writeln!(stdout)?;
+        }
+        Ok(())
+    }
+
+    /// List what's in a directory, under a heading with its name if `heading` is set.
+    fn list_directory(
+        &mut self,
+        process: &mut Process,
+        name: &str,
+        path: &VfsPath,
+        heading: bool,
+    ) -> Result<()> {
+        let mut entries = Vec::new();
+        if self.options.all {
+            // These don't appear in read_dir()
+            entries.push(Entry::new(".".into(), path.clone())?);
+            entries.push(Entry::new("..".into(), path.parent())?);
+        }
+        for child in path.read_dir()? {
+            let entry = Entry::new(child.filename(), child)?;
+            if self.options.all || !entry.is_hidden() {
+                entries.push(entry);
+            }
+        }
+        self.sort(&mut entries);
+
+        if self.listed {
+            writeln!(process.stdout)?;
+        }
+        self.listed = true;
+        if heading {
+            writeln!(process.stdout, "{name}:")?;
+        }
+        if self.options.long {
+            let blocks: u64 = entries.iter().map(|entry| entry.len.div_ceil(1024)).sum();
+            writeln!(process.stdout, "total {blocks}")?;
+        }
+        self.write_entries(&mut process.stdout, &entries)?;
+
+        if self.options.recursive {
+            for entry in entries {
+                if entry.is_dir && entry.name != "." && entry.name != ".." {
+                    let name = format!("{}/{}", name.trim_end_matches('/'), entry.name);
+                    self.list_directory(process, &name, &entry.path, true)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub async fn ls(process: &mut Process) -> Result<ExitCode> {
+    let mut options = Options::try_parse_from(process.args.iter())?;
+    let terminal = process.stdout.to_terminal().await?;
+
+    let colors = options.color.colors(terminal).then(|| {
+        Colors::new(
+            process
+                .env
+                .get("LS_COLORS")
+                .map(String::as_str)
+                .unwrap_or(DEFAULT_COLORS),
+        )
+    });
+    let width = (terminal && !options.one_per_line).then(utils::get_screen_width);
+
+    let targets = std::mem::take(&mut options.targets);
+    let mut lister = Lister {
+        options,
+        colors,
+        width,
+        listed: false,
+        code: ExitCode::SUCCESS,
+    };
+
+    // Files are listed first, then the contents of each directory.
+    let mut files = Vec::new();
+    let mut directories = Vec::new();
+    let many = targets.len() > 1;
+    for target in if targets.is_empty() {
+        vec![".".into()]
+    } else {
+        targets
+    } {
+        let path = process.get_path(&target)?;
+        if !path.exists()? {
+            writeln!(
+                process.stderr,
+                "ls: cannot access '{target}': No such file or directory"
+            )?;
+            lister.code = ExitCode::from(2);
+            continue;
+        }
+        let entry = Entry::new(target, path)?;
+        if entry.is_dir && !lister.options.directory {
+            directories.push(entry);
+        } else {
+            files.push(entry);
+        }
+    }
+
+    lister.sort(&mut files);
+    lister.sort(&mut directories);
+    if !files.is_empty() {
+        lister.write_entries(&mut process.stdout, &files)?;
+        lister.listed = true;
+    }
+    let heading = many || !files.is_empty() || lister.options.recursive;
+    for directory in directories {
+        lister.list_directory(process, &directory.name, &directory.path, heading)?;
+    }
+
+    Ok(lister.code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn human_sizes() {
+        assert_eq!(human_size(0), "0");
+        assert_eq!(human_size(6), "6");
+        assert_eq!(human_size(1023), "1023");
+        assert_eq!(human_size(1024), "1.0K");
+        assert_eq!(human_size(1536), "1.5K");
+        assert_eq!(human_size(1025), "1.1K");
+        assert_eq!(human_size(10 * 1024 - 1), "10K");
+        assert_eq!(human_size(300 * 1024 * 1024), "300M");
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(20744), (2026, 10, 18));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        let time = 20744 * 86400 + 14 * 3600 + 3 * 60;
+        assert_eq!(format_time(time, time + 60), "Oct 18 14:03");
+        assert_eq!(format_time(time, time + RECENT * 2), "Oct 18  2026");
+    }
+
+    #[test]
+    fn ls_colors() {
+        let colors = Colors::new("di=01;34:*.rs=33:ex=32");
+        let root = filesystem::get_root().unwrap();
+        let entry = |path: &str| {
+            let path = root.join(path).unwrap();
+            Entry::new(path.filename(), path).unwrap()
+        };
+        assert_eq!(colors.codes(&entry("usr")).unwrap(), "\x1b[1m\x1b[34m");
+        assert_eq!(colors.codes(&entry("bin/ls")).unwrap(), "\x1b[32m");
+        assert_eq!(colors.codes(&entry("etc/profile")), None);
     }
-    Ok(ExitCode::SUCCESS)
 }
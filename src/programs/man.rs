@@ -0,0 +1,794 @@
+use crate::{
+    process::{ExitCode, Process},
+    programs::{
+        self,
+        common::{
+            color_picker::{Color, ColorPicker},
+            shell_commands,
+        },
+    },
+    streams, utils,
+};
+use anyhow::{bail, Result};
+use clap::{Arg, Parser};
+use futures::join;
+use regex::RegexBuilder;
+use std::io::Write;
+use vfs::VfsPath;
+
+const MAN_DIR: &str = "/usr/share/man";
+
+/// How far the text of a page is indented, as well as tagged paragraphs and `.RS` blocks.
+const INDENT: usize = 7;
+
+/// Show the manual page for a command or file.
+///
+/// Pages are kept in /usr/share/man, in a directory for each section: 1 for commands and 5 for
+/// file formats. A section can be given before the name, as in "man 5 theme".
+/// Programs without a page there get one made from their options.
+///
+/// Pages are shown with $PAGER.
+#[derive(Parser)]
+pub(super) struct Options {
+    /// Search the names and summaries of pages for regular expressions, like apropos.
+    #[arg(short = 'k', long)]
+    apropos: bool,
+    /// The pages to show, or with -k, what to search for.
+    #[arg(required(true))]
+    names: Vec<String>,
+}
+
+/// A font text is shown in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Font {
+    Roman,
+    Bold,
+    Italic,
+}
+
+/// Turns the roff source of a page into lines of text.
+struct Renderer {
+    /// Show bold and italic text with escape codes.
+    styled: bool,
+    width: usize,
+    lines: Vec<String>,
+    /// Text waiting to be filled into a paragraph.
+    words: Vec<String>,
+    /// Where text starts, and where it started before each `.RS`.
+    indent: usize,
+    indents: Vec<usize>,
+    /// The tag of a `.TP` paragraph, which is the line after the macro.
+    tag: Option<String>,
+    expecting_tag: bool,
+    /// Between `.nf` and `.fi`, lines are shown as they are.
+    fill: bool,
+    /// The line the last heading is on, since paragraphs under it start right away.
+    heading: Option<usize>,
+    font: Font,
+    previous_font: Font,
+}
+
+impl Renderer {
+    fn new(styled: bool, width: usize) -> Self {
+        Self {
+            styled,
+            width,
+            lines: Vec::new(),
+            words: Vec::new(),
+            indent: INDENT,
+            indents: Vec::new(),
+            tag: None,
+            expecting_tag: false,
+            fill: true,
+            heading: None,
+            font: Font::Roman,
+            previous_font: Font::Roman,
+        }
+    }
+
+    /// Write text in a font. Each word is styled on its own, so no style is carried from one line
+    /// to the next when the text is filled.
+    fn style(&self, text: &str, font: Font) -> Result<String> {
+        let color = match font {
+            Font::Roman => None,
+            Font::Bold => Some(Color::Bold),
+            Font::Italic => Some(Color::Underline),
+        };
+        let Some(color) = color.filter(|_| self.styled) else {
+            return Ok(text.into());
+        };
+        let mut picker = ColorPicker::new(true);
+        picker.set_color(color);
+        let mut styled = Vec::new();
+        for (index, word) in text.split(' ').enumerate() {
+            if index > 0 {
+                styled.push(b' ');
+            }
+            if !word.is_empty() {
+                picker.write(&mut styled, word)?;
+            }
+        }
+        Ok(String::from_utf8(styled)?)
+    }
+
+    fn set_font(&mut self, font: Font) {
+        self.previous_font = self.font;
+        self.font = font;
+    }
+
+    /// Interpret the escapes in a line of text: font changes like `\fB`, and special characters
+    /// like `\-`.
+    fn text(&mut self, line: &str) -> Result<String> {
+        let mut text = String::new();
+        let mut run = String::new();
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                run.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('f') => {
+                    text.push_str(&self.style(&std::mem::take(&mut run), self.font)?);
+                    match chars.next() {
+                        Some('B') => self.set_font(Font::Bold),
+                        Some('I') => self.set_font(Font::Italic),
+                        Some('R') => self.set_font(Font::Roman),
+                        Some('P') => self.set_font(self.previous_font),
+                        _ => {}
+                    }
+                }
+                Some('(') => {
+                    let name: String = chars.by_ref().take(2).collect();
+                    match name.as_str() {
+                        "em" => run.push('—'),
+                        "en" => run.push('–'),
+                        "bu" => run.push('•'),
+                        _ => {}
+                    }
+                }
+                Some('e' | '\\') => run.push('\\'),
+                Some('"') => break,
+                Some('&' | 'c') | None => {}
+                Some(c) => run.push(c),
+            }
+        }
+        text.push_str(&self.style(&run, self.font)?);
+        Ok(text)
+    }
+
+    /// Leave a line empty, unless there already is one or it's right after a heading.
+    fn space(&mut self) {
+        let after_heading = self
+            .heading
+            .is_some_and(|line| line + 1 == self.lines.len());
+        if !after_heading && self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+
+    /// Fill the text so far into a paragraph, after the tag if it has one.
+    fn flush(&mut self) {
+        let indent = " ".repeat(self.indent);
+        let body_indent = " ".repeat(self.indent + INDENT);
+        let text = std::mem::take(&mut self.words).join(" ");
+        let (first_indent, rest_indent) = match self.tag.take() {
+            None if text.is_empty() => return,
+            None => (indent.clone(), indent),
+            // A short tag shares its line with the text.
+            Some(tag) if textwrap::core::display_width(&tag) < INDENT && !text.is_empty() => {
+                let padding = INDENT - textwrap::core::display_width(&tag);
+                (
+                    format!("{indent}{tag}{}", " ".repeat(padding)),
+                    body_indent.clone(),
+                )
+            }
+            Some(tag) => {
+                self.lines.push(format!("{indent}{tag}"));
+                (body_indent.clone(), body_indent)
+            }
+        };
+        if text.is_empty() {
+            return;
+        }
+        let options = textwrap::Options::new(std::cmp::max(self.width, rest_indent.len() + 1))
+            .initial_indent(&first_indent)
+            .subsequent_indent(&rest_indent);
+        for line in textwrap::wrap(&text, options) {
+            self.lines.push(line.into_owned());
+        }
+    }
+
+    /// Write the arguments of a macro like `.BR`, alternating between two fonts.
+    fn alternate(&mut self, arguments: &[String], fonts: [Font; 2]) -> Result<String> {
+        let mut text = String::new();
+        for (index, argument) in arguments.iter().enumerate() {
+            self.set_font(fonts[index % 2]);
+            text.push_str(&self.text(argument)?);
+        }
+        self.set_font(Font::Roman);
+        Ok(text)
+    }
+
+    /// Add a line of the page.
+    fn line(&mut self, line: &str) -> Result<()> {
+        if line.starts_with(".\\\"") || line.starts_with("'\\\"") {
+            return Ok(());
+        }
+        let Some(request) = line.strip_prefix('.') else {
+            let text = self.text(line)?;
+            return self.add_text(text);
+        };
+        let (name, rest) = request.split_once(' ').unwrap_or((request, ""));
+        let arguments = split_arguments(rest);
+        let text = match name {
+            "TH" => {
+                let title = format!(
+                    "{}({})",
+                    arguments.first().map(String::as_str).unwrap_or_default(),
+                    arguments.get(1).map(String::as_str).unwrap_or_default(),
+                );
+                let padding = self.width.saturating_sub(title.len() * 2).max(1);
+                self.lines
+                    .push(format!("{title}{}{title}", " ".repeat(padding)));
+                return Ok(());
+            }
+            "SH" | "SS" => {
+                self.flush();
+                self.space();
+                self.fill = true;
+                self.indents.clear();
+                self.indent = INDENT;
+                let heading = self.text(&arguments.join(" "))?;
+                let heading = self.style(&heading, Font::Bold)?;
+                let indent = if name == "SH" { 0 } else { 3 };
+                self.heading = Some(self.lines.len());
+                self.lines.push(format!("{}{heading}", " ".repeat(indent)));
+                return Ok(());
+            }
+            "PP" | "P" | "LP" | "TP" => {
+                self.flush();
+                self.space();
+                self.expecting_tag = name == "TP";
+                return Ok(());
+            }
+            "br" => {
+                self.flush();
+                return Ok(());
+            }
+            "nf" | "fi" => {
+                self.flush();
+                self.fill = name == "fi";
+                return Ok(());
+            }
+            "RS" => {
+                self.flush();
+                self.indents.push(self.indent);
+                self.indent += INDENT;
+                return Ok(());
+            }
+            "RE" => {
+                self.flush();
+                self.indent = self.indents.pop().unwrap_or(INDENT);
+                return Ok(());
+            }
+            "B" => self.alternate(&[arguments.join(" ")], [Font::Bold; 2])?,
+            "I" => self.alternate(&[arguments.join(" ")], [Font::Italic; 2])?,
+            "BR" => self.alternate(&arguments, [Font::Bold, Font::Roman])?,
+            "BI" => self.alternate(&arguments, [Font::Bold, Font::Italic])?,
+            "IB" => self.alternate(&arguments, [Font::Italic, Font::Bold])?,
+            "IR" => self.alternate(&arguments, [Font::Italic, Font::Roman])?,
+            "RB" => self.alternate(&arguments, [Font::Roman, Font::Bold])?,
+            "RI" => self.alternate(&arguments, [Font::Roman, Font::Italic])?,
+            // Anything else only matters to real typesetters.
+            _ => return Ok(()),
+        };
+        self.add_text(text)
+    }
+
+    /// Add text, as the tag of a paragraph, a line on its own or words to fill.
+    fn add_text(&mut self, text: String) -> Result<()> {
+        if self.expecting_tag {
+            self.expecting_tag = false;
+            self.tag = Some(text);
+        } else if !self.fill {
+            self.flush();
+            self.lines.push(
+                format!("{}{text}", " ".repeat(self.indent))
+                    .trim_end()
+                    .into(),
+            );
+        } else if text.trim().is_empty() {
+            self.flush();
+            self.space();
+        } else {
+            self.words.push(text.trim().into());
+        }
+        Ok(())
+    }
+
+    /// Finish the page, returning its lines.
+    fn finish(mut self) -> Vec<String> {
+        self.flush();
+        while self.lines.last().is_some_and(String::is_empty) {
+            self.lines.pop();
+        }
+        self.lines
+    }
+}
+
+/// Split the arguments of a macro at spaces, except where they're quoted.
+fn split_arguments(arguments: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut argument = String::new();
+    let mut quoted = false;
+    for c in arguments.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ' ' if !quoted => split.push(std::mem::take(&mut argument)),
+            c => argument.push(c),
+        }
+    }
+    split.push(argument);
+    split.retain(|argument| !argument.is_empty());
+    split
+}
+
+/// Render a page to lines of text.
+fn render(source: &str, styled: bool, width: usize) -> Result<Vec<String>> {
+    let mut renderer = Renderer::new(styled, width);
+    for line in source.lines() {
+        renderer.line(line)?;
+    }
+    Ok(renderer.finish())
+}
+
+/// The summary of a page, from the line under its NAME heading, like "cat - concatenate files".
+fn summary(source: &str) -> Option<String> {
+    let mut lines = source.lines().skip_while(|line| *line != ".SH NAME");
+    lines.next()?;
+    let line = lines.next()?;
+    let mut renderer = Renderer::new(false, usize::MAX);
+    renderer.text(line).ok()
+}
+
+/// Escape text for a page.
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e");
+    if text.starts_with(['.', '\'']) {
+        format!("\\&{text}")
+    } else {
+        text
+    }
+}
+
+/// The value an argument takes, like `FILES`.
+fn value_name(arg: &Arg) -> String {
+    match arg.get_value_names() {
+        Some([name, ..]) => name.to_string(),
+        _ => arg.get_id().to_string().to_uppercase(),
+    }
+}
+
+/// How an argument is written in the list of options, like `-n, --lines NUM`.
+fn usage(arg: &Arg) -> String {
+    if arg.is_positional() {
+        return format!("\\fI{}\\fR", value_name(arg));
+    }
+    let mut names = Vec::new();
+    if let Some(short) = arg.get_short() {
+        names.push(format!("\\fB\\-{short}\\fR"));
+    }
+    if let Some(long) = arg.get_long() {
+        names.push(format!("\\fB\\-\\-{}\\fR", long.replace('-', "\\-")));
+    }
+    let mut usage = names.join(", ");
+    if arg.get_action().takes_values() {
+        usage.push_str(&format!(" \\fI{}\\fR", value_name(arg)));
+    }
+    usage
+}
+
+/// How an argument is written in the synopsis, like `[-n NUM]`.
+fn synopsis(arg: &Arg) -> String {
+    let mut synopsis = if arg.is_positional() {
+        usage(arg)
+    } else {
+        // Just the short form, if there is one.
+        let name = match (arg.get_short(), arg.get_long()) {
+            (Some(short), _) => format!("\\fB\\-{short}\\fR"),
+            (None, Some(long)) => format!("\\fB\\-\\-{}\\fR", long.replace('-', "\\-")),
+            (None, None) => unreachable!("Options have a short or long name"),
+        };
+        if arg.get_action().takes_values() {
+            format!("{name} \\fI{}\\fR", value_name(arg))
+        } else {
+            name
+        }
+    };
+    let multiple = arg
+        .get_num_args()
+        .is_some_and(|range| range.max_values() > 1);
+    if multiple || matches!(arg.get_action(), clap::ArgAction::Append) {
+        synopsis.push_str("...");
+    }
+    if arg.is_required_set() {
+        synopsis
+    } else {
+        format!("[{synopsis}]")
+    }
+}
+
+/// Write the page of a program from its command line interface.
+fn program_page(mut command: clap::Command) -> String {
+    command.build();
+    let name = command.get_name().to_string();
+    let about = command.get_about().map(ToString::to_string);
+    let summary = about.unwrap_or_default();
+    let summary = summary.trim_end_matches('.');
+    let mut chars = summary.chars();
+    let summary = match (chars.next(), chars.next()) {
+        // Leave acronyms alone.
+        (Some(first), Some(second)) if !second.is_uppercase() => {
+            first.to_lowercase().collect::<String>() + &summary[first.len_utf8()..]
+        }
+        _ => summary.to_string(),
+    };
+
+    let mut page = String::new();
+    page.push_str(&format!(".TH {} 1\n", name.to_uppercase()));
+    page.push_str(&format!(".SH NAME\n{name} \\- {}\n", escape(&summary)));
+
+    let arguments: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .collect();
+    let (positionals, options): (Vec<&Arg>, Vec<&Arg>) =
+        arguments.iter().partition(|arg| arg.is_positional());
+    page.push_str(&format!(".SH SYNOPSIS\n.B {name}\n"));
+    for arg in options.iter().chain(positionals.iter()) {
+        page.push_str(&synopsis(arg));
+        page.push('\n');
+    }
+
+    page.push_str(".SH DESCRIPTION\n");
+    let description = command.get_long_about().or(command.get_about());
+    paragraphs(
+        &mut page,
+        &description.map(ToString::to_string).unwrap_or_default(),
+    );
+
+    page.push_str(".SH OPTIONS\n");
+    for arg in options.iter().chain(positionals.iter()) {
+        page.push_str(&format!(".TP\n{}\n", usage(arg)));
+        let help = arg.get_long_help().or(arg.get_help());
+        paragraphs(
+            &mut page,
+            &help.map(ToString::to_string).unwrap_or_default(),
+        );
+        let defaults = arg.get_default_values();
+        if arg.get_action().takes_values()
+            && !defaults.is_empty()
+            && !arg.is_hide_default_value_set()
+        {
+            let defaults: Vec<_> = defaults
+                .iter()
+                .map(|value| value.to_string_lossy())
+                .collect();
+            page.push_str(&format!("[default: {}]\n", escape(&defaults.join(", "))));
+        }
+    }
+    page
+}
+
+/// Write text with paragraphs separated by blank lines, keeping the lines of those that have
+/// several, since they're laid out by hand.
+fn paragraphs(page: &mut String, text: &str) {
+    for (index, paragraph) in text.split("\n\n").enumerate() {
+        if index > 0 {
+            page.push_str(".PP\n");
+        }
+        let verbatim = paragraph.contains('\n');
+        if verbatim {
+            page.push_str(".nf\n");
+        }
+        for line in paragraph.lines() {
+            page.push_str(&escape(line));
+            page.push('\n');
+        }
+        if verbatim {
+            page.push_str(".fi\n");
+        }
+    }
+}
+
+/// Where the source of a page comes from.
+enum Source {
+    /// Written by hand, in `man/man<section>/<name>.<section>`.
+    File(VfsPath),
+    /// Generated from the options of the program with the page's name.
+    Program,
+}
+
+/// A page, written by hand or generated for a program.
+struct Page {
+    name: String,
+    section: String,
+    source: Source,
+}
+
+impl Page {
+    /// Read or generate the roff source of the page.
+    fn source(&self) -> Result<String> {
+        Ok(match &self.source {
+            Source::File(path) => {
+                let mut source = String::new();
+                path.open_file()?.read_to_string(&mut source)?;
+                source
+            }
+            Source::Program => match programs::program_command(&self.name) {
+                Some(command) => program_page(command),
+                None => bail!("No program named {}", self.name),
+            },
+        })
+    }
+}
+
+/// All pages, or just those in a section, by section and then name. Programs without a page
+/// written by hand get one generated from their options.
+fn pages(process: &Process, section: Option<&str>) -> Result<Vec<Page>> {
+    let mut pages = Vec::new();
+    for directory in process.get_path(MAN_DIR)?.read_dir()? {
+        let Some(directory_section) = directory.filename().strip_prefix("man").map(String::from)
+        else {
+            continue;
+        };
+        if section.is_some_and(|section| section != directory_section) {
+            continue;
+        }
+        for path in directory.read_dir()? {
+            let filename = path.filename();
+            let Some((name, section)) = filename.rsplit_once('.') else {
+                continue;
+            };
+            if name.is_empty() || section != directory_section {
+                continue;
+            }
+            pages.push(Page {
+                name: name.into(),
+                section: section.into(),
+                source: Source::File(path),
+            });
+        }
+    }
+    if section.is_none_or(|section| section == "1") {
+        for &name in programs::PROGRAMS {
+            if pages
+                .iter()
+                .any(|page| page.name == name && page.section == "1")
+            {
+                continue;
+            }
+            pages.push(Page {
+                name: name.into(),
+                section: "1".into(),
+                source: Source::Program,
+            });
+        }
+    }
+    pages.sort_by(|a, b| (&a.section, &a.name).cmp(&(&b.section, &b.name)));
+    Ok(pages)
+}
+
+/// Print the summary of each page matching one of the patterns.
+async fn apropos(process: &mut Process, patterns: &[String]) -> Result<ExitCode> {
+    let patterns = patterns
+        .iter()
+        .map(|pattern| RegexBuilder::new(pattern).case_insensitive(true).build())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut code = ExitCode::FAILURE;
+    for page in pages(process, None)? {
+        let source = page.source()?;
+        let summary = summary(&source).unwrap_or_else(|| page.name.clone());
+        if patterns.iter().any(|pattern| pattern.is_match(&summary)) {
+            let description = summary
+                .split_once(" - ")
+                .map(|(_, description)| description)
+                .unwrap_or_default();
+            writeln!(
+                process.stdout,
+                "{} ({}) - {description}",
+                page.name, page.section
+            )?;
+            code = ExitCode::SUCCESS;
+        }
+    }
+    if code.is_failure() {
+        writeln!(process.stderr, "man: nothing appropriate")?;
+    }
+    Ok(code)
+}
+
+/// Show text with the pager in $PAGER.
+async fn page(process: &mut Process, text: &str) -> Result<ExitCode> {
+    let pager = process.env.get("PAGER").cloned().unwrap_or("less".into());
+    let mut args: Vec<String> = pager.split_whitespace().map(String::from).collect();
+    let Some(command) = args.first().cloned() else {
+        bail!("No pager");
+    };
+    // Show the pages in bold and italic, rather than their escape codes.
+    if command == "less" || command == "more" {
+        args.push("-R".into());
+    }
+
+    let (stdin, mut writer, mut backend) = streams::pipe();
+    let mut pager = process.clone();
+    pager.stdin = stdin;
+    pager.args = args;
+    let (_, code): (Result<()>, Result<ExitCode>) = join! {
+        backend.run(),
+        async {
+            writer.write_all(text.as_bytes())?;
+            writer.shutdown().await?;
+            // Boxed, because the pager could be man again.
+            let result = Box::pin(programs::exec_program(&mut pager, &command)).await;
+            pager.stdin.shutdown().await?;
+            match result? {
+                Some(code) => Ok(code),
+                None => bail!("Cannot find pager: {command}"),
+            }
+        },
+    };
+    code
+}
+
+pub async fn man(process: &mut Process) -> Result<ExitCode> {
+    let options = Options::try_parse_from(process.args.iter())?;
+
+    if options.apropos {
+        return apropos(process, &options.names).await;
+    }
+
+    let mut names = options.names.as_slice();
+    let section = match names {
+        [section, rest @ ..] if !rest.is_empty() && section.chars().all(|c| c.is_ascii_digit()) => {
+            names = rest;
+            Some(section.as_str())
+        }
+        _ => None,
+    };
+
+    let terminal = process.stdout.to_terminal().await?;
+    let pages = pages(process, section)?;
+    let mut code = ExitCode::SUCCESS;
+    let mut text = String::new();
+    for name in names {
+        // Shell builtins don't have pages of their own, but are all described on one.
+        let page = pages.iter().find(|page| &page.name == name).or_else(|| {
+            shell_commands::is_builtin(name)
+                .then(|| pages.iter().find(|page| page.name == "builtins"))
+                .flatten()
+        });
+        let Some(page) = page else {
+            match section {
+                Some(section) => writeln!(
+                    process.stderr,
+                    "man: No entry for {name} in section {section}"
+                )?,
+                None => writeln!(process.stderr, "man: No manual entry for {name}")?,
+            }
+            code = ExitCode::FAILURE;
+            continue;
+        };
+        let source = page.source()?;
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        for line in render(&source, terminal, utils::get_screen_width())? {
+            text.push_str(&line);
+            text.push('\n');
+        }
+    }
+
+    if text.is_empty() {
+        return Ok(code);
+    }
+    if terminal {
+        let pager_code = page(process, &text).await?;
+        if code.is_success() {
+            code = pager_code;
+        }
+    } else {
+        process.stdout.write_all(text.as_bytes())?;
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PAGE: &str = r#".\" A comment
+.TH CAT 1
+.SH NAME
+cat \- concatenate files
+.SH SYNOPSIS
+.B cat
+[\fB\-n\fR]
+[\fIFILES\fR...]
+.SH OPTIONS
+.TP
+\fB\-n\fR
+Number lines.
+.TP
+\fB\-\-number\-nonblank\fR
+Number lines that aren't blank.
+.SH EXAMPLES
+.RS
+.nf
+cat a  b
+.fi
+.RE
+"#;
+
+    #[test]
+    fn rendering() {
+        assert_eq!(
+            render(PAGE, false, 40).unwrap(),
+            vec![
+                "CAT(1)                            CAT(1)",
+                "",
+                "NAME",
+                "       cat - concatenate files",
+                "",
+                "SYNOPSIS",
+                "       cat [-n] [FILES...]",
+                "",
+                "OPTIONS",
+                "       -n     Number lines.",
+                "",
+                "       --number-nonblank",
+                "              Number lines that aren't",
+                "              blank.",
+                "",
+                "EXAMPLES",
+                "              cat a  b",
+            ]
+        );
+    }
+
+    #[test]
+    fn styles() {
+        let lines = render(".SH NAME\n\\fBcat\\fR \\fIa b\\fP c", true, 80).unwrap();
+        assert_eq!(lines[0], "\x1b[1mNAME\x1b[0m");
+        assert_eq!(
+            lines[1],
+            "       \x1b[1mcat\x1b[0m \x1b[4ma\x1b[0m \x1b[4mb\x1b[0m c"
+        );
+    }
+
+    #[test]
+    fn macro_arguments() {
+        assert_eq!(split_arguments(r#"a "b c"  d"#), vec!["a", "b c", "d"]);
+        assert_eq!(
+            render(".BR cowsay (1) \", \" x", false, 80).unwrap(),
+            vec!["       cowsay(1), x"]
+        );
+    }
+
+    #[test]
+    fn program_pages() {
+        let page = program_page(programs::program_command("head").unwrap());
+        assert_eq!(summary(&page).as_deref(), Some("head - show first n lines"));
+        let lines = render(&page, false, 80).unwrap();
+        assert!(lines.contains(&"       head [-n N] [-h] [FILE]".to_string()));
+        assert!(lines.contains(&"       -n N   How many lines to show [default: 10]".to_string()));
+        assert!(lines.contains(&"       FILE   The file to show".to_string()));
+    }
+
+    #[test]
+    fn summaries() {
+        assert_eq!(summary(PAGE).as_deref(), Some("cat - concatenate files"));
+        assert_eq!(summary(".TH X 1"), None);
+    }
+}
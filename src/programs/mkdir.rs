@@ -4,7 +4,7 @@ use clap::Parser;
 
 /// Create directory.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// The directories to create.
     #[arg(required(true))]
     directories: Vec<String>,
@@ -49,6 +49,10 @@ macro_rules! implement {
         $(
             mod $cmd;
         )*
+
+        /// The names of all programs.
+        pub const PROGRAMS: &[&str] = &[$(stringify!($cmd)),*];
+
         pub async fn exec_program(process: &mut Process, command: &str) -> Result<Option<ExitCode>> {
             let result = $(
                 if command == stringify!($cmd) {
@@ -71,10 +75,23 @@ macro_rules! implement {
                 }
             })
         }
+
+        /// Get the command line interface of a program, as described by its options.
+        pub fn program_command(command: &str) -> Option<clap::Command> {
+            $(
+                if command == stringify!($cmd) {
+                    return Some(
+                        <$cmd::Options as clap::CommandFactory>::command().name(stringify!($cmd)),
+                    );
+                }
+            )*
+            None
+        }
     }
 }
 
 implement!(
-    cat, clear, cowsay, cp, echo, fortune, find, grep, head, ls, mkdir, mv, pwd, rev, rm, rmdir,
-    sed, sh, sort, sponge, tail, tee, test, theme, touch, vi, wc, which, whoami
+    cat, clear, cowsay, cp, echo, fortune, find, grep, head, less, ls, man, mkdir, mv, nano, pwd,
+    rev, rm, rmdir, sed, sh, sort, sponge, tail, tee, test, theme, touch, vi, wc, which, whoami,
+    xargs
 );
@@ -4,7 +4,7 @@ use clap::Parser;
 
 /// Move a file.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// The source file.
     src: String,
     /// The destination file or directory.
@@ -0,0 +1,833 @@
+use crate::{
+    process::{ExitCode, Process},
+    programs::common::{
+        color_picker::Color,
+        highlight,
+        keys::Key,
+        unicode::{self, next_grapheme, prev_grapheme, TAB_SIZE},
+    },
+    streams::{InputMode, InputStream, OutputStream},
+    utils, AnsiCode, ControlChar,
+};
+use anyhow::{anyhow, Result};
+use ascii::AsciiChar;
+use clap::Parser;
+use regex::Regex;
+use std::io::{Read, Write};
+
+/// The shortcuts listed at the bottom of the screen, two rows of them.
+const SHORTCUTS: [[(&str, &str); 6]; 2] = [
+    [
+        ("^G", "Help"),
+        ("^O", "Write Out"),
+        ("^W", "Where Is"),
+        ("^K", "Cut"),
+        ("^_", "Go To Line"),
+        ("M-U", "Undo"),
+    ],
+    [
+        ("^X", "Exit"),
+        ("^R", "Read File"),
+        ("M-W", "Find Next"),
+        ("^U", "Paste"),
+        ("M-6", "Copy"),
+        ("M-E", "Redo"),
+    ],
+];
+
+const HELP: &str = "\
+nano: a simple editor with no modes
+
+Type to insert text where the cursor is, and move it around with the arrow keys.
+Commands are typed holding Ctrl (^) or Alt (M-):
+
+^O          Write the file out, to its own name or another
+^X          Exit, asking to save changes first
+^W or ^F    Search for text, ignoring case
+M-W         Search for the same text again
+^K          Cut the current line; cutting several in a row keeps them together
+^U          Paste what was last cut or copied
+M-6         Copy the current line
+^R          Read a file in where the cursor is
+^_          Go to a line, and column if given as LINE,COLUMN
+M-U, M-E    Undo and redo
+^A, ^E      Go to the start or end of the line
+^Y, ^V      Go up or down a screenful
+M-\\, M-/    Go to the first or last line
+^D          Delete the character under the cursor
+^L          Draw the screen again
+
+Prompts are answered with Enter, or cancelled with Esc.";
+
+/// Simple text editor, with the commands it has listed at the bottom of the screen.
+///
+/// Type to insert text, and use the arrow keys to move around.
+/// Save: ^O
+/// Exit: ^X
+/// Search: ^W
+/// Cut and paste lines: ^K and ^U
+/// Help: ^G
+#[derive(Parser)]
+#[command(verbatim_doc_comment)]
+pub(super) struct Options {
+    /// The file to edit.
+    file: Option<String>,
+}
+
+/// A line and byte in the text.
+type Position = (usize, usize);
+
+/// What an edit to the text was, so a run of typing can be undone at once.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Edit {
+    Type,
+    Cut,
+    Copy,
+    Other,
+}
+
+/// The text being edited, and the cursor in it.
+struct Text {
+    lines: Vec<String>,
+    cursor: Position,
+    /// The column the cursor is kept to going up and down, across lines too short to reach it.
+    wanted: usize,
+    /// Lines cut or copied, each with its line break.
+    cut: String,
+    /// The last edit, if the last key made one.
+    last: Option<Edit>,
+    undo: Vec<(Vec<String>, Position)>,
+    redo: Vec<(Vec<String>, Position)>,
+}
+
+impl Text {
+    fn new(mut lines: Vec<String>) -> Self {
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        Self {
+            lines,
+            cursor: (0, 0),
+            wanted: 0,
+            cut: String::new(),
+            last: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Remember the text before an edit, unless it continues the last one.
+    fn begin(&mut self, edit: Edit) {
+        if self.last != Some(edit) || edit == Edit::Other {
+            self.undo.push((self.lines.clone(), self.cursor));
+            self.redo.clear();
+        }
+        self.last = Some(edit);
+    }
+
+    /// Move the cursor to another line, keeping to the column it was wanted in.
+    fn go_to_line(&mut self, row: usize) {
+        let row = std::cmp::min(row, self.lines.len() - 1);
+        self.cursor = (
+            row,
+            unicode::index_at(&self.lines[row], self.wanted, TAB_SIZE),
+        );
+    }
+
+    /// Insert text at the cursor, leaving the cursor after it.
+    fn insert(&mut self, text: &str) {
+        let (row, index) = self.cursor;
+        let rest = self.lines[row].split_off(index);
+        let mut pieces = text.split('\n');
+        self.lines[row].push_str(pieces.next().unwrap_or_default());
+        let mut row = row;
+        for piece in pieces {
+            row += 1;
+            self.lines.insert(row, piece.to_string());
+        }
+        let index = self.lines[row].len();
+        self.lines[row].push_str(&rest);
+        self.cursor = (row, index);
+    }
+
+    /// Handle a key that moves the cursor or edits the text, with `rows` of the text shown on
+    /// the screen. Returns false if it's some other command.
+    fn handle_key(&mut self, key: Key, rows: usize) -> bool {
+        let (row, index) = self.cursor;
+        let line = &self.lines[row];
+        let last = std::mem::take(&mut self.last);
+        let mut keep_column = false;
+        // Emacs keys for moving, as well as the arrows. ^F is left to search, since some
+        // browsers won't let ^W through.
+        let key = match key {
+            Key::Char(c) if c == ControlChar::B => Key::Left,
+            Key::Char(c) if c == ControlChar::P => Key::Up,
+            Key::Char(c) if c == ControlChar::N => Key::Down,
+            key => key,
+        };
+        match key {
+            Key::Left => {
+                self.cursor = if index > 0 {
+                    (row, prev_grapheme(line, index))
+                } else if row > 0 {
+                    (row - 1, self.lines[row - 1].len())
+                } else {
+                    self.cursor
+                };
+            }
+            Key::Right if index < line.len() => {
+                self.cursor = (row, next_grapheme(line, index));
+            }
+            Key::Right if row + 1 < self.lines.len() => {
+                self.cursor = (row + 1, 0);
+            }
+            Key::Right => {}
+            Key::Up => {
+                self.go_to_line(row.saturating_sub(1));
+                keep_column = true;
+            }
+            Key::Down => {
+                self.go_to_line(row + 1);
+                keep_column = true;
+            }
+            Key::Char(c) if c == ControlChar::Y => {
+                self.go_to_line(row.saturating_sub(rows.saturating_sub(2).max(1)));
+                keep_column = true;
+            }
+            Key::Char(c) if c == ControlChar::V => {
+                self.go_to_line(row + rows.saturating_sub(2).max(1));
+                keep_column = true;
+            }
+            Key::Char(c) if c == ControlChar::A => self.cursor.1 = 0,
+            Key::Char(c) if c == ControlChar::E => self.cursor.1 = line.len(),
+            Key::Meta('\\' | '|') => self.cursor = (0, 0),
+            Key::Meta('/' | '?') => {
+                let last = self.lines.len() - 1;
+                self.cursor = (last, self.lines[last].len());
+            }
+            Key::Char('\n' | '\r') => {
+                self.begin(Edit::Other);
+                self.insert("\n");
+            }
+            Key::Char(c) if c == AsciiChar::BackSpace || c == AsciiChar::DEL => {
+                if index > 0 || row > 0 {
+                    self.last = last;
+                    self.begin(Edit::Type);
+                    if index > 0 {
+                        let start = prev_grapheme(&self.lines[row], index);
+                        self.lines[row].replace_range(start..index, "");
+                        self.cursor.1 = start;
+                    } else {
+                        let line = self.lines.remove(row);
+                        self.cursor = (row - 1, self.lines[row - 1].len());
+                        self.lines[row - 1].push_str(&line);
+                    }
+                }
+            }
+            Key::Char(c) if c == ControlChar::D => {
+                let length = line.len();
+                if index < length || row + 1 < self.lines.len() {
+                    self.last = last;
+                    self.begin(Edit::Type);
+                    if index < length {
+                        let end = next_grapheme(&self.lines[row], index);
+                        self.lines[row].replace_range(index..end, "");
+                    } else {
+                        let next = self.lines.remove(row + 1);
+                        self.lines[row].push_str(&next);
+                    }
+                }
+            }
+            Key::Char(c) if c == ControlChar::K => {
+                self.last = last;
+                if self.last != Some(Edit::Cut) {
+                    self.cut.clear();
+                }
+                self.begin(Edit::Cut);
+                if self.lines.len() == 1 {
+                    let line = std::mem::take(&mut self.lines[0]);
+                    self.cut.push_str(&line);
+                } else {
+                    let line = self.lines.remove(row);
+                    self.cut.push_str(&line);
+                    self.cut.push('\n');
+                    self.go_to_line(row);
+                }
+                self.cursor.1 = 0;
+            }
+            Key::Meta('6' | '^') => {
+                if last != Some(Edit::Copy) {
+                    self.cut.clear();
+                }
+                self.last = Some(Edit::Copy);
+                self.cut.push_str(line);
+                self.cut.push('\n');
+                if row + 1 < self.lines.len() {
+                    self.cursor = (row + 1, 0);
+                }
+            }
+            Key::Char(c) if c == ControlChar::U => {
+                if !self.cut.is_empty() {
+                    self.begin(Edit::Other);
+                    let cut = self.cut.clone();
+                    self.insert(&cut);
+                }
+            }
+            Key::Meta('u' | 'U') => {
+                if let Some((lines, cursor)) = self.undo.pop() {
+                    self.redo
+                        .push((std::mem::replace(&mut self.lines, lines), self.cursor));
+                    self.cursor = cursor;
+                }
+            }
+            Key::Meta('e' | 'E') => {
+                if let Some((lines, cursor)) = self.redo.pop() {
+                    self.undo
+                        .push((std::mem::replace(&mut self.lines, lines), self.cursor));
+                    self.cursor = cursor;
+                }
+            }
+            Key::Char(c) if c == '\t' || !c.is_control() => {
+                self.last = last;
+                self.begin(Edit::Type);
+                self.lines[row].insert(index, c);
+                self.cursor.1 += c.len_utf8();
+            }
+            _ => {
+                self.last = last;
+                return false;
+            }
+        }
+        if !keep_column {
+            self.wanted = unicode::column(&self.lines[self.cursor.0], self.cursor.1, TAB_SIZE);
+        }
+        true
+    }
+
+    /// Find the next match of a pattern after the cursor, going back to the start if there
+    /// isn't one before the end. Returns where it is, and whether it wrapped around.
+    fn find(&self, regex: &Regex) -> Option<(Position, bool)> {
+        let (row, index) = self.cursor;
+        let after = next_grapheme(&self.lines[row], index);
+        let rows = self.lines.len();
+        for step in 0..=rows {
+            let current = (row + step) % rows;
+            let line = &self.lines[current];
+            let from = if step == 0 { after } else { 0 };
+            let found = regex
+                .find_at(line, std::cmp::min(from, line.len()))
+                .filter(|found| step < rows || found.start() <= index);
+            if let Some(found) = found {
+                let wrapped = step > 0 && current <= row;
+                return Some(((current, found.start()), wrapped));
+            }
+        }
+        None
+    }
+}
+
+/// The editor: the text, the file it's from, and how it's shown.
+struct Nano<'a> {
+    process: &'a Process,
+    stdin: InputStream,
+    stdout: OutputStream,
+    /// The bottom row of the screen.
+    height: usize,
+    width: usize,
+    file: Option<String>,
+    text: Text,
+    /// The lines as they were last saved, to tell if they've been changed.
+    saved: Vec<String>,
+    /// The first line shown.
+    offset: usize,
+    /// The last text searched for.
+    search: Option<String>,
+    /// A message to show above the shortcuts.
+    status: Option<String>,
+}
+
+impl Nano<'_> {
+    /// How many rows of text fit between the title bar and the status bar.
+    fn rows(&self) -> usize {
+        std::cmp::max(1, self.height.saturating_sub(3))
+    }
+
+    fn modified(&self) -> bool {
+        self.text.lines != self.saved
+    }
+
+    /// Write a row of the screen in reverse video.
+    fn write_reversed(&mut self, row: usize, text: &str) -> Result<()> {
+        self.stdout
+            .write_all(&AnsiCode::AbsolutePosition(row, 0).to_bytes())?;
+        self.stdout.write_all(&AnsiCode::ClearLine.to_bytes())?;
+        highlight::write_highlighted(&mut self.stdout, text, &[(0..text.len(), Color::Reverse)])?;
+        Ok(())
+    }
+
+    fn draw_title(&mut self) -> Result<()> {
+        let name = self.file.as_deref().unwrap_or("New Buffer");
+        let modified = if self.modified() { "Modified  " } else { "" };
+        let left = "  nano";
+        let name_width = unicode::width(name);
+        let start = std::cmp::max(left.len() + 1, self.width.saturating_sub(name_width) / 2);
+        let mut title = format!("{left}{}{name}", " ".repeat(start - left.len()));
+        let padding = self
+            .width
+            .saturating_sub(unicode::width(&title) + modified.len());
+        title.push_str(&" ".repeat(padding));
+        title.push_str(modified);
+        self.write_reversed(0, &title)
+    }
+
+    /// List shortcuts on the two bottom rows, with the keys highlighted.
+    fn draw_shortcuts(&mut self, shortcuts: &[&[(&str, &str)]]) -> Result<()> {
+        for (index, row) in shortcuts.iter().enumerate() {
+            let screen_row = self.height - 1 + index;
+            self.stdout
+                .write_all(&AnsiCode::AbsolutePosition(screen_row, 0).to_bytes())?;
+            self.stdout.write_all(&AnsiCode::ClearLine.to_bytes())?;
+            let width = std::cmp::max(1, self.width / 6);
+            for (key, label) in row.iter() {
+                let label: String = format!(" {label}")
+                    .chars()
+                    .take(width - key.len())
+                    .collect();
+                highlight::write_highlighted(
+                    &mut self.stdout,
+                    key,
+                    &[(0..key.len(), Color::Reverse)],
+                )?;
+                let padding = width.saturating_sub(key.len() + label.len());
+                self.stdout
+                    .write_all(format!("{label}{}", " ".repeat(padding)).as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Show a message in the status bar, which is above the shortcuts.
+    fn draw_status(&mut self) -> Result<()> {
+        let row = self.height - 2;
+        self.stdout
+            .write_all(&AnsiCode::AbsolutePosition(row, 0).to_bytes())?;
+        self.stdout.write_all(&AnsiCode::ClearLine.to_bytes())?;
+        if let Some(status) = self.status.take() {
+            let status = format!("[ {status} ]");
+            let start = self.width.saturating_sub(unicode::width(&status)) / 2;
+            self.stdout
+                .write_all(&AnsiCode::AbsolutePosition(row, start).to_bytes())?;
+            highlight::write_highlighted(
+                &mut self.stdout,
+                &status,
+                &[(0..status.len(), Color::Reverse)],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The first column shown of the cursor's line, which scrolls sideways to keep the cursor
+    /// on the screen.
+    fn line_start(&self) -> usize {
+        let (row, index) = self.text.cursor;
+        let column = unicode::column(&self.text.lines[row], index, TAB_SIZE);
+        if column + 1 < self.width {
+            0
+        } else {
+            let page = std::cmp::max(1, self.width.saturating_sub(8));
+            column - column % page
+        }
+    }
+
+    /// Draw everything, keeping the cursor's line on the screen.
+    fn draw(&mut self) -> Result<()> {
+        let (row, index) = self.text.cursor;
+        let rows = self.rows();
+        if row < self.offset {
+            self.offset = row;
+        } else if row >= self.offset + rows {
+            self.offset = row + 1 - rows;
+        }
+
+        self.stdout.write_all(&AnsiCode::Clear.to_bytes())?;
+        self.draw_title()?;
+        let start = self.line_start();
+        for screen_row in 0..rows {
+            let line = self.offset + screen_row;
+            let Some(text) = self.text.lines.get(line) else {
+                break;
+            };
+            let shown = unicode::visible(
+                text,
+                if line == row { start } else { 0 },
+                self.width,
+                TAB_SIZE,
+            );
+            self.stdout
+                .write_all(&AnsiCode::AbsolutePosition(screen_row + 1, 0).to_bytes())?;
+            self.stdout.write_all(shown.as_bytes())?;
+        }
+        self.draw_status()?;
+        self.draw_shortcuts(&[&SHORTCUTS[0], &SHORTCUTS[1]])?;
+
+        let column = unicode::column(&self.text.lines[row], index, TAB_SIZE) - start;
+        self.stdout
+            .write_all(&AnsiCode::AbsolutePosition(row - self.offset + 1, column).to_bytes())?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    /// Ask for a line of text in the status bar, starting with `text`. Returns `None` if the
+    /// question is cancelled.
+    async fn prompt(&mut self, question: &str, text: &str) -> Result<Option<String>> {
+        self.draw_shortcuts(&[&[("Enter", "Accept")], &[("Esc", "Cancel")]])?;
+        let mut text = text.to_string();
+        let mut cursor = text.len();
+        let row = self.height - 2;
+        loop {
+            let shown = format!("{question}: {text}");
+            self.write_reversed(row, &format!("{shown:<width$}", width = self.width))?;
+            let column = unicode::width(question) + 2 + unicode::width(&text[..cursor]);
+            self.stdout
+                .write_all(&AnsiCode::AbsolutePosition(row, column).to_bytes())?;
+            self.stdout.flush()?;
+
+            match Key::read(&mut self.stdin).await? {
+                Key::Char('\n' | '\r') => return Ok(Some(text)),
+                Key::Escape => return Ok(None),
+                Key::Left => cursor = prev_grapheme(&text, cursor),
+                Key::Right => cursor = next_grapheme(&text, cursor),
+                Key::Char(c) if c == ControlChar::A => cursor = 0,
+                Key::Char(c) if c == ControlChar::E => cursor = text.len(),
+                Key::Char(c) if c == AsciiChar::BackSpace || c == AsciiChar::DEL => {
+                    let start = prev_grapheme(&text, cursor);
+                    text.replace_range(start..cursor, "");
+                    cursor = start;
+                }
+                Key::Char(c) if !c.is_control() => {
+                    text.insert(cursor, c);
+                    cursor += c.len_utf8();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Ask a yes or no question. Returns `None` if it's cancelled.
+    async fn ask(&mut self, question: &str) -> Result<Option<bool>> {
+        self.draw_shortcuts(&[&[("Y", "Yes")], &[("N", "No"), ("Esc", "Cancel")]])?;
+        let row = self.height - 2;
+        self.write_reversed(row, &format!("{question:<width$}", width = self.width))?;
+        self.stdout
+            .write_all(&AnsiCode::AbsolutePosition(row, unicode::width(question)).to_bytes())?;
+        self.stdout.flush()?;
+        loop {
+            match Key::read(&mut self.stdin).await? {
+                Key::Char('y' | 'Y') => return Ok(Some(true)),
+                Key::Char('n' | 'N') => return Ok(Some(false)),
+                Key::Escape => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    /// Ask where to save the text, and save it there. Returns true if it was saved.
+    async fn write_out(&mut self) -> Result<bool> {
+        let name = self.file.clone().unwrap_or_default();
+        let Some(name) = self.prompt("File Name to Write", &name).await? else {
+            self.status = Some("Cancelled".into());
+            return Ok(false);
+        };
+        if name.is_empty() {
+            self.status = Some("Cancelled".into());
+            return Ok(false);
+        }
+        let contents = self.text.lines.join("\n") + "\n";
+        let written = self
+            .process
+            .get_path(&name)
+            .and_then(|path| Ok(path.create_file()?.write_all(contents.as_bytes())?));
+        if let Err(err) = written {
+            self.status = Some(format!("Error writing {name}: {err}"));
+            return Ok(false);
+        }
+        let count = self.text.lines.len();
+        self.status = Some(format!(
+            "Wrote {count} line{}",
+            if count == 1 { "" } else { "s" }
+        ));
+        self.file = Some(name);
+        self.saved = self.text.lines.clone();
+        Ok(true)
+    }
+
+    /// Search for text, after the cursor, ignoring case.
+    fn search(&mut self, pattern: &str) {
+        let regex = Regex::new(&format!("(?i){}", regex::escape(pattern)))
+            .expect("Escaped text is a valid pattern");
+        match self.text.find(&regex) {
+            Some((position, wrapped)) => {
+                if position == self.text.cursor {
+                    self.status = Some("This is the only occurrence".into());
+                } else if wrapped {
+                    self.status = Some("Search Wrapped".into());
+                }
+                self.text.cursor = position;
+                self.text.wanted =
+                    unicode::column(&self.text.lines[position.0], position.1, TAB_SIZE);
+            }
+            None => self.status = Some(format!("\"{pattern}\" not found")),
+        }
+    }
+
+    /// Go to a line, and column if it's given as `LINE,COLUMN`.
+    fn go_to(&mut self, place: &str) -> Result<()> {
+        let mut numbers = place.split([',', ' ']).filter(|part| !part.is_empty());
+        let mut number = |default: usize| -> Result<usize> {
+            match numbers.next() {
+                None => Ok(default),
+                Some(number) => (number.parse::<usize>().ok())
+                    .filter(|&number| number > 0)
+                    .ok_or_else(|| anyhow!("Invalid line or column number")),
+            }
+        };
+        let row = number(self.text.cursor.0 + 1)? - 1;
+        let column = number(1)? - 1;
+        self.text.wanted = column;
+        self.text.go_to_line(row);
+        Ok(())
+    }
+
+    /// Insert a file's contents at the cursor.
+    fn read_in(&mut self, file: &str) -> Result<()> {
+        let mut contents = String::new();
+        self.process
+            .get_path(file)?
+            .open_file()?
+            .read_to_string(&mut contents)?;
+        self.text.begin(Edit::Other);
+        self.text.insert(&contents);
+        let count = contents.lines().count();
+        self.status = Some(format!(
+            "Read {count} line{}",
+            if count == 1 { "" } else { "s" }
+        ));
+        Ok(())
+    }
+
+    /// Show the help text until a key is pressed.
+    async fn help(&mut self) -> Result<()> {
+        self.stdout.write_all(&AnsiCode::Clear.to_bytes())?;
+        self.stdout.write_all(HELP.as_bytes())?;
+        self.stdout.write_all(b"\n\nPress any key to go back")?;
+        self.stdout.flush()?;
+        Key::read(&mut self.stdin).await?;
+        Ok(())
+    }
+
+    /// Handle a command that isn't an edit. Returns true if the editor should exit.
+    async fn command(&mut self, key: Key) -> Result<bool> {
+        match key {
+            Key::Char(c) if c == ControlChar::X => {
+                if !self.modified() {
+                    return Ok(true);
+                }
+                match self.ask("Save modified buffer? ").await? {
+                    Some(true) => return self.write_out().await,
+                    Some(false) => return Ok(true),
+                    None => self.status = Some("Cancelled".into()),
+                }
+            }
+            Key::Char(c) if c == ControlChar::O => {
+                self.write_out().await?;
+            }
+            Key::Char(c) if c == ControlChar::W || c == ControlChar::F => {
+                let question = match &self.search {
+                    Some(last) => format!("Search [{last}]"),
+                    None => "Search".into(),
+                };
+                match self.prompt(&question, "").await? {
+                    Some(pattern) if !pattern.is_empty() => {
+                        self.search(&pattern);
+                        self.search = Some(pattern);
+                    }
+                    Some(_) if self.search.is_some() => {
+                        let pattern = self.search.clone().unwrap_or_default();
+                        self.search(&pattern);
+                    }
+                    _ => self.status = Some("Cancelled".into()),
+                }
+            }
+            Key::Meta('w' | 'W') => match self.search.clone() {
+                Some(pattern) => self.search(&pattern),
+                None => self.status = Some("No current search pattern".into()),
+            },
+            Key::Char(c) if c == AsciiChar::US => {
+                let question = "Enter line number, column number";
+                if let Some(place) = self.prompt(question, "").await? {
+                    if let Err(err) = self.go_to(&place) {
+                        self.status = Some(err.to_string());
+                    }
+                }
+            }
+            Key::Char(c) if c == ControlChar::R => {
+                if let Some(file) = self.prompt("File to insert", "").await? {
+                    if let Err(err) = self.read_in(&file) {
+                        self.status = Some(format!("{file}: {err}"));
+                    }
+                }
+            }
+            Key::Char(c) if c == ControlChar::G => self.help().await?,
+            _ => {}
+        }
+        Ok(false)
+    }
+}
+
+pub async fn nano(process: &Process) -> Result<ExitCode> {
+    let options = Options::try_parse_from(&process.args)?;
+
+    let mut lines = Vec::new();
+    let mut status = None;
+    if let Some(file) = &options.file {
+        let path = process.get_path(file)?;
+        if path.exists()? {
+            let mut contents = String::new();
+            path.open_file()?.read_to_string(&mut contents)?;
+            let contents = contents.strip_suffix('\n').unwrap_or(&contents);
+            if !contents.is_empty() {
+                lines = contents.split('\n').map(String::from).collect();
+            }
+            status = Some(format!("Read {} lines", lines.len()));
+        } else {
+            status = Some("New File".into());
+        }
+    }
+
+    let mut stdin = process.stdin.clone();
+    stdin.set_mode(InputMode::Char).await?;
+    let text = Text::new(lines);
+    let mut nano = Nano {
+        process,
+        stdin,
+        stdout: process.stdout.clone(),
+        height: std::cmp::max(4, utils::js_term_get_screen_height()),
+        width: std::cmp::max(12, utils::get_screen_width()),
+        file: options.file,
+        saved: text.lines.clone(),
+        text,
+        offset: 0,
+        search: None,
+        status,
+    };
+
+    loop {
+        nano.draw()?;
+        let key = Key::read(&mut nano.stdin).await?;
+        let rows = nano.rows();
+        if !nano.text.handle_key(key, rows) && nano.command(key).await? {
+            break;
+        }
+    }
+
+    nano.stdout.write_all(&AnsiCode::Clear.to_bytes())?;
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Type keys into some text, starting with the cursor at `cursor`.
+    fn run(text: &str, cursor: Position, keys: &[Key]) -> Text {
+        let mut text = Text::new(text.split('\n').map(String::from).collect());
+        text.cursor = cursor;
+        text.wanted = unicode::column(&text.lines[cursor.0], cursor.1, TAB_SIZE);
+        for &key in keys {
+            assert!(text.handle_key(key, 10), "{key:?} wasn't handled");
+        }
+        text
+    }
+
+    fn chars(text: &str) -> Vec<Key> {
+        text.chars().map(Key::Char).collect()
+    }
+
+    #[test]
+    fn typing() {
+        let text = run("", (0, 0), &chars("hi\nthere\x08\x08\x08\x08\x08\x08!"));
+        assert_eq!(text.lines, ["hi!"]);
+        let text = run(
+            "ab",
+            (0, 1),
+            &[Key::Char('🐮'), Key::Left, Key::Char('\x04')],
+        );
+        assert_eq!((text.lines[0].as_str(), text.cursor), ("ab", (0, 1)));
+        // Delete at the end of a line joins the next one onto it.
+        let text = run("a\nb", (0, 1), &[Key::Char('\x04')]);
+        assert_eq!(text.lines, ["ab"]);
+    }
+
+    #[test]
+    fn moving() {
+        let text = run("abcdef\nab\n\tx", (0, 4), &[Key::Down]);
+        assert_eq!(text.cursor, (1, 2));
+        // The column is kept across short lines, and tabs count as the columns they take up.
+        let text = run("abcdef\nab\n\tx", (0, 4), &[Key::Down, Key::Down]);
+        assert_eq!(text.cursor, (2, 0));
+        let text = run("abcdef\nab\n\tx", (2, 1), &[Key::Up, Key::Up]);
+        assert_eq!(text.cursor, (0, 6));
+        let text = run("ab\ncd", (0, 2), &[Key::Right]);
+        assert_eq!(text.cursor, (1, 0));
+        let text = run("ab\ncd", (1, 0), &[Key::Left]);
+        assert_eq!(text.cursor, (0, 2));
+        let text = run("ab\ncd", (0, 1), &[Key::Char('\x05'), Key::Meta('/')]);
+        assert_eq!(text.cursor, (1, 2));
+    }
+
+    #[test]
+    fn cut_and_paste() {
+        let ctrl_k = Key::Char('\x0b');
+        let ctrl_u = Key::Char('\x15');
+        // Lines cut together are pasted together.
+        let keys = [ctrl_k, ctrl_k, Key::Down, ctrl_u];
+        let text = run("one\ntwo\nthree\nfour", (0, 1), &keys);
+        assert_eq!(text.lines, ["three", "one", "two", "four"]);
+        // Moving in between starts a new cut.
+        let keys = [ctrl_k, Key::Down, ctrl_k, ctrl_u];
+        let text = run("one\ntwo\nthree\nfour", (0, 0), &keys);
+        assert_eq!(text.lines, ["two", "three", "four"]);
+        // Copying leaves the line where it was.
+        let text = run("one\ntwo", (0, 0), &[Key::Meta('6'), ctrl_u]);
+        assert_eq!(text.lines, ["one", "one", "two"]);
+        let text = run("only", (0, 2), &[ctrl_k]);
+        assert_eq!(text.lines, [""]);
+        assert_eq!(text.cut, "only");
+    }
+
+    #[test]
+    fn undo() {
+        let mut keys = chars("ab cd");
+        keys.push(Key::Meta('u'));
+        assert_eq!(run("", (0, 0), &keys).lines, [""]);
+        keys.push(Key::Meta('e'));
+        assert_eq!(run("", (0, 0), &keys).lines, ["ab cd"]);
+        let text = run("x\ny", (0, 0), &[Key::Char('\x0b'), Key::Meta('u')]);
+        assert_eq!(
+            (text.lines.join("\n"), text.cursor),
+            ("x\ny".into(), (0, 0))
+        );
+    }
+
+    #[test]
+    fn searching() {
+        let text = run("one two\nthree\nTwo", (0, 4), &[]);
+        let regex = Regex::new("(?i)two").unwrap();
+        assert_eq!(text.find(&regex), Some(((2, 0), false)));
+        let text = run("one two\nthree\nTwo", (2, 0), &[]);
+        assert_eq!(text.find(&regex), Some(((0, 4), true)));
+        let text = run("one two\nthree", (0, 4), &[]);
+        assert_eq!(text.find(&regex), Some(((0, 4), true)));
+        assert_eq!(text.find(&Regex::new("four").unwrap()), None);
+    }
+}
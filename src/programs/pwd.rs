@@ -8,7 +8,7 @@ use futures::io::AsyncWriteExt;
 
 /// Print the name of the current working directory
 #[derive(Parser)]
-struct Options {}
+pub(super) struct Options {}
 
 pub async fn pwd(process: &mut Process) -> Result<ExitCode> {
     let _options = Options::try_parse_from(process.args.iter())?;
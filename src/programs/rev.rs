@@ -8,7 +8,7 @@ use futures::{io::AsyncWriteExt, try_join};
 
 /// Reverse lines characterwise.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// The files to reverse.
     files: Vec<String>,
 }
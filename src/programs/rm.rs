@@ -4,7 +4,7 @@ use clap::Parser;
 
 /// Remove/unlink a file.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// Ignore nonexistent files.
     #[arg(short, long)]
     force: bool,
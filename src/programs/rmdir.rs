@@ -1,12 +1,12 @@
 use crate::process::{ExitCode, Process};
 use anyhow::{bail, Result};
-use clap::Parser;
+use clap::{Parser, ValueHint};
 
 /// Remove a directory if empty.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// The directories to remove.
-    #[arg(required(true))]
+    #[arg(required(true), value_hint = ValueHint::DirPath)]
     dirs: Vec<String>,
 }
 
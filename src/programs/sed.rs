@@ -1,56 +1,1285 @@
 use crate::{
     process::{ExitCode, Process},
-    streams::{file_redirect_in, InputStream, OutputStream},
+    programs::common::basic_regex,
+    streams::InputStream,
 };
-use anyhow::Result;
-use clap::Parser;
-use futures::try_join;
-use sedregex::ReplaceCommand;
-use std::io::Write;
+use anyhow::{anyhow, bail, Result};
+use clap::{Parser, ValueHint};
+use regex::{Regex, RegexBuilder};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{Read, Write},
+};
+use vfs::VfsPath;
 
 /// Stream edit by regex
+///
+/// Each line is put in the pattern space, edited by the script, then printed. Commands can be
+/// separated by newlines or semicolons, and each can have an address to only run on some lines:
+///
+///   N, $                 Line N, or the last line
+///   FIRST~STEP           Every STEPth line, starting at line FIRST
+///   /REGEX/              Lines matching a regular expression
+///   ADDR1,ADDR2          Lines from ADDR1 up to ADDR2
+///   ADDR!                Lines not matching the address
+///
+/// The commands are:
+///
+///   s/REGEX/TEXT/FLAGS   Replace a match, or with g, all of them; p prints after a change
+///                        and w FILE writes the result to a file
+///   y/ABC/XYZ/           Replace each character with the one in the same place
+///   p, P                 Print the pattern space, or its first line
+///   l [WIDTH]            Print the pattern space with special characters escaped
+///   d, D                 Delete the pattern space, or its first line, and start again
+///   n, N                 Replace the pattern space with the next line, or append it
+///   a TEXT, i TEXT       Print text after or before the line
+///   c TEXT               Print text instead of the line
+///   r FILE               Print a file's contents after the line
+///   w FILE               Write the pattern space to a file, where /dev/stdout and
+///                        /dev/stderr are the output and errors
+///   h, H                 Copy or append the pattern space to the hold space
+///   g, G                 Copy or append the hold space to the pattern space
+///   x                    Exchange the hold and pattern spaces
+///   =                    Print the line number
+///   :LABEL, b LABEL      Name a place in the script, and go to it
+///   t LABEL, T LABEL     Go to a label if a replacement was or wasn't made
+///   q, Q                 Quit, after or without printing the pattern space
+///   { COMMANDS }         Group commands under one address
+///
+/// Not supported are the e, F, R, W, v and z commands, the e and M flags of s, ADDR,+N and
+/// ADDR,~N ranges, and -z.
 #[derive(Parser)]
-struct Options {
-    /// The regex pattern to use.
-    pattern: String,
-    /// The files to filter.
-    files: Vec<String>,
+#[command(verbatim_doc_comment)]
+pub(super) struct Options {
+    /// Only print lines when told to.
+    #[arg(short = 'n', long, alias = "silent")]
+    quiet: bool,
+    /// Add the script to the commands to run.
+    #[arg(short = 'e', long = "expression", value_name = "SCRIPT")]
+    expressions: Vec<String>,
+    /// Add the script in the file to the commands to run.
+    #[arg(short = 'f', long = "file", value_name = "SCRIPT-FILE", value_hint = ValueHint::FilePath)]
+    script_files: Vec<String>,
+    /// Edit files in place, rather than printing the result. With a suffix, each file is first
+    /// copied to its name followed by the suffix.
+    #[arg(
+        short = 'i',
+        long,
+        value_name = "SUFFIX",
+        require_equals = true,
+        num_args = 0..=1
+    )]
+    in_place: Option<Option<String>>,
+    /// Treat files as separate, with line numbers and $ for each one, rather than as one stream.
+    #[arg(short, long)]
+    separate: bool,
+    /// Break the lines printed by l after N characters, or never if it's 0.
+    #[arg(short = 'l', long, value_name = "N", default_value_t = 70)]
+    line_length: usize,
+    /// Regular expressions are extended, where ( ), { }, |, + and ? are special.
+    #[arg(short = 'E', short_alias = 'r', long)]
+    regexp_extended: bool,
+    /// The script, unless -e or -f is given, and the files to edit. Standard input is edited if
+    /// there aren't any.
+    args: Vec<String>,
 }
 
-async fn sed_inner<'a>(
-    stream: &mut InputStream,
-    out: &mut OutputStream,
-    pattern: &ReplaceCommand<'a>,
-) -> Result<()> {
-    while let Ok(line) = stream.get_line().await {
-        let line = pattern.execute(&line);
-        out.write_all(line.as_bytes())?;
-        out.write_all(b"\n")?;
+/// Which lines a command runs on.
+enum Address {
+    Line(usize),
+    /// Every `step`th line from `first`, or just `first` if `step` is 0.
+    Step {
+        first: usize,
+        step: usize,
+    },
+    Last,
+    /// A regular expression, or if it's empty, the one used last.
+    Regex(Option<Regex>),
+}
+
+impl Address {
+    fn matches(
+        &self,
+        line_number: usize,
+        last: bool,
+        pattern: &str,
+        last_regex: &mut Option<Regex>,
+    ) -> Result<bool> {
+        Ok(match self {
+            Self::Line(number) => line_number == *number,
+            Self::Step { first, step } => {
+                line_number >= *first && (line_number - first).is_multiple_of(*step)
+            }
+            Self::Last => last,
+            Self::Regex(regex) => resolve(regex, last_regex)?.is_match(pattern),
+        })
+    }
+}
+
+enum Command {
+    /// A `{`, with the index of its `}`.
+    Block(usize),
+    BlockEnd,
+    Label,
+    Substitute {
+        regex: Option<Regex>,
+        replacement: String,
+        global: bool,
+        /// Which match to start replacing at, from 1.
+        occurrence: usize,
+        print: bool,
+        /// A file to write the result to after a replacement.
+        write: Option<String>,
+    },
+    Transliterate(HashMap<char, char>),
+    Print,
+    PrintFirst,
+    /// Print unambiguously, breaking lines at a width other than the default.
+    List(Option<usize>),
+    Delete,
+    DeleteFirst,
+    Next,
+    AppendNext,
+    Append(String),
+    Insert(String),
+    Change(String),
+    Read(String),
+    Write(String),
+    Hold,
+    HoldAppend,
+    Get,
+    GetAppend,
+    Exchange,
+    LineNumber,
+    /// Go to a label, or the end of the script if there isn't one. Unless `when` is `None`, only
+    /// if whether a replacement was made since the last line was read is `when`.
+    Branch {
+        label: String,
+        when: Option<bool>,
+    },
+    Quit {
+        print: bool,
+        code: u8,
+    },
+}
+
+/// A command and the lines it runs on.
+struct Instruction {
+    start: Option<Address>,
+    end: Option<Address>,
+    negated: bool,
+    /// Inside the range from `start` to `end`.
+    active: bool,
+    command: Command,
+}
+
+impl Instruction {
+    fn selected(
+        &mut self,
+        line_number: usize,
+        last: bool,
+        pattern: &str,
+        last_regex: &mut Option<Regex>,
+    ) -> Result<bool> {
+        let selected = match (&self.start, &self.end) {
+            (None, _) => true,
+            (Some(start), None) => start.matches(line_number, last, pattern, last_regex)?,
+            (Some(_), Some(end)) if self.active => {
+                // The end of a range is only checked on the lines after its start.
+                self.active = !match end {
+                    Address::Line(number) => line_number >= *number,
+                    end => end.matches(line_number, last, pattern, last_regex)?,
+                };
+                true
+            }
+            (Some(start), Some(end)) => {
+                if start.matches(line_number, last, pattern, last_regex)? {
+                    self.active = match end {
+                        Address::Line(number) => *number > line_number,
+                        Address::Last => !last,
+                        Address::Step { .. } | Address::Regex(_) => true,
+                    };
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+        Ok(selected != self.negated)
+    }
+}
+
+/// Use a regular expression, or the last one used if it's empty.
+fn resolve(regex: &Option<Regex>, last_regex: &mut Option<Regex>) -> Result<Regex> {
+    if let Some(regex) = regex {
+        *last_regex = Some(regex.clone());
+    }
+    last_regex
+        .clone()
+        .ok_or_else(|| anyhow!("no previous regular expression"))
+}
+
+/// Turn the replacement in an `s` command into the syntax the regex crate expands, where
+/// `&` is the match and `\1` to `\9` are groups.
+fn expand_replacement(replacement: &str) -> String {
+    let mut expanded = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => expanded.push_str("${0}"),
+            '$' => expanded.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(c @ '0'..='9') => expanded.push_str(&format!("${{{c}}}")),
+                Some('n') => expanded.push('\n'),
+                Some('t') => expanded.push('\t'),
+                Some('$') => expanded.push_str("$$"),
+                Some(c) => expanded.push(c),
+                None => expanded.push('\\'),
+            },
+            c => expanded.push(c),
+        }
     }
+    expanded
+}
 
+/// Replace matches of `regex` in `text`, starting at match number `occurrence`, returning
+/// `None` if nothing was replaced.
+fn substitute(
+    text: &str,
+    regex: &Regex,
+    replacement: &str,
+    global: bool,
+    occurrence: usize,
+) -> Option<String> {
+    let mut result = String::new();
+    let mut last_end = 0;
+    let mut replaced = false;
+    for (index, captures) in regex.captures_iter(text).enumerate() {
+        if index + 1 < occurrence {
+            continue;
+        }
+        let whole = captures.get(0).expect("Captures always have a whole match");
+        result.push_str(&text[last_end..whole.start()]);
+        captures.expand(replacement, &mut result);
+        last_end = whole.end();
+        replaced = true;
+        if !global {
+            break;
+        }
+    }
+    if !replaced {
+        return None;
+    }
+    result.push_str(&text[last_end..]);
+    Some(result)
+}
+
+/// How `l` shows the pattern space, with escapes for special characters and `$` at the end.
+/// Lines are broken with `\` to fit within `width`, unless it's 0 or 1.
+fn unambiguous(text: &str, width: usize) -> String {
+    let mut result = String::new();
+    let mut column = 0;
+    for c in text.chars() {
+        let escaped = match c {
+            '\\' => "\\\\".into(),
+            '\x07' => "\\a".into(),
+            '\x08' => "\\b".into(),
+            '\x0c' => "\\f".into(),
+            '\n' => "\\n".into(),
+            '\r' => "\\r".into(),
+            '\t' => "\\t".into(),
+            '\x0b' => "\\v".into(),
+            c if c.is_control() => c
+                .to_string()
+                .bytes()
+                .map(|byte| format!("\\{byte:03o}"))
+                .collect(),
+            c => c.to_string(),
+        };
+        let length = escaped.chars().count();
+        if width > 1 && column + length > width - 1 {
+            result.push_str("\\\n");
+            column = 0;
+        }
+        column += length;
+        result.push_str(&escaped);
+    }
+    result.push('$');
+    result
+}
+
+/// The contents of a file for `r`, without its last newline. Files that are empty or can't be
+/// read add nothing.
+fn read_file(cwd: &VfsPath, file: &str) -> Option<String> {
+    let mut contents = String::new();
+    cwd.join(file)
+        .ok()?
+        .open_file()
+        .ok()?
+        .read_to_string(&mut contents)
+        .ok()?;
+    if contents.is_empty() {
+        return None;
+    }
+    if contents.ends_with('\n') {
+        contents.pop();
+    }
+    Some(contents)
+}
+
+/// Write a line to a file opened for `w`, or to the output if it's /dev/stdout.
+fn write_line(
+    outputs: &mut HashMap<String, Box<dyn Write + Send>>,
+    out: &mut impl Write,
+    file: &str,
+    line: &str,
+) -> Result<()> {
+    match outputs.get_mut(file) {
+        Some(output) => writeln!(output, "{line}")?,
+        None => writeln!(out, "{line}")?,
+    }
     Ok(())
 }
 
+/// Parses a script into instructions.
+struct ScriptParser {
+    chars: Vec<char>,
+    position: usize,
+    extended: bool,
+}
+
+impl ScriptParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.position += 1;
+        c
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> anyhow::Error {
+        anyhow!("char {}: {message}", self.position)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.position += 1;
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        self.chars[start..self.position]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    /// Read up to an unescaped delimiter, which is consumed. Escaped delimiters are unescaped,
+    /// and other escapes are kept.
+    fn delimited(&mut self, delimiter: char) -> Result<String> {
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                None | Some('\n') => return Err(self.error(format!("unterminated `{delimiter}'"))),
+                Some(c) if c == delimiter => return Ok(text),
+                Some('\\') => match self.next() {
+                    Some(c) if c == delimiter => text.push(c),
+                    Some('\n') => text.push('\n'),
+                    Some(c) => {
+                        text.push('\\');
+                        text.push(c);
+                    }
+                    None => return Err(self.error(format!("unterminated `{delimiter}'"))),
+                },
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    fn regex(&mut self, pattern: &str, ignore_case: bool) -> Result<Option<Regex>> {
+        if pattern.is_empty() {
+            return Ok(None);
+        }
+        let pattern = if self.extended {
+            pattern.into()
+        } else {
+            basic_regex::to_extended(pattern)
+        };
+        Ok(Some(
+            RegexBuilder::new(&pattern)
+                .case_insensitive(ignore_case)
+                .dot_matches_new_line(true)
+                .build()?,
+        ))
+    }
+
+    fn address(&mut self) -> Result<Option<Address>> {
+        let delimiter = match self.peek() {
+            Some('0'..='9') => {
+                let number = self
+                    .number()
+                    .ok_or_else(|| self.error("invalid line number"))?;
+                if self.peek() != Some('~') {
+                    return Ok(Some(Address::Line(number)));
+                }
+                self.position += 1;
+                let step = self
+                    .number()
+                    .ok_or_else(|| self.error("expected a step after `~'"))?;
+                return Ok(Some(Address::Step {
+                    first: number,
+                    step,
+                }));
+            }
+            Some('$') => {
+                self.position += 1;
+                return Ok(Some(Address::Last));
+            }
+            Some('/') => '/',
+            Some('\\') => {
+                self.position += 1;
+                self.peek()
+                    .ok_or_else(|| self.error("expected a delimiter"))?
+            }
+            _ => return Ok(None),
+        };
+        self.position += 1;
+        let pattern = self.delimited(delimiter)?;
+        let ignore_case = matches!(self.peek(), Some('I'));
+        if ignore_case {
+            self.position += 1;
+        }
+        Ok(Some(Address::Regex(self.regex(&pattern, ignore_case)?)))
+    }
+
+    /// The text of `a`, `i` or `c`, either after `\` and a newline or on the same line.
+    fn text(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        if self.peek() == Some('\\') {
+            self.position += 1;
+            if self.peek() == Some('\n') {
+                self.position += 1;
+            }
+        }
+        let mut text = String::new();
+        if self.peek().is_none() {
+            return Err(self.error("expected text after `a', `c' or `i'"));
+        }
+        while let Some(c) = self.peek() {
+            if c == '\n' {
+                break;
+            }
+            self.position += 1;
+            if c == '\\' {
+                match self.next() {
+                    Some('t') => text.push('\t'),
+                    Some(c) => text.push(c),
+                    None => {}
+                }
+            } else {
+                text.push(c);
+            }
+        }
+        Ok(text)
+    }
+
+    /// A label, up to the end of the line or a semicolon.
+    fn label(&mut self) -> String {
+        self.skip_whitespace();
+        let start = self.position;
+        while !matches!(self.peek(), None | Some('\n' | ';')) {
+            self.position += 1;
+        }
+        self.chars[start..self.position]
+            .iter()
+            .collect::<String>()
+            .trim_end()
+            .into()
+    }
+
+    /// The file of `r` or `w`, which is the rest of the line.
+    fn file_name(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        let start = self.position;
+        while !matches!(self.peek(), None | Some('\n')) {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Err(self.error("missing filename in r/w commands"));
+        }
+        Ok(self.chars[start..self.position].iter().collect())
+    }
+
+    fn substitute(&mut self) -> Result<Command> {
+        let delimiter = self
+            .next()
+            .filter(|c| !matches!(c, '\n' | '\\'))
+            .ok_or_else(|| self.error("unterminated `s' command"))?;
+        let pattern = self.delimited(delimiter)?;
+        let replacement = expand_replacement(&self.delimited(delimiter)?);
+        let mut global = false;
+        let mut print = false;
+        let mut ignore_case = false;
+        let mut occurrence = 1;
+        let mut write = None;
+        loop {
+            match self.peek() {
+                Some('g') => global = true,
+                Some('p') => print = true,
+                Some('i' | 'I') => ignore_case = true,
+                Some('1'..='9') => {
+                    occurrence = self.number().unwrap_or(1);
+                    continue;
+                }
+                // The file takes the rest of the line, so comes last.
+                Some('w') => {
+                    self.position += 1;
+                    write = Some(self.file_name()?);
+                    break;
+                }
+                _ => break,
+            }
+            self.position += 1;
+        }
+        Ok(Command::Substitute {
+            regex: self.regex(&pattern, ignore_case)?,
+            replacement,
+            global,
+            occurrence,
+            print,
+            write,
+        })
+    }
+
+    fn transliterate(&mut self) -> Result<Command> {
+        let delimiter = self
+            .next()
+            .filter(|c| !matches!(c, '\n' | '\\'))
+            .ok_or_else(|| self.error("unterminated `y' command"))?;
+        let unescape = |text: String| -> Vec<char> {
+            let mut chars = Vec::new();
+            let mut iter = text.chars();
+            while let Some(c) = iter.next() {
+                chars.push(match (c, c == '\\') {
+                    (_, true) => match iter.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some(c) => c,
+                        None => '\\',
+                    },
+                    (c, false) => c,
+                });
+            }
+            chars
+        };
+        let from = unescape(self.delimited(delimiter)?);
+        let to = unescape(self.delimited(delimiter)?);
+        if from.len() != to.len() {
+            return Err(self.error("strings for `y' command are different lengths"));
+        }
+        Ok(Command::Transliterate(from.into_iter().zip(to).collect()))
+    }
+
+    fn command(&mut self) -> Result<Command> {
+        let c = self.next().ok_or_else(|| self.error("missing command"))?;
+        Ok(match c {
+            '{' => Command::Block(0),
+            '}' => Command::BlockEnd,
+            ':' => Command::Label,
+            's' => self.substitute()?,
+            'y' => self.transliterate()?,
+            'p' => Command::Print,
+            'P' => Command::PrintFirst,
+            'l' => {
+                self.skip_whitespace();
+                Command::List(self.number())
+            }
+            'd' => Command::Delete,
+            'D' => Command::DeleteFirst,
+            'n' => Command::Next,
+            'N' => Command::AppendNext,
+            'a' => Command::Append(self.text()?),
+            'i' => Command::Insert(self.text()?),
+            'c' => Command::Change(self.text()?),
+            'r' => Command::Read(self.file_name()?),
+            'w' => Command::Write(self.file_name()?),
+            'h' => Command::Hold,
+            'H' => Command::HoldAppend,
+            'g' => Command::Get,
+            'G' => Command::GetAppend,
+            'x' => Command::Exchange,
+            '=' => Command::LineNumber,
+            'b' | 't' | 'T' => Command::Branch {
+                label: self.label(),
+                when: match c {
+                    't' => Some(true),
+                    'T' => Some(false),
+                    _ => None,
+                },
+            },
+            'q' | 'Q' => {
+                self.skip_whitespace();
+                Command::Quit {
+                    print: c == 'q',
+                    code: match self.number() {
+                        Some(code) => u8::try_from(code)?,
+                        None => 0,
+                    },
+                }
+            }
+            c => return Err(self.error(format!("unknown command: `{c}'"))),
+        })
+    }
+
+    fn parse(mut self) -> Result<Script> {
+        let mut instructions: Vec<Instruction> = Vec::new();
+        let mut labels = HashMap::new();
+        let mut blocks = Vec::new();
+        loop {
+            while matches!(self.peek(), Some(' ' | '\t' | '\n' | ';')) {
+                self.position += 1;
+            }
+            match self.peek() {
+                None => break,
+                Some('#') => {
+                    while !matches!(self.next(), None | Some('\n')) {}
+                    continue;
+                }
+                _ => {}
+            }
+
+            let start = self.address()?;
+            let end = if start.is_some() && self.peek() == Some(',') {
+                self.position += 1;
+                self.skip_whitespace();
+                Some(
+                    self.address()?
+                        .ok_or_else(|| self.error("unexpected `,'"))?,
+                )
+            } else {
+                None
+            };
+            // Line 0 only makes sense as the start of a range ending at a regular expression,
+            // which can then end on the first line.
+            let from_zero = matches!(start, Some(Address::Line(0)));
+            if from_zero && !matches!(end, Some(Address::Regex(_))) {
+                return Err(self.error("invalid usage of line address 0"));
+            }
+            self.skip_whitespace();
+            let mut negated = false;
+            while self.peek() == Some('!') {
+                negated = true;
+                self.position += 1;
+                self.skip_whitespace();
+            }
+
+            let command = self.command()?;
+            let opens_block = matches!(command, Command::Block(_));
+            match &command {
+                Command::Block(_) => blocks.push(instructions.len()),
+                Command::BlockEnd => {
+                    if start.is_some() {
+                        return Err(self.error("} doesn't want any addresses"));
+                    }
+                    let block = blocks.pop().ok_or_else(|| self.error("unexpected `}'"))?;
+                    instructions[block].command = Command::Block(instructions.len());
+                }
+                Command::Label => {
+                    labels.insert(self.label(), instructions.len());
+                }
+                _ => {}
+            }
+            instructions.push(Instruction {
+                start,
+                end,
+                negated,
+                active: from_zero,
+                command,
+            });
+
+            self.skip_whitespace();
+            match self.peek() {
+                _ if opens_block => {}
+                None | Some('\n' | ';' | '}' | '#') => {}
+                Some(c) => return Err(self.error(format!("extra characters after command: `{c}'"))),
+            }
+        }
+        if !blocks.is_empty() {
+            return Err(self.error("unmatched `{'"));
+        }
+        for instruction in &instructions {
+            if let Command::Branch { label, .. } = &instruction.command {
+                if !label.is_empty() && !labels.contains_key(label) {
+                    bail!("can't find label for jump to `{label}'");
+                }
+            }
+        }
+        Ok(Script {
+            instructions,
+            labels,
+        })
+    }
+}
+
+/// A parsed script, with where each label is.
+struct Script {
+    instructions: Vec<Instruction>,
+    labels: HashMap<String, usize>,
+}
+
+/// Where lines are read from.
+enum Source {
+    Stream(InputStream),
+    Lines(VecDeque<String>),
+}
+
+/// Reads lines from each source in turn, a line ahead so it's known which is the last.
+struct Reader {
+    sources: VecDeque<Source>,
+    ahead: Option<String>,
+}
+
+impl Reader {
+    async fn new(sources: VecDeque<Source>) -> Self {
+        let mut reader = Self {
+            sources,
+            ahead: None,
+        };
+        reader.ahead = reader.read().await;
+        reader
+    }
+
+    async fn read(&mut self) -> Option<String> {
+        loop {
+            let line = match self.sources.front_mut()? {
+                Source::Stream(stream) => stream.get_line().await.ok(),
+                Source::Lines(lines) => lines.pop_front(),
+            };
+            if line.is_some() {
+                return line;
+            }
+            self.sources.pop_front();
+        }
+    }
+
+    async fn next_line(&mut self) -> Option<String> {
+        let line = self.ahead.take()?;
+        self.ahead = self.read().await;
+        Some(line)
+    }
+
+    fn is_last(&self) -> bool {
+        self.ahead.is_none()
+    }
+}
+
+/// Runs a script over lines, keeping the hold space and line numbers between inputs.
+struct Sed {
+    script: Script,
+    quiet: bool,
+    line_length: usize,
+    /// Where `r` reads files from.
+    cwd: VfsPath,
+    /// The files written by `w`, which are all opened before any lines are read.
+    outputs: HashMap<String, Box<dyn Write + Send>>,
+    hold: String,
+    line_number: usize,
+    last_regex: Option<Regex>,
+}
+
+impl Sed {
+    /// Start on a file of its own, with line numbers starting again and outside any range, except
+    /// ranges from line 0 which start inside.
+    fn start_file(&mut self) {
+        self.line_number = 0;
+        for instruction in &mut self.script.instructions {
+            instruction.active = matches!(instruction.start, Some(Address::Line(0)));
+        }
+    }
+
+    /// Edit every line from the reader, returning an exit code if the script quit.
+    async fn run(&mut self, reader: &mut Reader, out: &mut impl Write) -> Result<Option<u8>> {
+        let Self {
+            script,
+            quiet,
+            line_length,
+            cwd,
+            outputs,
+            hold,
+            line_number,
+            last_regex,
+        } = self;
+        let count = script.instructions.len();
+        let mut restart = None;
+        loop {
+            let mut pattern = match restart.take() {
+                Some(pattern) => pattern,
+                None => match reader.next_line().await {
+                    Some(line) => {
+                        *line_number += 1;
+                        line
+                    }
+                    None => return Ok(None),
+                },
+            };
+            let mut appended = Vec::new();
+            let mut substituted = false;
+            let mut print = !*quiet;
+            let mut quit = None;
+            let mut index = 0;
+            while let Some(instruction) = script.instructions.get_mut(index) {
+                index += 1;
+                if !instruction.selected(*line_number, reader.is_last(), &pattern, last_regex)? {
+                    if let Command::Block(end) = instruction.command {
+                        index = end + 1;
+                    }
+                    continue;
+                }
+                match &instruction.command {
+                    Command::Block(_) | Command::BlockEnd | Command::Label => {}
+                    Command::Substitute {
+                        regex,
+                        replacement,
+                        global,
+                        occurrence,
+                        print,
+                        write,
+                    } => {
+                        let regex = resolve(regex, last_regex)?;
+                        if let Some(result) =
+                            substitute(&pattern, &regex, replacement, *global, *occurrence)
+                        {
+                            pattern = result;
+                            substituted = true;
+                            if *print {
+                                writeln!(out, "{pattern}")?;
+                            }
+                            if let Some(file) = write {
+                                write_line(outputs, out, file, &pattern)?;
+                            }
+                        }
+                    }
+                    Command::Transliterate(map) => {
+                        pattern = pattern
+                            .chars()
+                            .map(|c| *map.get(&c).unwrap_or(&c))
+                            .collect();
+                    }
+                    Command::Print => writeln!(out, "{pattern}")?,
+                    Command::PrintFirst => {
+                        writeln!(out, "{}", pattern.split('\n').next().unwrap_or_default())?
+                    }
+                    Command::List(width) => {
+                        writeln!(out, "{}", unambiguous(&pattern, width.unwrap_or(*line_length)))?
+                    }
+                    Command::Delete => {
+                        print = false;
+                        break;
+                    }
+                    Command::DeleteFirst => {
+                        print = false;
+                        if let Some((_, rest)) = pattern.split_once('\n') {
+                            restart = Some(rest.to_string());
+                        }
+                        break;
+                    }
+                    Command::Next | Command::AppendNext if reader.is_last() => {
+                        // Without another line, finish as if the script had ended.
+                        quit = Some(0);
+                        break;
+                    }
+                    Command::Next => {
+                        if print {
+                            writeln!(out, "{pattern}")?;
+                        }
+                        for text in appended.drain(..) {
+                            writeln!(out, "{text}")?;
+                        }
+                        pattern = reader.next_line().await.unwrap_or_default();
+                        *line_number += 1;
+                    }
+                    Command::AppendNext => {
+                        pattern.push('\n');
+                        pattern.push_str(&reader.next_line().await.unwrap_or_default());
+                        *line_number += 1;
+                    }
+                    Command::Append(text) => appended.push(text.clone()),
+                    Command::Insert(text) => writeln!(out, "{text}")?,
+                    Command::Read(file) => appended.extend(read_file(cwd, file)),
+                    Command::Write(file) => write_line(outputs, out, file, &pattern)?,
+                    Command::Change(text) => {
+                        // Ranges are changed to one copy of the text, at their end.
+                        if instruction.negated || !instruction.active {
+                            writeln!(out, "{text}")?;
+                        }
+                        print = false;
+                        break;
+                    }
+                    Command::Hold => *hold = pattern.clone(),
+                    Command::HoldAppend => {
+                        hold.push('\n');
+                        hold.push_str(&pattern);
+                    }
+                    Command::Get => pattern = hold.clone(),
+                    Command::GetAppend => {
+                        pattern.push('\n');
+                        pattern.push_str(hold);
+                    }
+                    Command::Exchange => std::mem::swap(&mut pattern, hold),
+                    Command::LineNumber => writeln!(out, "{line_number}")?,
+                    Command::Branch { label, when } => {
+                        if when.is_none_or(|when| when == substituted) {
+                            substituted = false;
+                            index = if label.is_empty() {
+                                count
+                            } else {
+                                script.labels[label]
+                            };
+                        }
+                    }
+                    Command::Quit {
+                        print: printed,
+                        code,
+                    } => {
+                        print &= *printed;
+                        quit = Some(*code);
+                        break;
+                    }
+                }
+            }
+            if print {
+                writeln!(out, "{pattern}")?;
+            }
+            for text in appended {
+                writeln!(out, "{text}")?;
+            }
+            if quit.is_some() {
+                return Ok(quit);
+            }
+        }
+    }
+}
+
+/// The exit code after quitting, which is the one given to `q` unless that's 0 and there was an
+/// error before.
+fn exit_code(quit: u8, code: ExitCode) -> ExitCode {
+    if quit == 0 {
+        code
+    } else {
+        ExitCode::from(quit)
+    }
+}
+
 pub async fn sed(process: &mut Process) -> Result<ExitCode> {
-    let options = Options::try_parse_from(process.args.iter())?;
-    let pattern = ReplaceCommand::new(&options.pattern)?;
+    // The suffix can be given straight after -i, like -i.bak, as well as with an equals sign.
+    let args = process.args.iter().map(|arg| match arg.strip_prefix("-i") {
+        Some(suffix) if !suffix.is_empty() && !suffix.starts_with('=') => format!("-i={suffix}"),
+        _ => arg.clone(),
+    });
+    let mut options = Options::try_parse_from(args)?;
+
+    let mut script = options.expressions.join("\n");
+    for file in &options.script_files {
+        let mut contents = String::new();
+        process
+            .get_path(file)?
+            .open_file()
+            .map_err(|_| anyhow!("couldn't open file {file}: No such file or directory"))?
+            .read_to_string(&mut contents)?;
+        if !script.is_empty() {
+            script.push('\n');
+        }
+        script.push_str(&contents);
+    }
+    if options.expressions.is_empty() && options.script_files.is_empty() {
+        if options.args.is_empty() {
+            bail!("no script specified");
+        }
+        script = options.args.remove(0);
+    }
+    let parser = ScriptParser {
+        chars: script.chars().collect(),
+        position: 0,
+        extended: options.regexp_extended,
+    };
+    let mut sed = Sed {
+        script: parser.parse()?,
+        quiet: options.quiet,
+        line_length: options.line_length,
+        cwd: process.cwd.clone(),
+        outputs: HashMap::new(),
+        hold: String::new(),
+        line_number: 0,
+        last_regex: None,
+    };
+    for instruction in &sed.script.instructions {
+        let (Command::Write(file) | Command::Substitute {
+            write: Some(file), ..
+        }) = &instruction.command
+        else {
+            continue;
+        };
+        if file == "/dev/stdout" || sed.outputs.contains_key(file) {
+            continue;
+        }
+        let output: Box<dyn Write + Send> = if file == "/dev/stderr" {
+            Box::new(process.stderr.clone())
+        } else {
+            process
+                .get_path(file)?
+                .create_file()
+                .map_err(|_| anyhow!("couldn't open file {file}: No such file or directory"))?
+        };
+        sed.outputs.insert(file.clone(), output);
+    }
 
-    if options.files.is_empty() {
-        let mut stdin = process.stdin.clone();
-        sed_inner(&mut stdin, &mut process.stdout, &pattern).await?;
+    if options.in_place.is_some() && options.args.is_empty() {
+        bail!("no input files");
+    }
+    let files = if options.args.is_empty() {
+        vec!["-".to_string()]
     } else {
-        for file in options.files {
-            let fp = process.get_path(file)?.open_file()?;
-            let (mut fp, mut backend) = file_redirect_in(Box::new(fp));
-            try_join! {
-                async {
-                    sed_inner(&mut fp, &mut process.stdout, &pattern).await?;
-                    fp.shutdown().await?;
-                    Ok(())
-                },
-                backend.run()
-            }?;
+        options.args
+    };
+    let separate = options.separate || options.in_place.is_some();
+    let mut code = ExitCode::SUCCESS;
+    let mut sources = VecDeque::new();
+    for file in files {
+        let source = if file == "-" && options.in_place.is_none() {
+            Source::Stream(process.stdin.clone())
+        } else {
+            let path = process.get_path(&file)?;
+            let mut contents = String::new();
+            if path.is_dir()? {
+                writeln!(
+                    process.stderr,
+                    "sed: couldn't edit {file}: not a regular file"
+                )?;
+                code = ExitCode::from(2);
+                continue;
+            }
+            let Ok(mut opened) = path.open_file() else {
+                writeln!(
+                    process.stderr,
+                    "sed: can't read {file}: No such file or directory"
+                )?;
+                code = ExitCode::from(2);
+                continue;
+            };
+            opened.read_to_string(&mut contents)?;
+            if let Some(Some(suffix)) = &options.in_place {
+                process
+                    .get_path(format!("{file}{suffix}"))?
+                    .create_file()?
+                    .write_all(contents.as_bytes())?;
+            }
+            Source::Lines(contents.lines().map(String::from).collect())
+        };
+        if !separate {
+            sources.push_back(source);
+            continue;
+        }
+
+        sed.start_file();
+        let mut reader = Reader::new(VecDeque::from([source])).await;
+        let quit = if options.in_place.is_some() {
+            let mut edited = Vec::new();
+            let quit = sed.run(&mut reader, &mut edited).await?;
+            process.get_path(&file)?.create_file()?.write_all(&edited)?;
+            quit
+        } else {
+            sed.run(&mut reader, &mut process.stdout).await?
+        };
+        if let Some(quit) = quit {
+            return Ok(exit_code(quit, code));
+        }
+    }
+    if !separate {
+        let mut reader = Reader::new(sources).await;
+        if let Some(quit) = sed.run(&mut reader, &mut process.stdout).await? {
+            return Ok(exit_code(quit, code));
         }
     }
-    Ok(ExitCode::SUCCESS)
+    Ok(code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::programs::common::testing::{self, contents, file_system};
+    use futures::executor::block_on;
+    use vfs::MemoryFS;
+
+    fn run(script: &str, input: &str, quiet: bool) -> Result<String> {
+        let parser = ScriptParser {
+            chars: script.chars().collect(),
+            position: 0,
+            extended: false,
+        };
+        let mut sed = Sed {
+            script: parser.parse()?,
+            quiet,
+            line_length: 70,
+            cwd: MemoryFS::new().into(),
+            outputs: HashMap::new(),
+            hold: String::new(),
+            line_number: 0,
+            last_regex: None,
+        };
+        let lines = input.lines().map(String::from).collect();
+        let mut out = Vec::new();
+        block_on(async {
+            let mut reader = Reader::new(VecDeque::from([Source::Lines(lines)])).await;
+            sed.run(&mut reader, &mut out).await
+        })?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    const LINES: &str = "one\ntwo\nthree\nfour\nfive\n";
+
+    /// Run sed as a program, where `lines` holds `LINES` and `a1` and `b1` hold the start and
+    /// end of a range. Returns its output and the contents of each of `files` afterwards.
+    async fn run_program(args: &[&str], files: &[&str]) -> (String, Vec<String>) {
+        let cwd = file_system(&[
+            ("lines", LINES),
+            ("a1", "x\nstart\ny\n"),
+            ("b1", "p\nq\nend\nz\n"),
+        ]);
+        let (result, output) = testing::run(&cwd, args, "", sed).await;
+        result.unwrap();
+        let files = files
+            .iter()
+            .map(|file| contents(&cwd, file).unwrap_or_default())
+            .collect();
+        (output, files)
+    }
+
+    #[futures_test::test]
+    async fn programs() {
+        let (output, _) = run_program(&["sed", "-e", "1i\\", "-e", "zero", "lines"], &[]).await;
+        assert_eq!(output, format!("zero\n{LINES}"));
+        let (output, files) = run_program(&["sed", "-i", "2,$d", "lines"], &["lines"]).await;
+        assert_eq!((output.as_str(), files[0].as_str()), ("", "one\n"));
+        // A range left open at the end of one file doesn't carry on into the next.
+        let (_, files) =
+            run_program(&["sed", "-i", "/start/,/end/d", "a1", "b1"], &["a1", "b1"]).await;
+        assert_eq!(files, ["x\n", "p\nq\nend\nz\n"]);
+        let (output, _) = run_program(&["sed", "-n", "1p;$p", "a1", "b1"], &[]).await;
+        assert_eq!(output, "x\nz\n");
+        let (output, _) = run_program(&["sed", "-s", "-n", "1p;$p", "a1", "b1"], &[]).await;
+        assert_eq!(output, "x\ny\np\nz\n");
+    }
+
+    #[futures_test::test]
+    async fn backups() {
+        let (_, files) =
+            run_program(&["sed", "-i.bak", "s/o/0/", "lines"], &["lines.bak", "lines"]).await;
+        assert_eq!(files, [LINES, "0ne\ntw0\nthree\nf0ur\nfive\n"]);
+    }
+
+    #[futures_test::test]
+    async fn files() {
+        let script = ["-e", "/e/w out", "-e", "s/o/0/w changed", "-e", "2r a1", "-e", "$r none"];
+        let args = [&["sed"], &script[..], &["lines"]].concat();
+        let (output, files) = run_program(&args, &["out", "changed"]).await;
+        assert_eq!(output, "0ne\ntw0\nx\nstart\ny\nthree\nf0ur\nfive\n");
+        assert_eq!(files, ["one\nthree\nfive\n", "0ne\ntw0\nf0ur\n"]);
+    }
+
+    #[test]
+    fn substitution() {
+        assert_eq!(run("s/o/0/", "foo\n", false).unwrap(), "f0o\n");
+        assert_eq!(run("s/o/0/g", "foo\n", false).unwrap(), "f00\n");
+        assert_eq!(run("s/o/0/2", "fooo\n", false).unwrap(), "fo0o\n");
+        assert_eq!(run("s/o/0/2g", "fooo\n", false).unwrap(), "fo00\n");
+        assert_eq!(
+            run(r"s/\(.\)\(.\)/\2\1 [&]/", "ab\n", false).unwrap(),
+            "ba [ab]\n"
+        );
+        assert_eq!(run("s|/|\\||g", "/a/\n", false).unwrap(), "|a|\n");
+        assert_eq!(run("s/A/b/I", "a\n", false).unwrap(), "b\n");
+        assert_eq!(run("s/x/$1/", "x\n", false).unwrap(), "$1\n");
+        assert_eq!(run("/t/s//T/p", LINES, true).unwrap(), "Two\nThree\n");
+    }
+
+    #[test]
+    fn addresses() {
+        assert_eq!(run("2p", LINES, true).unwrap(), "two\n");
+        assert_eq!(run("$p", LINES, true).unwrap(), "five\n");
+        assert_eq!(run("2,4d", LINES, false).unwrap(), "one\nfive\n");
+        assert_eq!(
+            run("/two/,/four/!d", LINES, false).unwrap(),
+            "two\nthree\nfour\n"
+        );
+        assert_eq!(run("/f/,$p", LINES, true).unwrap(), "four\nfive\n");
+        assert_eq!(run("4,2p", LINES, true).unwrap(), "four\n");
+        assert_eq!(run("0,/o/p", LINES, true).unwrap(), "one\n");
+        assert_eq!(run("1,/o/p", LINES, true).unwrap(), "one\ntwo\n");
+        assert_eq!(run("0~2p", LINES, true).unwrap(), "two\nfour\n");
+        assert_eq!(run("2~3p", LINES, true).unwrap(), "two\nfive\n");
+        assert_eq!(run("3~0p", LINES, true).unwrap(), "three\n");
+        assert_eq!(
+            run("2,3{s/^/> /;p}", LINES, true).unwrap(),
+            "> two\n> three\n"
+        );
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            run("1a\\\nafter", "x\ny\n", false).unwrap(),
+            "x\nafter\ny\n"
+        );
+        assert_eq!(run("$i before", "x\ny\n", false).unwrap(), "x\nbefore\ny\n");
+        assert_eq!(
+            run("2,4c\\gone", LINES, false).unwrap(),
+            "one\ngone\nfive\n"
+        );
+        assert_eq!(run("y/abc/xyz/", "aabbcc\n", false).unwrap(), "xxyyzz\n");
+        assert_eq!(run("=", "x\ny\n", true).unwrap(), "1\n2\n");
+        assert_eq!(run("l", "a\tb\\\x01é\n", true).unwrap(), "a\\tb\\\\\\001é$\n");
+        assert_eq!(run("l 5", "abcdefg\n", true).unwrap(), "abcd\\\nefg$\n");
+        assert_eq!(run("w /dev/stdout", "x\n", false).unwrap(), "x\nx\n");
+        assert_eq!(run("3q", LINES, false).unwrap(), "one\ntwo\nthree\n");
+        assert_eq!(run("3Q", LINES, false).unwrap(), "one\ntwo\n");
+    }
+
+    #[test]
+    fn hold_space() {
+        // Reverse the lines.
+        assert_eq!(run("1!G;h;$!d", "a\nb\nc\n", false).unwrap(), "c\nb\na\n");
+        // Join the lines.
+        assert_eq!(
+            run(":a;N;$!ba;s/\\n/,/g", "a\nb\nc\n", false).unwrap(),
+            "a,b,c\n"
+        );
+        assert_eq!(run("n;d", LINES, false).unwrap(), "one\nthree\nfive\n");
+        assert_eq!(run("$!N;P;D", "a\nb\nc\n", false).unwrap(), "a\nb\nc\n");
+        assert_eq!(run("x", "a\nb\n", false).unwrap(), "\na\n");
+        assert_eq!(run("h;s/a/b/;H;x", "a\n", false).unwrap(), "a\nb\n");
+        // Double space.
+        assert_eq!(run("G", "a\nb\n", false).unwrap(), "a\n\nb\n\n");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(run("k", "", false).is_err());
+        assert!(run("s/a/b", "", false).is_err());
+        assert!(run("{p", "", false).is_err());
+        assert!(run("b nowhere", "", false).is_err());
+        assert!(run("y/ab/c/", "", false).is_err());
+        assert!(run("0d", "", false).is_err());
+        assert!(run("0,5p", "", false).is_err());
+        assert!(run("1i\\", "", false).is_err());
+        assert!(run("r", "", false).is_err());
+        assert!(run("s/a/b/w", "", false).is_err());
+        assert!(run("1~p", "", false).is_err());
+    }
 }
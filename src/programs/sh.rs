@@ -2,8 +2,10 @@ use crate::{
     filesystem,
     process::{ExitCode, Process},
     programs::common::{
+        completion::{self, CompletionSpec},
         extendable_iterator::ExtendableIterator,
-        readline::{FileBasedHistory, Readline},
+        highlight,
+        readline::{EditingMode, FileBasedHistory, Readline},
         shell_commands,
     },
     streams,
@@ -27,6 +29,8 @@ const HISTORY_FILE: &str = "/etc/.sh_history";
 #[derive(Default, Clone)]
 pub struct ShellContext {
     pub variables: HashMap<String, String>,
+    pub completions: HashMap<String, CompletionSpec>,
+    pub editing_mode: EditingMode,
     pub do_exit_with: Option<ExitCode>,
 }
 
@@ -415,6 +419,8 @@ fn dispatch<'a>(
                 let command = args[0].clone();
                 if command == "cd" {
                     shell_commands::cd(process, args).await
+                } else if command == "complete" {
+                    shell_commands::complete(ctx, process, args).await
                 } else if command == "env" {
                     shell_commands::env(process, args).await
                 } else if command == "exec" {
@@ -425,6 +431,8 @@ fn dispatch<'a>(
                     shell_commands::export(ctx, process, args).await
                 } else if command == "read" {
                     shell_commands::read(ctx, process, args).await
+                } else if command == "set" {
+                    shell_commands::set(ctx, process, args).await
                 } else if command == "source" || command == "." {
                     shell_commands::source(ctx, process, args).await
                 } else if command == "true" {
@@ -612,9 +620,23 @@ pub fn run_script<'a>(
     .boxed()
 }
 
+/// Check if a command can be run, in the same way `which` does.
+fn command_exists(cwd: &VfsPath, bin_paths: &[VfsPath], command: &str) -> bool {
+    let is_file =
+        |path: vfs::VfsResult<VfsPath>| path.and_then(|path| path.is_file()).unwrap_or(false);
+
+    if shell_commands::is_builtin(command) {
+        true
+    } else if command.contains('/') {
+        is_file(cwd.join(command.trim_end_matches('/')))
+    } else {
+        bin_paths.iter().any(|path| is_file(path.join(command)))
+    }
+}
+
 /// Unix shell.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// A command to run.
     #[arg(short, conflicts_with = "script")]
     command: Option<String>,
@@ -664,96 +686,27 @@ pub async fn sh(process: &mut Process) -> Result<ExitCode> {
     let readline_history = FileBasedHistory::new(process.get_path(HISTORY_FILE)?);
     let mut readline = Readline::new(readline_history);
 
-    let bin_paths: Result<Vec<VfsPath>> = process
-        .env
-        .get("PATH")
-        .ok_or_else(|| anyhow!("Could not get PATH variable"))?
-        .split(':')
-        .map(|path| process.get_path(path))
-        .collect();
-    let bin_paths = bin_paths?;
-
     loop {
-        let tab_completer = |section: String, start: usize| {
-            let word = &section[start..];
-            let words: Vec<&str> = section.split_whitespace().collect();
-            let mut suggestions = Vec::new();
-            let mut skip_path_completion = false;
-
-            // Commands occur at start of line, or after pipes
-            if (words.is_empty() || !section.ends_with(' '))
-                && (words.len() < 2 || ["|", "&&", "||", ";"].contains(&words[words.len() - 2]))
-            {
-                skip_path_completion = true;
-                // External(as in, not part of the shell) commands.
-                for path in bin_paths.clone() {
-                    for command in path.read_dir()? {
-                        let mut filename = command.filename();
-                        if command.is_file()? && filename.starts_with(word) {
-                            filename.push(' ');
-                            suggestions.push(filename);
-                        }
-                    }
-                }
+        readline.set_editing_mode(ctx.editing_mode);
+
+        // Highlight commands according to the current directory and PATH.
+        let cwd = process.cwd.clone();
+        let bin_paths: Vec<VfsPath> = process
+            .env
+            .get("PATH")
+            .map(|paths| paths.split(':'))
+            .into_iter()
+            .flatten()
+            .filter_map(|path| process.get_path(path).ok())
+            .collect();
+        readline.set_highlighter(move |line| {
+            highlight::highlight_shell(line, |command| command_exists(&cwd, &bin_paths, command))
+        });
 
-                // Shell commands
-                for command in shell_commands::COMMANDS {
-                    if command.starts_with(&section) {
-                        let mut command = String::from(command);
-                        command.push(' ');
-                        suggestions.push(command);
-                    }
-                }
-            }
-
-            if word.starts_with('/')
-                || word.starts_with("./")
-                || word.starts_with("../")
-                || word.starts_with("~/")
-            {
-                skip_path_completion = false;
-            }
-
-            // Path completion
-            if !skip_path_completion {
-                let (path_str, file) = if let Some(slash) = word.rfind('/') {
-                    (
-                        if slash == 0 { "/" } else { &word[0..slash] },
-                        &word[slash + 1..],
-                    )
-                } else {
-                    ("", word)
-                };
-
-                let Some(home) = process.env.get("HOME") else {
-                    bail!("No ${{HOME}} environmental variable");
-                };
-                let path = process.get_path(path_str.replace('~', home))?;
-
-                if path.exists()? {
-                    for entity in path.read_dir()? {
-                        let filename = entity.filename();
-                        if filename.starts_with(file) {
-                            let mut suggestion = if path_str.is_empty() {
-                                filename
-                            } else if path_str == "/" {
-                                format!("/{filename}")
-                            } else {
-                                format!("{path_str}/{filename}")
-                            };
-
-                            if entity.is_file()? {
-                                suggestion.push(' ');
-                            } else {
-                                suggestion.push('/');
-                            }
-                            suggestions.push(suggestion);
-                        }
-                    }
-                }
-            }
-
-            Ok(suggestions)
+        let tab_completer = |section: String, start: usize| {
+            let ctx = &ctx;
+            let process = &*process;
+            async move { completion::complete(ctx, process, &section, start).await }
         };
 
         // User-specified prompt
@@ -855,6 +808,7 @@ mod test {
         let stderr = stdout.clone();
         let cwd: VfsPath = MemoryFS::new().into();
         Process {
+            terminal: stdin.clone(),
             stdin,
             stderr,
             stdout,
@@ -1,58 +1,694 @@
 use crate::process::{ExitCode, Process};
-use anyhow::{bail, Result};
-use clap::Parser;
+use anyhow::{anyhow, bail, Result};
+use clap::{ArgAction, Parser, ValueHint};
 use futures::AsyncReadExt;
-use std::{collections::HashSet, io::Write};
+use std::{cmp::Ordering, io::Write};
 
 /// Sort files or stdin.
+///
+/// Lines are compared by keys given with -k, or if there aren't any, by the whole line. A key is
+/// FIELD[.CHAR][OPTIONS][,FIELD[.CHAR][OPTIONS]], the text from a character of one field to a
+/// character of another (the end of the field if CHAR is 0 or missing, or the end of the line if
+/// there's no second field). OPTIONS are any of b, f, g, h, n, r and V, which work like the
+/// global options of the same names but only for that key.
+///
+/// Fields are separated by the -t character, or else each begins with the blanks before it.
+/// Lines with equal keys are compared by all their text, unless -s or -u is given.
 #[derive(Parser)]
-struct Options {
+#[command(disable_help_flag = true)]
+pub(super) struct Options {
+    /// Ignore blanks at the start of keys.
+    #[arg(short = 'b', long)]
+    ignore_leading_blanks: bool,
+    /// Compare letters regardless of case.
+    #[arg(short = 'f', long)]
+    ignore_case: bool,
+    /// Compare numbers, like -12.5.
+    #[arg(short = 'n', long)]
+    numeric_sort: bool,
+    /// Compare floating point numbers, like 1.5e3 or inf.
+    #[arg(short = 'g', long)]
+    general_numeric_sort: bool,
+    /// Compare human readable sizes, like 2K or 1G.
+    #[arg(short = 'h', long)]
+    human_numeric_sort: bool,
+    /// Compare version numbers, like 1.10.2.
+    #[arg(short = 'V', long)]
+    version_sort: bool,
     /// Sort in reverse order.
     #[arg(short, long)]
     reverse: bool,
-    /// Remove repeated lines.
+    /// Only print the first of lines with equal keys.
     #[arg(short, long)]
     unique: bool,
+    /// Sort by a key, which can be given more than once.
+    #[arg(short = 'k', long = "key", value_name = "KEYDEF")]
+    keys: Vec<String>,
+    /// Separate fields with this character, rather than blanks.
+    #[arg(short = 't', long, value_name = "SEP")]
+    field_separator: Option<char>,
+    /// Keep lines with equal keys in the order they were read.
+    #[arg(short, long)]
+    stable: bool,
+    /// Check the input is sorted rather than sorting it, printing the first line that isn't.
+    #[arg(short, long)]
+    check: bool,
+    /// Like -c, but don't print anything.
+    #[arg(short = 'C')]
+    check_quietly: bool,
+    /// Write to the file rather than stdout, which can be one of those being sorted.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    output: Option<String>,
+    /// Lines end with a NUL character, rather than a newline.
+    #[arg(short, long)]
+    zero_terminated: bool,
+    /// Print help.
+    #[arg(long, action = ArgAction::Help)]
+    help: Option<bool>,
     /// The files to concatenate and sort.
     files: Vec<String>,
 }
 
+/// What keys are compared as.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Kind {
+    #[default]
+    Text,
+    Numeric,
+    General,
+    Human,
+    Version,
+}
+
+/// How a key is compared.
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Modifiers {
+    kind: Kind,
+    ignore_case: bool,
+    reverse: bool,
+    /// Skip blanks at the start of the first field.
+    blanks_start: bool,
+    /// Skip blanks at the start of the last field.
+    blanks_end: bool,
+}
+
+/// The part of a line to compare.
+struct Key {
+    /// The field and character the key starts at, from 1.
+    field: usize,
+    char: usize,
+    /// The field the key ends in, or `None` for the end of the line.
+    end_field: Option<usize>,
+    /// The character the key ends at, or 0 for the end of the field.
+    end_char: usize,
+    modifiers: Modifiers,
+}
+
+impl Key {
+    /// The key for a whole line.
+    fn line(modifiers: Modifiers) -> Self {
+        Self {
+            field: 1,
+            char: 1,
+            end_field: None,
+            end_char: 0,
+            modifiers,
+        }
+    }
+
+    /// Parse a key like `2,2n` or `1.3b,1.4`, using `global` unless it has options of its own.
+    fn parse(definition: &str, global: Modifiers) -> Result<Self> {
+        let invalid = || anyhow!("invalid key: '{definition}'");
+        let (start, end) = match definition.split_once(',') {
+            Some((start, end)) => (start, Some(end)),
+            None => (definition, None),
+        };
+        let mut modifiers = Modifiers::default();
+        let mut position = |text: &str, end: bool| -> Result<(usize, Option<usize>)> {
+            let options = text.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+            let (field, char) = match text[..text.len() - options.len()].split_once('.') {
+                Some((field, char)) => (field.parse()?, Some(char.parse()?)),
+                None => (text[..text.len() - options.len()].parse()?, None),
+            };
+            for option in options.chars() {
+                match option {
+                    'b' if end => modifiers.blanks_end = true,
+                    'b' => modifiers.blanks_start = true,
+                    'f' => modifiers.ignore_case = true,
+                    'r' => modifiers.reverse = true,
+                    'n' => modifiers.kind = Kind::Numeric,
+                    'g' => modifiers.kind = Kind::General,
+                    'h' => modifiers.kind = Kind::Human,
+                    'V' => modifiers.kind = Kind::Version,
+                    _ => return Err(invalid()),
+                }
+            }
+            Ok((field, char))
+        };
+
+        let (field, char) = position(start, false).map_err(|_| invalid())?;
+        let (end_field, end_char) = match end {
+            Some(end) => {
+                let (field, char) = position(end, true).map_err(|_| invalid())?;
+                (Some(field), char.unwrap_or(0))
+            }
+            None => (None, 0),
+        };
+        if field == 0 || end_field == Some(0) {
+            bail!("invalid key: '{definition}': fields are numbered from 1");
+        }
+        if char == Some(0) {
+            bail!("invalid key: '{definition}': character offset is zero");
+        }
+        Ok(Self {
+            field,
+            char: char.unwrap_or(1),
+            end_field,
+            end_char,
+            modifiers: if modifiers == Modifiers::default() {
+                global
+            } else {
+                modifiers
+            },
+        })
+    }
+
+    /// Find the key in a line.
+    fn extract<'a>(&self, line: &'a str, separator: Option<char>) -> &'a str {
+        let fields = fields(line, separator);
+        let start = match fields.get(self.field - 1) {
+            Some(field) => offset(line, *field, self.modifiers.blanks_start, self.char - 1),
+            None => line.len(),
+        };
+        let end = match self
+            .end_field
+            .and_then(|end_field| fields.get(end_field - 1))
+        {
+            Some(field) if self.end_char == 0 => field.1,
+            Some(field) => offset(line, *field, self.modifiers.blanks_end, self.end_char),
+            None => line.len(),
+        };
+        &line[start..end.max(start)]
+    }
+
+    fn compare(&self, a: &str, b: &str, separator: Option<char>) -> Ordering {
+        let a = self.extract(a, separator);
+        let b = self.extract(b, separator);
+        let ordering = match self.modifiers.kind {
+            Kind::Text if self.modifiers.ignore_case => a
+                .chars()
+                .flat_map(char::to_uppercase)
+                .cmp(b.chars().flat_map(char::to_uppercase)),
+            Kind::Text => a.cmp(b),
+            Kind::Numeric => compare_numbers(a, b),
+            Kind::General => general_number(a).cmp(&general_number(b)),
+            Kind::Human => compare_human(a, b),
+            Kind::Version => compare_versions(a, b),
+        };
+        if self.modifiers.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// Where each field of a line is. Without a separator, fields include the blanks before them.
+fn fields(line: &str, separator: Option<char>) -> Vec<(usize, usize)> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    let mut in_field = false;
+    for (index, c) in line.char_indices() {
+        match separator {
+            Some(separator) if c == separator => {
+                fields.push((start, index));
+                start = index + c.len_utf8();
+            }
+            Some(_) => {}
+            None if is_blank(c) && in_field => {
+                fields.push((start, index));
+                start = index;
+                in_field = false;
+            }
+            None => in_field |= !is_blank(c),
+        }
+    }
+    fields.push((start, line.len()));
+    fields
+}
+
+/// Where the text `chars` characters into a field starts, optionally skipping blanks first.
+fn offset(line: &str, (start, end): (usize, usize), skip_blanks: bool, chars: usize) -> usize {
+    let mut field = &line[start..end];
+    if skip_blanks {
+        field = field.trim_start_matches(is_blank);
+    }
+    let skipped = end - start - field.len();
+    start
+        + skipped
+        + field
+            .char_indices()
+            .nth(chars)
+            .map_or(field.len(), |(index, _)| index)
+}
+
+/// Split the number at the start of some text into whether it's negative, its integer digits
+/// without leading zeros, its fraction digits without trailing zeros, and the rest of the text.
+fn parse_number(text: &str) -> (bool, &str, &str, &str) {
+    let text = text.trim_start_matches(is_blank);
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let integer_end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (integer, mut rest) = text.split_at(integer_end);
+    let mut fraction = "";
+    if let Some(after_point) = rest.strip_prefix('.') {
+        let fraction_end = after_point
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(after_point.len());
+        (fraction, rest) = after_point.split_at(fraction_end);
+    }
+    let integer = integer.trim_start_matches('0');
+    let fraction = fraction.trim_end_matches('0');
+    // There's no such thing as negative zero.
+    let negative = negative && !(integer.is_empty() && fraction.is_empty());
+    (negative, integer, fraction, rest)
+}
+
+/// Compare the numbers at the start of two strings, as strings so they can be any length.
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let (a_negative, a_integer, a_fraction, _) = parse_number(a);
+    let (b_negative, b_integer, b_fraction, _) = parse_number(b);
+    let ordering = a_integer
+        .len()
+        .cmp(&b_integer.len())
+        .then(a_integer.cmp(b_integer))
+        .then(a_fraction.cmp(b_fraction));
+    match (a_negative, b_negative) {
+        (false, true) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+        (true, true) => ordering.reverse(),
+        (false, false) => ordering,
+    }
+}
+
+/// Compare sizes like `2K` and `1G`, by sign, then suffix, then number.
+fn compare_human(a: &str, b: &str) -> Ordering {
+    const SUFFIXES: &str = "KMGTPEZY";
+    let sign = |text: &str| -> (i8, Option<usize>) {
+        let (negative, integer, fraction, rest) = parse_number(text);
+        let sign = match (negative, integer.is_empty() && fraction.is_empty()) {
+            (true, _) => -1,
+            (false, true) => 0,
+            (false, false) => 1,
+        };
+        let suffix = rest
+            .chars()
+            .next()
+            .and_then(|c| SUFFIXES.find(c.to_ascii_uppercase()));
+        (sign, suffix)
+    };
+    let (a_sign, a_suffix) = sign(a);
+    let (b_sign, b_suffix) = sign(b);
+    let suffixes = if a_sign < 0 {
+        b_suffix.cmp(&a_suffix)
+    } else {
+        a_suffix.cmp(&b_suffix)
+    };
+    a_sign
+        .cmp(&b_sign)
+        .then(suffixes)
+        .then_with(|| compare_numbers(a, b))
+}
+
+/// A floating point number at the start of some text, which sorts lines without numbers first,
+/// then NaN, then the numbers in order.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum GeneralNumber {
+    None,
+    NaN,
+    Number(Float),
+}
+
+/// A float that's never NaN, so it can be ordered.
+#[derive(PartialEq)]
+struct Float(f64);
+
+impl Eq for Float {}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Float {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+fn general_number(text: &str) -> GeneralNumber {
+    let text = text.trim_start_matches(is_blank);
+    // The longest prefix that's a number.
+    let candidate_end = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-')))
+        .unwrap_or(text.len());
+    let candidate = &text[..candidate_end];
+    for end in (1..=candidate.len()).rev() {
+        if let Ok(number) = candidate[..end].parse::<f64>() {
+            return if number.is_nan() {
+                GeneralNumber::NaN
+            } else {
+                GeneralNumber::Number(Float(number))
+            };
+        }
+    }
+    GeneralNumber::None
+}
+
+/// Compare the text between the numbers in versions, where letters come before other
+/// characters, and `~` before even the end of the text, so `1.0~rc1` comes before `1.0`.
+fn compare_version_text(a: &str, b: &str) -> Ordering {
+    fn order(c: Option<char>) -> i64 {
+        match c {
+            None => 0,
+            Some('~') => -1,
+            Some(c) if c.is_ascii_alphabetic() => c as i64,
+            Some(c) => c as i64 + 0x110000,
+        }
+    }
+    let (mut a, mut b) = (a.chars(), b.chars());
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (a, b) => match order(a).cmp(&order(b)) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            },
+        }
+    }
+}
+
+/// Compare version numbers, where runs of digits are compared as numbers and the text between
+/// them as text.
+fn compare_versions(mut a: &str, mut b: &str) -> Ordering {
+    fn split(text: &str, digits: bool) -> (&str, &str) {
+        let end = text
+            .find(|c: char| c.is_ascii_digit() != digits)
+            .unwrap_or(text.len());
+        text.split_at(end)
+    }
+    while !a.is_empty() || !b.is_empty() {
+        let (a_text, a_rest) = split(a, false);
+        let (b_text, b_rest) = split(b, false);
+        let (a_digits, a_rest) = split(a_rest, true);
+        let (b_digits, b_rest) = split(b_rest, true);
+        let a_digits = a_digits.trim_start_matches('0');
+        let b_digits = b_digits.trim_start_matches('0');
+        let ordering = compare_version_text(a_text, b_text).then(
+            a_digits
+                .len()
+                .cmp(&b_digits.len())
+                .then(a_digits.cmp(b_digits)),
+        );
+        if ordering.is_ne() {
+            return ordering;
+        }
+        (a, b) = (a_rest, b_rest);
+    }
+    Ordering::Equal
+}
+
+/// Compares lines by their keys.
+struct Sorter {
+    keys: Vec<Key>,
+    separator: Option<char>,
+    /// Compare lines with equal keys by their whole text.
+    last_resort: bool,
+    reverse: bool,
+}
+
+impl Sorter {
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        let ordering = self
+            .keys
+            .iter()
+            .map(|key| key.compare(a, b, self.separator))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal);
+        if ordering.is_eq() && self.last_resort {
+            if self.reverse {
+                b.cmp(a)
+            } else {
+                a.cmp(b)
+            }
+        } else {
+            ordering
+        }
+    }
+}
+
 pub async fn sort(process: &mut Process) -> Result<ExitCode> {
-    let options = Options::try_parse_from(process.args.iter())?;
-    let mut contents = String::new();
+    let mut options = Options::try_parse_from(process.args.iter())?;
 
+    let global = Modifiers {
+        kind: match (
+            options.numeric_sort,
+            options.general_numeric_sort,
+            options.human_numeric_sort,
+            options.version_sort,
+        ) {
+            (true, false, false, false) => Kind::Numeric,
+            (false, true, false, false) => Kind::General,
+            (false, false, true, false) => Kind::Human,
+            (false, false, false, true) => Kind::Version,
+            (false, false, false, false) => Kind::Text,
+            _ => bail!("options -ghnV are incompatible"),
+        },
+        ignore_case: options.ignore_case,
+        reverse: options.reverse,
+        blanks_start: options.ignore_leading_blanks,
+        blanks_end: options.ignore_leading_blanks,
+    };
+    let mut keys = options
+        .keys
+        .iter()
+        .map(|key| Key::parse(key, global))
+        .collect::<Result<Vec<_>>>()?;
+    if keys.is_empty() {
+        keys.push(Key::line(global));
+    }
+    let sorter = Sorter {
+        keys,
+        separator: options.field_separator,
+        last_resort: !options.stable && !options.unique,
+        reverse: options.reverse,
+    };
+
+    let check = options.check || options.check_quietly;
+    if check && options.files.len() > 1 {
+        bail!("extra operand '{}' not allowed with -c", options.files[1]);
+    }
     if options.files.is_empty() {
-        process.stdin.read_to_string(&mut contents).await?;
+        options.files.push("-".into());
     }
 
-    for arg in options.files.into_iter() {
-        let path = process.get_path(arg)?;
-        if !path.exists()? {
-            bail!("No such file {}", path.as_str());
+    let mut contents = String::new();
+    for arg in options.files.iter() {
+        if arg == "-" {
+            process.stdin.read_to_string(&mut contents).await?;
+        } else {
+            let path = process.get_path(arg)?;
+            if !path.exists()? {
+                bail!("No such file {}", path.as_str());
+            }
+            if !path.is_file()? {
+                bail!("{} is not a file", path.as_str());
+            }
+            let mut file = path.open_file()?;
+            file.read_to_string(&mut contents)?;
         }
-        if !path.is_file()? {
-            bail!("{} is not a file", path.as_str());
+        // So the last line of one file isn't joined to the first of the next.
+        let terminator = if options.zero_terminated { '\0' } else { '\n' };
+        if !contents.is_empty() && !contents.ends_with(terminator) {
+            contents.push(terminator);
         }
-        let mut file = path.open_file()?;
-        file.read_to_string(&mut contents)?;
     }
+    let mut lines: Vec<&str> = if options.zero_terminated {
+        contents.split_terminator('\0').collect()
+    } else {
+        contents.lines().collect()
+    };
 
-    let mut lines: Vec<&str> = contents.lines().collect();
-    if options.unique {
-        let set = lines.into_iter().collect::<HashSet<_>>();
-        lines = set.into_iter().collect();
+    if check {
+        for (index, pair) in lines.windows(2).enumerate() {
+            let ordering = sorter.compare(pair[0], pair[1]);
+            if ordering.is_gt() || (options.unique && ordering.is_eq()) {
+                if !options.check_quietly {
+                    writeln!(
+                        process.stderr,
+                        "sort: {}:{}: disorder: {}",
+                        options.files[0],
+                        index + 2,
+                        pair[1]
+                    )?;
+                }
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
     }
 
-    lines.sort();
-
-    if options.reverse {
-        lines = lines.into_iter().rev().collect();
+    lines.sort_by(|a, b| sorter.compare(a, b));
+    if options.unique {
+        lines.dedup_by(|a, b| sorter.compare(a, b).is_eq());
     }
 
+    let terminator = if options.zero_terminated { "\0" } else { "\n" };
+    let mut output = String::new();
     for line in lines {
-        process.stdout.write_all(line.as_bytes())?;
-        process.stdout.write_all(b"\n")?;
+        output.push_str(line);
+        output.push_str(terminator);
+    }
+    // Everything has been read by now, so the output can be one of the files.
+    match options.output {
+        Some(file) => process
+            .get_path(file)?
+            .create_file()?
+            .write_all(output.as_bytes())?,
+        None => process.stdout.write_all(output.as_bytes())?,
     }
 
     Ok(ExitCode::SUCCESS)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sorted(lines: &[&str], keys: &[&str], global: Modifiers) -> Vec<String> {
+        let mut keys: Vec<Key> = keys
+            .iter()
+            .map(|key| Key::parse(key, global).unwrap())
+            .collect();
+        if keys.is_empty() {
+            keys.push(Key::line(global));
+        }
+        let sorter = Sorter {
+            keys,
+            separator: None,
+            last_resort: true,
+            reverse: global.reverse,
+        };
+        let mut lines = lines.to_vec();
+        lines.sort_by(|a, b| sorter.compare(a, b));
+        lines.into_iter().map(String::from).collect()
+    }
+
+    fn kind(kind: Kind) -> Modifiers {
+        Modifiers {
+            kind,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            sorted(
+                &["10", "9", "-2", "-10", "0.5", "abc", "-0"],
+                &[],
+                kind(Kind::Numeric)
+            ),
+            ["-10", "-2", "-0", "abc", "0.5", "9", "10"]
+        );
+        assert_eq!(
+            sorted(
+                &["1e3", "inf", "x", "-inf", "nan", "20"],
+                &[],
+                kind(Kind::General)
+            ),
+            ["x", "nan", "-inf", "20", "1e3", "inf"]
+        );
+        assert_eq!(
+            sorted(&["2K", "1G", "512", "-1M", "3K"], &[], kind(Kind::Human)),
+            ["-1M", "512", "2K", "3K", "1G"]
+        );
+        assert_eq!(
+            sorted(
+                &["1.10.0", "1.9.2", "1.9.10", "1.0a"],
+                &[],
+                kind(Kind::Version)
+            ),
+            ["1.0a", "1.9.2", "1.9.10", "1.10.0"]
+        );
+        assert_eq!(
+            sorted(&["-3", "abc", "1.0", "1.0~rc1"], &[], kind(Kind::Version)),
+            ["1.0~rc1", "1.0", "abc", "-3"]
+        );
+    }
+
+    #[test]
+    fn keys() {
+        let lines = ["b 2", "a 10", "c 1"];
+        assert_eq!(
+            sorted(&lines, &["2n"], Modifiers::default()),
+            ["c 1", "b 2", "a 10"]
+        );
+        assert_eq!(
+            sorted(&lines, &["2,2nr"], Modifiers::default()),
+            ["a 10", "b 2", "c 1"]
+        );
+        assert_eq!(
+            sorted(&["x  b", "y a"], &["2"], Modifiers::default()),
+            ["x  b", "y a"]
+        );
+        assert_eq!(
+            sorted(&["x  b", "y a"], &["2b"], Modifiers::default()),
+            ["y a", "x  b"]
+        );
+        assert_eq!(
+            sorted(&["ab", "ba", "ca"], &["1.2"], Modifiers::default()),
+            ["ba", "ca", "ab"]
+        );
+        assert!(Key::parse("0", Modifiers::default()).is_err());
+        assert!(Key::parse("1x", Modifiers::default()).is_err());
+        assert!(Key::parse("2.0", Modifiers::default()).is_err());
+        assert!(Key::parse("2.1,2.0", Modifiers::default()).is_ok());
+    }
+
+    #[test]
+    fn extraction() {
+        let key = Key::parse("2,3", Modifiers::default()).unwrap();
+        assert_eq!(key.extract("a:b:c:d", Some(':')), "b:c");
+        assert_eq!(key.extract(" a  b c d", None), "  b c");
+        let key = Key::parse("1.2,1.3", Modifiers::default()).unwrap();
+        assert_eq!(key.extract("abcd", None), "bc");
+        let key = Key::parse("4", Modifiers::default()).unwrap();
+        assert_eq!(key.extract("a b", None), "");
+    }
+
+    #[test]
+    fn case() {
+        let modifiers = Modifiers {
+            ignore_case: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            sorted(&["b", "A", "a", "B"], &[], modifiers),
+            ["A", "a", "B", "b"]
+        );
+        assert_eq!(
+            sorted(&["b", "A", "a", "B"], &[], Modifiers::default()),
+            ["A", "B", "a", "b"]
+        );
+    }
+}
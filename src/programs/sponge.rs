@@ -6,7 +6,7 @@ use std::io::Write;
 
 /// Soak up standard input and write to file.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// The file to which to write
     file: String,
 }
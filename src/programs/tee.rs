@@ -9,7 +9,7 @@ use std::io::Write;
 
 /// Read from stdin and write to stdout and file.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// The files to which to write
     files: Vec<String>,
 }
@@ -5,7 +5,7 @@ use regex::Regex;
 
 /// Evaluate conditional expression.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// The first argument.
     arg1: String,
     /// The operation.
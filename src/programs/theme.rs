@@ -6,14 +6,14 @@ use anyhow::{bail, Result};
 use clap::Parser;
 use futures::io::AsyncWriteExt;
 
-const THEMES_DIR: &str = "/usr/share/theme/themes";
+pub(super) const THEMES_DIR: &str = "/usr/share/theme/themes";
 
 /// Change the terminal theme.
 ///
 /// Use without arguments to see available themes.
 #[derive(Parser)]
 #[command(verbatim_doc_comment)]
-struct Options {
+pub(super) struct Options {
     /// The theme to switch to.
     theme: Option<String>,
 }
@@ -5,7 +5,7 @@ use futures::io::AsyncWriteExt;
 
 /// Create a file if it does not exist.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// The file(s) to touch or create.
     #[arg(required(true))]
     files: Vec<String>,
@@ -0,0 +1,634 @@
+//! How lines are laid out on the screen, with tabs expanded and long lines wrapped onto
+//! several rows, and drawing the windows they're shown in.
+use super::{
+    message,
+    normal::{self, Screen},
+    search,
+    window::{Rect, Window},
+    Editor, Mode,
+};
+use crate::{
+    programs::common::{
+        color_picker::Color,
+        highlight::{self, Highlight},
+        unicode,
+    },
+    streams::InputMode,
+    AnsiCode,
+};
+use anyhow::Result;
+use std::{io::Write, ops::Range};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Where a character of a line is shown once the line is wrapped.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Cell {
+    /// The byte of the line the character starts at.
+    index: usize,
+    row: usize,
+    column: usize,
+    /// How many columns it takes up, which for a tab is up to the next tab stop.
+    width: usize,
+}
+
+/// Lay out each character of a line in rows of `width` columns, followed by where the end of
+/// the line would go. A wide character that doesn't fit at the end of a row goes on the next.
+fn cells(line: &str, width: usize, tabstop: usize) -> Vec<Cell> {
+    let (mut row, mut column) = (0, 0);
+    // Tab stops are counted along the whole line, as if it weren't wrapped.
+    let mut unwrapped = 0;
+    let mut cells = Vec::new();
+    for (index, grapheme) in line.grapheme_indices(true) {
+        let size = unicode::cell_width(grapheme, unwrapped, tabstop);
+        if grapheme != "\t" && column > 0 && column + size > width {
+            (row, column) = (row + 1, 0);
+        }
+        cells.push(Cell {
+            index,
+            row,
+            column,
+            width: size,
+        });
+        unwrapped += size;
+        column += size;
+        if column >= width {
+            (row, column) = (row + column / width, column % width);
+        }
+    }
+    cells.push(Cell {
+        index: line.len(),
+        row,
+        column,
+        width: 0,
+    });
+    cells
+}
+
+/// How many rows a line takes up when wrapped at `width` columns.
+pub fn height(line: &str, width: usize, tabstop: usize) -> usize {
+    let end = cells(line, width, tabstop)
+        .last()
+        .copied()
+        .expect("The end is always laid out");
+    std::cmp::max(1, end.row + usize::from(end.column > 0))
+}
+
+/// Which row of a wrapped line a byte is on, and its column in that row.
+pub fn position(line: &str, index: usize, width: usize, tabstop: usize) -> (usize, usize) {
+    cells(line, width, tabstop)
+        .into_iter()
+        .find(|cell| cell.index >= index)
+        .map_or((0, 0), |cell| (cell.row, cell.column))
+}
+
+/// The character of a wrapped line shown at a row and column, or the last one before it if
+/// there's none there.
+pub fn index_at(line: &str, (row, column): (usize, usize), width: usize, tabstop: usize) -> usize {
+    cells(line, width, tabstop)
+        .into_iter()
+        .take_while(|cell| (cell.row, cell.column) <= (row, column) && cell.index < line.len())
+        .last()
+        .map_or(0, |cell| cell.index)
+}
+
+impl Editor<'_> {
+    /// The cursor and first row shown of a window, which the editor keeps itself for the
+    /// current window.
+    fn window_state(&self, index: usize) -> Window {
+        if index == self.window {
+            Window {
+                buffer: self.buffer,
+                cursor: self.cursor,
+                offset: self.offset,
+            }
+        } else {
+            self.windows[index]
+        }
+    }
+
+    /// How many columns line numbers take up, including the space after them.
+    fn gutter(&self, lines: &[String]) -> usize {
+        if self.settings.number || self.settings.relative_number {
+            std::cmp::max(3, lines.len().to_string().len()) + 1
+        } else {
+            0
+        }
+    }
+
+    /// How many columns of text fit in a row of a window, after its line numbers.
+    fn text_width(&self, lines: &[String], view: Rect) -> usize {
+        std::cmp::max(1, view.width.saturating_sub(self.gutter(lines)))
+    }
+
+    /// The line number shown before a row of a window, which is counted from the cursor's row
+    /// with `relativenumber`.
+    fn line_number(&self, window: usize, lines: &[String], row: usize) -> String {
+        let gutter = self.gutter(lines);
+        if gutter == 0 {
+            return String::new();
+        }
+        let width = gutter - 1;
+        let (cursor, _) = self.window_state(window).cursor;
+        if !self.settings.relative_number {
+            format!("{:>width$} ", row + 1)
+        } else if row == cursor && self.settings.number {
+            format!("{:<width$} ", row + 1)
+        } else {
+            format!("{:>width$} ", row.abs_diff(cursor))
+        }
+    }
+
+    /// How many rows a line of the current buffer takes up in the current window.
+    pub(super) fn line_height(&self, row: usize) -> usize {
+        let width = self.text_width(&self.lines, self.view());
+        height(&self.lines[row], width, self.settings.tabstop)
+    }
+
+    /// Which row of a window a line starts on, if it's shown in full. The first line shown
+    /// always is, even if it's cut off.
+    pub(super) fn row_top(&self, index: usize, rect: Rect, row: usize) -> Option<usize> {
+        let window = self.window_state(index);
+        let lines = self.buffer_lines(window.buffer);
+        let view = self.text_rect(rect);
+        let width = self.text_width(lines, view);
+        let line_height = |row: usize| height(&lines[row], width, self.settings.tabstop);
+        if row < window.offset {
+            return None;
+        }
+        let mut top = 0;
+        for row in window.offset..row {
+            top += line_height(row);
+            if top >= view.height {
+                return None;
+            }
+        }
+        (row == window.offset || top + line_height(row) <= view.height).then_some(top)
+    }
+
+    /// The lines shown in full in the current window.
+    fn shown_rows(&self) -> Range<usize> {
+        let view = self.view();
+        let mut top = 0;
+        let mut end = self.offset;
+        while end < self.lines.len() {
+            top += self.line_height(end);
+            if end > self.offset && top > view.height {
+                break;
+            }
+            end += 1;
+        }
+        self.offset..end
+    }
+
+    /// What the current window shows, for motions that depend on it.
+    pub(super) fn screen(&self) -> Screen {
+        Screen {
+            rows: self.shown_rows(),
+            width: self.text_width(&self.lines, self.view()),
+            tabstop: self.settings.tabstop,
+        }
+    }
+
+    /// Scroll the current window as little as possible to show a line in full. Returns true if
+    /// it scrolled.
+    pub(super) fn scroll_to(&mut self, row: usize) -> bool {
+        let offset = self.offset;
+        if row < self.offset {
+            self.offset = row;
+        } else {
+            // Go back from the line while the ones before it fit above it.
+            let height = self.view().height;
+            let mut top = row;
+            let mut used = self.line_height(row);
+            while top > self.offset && used + self.line_height(top - 1) <= height {
+                top -= 1;
+                used += self.line_height(top);
+            }
+            self.offset = top;
+        }
+        self.offset != offset
+    }
+
+    /// What to highlight in a row of a window: its syntax, then matches of the search pattern
+    /// over that, then the selection if it's the current window.
+    fn highlights(&self, index: usize, lines: &[String], row: usize) -> Vec<Highlight> {
+        let window = self.window_state(index);
+        let mut highlights = (self.buffer_highlighter(window.buffer))
+            .get(lines, row)
+            .to_vec();
+        let mut overlay = |range: Range<usize>, color| {
+            highlights.retain(|(other, _)| other.end <= range.start || other.start >= range.end);
+            highlights.push((range, color));
+        };
+        if let (Some(search), true) = (&self.search, self.highlight_search) {
+            for (range, color) in search::highlight_matches(&lines[row], &search.regex) {
+                overlay(range, color);
+            }
+        }
+        let selected = (self.visual)
+            .filter(|_| index == self.window)
+            .and_then(|visual| visual.selected(lines, self.cursor, row));
+        if let Some(selected) = selected {
+            overlay(selected, Color::Reverse);
+        }
+        highlights.sort_by_key(|(range, _)| range.start);
+        highlights
+    }
+
+    /// Write a line at a position on the screen after its line number, wrapped onto at most
+    /// `rows` rows of `width` columns that are each padded with spaces. Returns how many rows
+    /// it took up.
+    fn write_line(
+        &self,
+        (row, column): (usize, usize),
+        (rows, width): (usize, usize),
+        number: &str,
+        line: &str,
+        highlights: &[Highlight],
+    ) -> Result<usize> {
+        let mut stdout = self.stdout.clone();
+        let number = &number[..std::cmp::min(number.len(), width.saturating_sub(1))];
+        let text_width = width - number.len();
+
+        // Each row's text, with where each character of the line starts in it.
+        let mut parts: Vec<(String, Vec<(usize, usize)>)> = Vec::new();
+        let cells = cells(line, text_width, self.settings.tabstop);
+        for pair in cells.windows(2) {
+            let (cell, next) = (pair[0], pair[1]);
+            let grapheme = &line[cell.index..next.index];
+            // Tabs are spaces, which carry on to the next row if they don't fit.
+            let mut pieces = Vec::new();
+            if grapheme == "\t" {
+                let (mut part, mut column, mut left) = (cell.row, cell.column, cell.width);
+                while left > 0 {
+                    let spaces = std::cmp::min(left, text_width - column);
+                    pieces.push((part, " ".repeat(spaces)));
+                    (part, column, left) = (part + 1, 0, left - spaces);
+                }
+            } else {
+                pieces.push((cell.row, grapheme.to_string()));
+            }
+            for (part, text) in pieces {
+                parts.resize_with(std::cmp::max(parts.len(), part + 1), Default::default);
+                let (row_text, offsets) = &mut parts[part];
+                offsets.push((cell.index, row_text.len()));
+                row_text.push_str(&text);
+            }
+        }
+
+        let height = std::cmp::min(rows, height(line, text_width, self.settings.tabstop));
+        for part in 0..height {
+            stdout.write_all(&AnsiCode::AbsolutePosition(row + part, column).to_bytes())?;
+            if part == 0 && !number.is_empty() {
+                let highlight = [(0..number.len(), Color::Yellow)];
+                highlight::write_highlighted(&mut stdout, number, &highlight)?;
+            } else {
+                stdout.write_all(" ".repeat(number.len()).as_bytes())?;
+            }
+
+            let (text, offsets) = parts.get(part).map_or(("", &[][..]), |(text, offsets)| {
+                (text.as_str(), offsets.as_slice())
+            });
+            // Where a byte of the line is in this row's text, if it's shown here at all.
+            let clip = |index: usize| {
+                (offsets.iter())
+                    .find(|&&(start, _)| start >= index)
+                    .map_or(text.len(), |&(_, offset)| offset)
+            };
+            let highlights: Vec<Highlight> = highlights
+                .iter()
+                .map(|(range, color)| (clip(range.start)..clip(range.end), *color))
+                .filter(|(range, _)| !range.is_empty())
+                .collect();
+            highlight::write_highlighted(&mut stdout, text, &highlights)?;
+            let padding = text_width.saturating_sub(unicode::width(text));
+            stdout.write_all(" ".repeat(padding).as_bytes())?;
+        }
+        Ok(height)
+    }
+
+    /// Draw a row of the current buffer in every window it's shown in.
+    pub(super) fn draw_row(&mut self, row: usize) -> Result<()> {
+        for (index, rect) in self.window_rects().into_iter().enumerate() {
+            if self.window_state(index).buffer == self.buffer {
+                let highlights = self.highlights(index, &self.lines, row);
+                self.write_row(index, rect, row, &highlights)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw a row of the current buffer in a window, with its own highlights, if it's shown.
+    fn write_row(
+        &self,
+        index: usize,
+        rect: Rect,
+        row: usize,
+        highlights: &[Highlight],
+    ) -> Result<()> {
+        let Some(top) = self.row_top(index, rect, row) else {
+            return Ok(());
+        };
+        let view = self.text_rect(rect);
+        let number = self.line_number(index, &self.lines, row);
+        let position = (view.top + top, view.left);
+        let space = (view.height - top, view.width);
+        self.write_line(position, space, &number, &self.lines[row], highlights)?;
+        Ok(())
+    }
+
+    /// Draw a row of the current window with a match highlighted, for confirming a
+    /// substitution.
+    pub(super) fn draw_match(&self, row: usize, range: Range<usize>) -> Result<()> {
+        let rect = self.window_rects()[self.window];
+        self.write_row(self.window, rect, row, &[(range, Color::Reverse)])
+    }
+
+    /// The mode shown in the current window's status line.
+    fn mode_name(&self) -> &'static str {
+        match (self.mode, self.visual) {
+            (Mode::Insert, _) => "INSERT",
+            (_, Some(visual)) => visual.selection.name().trim_matches(['-', ' ']),
+            _ => "NORMAL",
+        }
+    }
+
+    /// Draw a window's status line, with the name of its buffer, the mode if it's the current
+    /// window, and where its cursor is.
+    fn draw_status_line(&self, index: usize, rect: Rect) -> Result<()> {
+        let view = self.text_rect(rect);
+        let window = self.window_state(index);
+        let lines = self.buffer_lines(window.buffer);
+
+        let modified = if self.buffer_modified(window.buffer) {
+            " [+]"
+        } else {
+            ""
+        };
+        let mut left = format!("{}{modified}", self.buffer_name(window.buffer));
+        if index == self.window {
+            left = format!("{} {left}", self.mode_name());
+        }
+        let (row, column) = window.cursor;
+        let row = std::cmp::min(row, lines.len() - 1);
+        let column = unicode::column(&lines[row], column, self.settings.tabstop) + 1;
+        let percent = (row + 1) * 100 / lines.len();
+        let right = format!("{}:{column} {percent:>4}%", row + 1);
+
+        // Leave out the end of the name if there isn't room for it.
+        let space = rect.width.saturating_sub(right.len() + 1);
+        let mut shown = 0;
+        let left: String = (left.graphemes(true))
+            .take_while(|grapheme| {
+                shown += unicode::grapheme_width(grapheme);
+                shown <= space
+            })
+            .collect();
+        let padding = " ".repeat(space.saturating_sub(unicode::width(&left)));
+        let status = format!("{left}{padding} {right}");
+        let color = if index == self.window {
+            Color::Reverse
+        } else {
+            Color::Dim
+        };
+        let position = (view.top + view.height, rect.left);
+        let highlight = [(0..status.len(), color)];
+        self.write_line(position, (1, rect.width), "", &status, &highlight)?;
+        Ok(())
+    }
+
+    /// Draw a window's rows, its status line and the separator to the right of it.
+    fn draw_window(&self, index: usize, rect: Rect) -> Result<()> {
+        let window = self.window_state(index);
+        let lines = self.buffer_lines(window.buffer);
+        let view = self.text_rect(rect);
+        let width = self.text_width(lines, view);
+
+        let mut top = 0;
+        let mut row = window.offset;
+        while top < view.height {
+            let position = (view.top + top, view.left);
+            let space = (view.height - top, view.width);
+            let Some(line) = lines.get(row) else {
+                top += self.write_line(position, space, "", "", &[])?;
+                continue;
+            };
+            // Lines after the first that don't fit are left out, with rows of `@` instead.
+            if row > window.offset && height(line, width, self.settings.tabstop) > space.0 {
+                for top in top..view.height {
+                    self.write_line((view.top + top, view.left), (1, view.width), "", "@", &[])?;
+                }
+                break;
+            }
+            let highlights = self.highlights(index, lines, row);
+            let number = self.line_number(index, lines, row);
+            top += self.write_line(position, space, &number, line, &highlights)?;
+            row += 1;
+        }
+        self.draw_status_line(index, rect)?;
+
+        let column = rect.left + rect.width;
+        if column < self.width {
+            let mut stdout = self.stdout.clone();
+            for row in rect.top..rect.top + rect.height {
+                stdout.write_all(&AnsiCode::AbsolutePosition(row, column).to_bytes())?;
+                stdout.write_all(b"|")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Clear the screen and draw every window.
+    pub(super) fn draw_screen(&mut self) -> Result<()> {
+        self.update_highlighters();
+        self.stdout.write_all(&AnsiCode::Clear.to_bytes())?;
+        for (index, rect) in self.window_rects().into_iter().enumerate() {
+            self.draw_window(index, rect)?;
+        }
+        Ok(())
+    }
+
+    /// Bring the screen up to date.
+    pub(super) async fn draw(&mut self) -> Result<()> {
+        let (row, _) = self.cursor;
+        let rect = self.window_rects()[self.window];
+        let view = self.text_rect(rect);
+
+        // Scrolling only needs the current window drawn again, as does moving to another line
+        // when line numbers are relative to it.
+        let moved = self.windows[self.window].cursor.0 != row;
+        let scrolled = self.scroll_to(row);
+        self.update_highlighters();
+        if (scrolled || (moved && self.settings.relative_number)) && !self.reset {
+            self.draw_window(self.window, rect)?;
+        }
+
+        if self.reset {
+            self.draw_screen()?;
+            self.stdin.set_mode(InputMode::Char).await?;
+            self.reset = false;
+            if let Some(visual) = self.visual {
+                message(&mut self.stdout, self.height, visual.selection.name())?;
+            }
+        }
+        if let Some(text) = self.status.take() {
+            message(&mut self.stdout, self.height, &text)?;
+        }
+
+        let line = &self.lines[row];
+        self.cursor.1 = if self.mode == Mode::Normal {
+            normal::clamp(line, self.cursor.1)
+        } else {
+            unicode::grapheme_start(line, self.cursor.1)
+        };
+        self.draw_row(row)?;
+        self.draw_status_line(self.window, rect)?;
+        let window = &mut self.windows[self.window];
+        window.cursor = self.cursor;
+        window.offset = self.offset;
+
+        // The cursor can be past the end of a line that fills its last row, in insert mode.
+        let line = &self.lines[row];
+        let width = self.text_width(&self.lines, view);
+        let tabstop = self.settings.tabstop;
+        let (mut part, mut column) = position(line, self.cursor.1, width, tabstop);
+        let height = height(line, width, tabstop);
+        if part >= height {
+            (part, column) = (height - 1, width - 1);
+        }
+        let top = self.row_top(self.window, rect, row).unwrap_or_default();
+        let row = std::cmp::min(view.top + top + part, view.top + view.height - 1);
+        let column = view.left + self.gutter(&self.lines) + column;
+        self.stdout
+            .write_all(&AnsiCode::AbsolutePosition(row, column).to_bytes())?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{super::test::*, *};
+    use regex::Regex;
+
+    /// What was written to the screen, without the escape codes that moved the cursor or
+    /// colored it.
+    fn text(screen: &str) -> String {
+        let codes = Regex::new("\x1b(\\[[0-9;]*[A-Za-z]|c)").unwrap();
+        codes.replace_all(screen, "").into_owned()
+    }
+
+    #[test]
+    fn wrapping() {
+        let line = "abcdefghij";
+        assert_eq!(height(line, 4, 8), 3);
+        assert_eq!(height(line, 10, 8), 1);
+        assert_eq!(height("", 4, 8), 1);
+        assert_eq!(position(line, 5, 4, 8), (1, 1));
+        assert_eq!(position(line, 10, 5, 8), (2, 0));
+        assert_eq!(index_at(line, (1, 1), 4, 8), 5);
+        assert_eq!(index_at(line, (2, 3), 4, 8), 9);
+        assert_eq!(index_at("", (0, 3), 4, 8), 0);
+
+        // A tab takes up the columns to the next tab stop.
+        let line = "a\tbc";
+        assert_eq!(unicode::column(line, 2, 8), 8);
+        assert_eq!(height(line, 8, 8), 2);
+        assert_eq!(position(line, 3, 8, 8), (1, 1));
+        assert_eq!(index_at(line, (0, 5), 8, 8), 1);
+        assert_eq!(index_at(line, (1, 0), 8, 8), 2);
+
+        // Wide characters take up two columns, and go on the next row if they'd be split.
+        let line = "a🐮b漢字";
+        assert_eq!(unicode::column(line, 5, 8), 3);
+        assert_eq!(unicode::column(line, line.len(), 8), 8);
+        assert_eq!(height(line, 4, 8), 2);
+        assert_eq!(position(line, 6, 4, 8), (1, 0));
+        assert_eq!(position(line, 9, 4, 8), (1, 2));
+        assert_eq!(height("ab🐮", 3, 8), 2);
+        assert_eq!(position("ab🐮", 2, 3, 8), (1, 0));
+        assert_eq!(index_at(line, (0, 2), 4, 8), 1);
+        assert_eq!(index_at(line, (0, 3), 4, 8), 5);
+        assert_eq!(index_at(line, (1, 1), 4, 8), 6);
+        // An accent shares the column of the letter it's on.
+        assert_eq!(unicode::column("e\u{301}x", 3, 8), 1);
+    }
+
+    #[futures_test::test]
+    async fn status_line() {
+        let cwd = file_system(&[("a", "one\n\ttwo\nthree\n")]);
+        let (result, screen) = edit(&cwd, &[], ":q\n").await;
+        result.unwrap();
+        assert!(text(&screen).contains("NORMAL [No Name]"));
+        assert!(text(&screen).contains(" 1:1  100%"));
+
+        // The column is where the cursor is shown, after tabs are expanded.
+        let (result, screen) = edit(&cwd, &["a"], ":set ts=4\nj$:q\n").await;
+        result.unwrap();
+        assert!(text(&screen).contains("NORMAL a"));
+        assert!(text(&screen).contains(" 1:1   33%"));
+        assert!(text(&screen).contains(" 2:7   66%"));
+
+        // A file that doesn't exist yet is shown by name, with its changes.
+        let (result, screen) = edit(&cwd, &["missing"], "ix\x1b\x1b:q!\n").await;
+        result.unwrap();
+        assert!(text(&screen).contains("INSERT missing "));
+        assert!(text(&screen).contains("INSERT missing [+] "));
+        assert!(text(&screen).contains("NORMAL missing [+] "));
+    }
+
+    #[futures_test::test]
+    async fn line_numbers() {
+        let cwd = file_system(&[("a", "one\ntwo\nthree\n")]);
+        let (result, screen) = edit(&cwd, &["a"], ":set nu\n:q\n").await;
+        result.unwrap();
+        assert!(text(&screen).contains("  1 one"));
+        assert!(text(&screen).contains("  3 three"));
+
+        // Relative numbers count from the cursor's line, which shows its own number with both.
+        let (result, screen) = edit(&cwd, &["a"], ":set rnu\nj:set nu\n:q\n").await;
+        result.unwrap();
+        assert!(text(&screen).contains("  1 one  "));
+        assert!(text(&screen).contains("  0 two  "));
+        assert!(text(&screen).contains("2   two  "));
+        assert!(text(&screen).contains("  1 three  "));
+
+        // Options that don't exist or don't take that value are errors.
+        let keys = ":set bogus\n :set nu=2\n :set ts=0\n :q\n";
+        let (result, screen) = edit(&cwd, &["a"], keys).await;
+        result.unwrap();
+        assert!(text(&screen).contains("Unknown option: bogus"));
+        assert!(text(&screen).contains("Unknown option: nu"));
+        assert!(text(&screen).contains("Argument must be positive: ts=0"));
+        assert!(!text(&screen).contains("  1 one"));
+    }
+
+    #[futures_test::test]
+    async fn soft_wrapping() {
+        // A line longer than the screen is wide goes on to the next row.
+        let long = "a".repeat(80) + "bc";
+        let cwd = file_system(&[("a", &format!("{long}\nend\n"))]);
+        let (result, screen) = edit(&cwd, &["a"], "gjx:wq\n").await;
+        result.unwrap();
+        assert!(text(&screen).contains(&format!("{}bc{}", "a".repeat(80), " ".repeat(78))));
+        assert!(text(&screen).contains(" 1:81 "));
+        assert_eq!(
+            contents(&cwd, "a").as_deref(),
+            Some(&*format!("{}c\nend\n", "a".repeat(80)))
+        );
+
+        // Moving by rows stops at the first and last rows.
+        let (result, _) = edit(&cwd, &["a"], "gkxGgjgjx:wq\n").await;
+        result.unwrap();
+        assert_eq!(
+            contents(&cwd, "a").as_deref(),
+            Some(&*format!("{}c\nnd\n", "a".repeat(79)))
+        );
+
+        // Line numbers leave less room for the text.
+        let (result, screen) = edit(&cwd, &["a"], ":set nu\n:q\n").await;
+        result.unwrap();
+        assert!(text(&screen).contains(&format!("  1 {}", "a".repeat(76))));
+        assert!(text(&screen).contains("    aaac  "));
+    }
+}
@@ -0,0 +1,1026 @@
+//! Ex commands, which are typed after `:`.
+use super::{
+    marks::Marks,
+    message, normal, read_file,
+    registers::{Register, Registers},
+    restore, search, split_lines,
+    window::Buffer,
+    Editor, Search,
+};
+use crate::{
+    programs::exec_program,
+    streams::{self, InputMode},
+    AnsiCode,
+};
+use anyhow::{anyhow, bail, Result};
+use ascii::AsciiChar;
+use futures::{io::AsyncReadExt, join};
+use regex::Regex;
+use search::Substitution;
+use std::io::Write;
+
+/// A range of lines, as zero-based indices of the first and last lines.
+pub type LineRange = (usize, usize);
+
+/// What the addresses in a command refer to.
+#[derive(Clone, Copy)]
+pub struct Context<'a> {
+    pub lines: &'a [String],
+    /// The index of the cursor's line.
+    pub current: usize,
+    pub marks: &'a Marks,
+    /// The last search pattern, which an empty pattern like `//` stands for.
+    pub pattern: Option<&'a str>,
+    pub ignore_case: bool,
+}
+
+/// Split off a pattern ending with `delimiter`, or the end of the text. Escaped delimiters are
+/// part of the pattern.
+fn split_pattern(text: &str, delimiter: char) -> (String, &str) {
+    let mut pattern = String::new();
+    let mut chars = text.char_indices();
+    while let Some((index, c)) = chars.next() {
+        if c == delimiter {
+            return (pattern, &text[index + c.len_utf8()..]);
+        }
+        if c == '\\' {
+            match chars.next() {
+                Some((_, c)) if c == delimiter => pattern.push(c),
+                Some((_, c)) => {
+                    pattern.push('\\');
+                    pattern.push(c);
+                }
+                None => pattern.push('\\'),
+            }
+        } else {
+            pattern.push(c);
+        }
+    }
+    (pattern, "")
+}
+
+/// Parse one address, like `12`, `.`, `$`, `'<`, `/pattern/` or `.+3`. Returns `None` if there
+/// isn't one.
+fn parse_address<'a>(command: &'a str, context: &Context) -> Result<(Option<usize>, &'a str)> {
+    let Context {
+        lines,
+        current,
+        marks,
+        ..
+    } = *context;
+    let last = lines.len() - 1;
+    let mut rest = command;
+    let mut address = match rest.chars().next() {
+        Some('\'') => {
+            let Some(name) = rest[1..].chars().next() else {
+                bail!("Invalid range");
+            };
+            let Some((line, _)) = marks.get(name) else {
+                bail!("Mark not set");
+            };
+            rest = &rest[1 + name.len_utf8()..];
+            Some(std::cmp::min(line, last))
+        }
+        Some(delimiter @ ('/' | '?')) => {
+            let (pattern, after) = split_pattern(&rest[1..], delimiter);
+            rest = after;
+            let pattern = match (pattern.is_empty(), context.pattern) {
+                (false, _) => pattern,
+                (true, Some(pattern)) => pattern.to_string(),
+                (true, None) => bail!("No previous regular expression"),
+            };
+            let regex = search::compile(&pattern, context.ignore_case)?;
+            // The next matching line after the cursor's, or the previous one with `?`.
+            let is_match = |row: &usize| regex.is_match(&lines[*row]);
+            let row = if delimiter == '/' {
+                (current + 1..=last).chain(0..=current).find(is_match)
+            } else {
+                (0..current)
+                    .rev()
+                    .chain((current..=last).rev())
+                    .find(is_match)
+            };
+            match row {
+                Some(row) => Some(row),
+                None => bail!("Pattern not found: {pattern}"),
+            }
+        }
+        Some('.') => {
+            rest = &rest[1..];
+            Some(current)
+        }
+        Some('$') => {
+            rest = &rest[1..];
+            Some(last)
+        }
+        Some(c) if c.is_ascii_digit() => {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let number: usize = rest[0..end].parse()?;
+            rest = &rest[end..];
+            // Line 0 is treated like line 1.
+            Some(number.saturating_sub(1))
+        }
+        _ => None,
+    };
+
+    // Offsets, which are relative to the current line if there's no address.
+    while let Some(sign @ ('+' | '-')) = rest.chars().next() {
+        rest = &rest[1..];
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let offset: usize = if end == 0 { 1 } else { rest[0..end].parse()? };
+        rest = &rest[end..];
+        let base = address.unwrap_or(current);
+        address = Some(if sign == '+' {
+            base + offset
+        } else if let Some(line) = base.checked_sub(offset) {
+            line
+        } else {
+            bail!("Invalid range");
+        });
+    }
+
+    if address.is_some_and(|address| address > last) {
+        bail!("Invalid range");
+    }
+    Ok((address, rest))
+}
+
+/// Parse the range at the start of a command, returning the range if there is one, and the
+/// rest of the command. Addresses separated by `;` rather than `,` are relative to the first.
+pub fn parse_range<'a>(
+    command: &'a str,
+    context: &Context,
+) -> Result<(Option<LineRange>, &'a str)> {
+    let command = command.trim_start();
+    if let Some(rest) = command.strip_prefix('%') {
+        return Ok((Some((0, context.lines.len() - 1)), rest));
+    }
+
+    let (start, rest) = parse_address(command, context)?;
+    let separator = rest.chars().next();
+    if !matches!(separator, Some(',' | ';')) {
+        return Ok((start.map(|start| (start, start)), rest));
+    }
+    let start = start.unwrap_or(context.current);
+    let context = Context {
+        current: if separator == Some(';') {
+            start
+        } else {
+            context.current
+        },
+        ..*context
+    };
+    let (end, rest) = parse_address(&rest[1..], &context)?;
+    let end = end.unwrap_or(context.current);
+    if start > end {
+        bail!("Backwards range given");
+    }
+    Ok((Some((start, end)), rest))
+}
+
+/// Parse the address that `:m` and `:t` put lines after, returning the index to insert them
+/// at. Address `0` puts them above the first line.
+fn parse_destination(arguments: &str, context: &Context) -> Result<usize> {
+    if arguments == "0" {
+        return Ok(0);
+    }
+    match parse_address(arguments, context)? {
+        (Some(address), "") => Ok(address + 1),
+        (Some(_), rest) => bail!("Trailing characters: {rest}"),
+        (None, _) => bail!("Invalid address"),
+    }
+}
+
+/// Split a command, after its range, into its name, whether the name's followed by `!`, and
+/// its arguments. Names are letters, or a single other character like `!` or `=`.
+fn split_command(command: &str) -> (&str, bool, &str) {
+    let command = command.trim_start();
+    let end = match command.find(|c: char| !c.is_ascii_alphabetic()) {
+        Some(0) => command.chars().next().map_or(0, char::len_utf8),
+        Some(end) => end,
+        None => command.len(),
+    };
+    let (name, rest) = command.split_at(end);
+    match rest.strip_prefix('!') {
+        Some(rest) if name != "!" => (name, true, rest.trim()),
+        _ => (name, false, rest.trim()),
+    }
+}
+
+/// Parse the arguments of `:d` and `:y`: a register, then a count of lines.
+fn parse_register_count(arguments: &str) -> Result<(Option<char>, Option<usize>)> {
+    let mut rest = arguments;
+    let register = rest
+        .chars()
+        .next()
+        .filter(|c| !c.is_ascii_digit() && Registers::is_valid(*c));
+    if let Some(register) = register {
+        rest = rest[register.len_utf8()..].trim_start();
+    }
+    let count = match rest {
+        "" => None,
+        count => match count.parse() {
+            Ok(0) | Err(_) => bail!("Trailing characters: {count}"),
+            Ok(count) => Some(count),
+        },
+    };
+    Ok((register, count))
+}
+
+/// Check if `name` is `command`, or an abbreviation of it at least `shortest` long.
+fn is_command(name: &str, command: &str, shortest: usize) -> bool {
+    name.len() >= shortest && command.starts_with(name)
+}
+
+impl Editor<'_> {
+    /// Replace matches of `regex` in a range of lines, asking about each one first if the
+    /// substitution has the `c` flag. Returns how many were replaced, and the last line changed.
+    pub(super) async fn substitute(
+        &mut self,
+        (start, end): LineRange,
+        regex: &Regex,
+        substitution: &Substitution,
+    ) -> Result<(usize, Option<usize>)> {
+        let replacement = search::expand_replacement(&substitution.replacement);
+        let mut confirm = substitution.confirm;
+        let mut count = 0;
+        let mut last_row = None;
+
+        for row in start..=end {
+            // Decide which matches to replace, stopping part way through if asked to.
+            let mut decisions = Vec::new();
+            let mut stop = false;
+            for range in search::substitution_targets(&self.lines[row], regex, substitution.global)
+            {
+                if stop {
+                    decisions.push(false);
+                    continue;
+                }
+                if !confirm {
+                    decisions.push(true);
+                    continue;
+                }
+
+                self.reset = true;
+                self.scroll_to(row);
+                self.draw_screen()?;
+                self.draw_match(row, range.clone())?;
+                message(
+                    &mut self.stdout,
+                    self.height,
+                    &format!("replace with {} (y/n/a/q/l)?", substitution.replacement),
+                )?;
+                self.stdout.flush()?;
+
+                let answer = loop {
+                    match self.stdin.get_char().await? {
+                        c @ ('y' | 'n' | 'a' | 'q' | 'l') => break c,
+                        c if c == AsciiChar::ESC => break 'q',
+                        _ => {}
+                    }
+                };
+                match answer {
+                    'y' => decisions.push(true),
+                    'n' => decisions.push(false),
+                    'a' => {
+                        confirm = false;
+                        decisions.push(true);
+                    }
+                    'l' => {
+                        decisions.push(true);
+                        stop = true;
+                    }
+                    _ => {
+                        decisions.push(false);
+                        stop = true;
+                    }
+                }
+            }
+
+            let mut decisions = decisions.into_iter();
+            let line = search::substitute(
+                &self.lines[row],
+                regex,
+                &replacement,
+                substitution.global,
+                |_| {
+                    let accept = decisions.next().unwrap_or_default();
+                    count += usize::from(accept);
+                    accept
+                },
+            );
+            if let Some(line) = line {
+                self.lines[row] = line;
+                last_row = Some(row);
+            }
+            if stop {
+                break;
+            }
+        }
+
+        Ok((count, last_row))
+    }
+
+    /// Run a `:s` command with the arguments after the `s`.
+    async fn substitute_command(
+        &mut self,
+        range: Option<LineRange>,
+        arguments: &str,
+    ) -> Result<()> {
+        let substitution = if arguments.trim().is_empty() || arguments == "&" {
+            // Repeat the last substitution, keeping its flags only for `:&&`.
+            let Some(last) = &self.last_substitution else {
+                bail!("No previous substitute regular expression");
+            };
+            if arguments == "&" {
+                last.clone()
+            } else {
+                Substitution {
+                    global: false,
+                    confirm: false,
+                    ..last.clone()
+                }
+            }
+        } else {
+            Substitution::parse(arguments)?
+        };
+
+        // An empty pattern means the last search pattern.
+        let pattern = if substitution.pattern.is_empty() {
+            match &self.search {
+                Some(search) => search.pattern.clone(),
+                None => bail!("No previous regular expression"),
+            }
+        } else {
+            substitution.pattern.clone()
+        };
+        let ignore_case = substitution
+            .ignore_case
+            .unwrap_or(self.settings.ignore_case);
+        let regex = search::compile(&pattern, ignore_case)?;
+
+        let (start, end) = range.unwrap_or((self.cursor.0, self.cursor.0));
+        let found = self.lines[start..=end]
+            .iter()
+            .any(|line| regex.is_match(line));
+        let (count, last_row) = self.substitute((start, end), &regex, &substitution).await?;
+        if let Some(last_row) = last_row {
+            self.cursor = (last_row, 0);
+        }
+        if !found {
+            self.status = Some(format!("Pattern not found: {pattern}"));
+        } else if count > 1 {
+            self.status = Some(format!("{count} substitutions"));
+        }
+
+        // The pattern becomes the one to search for.
+        self.search = Some(Search {
+            regex: search::compile(&pattern, self.settings.ignore_case)?,
+            pattern: pattern.clone(),
+            forward: self.search.as_ref().is_none_or(|search| search.forward),
+        });
+        self.highlight_search = true;
+        self.reset = true;
+        self.last_substitution = Some(Substitution {
+            pattern,
+            ..substitution
+        });
+        Ok(())
+    }
+
+    /// Where addresses in a command point to.
+    fn context(&self) -> Context<'_> {
+        Context {
+            lines: &self.lines,
+            current: self.cursor.0,
+            marks: &self.normal.marks,
+            pattern: self.search.as_ref().map(|search| search.pattern.as_str()),
+            ignore_case: self.settings.ignore_case,
+        }
+    }
+
+    /// Replace a range of lines, returning the old ones. Lines that replace them aren't ones a
+    /// `:g` command will run on.
+    fn replace_lines(&mut self, (start, end): LineRange, lines: Vec<String>) -> Vec<String> {
+        if let Some(global) = self.global.as_mut() {
+            global.splice(start..=end, vec![false; lines.len()]);
+        }
+        let replaced = self.lines.splice(start..=end, lines).collect();
+        if self.lines.is_empty() {
+            self.insert_lines(0, vec![String::new()]);
+        }
+        replaced
+    }
+
+    /// Insert lines above the line at index `at`.
+    fn insert_lines(&mut self, at: usize, lines: Vec<String>) {
+        if let Some(global) = self.global.as_mut() {
+            global.splice(at..at, vec![false; lines.len()]);
+        }
+        self.lines.splice(at..at, lines);
+    }
+
+    /// Say how many lines a command changed, if it's more than a couple.
+    fn report(&mut self, count: usize, what: &str) {
+        if count > 2 {
+            self.status = Some(format!("{count} {what}"));
+        }
+    }
+
+    /// Run a shell command with `input` as its standard input, returning what it writes to
+    /// standard output and standard error.
+    async fn run_shell(&self, command: &str, input: &str) -> Result<String> {
+        let (stdin, mut input_writer, mut input_backend) = streams::pipe();
+        let (mut output_reader, stdout, mut output_backend) = streams::pipe();
+        let mut process = self.process.clone();
+        process.stdin = stdin;
+        process.stdout = stdout.clone();
+        process.stderr = stdout.clone();
+        process.args = vec!["sh".into(), "-c".into(), command.into()];
+
+        let (_, _, output): (Result<()>, Result<()>, Result<String>) = join! {
+            input_backend.run(),
+            output_backend.run(),
+            async {
+                input_writer.write_all(input.as_bytes())?;
+                input_writer.shutdown().await?;
+                // Boxed, because the program could be vi again.
+                let result = Box::pin(exec_program(&mut process, "sh")).await;
+                stdout.shutdown().await?;
+                let mut output = String::new();
+                output_reader.read_to_string(&mut output).await?;
+                output_reader.shutdown().await?;
+                process.stdin.shutdown().await?;
+                result?;
+                Ok(output)
+            },
+        };
+        output
+    }
+
+    /// Run a shell command on the terminal, for `:!`.
+    async fn run_interactive(&mut self, command: &str) -> Result<()> {
+        self.stdout.write_all(&AnsiCode::Clear.to_bytes())?;
+        self.stdin.set_mode(InputMode::Line).await?;
+        let mut process = self.process.clone();
+        process.args = vec!["sh".into(), "-c".into(), command.into()];
+        let result = Box::pin(exec_program(&mut process, "sh")).await;
+        self.stdin.set_mode(InputMode::Char).await?;
+        result?;
+        self.wait().await
+    }
+
+    /// Run an ex command, then show any error or lines it printed. Returns true if the editor
+    /// should quit.
+    pub(super) async fn ex_command(&mut self, command: &str) -> Result<bool> {
+        self.reset = true;
+        let result = self.global_command(command).await;
+        self.global = None;
+        self.cursor.0 = std::cmp::min(self.cursor.0, self.lines.len() - 1);
+        let quit = match result {
+            Ok(quit) => quit,
+            Err(err) => {
+                self.output.push_str(&err.to_string());
+                false
+            }
+        };
+        if !self.output.is_empty() {
+            let output = std::mem::take(&mut self.output);
+            self.show(output.trim_end()).await?;
+        }
+        Ok(quit)
+    }
+
+    /// Run a command, which can be `:g/pattern/command` to run another command on each line
+    /// matching a pattern, or `:v` to run it on each line that doesn't.
+    async fn global_command(&mut self, command: &str) -> Result<bool> {
+        let context = self.context();
+        let (range, rest) = parse_range(command, &context)?;
+        let (name, force, arguments) = split_command(rest);
+        if !is_command(name, "global", 1) && !is_command(name, "vglobal", 1) {
+            return self.command(command).await;
+        }
+
+        let delimiter = arguments
+            .chars()
+            .next()
+            .filter(|c| !c.is_alphanumeric() && *c != '\\');
+        let Some(delimiter) = delimiter else {
+            bail!("Regular expression missing from :global");
+        };
+        let (pattern, command) = split_pattern(&arguments[delimiter.len_utf8()..], delimiter);
+        let pattern = match (pattern.is_empty(), context.pattern) {
+            (false, _) => pattern,
+            (true, Some(pattern)) => pattern.to_string(),
+            (true, None) => bail!("No previous regular expression"),
+        };
+        let regex = search::compile(&pattern, self.settings.ignore_case)?;
+
+        // Mark the lines first, so the command can change them.
+        let (start, end) = range.unwrap_or((0, self.lines.len() - 1));
+        let matching = !force && name.starts_with('g');
+        let marked: Vec<bool> = (0..self.lines.len())
+            .map(|row| (start..=end).contains(&row) && regex.is_match(&self.lines[row]) == matching)
+            .collect();
+        if !marked.contains(&true) {
+            if matching {
+                bail!("Pattern not found: {pattern}");
+            }
+            bail!("Pattern found in every line: {pattern}");
+        }
+        let command = match command.trim() {
+            "" => "p",
+            command => command,
+        };
+
+        // The pattern becomes the one to search for.
+        self.search = Some(Search {
+            regex,
+            pattern,
+            forward: self.search.as_ref().is_none_or(|search| search.forward),
+        });
+        self.highlight_search = true;
+
+        self.global = Some(marked);
+        while let Some(row) =
+            (self.global.as_ref()).and_then(|marked| marked.iter().position(|&marked| marked))
+        {
+            if let Some(marked) = self.global.as_mut() {
+                marked[row] = false;
+            }
+            self.cursor = (row, 0);
+            if self.command(command).await? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Run a command other than `:g`. Returns true if the editor should quit.
+    async fn command(&mut self, command: &str) -> Result<bool> {
+        let (range, command) = parse_range(command, &self.context())?;
+
+        // Substitution, which has arguments that aren't separated by whitespace.
+        let substitute_arguments = command
+            .strip_prefix('s')
+            .filter(|arguments| !arguments.starts_with(char::is_alphanumeric))
+            .or_else(|| command.strip_prefix('&'));
+        if let Some(arguments) = substitute_arguments {
+            self.substitute_command(range, arguments).await?;
+            return Ok(false);
+        }
+
+        let (name, force, arguments) = split_command(command);
+        let last = self.lines.len() - 1;
+        let (start, end) = range.unwrap_or((self.cursor.0, self.cursor.0));
+
+        if name.is_empty() {
+            if !arguments.is_empty() {
+                bail!("Unknown command: {arguments}");
+            }
+            // Just a line number goes to that line.
+            if range.is_some() {
+                self.normal.marks.jump(self.cursor);
+                self.cursor = (end, 0);
+            }
+        } else if name == "!" {
+            // With a range, the lines are filtered through the command.
+            match range {
+                Some(range) => {
+                    let input = self.lines[start..=end].join("\n") + "\n";
+                    let lines = split_lines(&self.run_shell(arguments, &input).await?);
+                    self.replace_lines(range, lines);
+                    self.cursor = (start, 0);
+                    self.report(end + 1 - start, "lines filtered");
+                }
+                None => self.run_interactive(arguments).await?,
+            }
+        } else if is_command(name, "global", 1) || is_command(name, "vglobal", 1) {
+            bail!("Cannot do :global recursive");
+        } else if is_command(name, "delete", 1) || is_command(name, "yank", 1) {
+            let (register, count) = parse_register_count(arguments)?;
+            let (start, end) = match count {
+                Some(count) => (end, std::cmp::min(end + count - 1, last)),
+                None => (start, end),
+            };
+            if name.starts_with('d') {
+                let lines = self.replace_lines((start, end), Vec::new());
+                self.normal
+                    .registers
+                    .delete(register, Register::Lines(lines));
+                let row = std::cmp::min(start, self.lines.len() - 1);
+                self.cursor = (row, normal::first_non_blank(&self.lines[row]));
+                self.report(end + 1 - start, "fewer lines");
+            } else {
+                let lines = self.lines[start..=end].to_vec();
+                self.normal.registers.yank(register, Register::Lines(lines));
+                self.report(end + 1 - start, "lines yanked");
+            }
+        } else if is_command(name, "move", 1) {
+            let destination = parse_destination(arguments, &self.context())?;
+            if destination > start && destination <= end {
+                bail!("Cannot move a range of lines into itself");
+            }
+            let count = end + 1 - start;
+            let at = if destination > end {
+                destination - count
+            } else {
+                destination
+            };
+            if at != start {
+                let lines = self.replace_lines((start, end), Vec::new());
+                self.insert_lines(at, lines);
+            }
+            self.cursor = (at + count - 1, 0);
+            self.report(count, "lines moved");
+        } else if name == "t" || is_command(name, "copy", 2) {
+            let at = parse_destination(arguments, &self.context())?;
+            let lines = self.lines[start..=end].to_vec();
+            let count = lines.len();
+            self.insert_lines(at, lines);
+            self.cursor = (at + count - 1, 0);
+            self.report(count, "more lines");
+        } else if is_command(name, "print", 1) || is_command(name, "number", 2) || name == "#" {
+            for row in start..=end {
+                let line = &self.lines[row];
+                if name.starts_with('p') {
+                    self.output.push_str(&format!("{line}\n"));
+                } else {
+                    self.output.push_str(&format!("{:>3} {line}\n", row + 1));
+                }
+            }
+            self.cursor = (end, normal::first_non_blank(&self.lines[end]));
+        } else if name == "=" {
+            let number = range.map_or(self.lines.len(), |(_, end)| end + 1);
+            self.status = Some(number.to_string());
+        } else if is_command(name, "read", 1) {
+            // `:r !command` reads what the command outputs.
+            let shell = if force {
+                Some(arguments)
+            } else {
+                arguments.strip_prefix('!')
+            };
+            let lines = match shell {
+                Some(command) => split_lines(&self.run_shell(command, "").await?),
+                None => {
+                    let file = match arguments {
+                        "" => self.file.clone().ok_or_else(|| anyhow!("No file name"))?,
+                        file => file.to_string(),
+                    };
+                    read_file(self.process, &file)?
+                        .ok_or_else(|| anyhow!("Can't open file {file}"))?
+                }
+            };
+            if !lines.is_empty() {
+                self.insert_lines(end + 1, lines);
+                self.cursor = (end + 1, 0);
+            }
+        } else if is_command(name, "write", 1) || name == "wq" {
+            let lines = match range {
+                Some((start, end)) => &self.lines[start..=end],
+                None => &self.lines[..],
+            };
+            let contents = lines.join("\n") + "\n";
+
+            // `:w !command` gives the lines to the command, and shows what it outputs.
+            if let Some(command) = arguments.strip_prefix('!') {
+                let output = self.run_shell(command, &contents).await?;
+                self.output.push_str(&output);
+                return Ok(false);
+            }
+
+            // Set file name if non exists yet.
+            if self.file.is_none() {
+                if arguments.is_empty() {
+                    bail!("No file name");
+                }
+                self.file = Some(arguments.to_string());
+            }
+            let file = match arguments {
+                "" => self
+                    .file
+                    .clone()
+                    .expect("BUG: file name should have been set already"),
+                file => file.to_string(),
+            };
+            let whole = range.is_none_or(|range| range == (0, last));
+            let current = self.file.as_ref() == Some(&file);
+            if current && !whole && !force {
+                bail!("Use ! to write partial buffer");
+            }
+
+            // save
+            let mut handle = self.process.get_path(&file)?.create_file()?;
+            handle.write_all(contents.as_bytes())?;
+            if current && whole {
+                self.undo.mark_saved();
+            }
+            self.status = Some(format!(
+                "\"{file}\" {}L, {}B written",
+                lines.len(),
+                contents.len()
+            ));
+
+            if name == "wq" {
+                return self.quit(force);
+            }
+        } else if range.is_some() {
+            bail!("No range allowed");
+        } else if is_command(name, "edit", 1) && arguments.is_empty() {
+            // Load the file again, losing any changes.
+            if self.undo.modified() && !force {
+                bail!("No write since last change (add ! to override)");
+            }
+            let file = self.file.clone().ok_or_else(|| anyhow!("No file name"))?;
+            let lines = read_file(self.process, &file)?.unwrap_or_default();
+            self.status = Some(format!("\"{file}\" {}L", lines.len()));
+            let Buffer { lines, undo, .. } = Buffer::new(Some(file), lines);
+            self.lines = lines;
+            self.undo = undo;
+            self.normal.marks = Marks::default();
+            self.cursor = (std::cmp::min(self.cursor.0, self.lines.len() - 1), 0);
+            // There's nothing left for `:g` to do in the old lines.
+            self.global = None;
+            self.reset = true;
+        } else if is_command(name, "edit", 1) {
+            self.check_leave(force)?;
+            let buffer = self.open(arguments).await?;
+            self.switch_buffer(buffer);
+        } else if is_command(name, "next", 1)
+            || is_command(name, "Next", 1)
+            || is_command(name, "previous", 4)
+            || is_command(name, "first", 3)
+            || is_command(name, "rewind", 3)
+            || is_command(name, "last", 2)
+        {
+            let index = match name.as_bytes()[0] {
+                b'n' => self.argument + 1,
+                b'N' | b'p' => (self.argument.checked_sub(1))
+                    .ok_or_else(|| anyhow!("Cannot go before first file"))?,
+                b'f' | b'r' => 0,
+                _ => self.arguments.len().saturating_sub(1),
+            };
+            let Some(file) = self.arguments.get(index).cloned() else {
+                if self.arguments.is_empty() {
+                    bail!("There is no argument list");
+                }
+                bail!("Cannot go beyond last file");
+            };
+            self.check_leave(force)?;
+            let buffer = self.open(&file).await?;
+            self.switch_buffer(buffer);
+            self.argument = index;
+        } else if is_command(name, "args", 2) {
+            let files: Vec<String> = (self.arguments.iter().enumerate())
+                .map(|(index, file)| {
+                    if index == self.argument {
+                        format!("[{file}]")
+                    } else {
+                        file.clone()
+                    }
+                })
+                .collect();
+            self.status = Some(files.join(" "));
+        } else if name == "ls" || name == "files" || name == "buffers" {
+            for index in 0..self.buffers.len() {
+                let current = if index == self.buffer { '%' } else { ' ' };
+                let shown = self.windows.iter().any(|window| window.buffer == index);
+                let active = if shown { 'a' } else { 'h' };
+                let modified = if self.buffer_modified(index) {
+                    '+'
+                } else {
+                    ' '
+                };
+                let (row, _) = if index == self.buffer {
+                    self.cursor
+                } else {
+                    self.buffers[index].cursor
+                };
+                let name = format!("\"{}\"", self.buffer_name(index));
+                self.output.push_str(&format!(
+                    "{:>3} {current}{active} {modified} {name:<30} line {}\n",
+                    index + 1,
+                    row + 1
+                ));
+            }
+        } else if is_command(name, "bnext", 2)
+            || is_command(name, "bNext", 2)
+            || is_command(name, "bprevious", 2)
+        {
+            let count = self.buffers.len();
+            let index = if name.starts_with("bn") {
+                (self.buffer + 1) % count
+            } else {
+                (self.buffer + count - 1) % count
+            };
+            self.check_leave(force)?;
+            self.switch_buffer(index);
+        } else if is_command(name, "buffer", 1) {
+            // A buffer is given by its number, or part of its name.
+            let index = match arguments.parse::<usize>() {
+                _ if arguments.is_empty() => self.buffer,
+                Ok(number) => (number.checked_sub(1))
+                    .filter(|&index| index < self.buffers.len())
+                    .ok_or_else(|| anyhow!("Buffer {number} does not exist"))?,
+                Err(_) => {
+                    let matches: Vec<usize> = (0..self.buffers.len())
+                        .filter(|&index| self.buffer_name(index).contains(arguments))
+                        .collect();
+                    match matches[..] {
+                        [index] => index,
+                        [] => bail!("No matching buffer for {arguments}"),
+                        _ => bail!("More than one match for {arguments}"),
+                    }
+                }
+            };
+            if index != self.buffer {
+                self.check_leave(force)?;
+            }
+            self.switch_buffer(index);
+        } else if name == "new" || is_command(name, "vnew", 3) {
+            self.buffers.push(Buffer::new(None, Vec::new()));
+            self.split(name.starts_with('v'), self.buffers.len() - 1)?;
+        } else if is_command(name, "split", 2) || is_command(name, "vsplit", 2) {
+            let buffer = match arguments {
+                "" => self.buffer,
+                file => self.open(file).await?,
+            };
+            self.split(name.starts_with('v'), buffer)?;
+        } else if is_command(name, "close", 3) {
+            return self.window_command('c', None);
+        } else if is_command(name, "only", 2) {
+            return self.window_command('o', None);
+        } else if is_command(name, "set", 2) {
+            if arguments.is_empty() || arguments == "all" {
+                let list = self.settings.list();
+                self.output.push_str(&list);
+            } else {
+                let mut values = Vec::new();
+                for argument in arguments.split_whitespace() {
+                    values.extend(self.settings.set(argument)?);
+                }
+                if !values.is_empty() {
+                    self.status = Some(values.join("  "));
+                }
+            }
+            // Searches follow `ignorecase`.
+            if let Some(search) = self.search.as_mut() {
+                search.regex = search::compile(&search.pattern, self.settings.ignore_case)?;
+            }
+        } else if is_command(name, "registers", 3) || name == "display" {
+            // Only the registers given, if any.
+            let names: String = arguments.split_whitespace().collect();
+            self.output.push_str("Type Name Content\n");
+            for (name, register) in self.normal.registers.list() {
+                if names.is_empty() || names.contains(name) {
+                    self.output.push_str(&register.describe(name));
+                    self.output.push('\n');
+                }
+            }
+        } else if is_command(name, "marks", 4) {
+            let names: String = arguments.split_whitespace().collect();
+            self.output.push_str("mark line  col text\n");
+            for (name, (row, column)) in self.normal.marks.list() {
+                if names.is_empty() || names.contains(name) {
+                    let text = self.lines.get(row).map_or("", |line| line.trim());
+                    self.output
+                        .push_str(&format!(" {name} {:>6} {column:>4} {text}\n", row + 1));
+                }
+            }
+        } else if is_command(name, "nohlsearch", 3) {
+            self.highlight_search = false;
+        } else if "quit".starts_with(name) {
+            if !arguments.is_empty() {
+                bail!("Unexpected arguments");
+            }
+            return self.quit(force);
+        } else if is_command(name, "qall", 2) || is_command(name, "quitall", 5) {
+            return self.quit_all(force);
+        } else if is_command(name, "wall", 2) || is_command(name, "wqall", 3) {
+            for index in 0..self.buffers.len() {
+                if !self.buffer_modified(index) {
+                    continue;
+                }
+                let Some(file) = self.buffer_file(index) else {
+                    bail!("No file name for buffer {}", index + 1);
+                };
+                let contents = self.buffer_lines(index).join("\n") + "\n";
+                let mut handle = self.process.get_path(file)?.create_file()?;
+                handle.write_all(contents.as_bytes())?;
+                if index == self.buffer {
+                    self.undo.mark_saved();
+                } else {
+                    self.buffers[index].undo.mark_saved();
+                }
+            }
+            if name.starts_with("wq") {
+                return self.quit_all(force);
+            }
+        } else if is_command(name, "undo", 1)
+            || is_command(name, "redo", 3)
+            || is_command(name, "earlier", 2)
+            || is_command(name, "later", 3)
+        {
+            let count = match arguments {
+                "" => None,
+                count => Some(count.parse().map_err(|_| anyhow!("Invalid argument"))?),
+            };
+            let lines = match (name.as_bytes()[0], count) {
+                (b'u', Some(count)) => self.undo.go_to_change(count),
+                (b'u', None) => self.undo.undo(),
+                (b'r', _) => self.undo.redo(),
+                (b'e', count) => Some(self.undo.earlier(count.unwrap_or(1))),
+                (_, count) => Some(self.undo.later(count.unwrap_or(1))),
+            };
+            match lines {
+                Some(lines) => self.cursor = (restore(&mut self.lines, lines), 0),
+                None if count.is_some() => bail!("Undo number not found"),
+                None => {}
+            }
+        } else {
+            bail!("Unknown command: {name}");
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        let lines: Vec<String> = (1..=10).map(|number| format!("line {number}")).collect();
+        let mut marks = Marks::default();
+        marks.set('<', (1, 0));
+        let context = Context {
+            lines: &lines,
+            current: 4,
+            marks: &marks,
+            pattern: Some("line 2"),
+            ignore_case: false,
+        };
+        let parse = |command| parse_range(command, &context).unwrap();
+        assert_eq!(parse("w"), (None, "w"));
+        assert_eq!(parse("%s/a/b/"), (Some((0, 9)), "s/a/b/"));
+        assert_eq!(parse("1,5s"), (Some((0, 4)), "s"));
+        assert_eq!(parse(".,$d"), (Some((4, 9)), "d"));
+        assert_eq!(parse("3"), (Some((2, 2)), ""));
+        assert_eq!(parse("+2"), (Some((6, 6)), ""));
+        assert_eq!(parse(".-1,.+1"), (Some((3, 5)), ""));
+        assert_eq!(parse(",$-2"), (Some((4, 7)), ""));
+        assert_eq!(parse("'<,.s"), (Some((1, 4)), "s"));
+        assert_eq!(parse("2;+1"), (Some((1, 2)), ""));
+        assert!(parse_range("20", &context).is_err());
+        assert!(parse_range("5,2", &context).is_err());
+        assert!(parse_range("-9", &context).is_err());
+        assert!(parse_range("'>", &context).is_err());
+
+        // Patterns search forward or backward from the current line, wrapping around.
+        assert_eq!(parse("/line 1/d"), (Some((9, 9)), "d"));
+        assert_eq!(parse("/line [0-9]$/,/10/p"), (Some((5, 9)), "p"));
+        assert_eq!(parse("?line 4?"), (Some((3, 3)), ""));
+        assert_eq!(parse("?line 5"), (Some((4, 4)), ""));
+        assert_eq!(parse("//+1"), (Some((2, 2)), ""));
+        assert!(parse_range("/nothing/", &context).is_err());
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(split_command("s/a/b/"), ("s", false, "/a/b/"));
+        assert_eq!(split_command("w! file"), ("w", true, "file"));
+        assert_eq!(split_command("w !sort"), ("w", false, "!sort"));
+        assert_eq!(split_command("!ls -l"), ("!", false, "ls -l"));
+        assert_eq!(split_command("m0"), ("m", false, "0"));
+        assert_eq!(split_command("g!/x/d"), ("g", true, "/x/d"));
+        assert_eq!(split_command("="), ("=", false, ""));
+
+        assert_eq!(split_pattern("a\\/b/d", '/'), ("a/b".into(), "d"));
+        assert_eq!(split_pattern("a\\.b", '/'), ("a\\.b".into(), ""));
+
+        assert_eq!(parse_register_count("").unwrap(), (None, None));
+        assert_eq!(parse_register_count("a 3").unwrap(), (Some('a'), Some(3)));
+        assert_eq!(parse_register_count("4").unwrap(), (None, Some(4)));
+        assert!(parse_register_count("a b").is_err());
+
+        let lines: Vec<String> = ["one", "two", "three"].map(String::from).to_vec();
+        let marks = Marks::default();
+        let context = Context {
+            lines: &lines,
+            current: 1,
+            marks: &marks,
+            pattern: None,
+            ignore_case: true,
+        };
+        assert_eq!(parse_destination("0", &context).unwrap(), 0);
+        assert_eq!(parse_destination(".", &context).unwrap(), 2);
+        assert_eq!(parse_destination("$", &context).unwrap(), 3);
+        assert_eq!(parse_destination("/ONE/", &context).unwrap(), 1);
+        assert!(parse_destination("", &context).is_err());
+        assert!(parse_destination("1x", &context).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+//! Marks, which remember positions in the file, and the jump list.
+use super::normal::Position;
+use std::collections::BTreeMap;
+
+/// How many jumps are remembered.
+const MAX_JUMPS: usize = 100;
+
+/// Marks set with `m`, and where the cursor jumped from.
+#[derive(Default)]
+pub struct Marks {
+    marks: BTreeMap<char, Position>,
+    /// Positions jumped from, oldest first.
+    jumps: Vec<Position>,
+    /// Where in the jump list Ctrl-O and Ctrl-I have got to, which is past the end unless
+    /// going back through it.
+    index: usize,
+}
+
+impl Marks {
+    /// Check if a mark can be set with `m`.
+    pub fn is_valid(name: char) -> bool {
+        name.is_ascii_alphabetic() || matches!(name, '\'' | '`' | '<' | '>')
+    }
+
+    pub fn get(&self, name: char) -> Option<Position> {
+        let name = if name == '`' { '\'' } else { name };
+        self.marks.get(&name).copied()
+    }
+
+    pub fn set(&mut self, name: char, position: Position) {
+        let name = if name == '`' { '\'' } else { name };
+        self.marks.insert(name, position);
+    }
+
+    /// Every mark that's set, in order.
+    pub fn list(&self) -> impl Iterator<Item = (char, Position)> + '_ {
+        self.marks.iter().map(|(name, position)| (*name, *position))
+    }
+
+    /// Remember a position being jumped from, which also becomes the `''` mark.
+    pub fn jump(&mut self, from: Position) {
+        self.set('\'', from);
+        // Only the latest jump from each line is kept.
+        self.jumps.retain(|jump| jump.0 != from.0);
+        self.jumps.push(from);
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.index = self.jumps.len();
+    }
+
+    /// Go back through the jump list from `current`, for Ctrl-O.
+    pub fn back(&mut self, current: Position, count: usize) -> Option<Position> {
+        if self.index == self.jumps.len() {
+            // Remember where this started, so Ctrl-I can come back.
+            self.jump(current);
+            self.index -= 1;
+        }
+        self.index = self.index.checked_sub(count)?;
+        Some(self.jumps[self.index])
+    }
+
+    /// Go forward through the jump list, for Ctrl-I.
+    pub fn forward(&mut self, count: usize) -> Option<Position> {
+        let index = self.index + count;
+        let position = *self.jumps.get(index)?;
+        self.index = index;
+        Some(position)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn jumps() {
+        let mut marks = Marks::default();
+        assert_eq!(marks.back((0, 0), 1), None);
+        let mut marks = Marks::default();
+        marks.jump((2, 0));
+        marks.jump((5, 2));
+        marks.jump((1, 3));
+        assert_eq!(marks.get('`'), Some((1, 3)));
+        assert_eq!(marks.back((9, 0), 1), Some((1, 3)));
+        assert_eq!(marks.back((1, 3), 2), Some((2, 0)));
+        assert_eq!(marks.back((2, 0), 1), None);
+        assert_eq!(marks.forward(1), Some((5, 2)));
+        assert_eq!(marks.forward(2), Some((9, 0)));
+        assert_eq!(marks.forward(1), None);
+        marks.set('a', (2, 2));
+        let names: String = marks.list().map(|(name, _)| name).collect();
+        assert_eq!(names, "'a");
+    }
+}
@@ -0,0 +1,594 @@
+use crate::{
+    process::{ExitCode, Process},
+    programs::common::{
+        keys::Key,
+        readline::{NullHistory, Readline},
+        unicode,
+    },
+    streams::{InputMode, InputStream, OutputStream},
+    utils, AnsiCode, ControlChar,
+};
+use anyhow::Result;
+use ascii::AsciiChar;
+use clap::Parser;
+use normal::{Action, Normal, Position, Visual};
+use regex::Regex;
+use search::Substitution;
+use settings::Settings;
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+};
+use syntax::Highlighter;
+use undo::UndoTree;
+use window::{Buffer, Layout, Window};
+
+mod display;
+mod ex;
+mod marks;
+mod normal;
+mod registers;
+mod search;
+mod settings;
+mod swap;
+mod syntax;
+mod undo;
+mod window;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Mode {
+    Insert,
+    Normal,
+}
+
+/// Replace the lines with ones from undo history, returning the first row that changed.
+fn restore(buffers: &mut Vec<String>, lines: &[String]) -> usize {
+    let row = (0..buffers.len())
+        .find(|&row| buffers.get(row) != lines.get(row))
+        .unwrap_or(buffers.len());
+    *buffers = lines.to_vec();
+    if buffers.is_empty() {
+        buffers.push(String::new());
+    }
+    std::cmp::min(row, buffers.len() - 1)
+}
+
+/// Split text into lines, without the line break at the end.
+fn split_lines(text: &str) -> Vec<String> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    text.split('\n').map(String::from).collect()
+}
+
+/// Read the lines of a file, or `None` if it doesn't exist.
+fn read_file(process: &Process, file: &str) -> Result<Option<Vec<String>>> {
+    let file = process.get_path(file)?;
+    if !file.exists()? {
+        return Ok(None);
+    }
+    let mut contents = String::new();
+    file.open_file()?.read_to_string(&mut contents)?;
+    Ok(Some(split_lines(&contents)))
+}
+
+/// The last pattern searched for.
+struct Search {
+    pattern: String,
+    regex: Regex,
+    forward: bool,
+}
+
+/// Show a message on the bottom line.
+fn message(stdout: &mut OutputStream, height: usize, text: &str) -> Result<()> {
+    stdout.write_all(&AnsiCode::AbsolutePosition(height, 0).to_bytes())?;
+    stdout.write_all(&AnsiCode::ClearLine.to_bytes())?;
+    stdout.write_all(text.as_bytes())?;
+    Ok(())
+}
+
+/// Visual file editor.
+///
+/// Press the 'i' key to go into "input mode"
+/// Press <esc> to go into "normal mode"
+///
+/// Use the arrow keys to navigate in either mode.
+///
+/// Commands take counts and motions, like 3dw, ci( or 2>>
+/// Select text with v, V or Ctrl-V, then d, y, c, >, < or ~
+/// Registers: "ayy, then "ap to put it (:reg lists them)
+/// Marks: ma, then 'a to go back to it (Ctrl-O and Ctrl-I go through jumps)
+/// Repeat the last change: .
+/// Undo: u
+/// Redo: Ctrl-R
+/// Search: /pattern, then n for the next match
+/// Replace: <esc> :%s/pattern/replacement/g
+/// Ex commands: :g/pattern/d, :m0, :t., :r !fortune, :%!sort, :e file
+/// Options: :set number, :set relativenumber, :set tabstop=4, :set ignorecase
+/// Long lines wrap: gj and gk move by rows of the screen
+/// Shell scripts, Rust, Markdown and themes are highlighted
+/// Files: vi a b c, then :n, :prev, :ls and :b 2 to go between them
+/// Windows: :split, :vsplit, then Ctrl-W w, h, j, k or l to move between them
+/// Changes are kept in a swap file, .name.swp, until vi quits, to recover them if it's stopped
+///
+/// Save and quit: <esc> :wq
+/// Quit without saving: <esc> :q!
+#[derive(Parser)]
+#[command(verbatim_doc_comment)]
+pub(super) struct Options {
+    /// The files to edit.
+    files: Vec<String>,
+}
+
+/// Everything about the files being edited and how they're shown.
+struct Editor<'a> {
+    process: &'a Process,
+    stdin: InputStream,
+    stdout: OutputStream,
+    /// Number of rows of windows on the screen, with the bottom line below them.
+    height: usize,
+    width: usize,
+    /// The current buffer's file and lines.
+    file: Option<String>,
+    lines: Vec<String>,
+    highlighter: Highlighter,
+    /// The current window's cursor.
+    cursor: Position,
+    /// The first row shown in the current window.
+    offset: usize,
+    /// Every buffer, with the current one's contents kept in the fields above.
+    buffers: Vec<Buffer>,
+    buffer: usize,
+    windows: Vec<Window>,
+    window: usize,
+    layout: Layout,
+    /// The files given to edit, and which of them `:n` and `:prev` are at.
+    arguments: Vec<String>,
+    argument: usize,
+    mode: Mode,
+    /// If the whole screen needs to be drawn again.
+    reset: bool,
+    undo: UndoTree,
+    normal: Normal,
+    readline: Readline<NullHistory>,
+    search: Option<Search>,
+    /// Whether matches of the search pattern are highlighted.
+    highlight_search: bool,
+    last_substitution: Option<Substitution>,
+    /// A message to show at the bottom of the screen once it's drawn.
+    status: Option<String>,
+    /// Keys to handle before reading any more, when repeating a change.
+    pending: VecDeque<Key>,
+    /// Text being selected in visual mode.
+    visual: Option<Visual>,
+    settings: Settings,
+    /// Lines printed by an ex command, shown once it's finished.
+    output: String,
+    /// Which lines a `:g` command has yet to run on.
+    global: Option<Vec<bool>>,
+    /// Keys typed since the swap file was last brought up to date.
+    typed: usize,
+}
+
+impl Editor<'_> {
+    /// Show a message, like an error, until a key is pressed.
+    async fn show(&mut self, message: &str) -> Result<()> {
+        self.stdout.write_all(&AnsiCode::Clear.to_bytes())?;
+        self.stdout.write_all(message.as_bytes())?;
+        self.stdout.write_all(b"\n")?;
+        self.wait().await
+    }
+
+    /// Wait for a key to be pressed before drawing the screen again.
+    async fn wait(&mut self) -> Result<()> {
+        self.reset = true;
+        self.stdout.write_all(b"\nPress any key to continue\n")?;
+        self.stdin.get_char().await?;
+        Ok(())
+    }
+
+    /// Read a line, like a search pattern or ex command, from the bottom of the screen.
+    async fn prompt(&mut self, prompt: &str) -> Result<String> {
+        self.reset = true;
+        self.stdout
+            .write_all(&AnsiCode::AbsolutePosition(self.height, 0).to_bytes())?;
+        let line = self
+            .readline
+            .get_line(prompt, &mut self.stdin, &mut self.stdout, |_, _| async {
+                Ok(Default::default())
+            })
+            .await?;
+        self.stdin.set_mode(InputMode::Char).await?;
+        Ok(line)
+    }
+
+    /// Handle a key typed in insert mode.
+    fn insert_key(&mut self, key: Key) {
+        let (row, column) = self.cursor;
+        if key == Key::Escape || key == Key::Char(ControlChar::D as u8 as char) {
+            self.normal.finish_insert(&mut self.lines);
+            self.cursor.1 = normal::prev(&self.lines[row], column);
+            self.mode = Mode::Normal;
+            return;
+        }
+        self.normal.record(key);
+        let height = self.line_height(row);
+
+        let line = &mut self.lines[row];
+        match key {
+            // Keep to the same column of the screen, or the end of the line if it's shorter.
+            Key::Up | Key::Down => {
+                let tabstop = self.settings.tabstop;
+                let wanted = unicode::column(line, column, tabstop);
+                let target = if key == Key::Up {
+                    row.saturating_sub(1)
+                } else {
+                    std::cmp::min(row + 1, self.lines.len() - 1)
+                };
+                let line = &self.lines[target];
+                let index = if unicode::column(line, line.len(), tabstop) <= wanted {
+                    line.len()
+                } else {
+                    display::index_at(line, (0, wanted), usize::MAX, tabstop)
+                };
+                self.cursor = (target, index);
+            }
+            Key::Left => self.cursor.1 = normal::prev(line, column),
+            Key::Right => self.cursor.1 = normal::next(line, column),
+            Key::Char(c) if c == ControlChar::A => self.cursor.1 = 0,
+            Key::Char(c) if c == ControlChar::E => self.cursor.1 = line.len(),
+            Key::Char(c) if c == AsciiChar::BackSpace => {
+                if column > 0 {
+                    let start = normal::prev(line, column);
+                    line.replace_range(start..column, "");
+                    self.cursor.1 = start;
+                // Merge this line with previous
+                } else if row > 0 {
+                    let line = self.lines.remove(row);
+                    let previous = &mut self.lines[row - 1];
+                    self.cursor = (row - 1, previous.len());
+                    previous.push_str(&line);
+                    self.reset = true;
+                }
+            }
+            Key::Char('\n' | '\r') => {
+                let rest = line.split_off(column);
+                self.lines.insert(row + 1, rest);
+                self.cursor = (row + 1, 0);
+                self.reset = true;
+            }
+            Key::Char(c) if !c.is_control() || c == '\t' => {
+                line.insert(column, c);
+                self.cursor.1 += c.len_utf8();
+            }
+            _ => {}
+        }
+        // Lines below move if this one now wraps onto more or fewer rows.
+        if row < self.lines.len() && self.line_height(row) != height {
+            self.reset = true;
+        }
+    }
+
+    /// Go back or forward through undo history.
+    fn restore_undo(&mut self, lines: Option<Vec<String>>) {
+        if let Some(lines) = lines {
+            self.cursor = (restore(&mut self.lines, &lines), 0);
+            self.reset = true;
+        }
+    }
+
+    /// Search for the next match of the search pattern, or the previous one if `reverse`.
+    fn search_again(&mut self, reverse: bool, count: usize) {
+        let Some(search) = &self.search else {
+            self.status = Some("No previous regular expression".into());
+            return;
+        };
+        if !self.highlight_search {
+            self.highlight_search = true;
+            self.reset = true;
+        }
+
+        let forward = search.forward != reverse;
+        let start = self.cursor;
+        for _ in 0..count {
+            match search::find(&self.lines, &search.regex, self.cursor, forward) {
+                Some((position, wrapped)) => {
+                    self.cursor = position;
+                    if wrapped {
+                        self.status = Some(if forward {
+                            "search hit BOTTOM, continuing at TOP".into()
+                        } else {
+                            "search hit TOP, continuing at BOTTOM".into()
+                        });
+                    }
+                }
+                None => {
+                    self.status = Some(format!("Pattern not found: {}", search.pattern));
+                    break;
+                }
+            }
+        }
+        if self.cursor != start {
+            self.normal.marks.jump(start);
+        }
+    }
+
+    /// Handle a key typed in normal mode. Returns true if the editor should quit.
+    async fn normal_key(&mut self, key: Key) -> Result<bool> {
+        let screen = self.screen();
+        let before = self.cursor;
+        let visual = self.visual;
+        let action = match self.visual.as_mut() {
+            Some(visual) => self.normal.handle_visual_key(
+                key,
+                &mut self.lines,
+                &mut self.cursor,
+                visual,
+                screen.clone(),
+            ),
+            None => self
+                .normal
+                .handle_key(key, &mut self.lines, &mut self.cursor, screen.clone()),
+        };
+        let Some(action) = action else {
+            return Ok(false);
+        };
+
+        // Stop selecting once something's done with the selection, which is redrawn either way.
+        if let Some(visual) = visual {
+            self.reset = true;
+            if matches!(action, Action::Normal | Action::Insert | Action::Ex) {
+                // Remember the selection for the `'<` and `'>` marks.
+                let (start, end) = visual.ends(before);
+                self.normal.marks.set('<', start);
+                self.normal.marks.set('>', end);
+                self.visual = None;
+            }
+        }
+
+        // Redraw everything if lines other than the cursor's changed, or it changed how many
+        // rows it wraps onto.
+        let previous = self.undo.current();
+        let changed = |row: usize, old: &String, new: &String| {
+            old != new
+                && (row != self.cursor.0
+                    || display::height(old, screen.width, screen.tabstop)
+                        != display::height(new, screen.width, screen.tabstop))
+        };
+        if previous.len() != self.lines.len()
+            || (previous.iter().zip(&self.lines).enumerate())
+                .any(|(row, (old, new))| changed(row, old, new))
+        {
+            self.reset = true;
+        }
+
+        let (row, column) = self.cursor;
+        match action {
+            Action::Done | Action::Normal => {}
+            Action::Visual(selection) => {
+                match self.visual.as_mut() {
+                    Some(visual) => visual.selection = selection,
+                    None => self.visual = Some(Visual::new(selection, self.cursor)),
+                }
+                self.reset = true;
+            }
+            Action::Insert => self.mode = Mode::Insert,
+            Action::Undo { count } | Action::Redo { count } => {
+                let mut lines = None;
+                for _ in 0..count {
+                    let state = match action {
+                        Action::Undo { .. } => self.undo.undo(),
+                        _ => self.undo.redo(),
+                    };
+                    match state {
+                        Some(state) => lines = Some(state.to_vec()),
+                        None => break,
+                    }
+                }
+                self.restore_undo(lines);
+            }
+            Action::UndoLine => {
+                let mut line = self.lines[row].clone();
+                if self.undo.undo_line(row, &mut line) {
+                    self.lines[row] = line;
+                    self.cursor.1 = 0;
+                }
+            }
+            Action::Repeat { count } => {
+                for key in self.normal.repeat_keys(count).into_iter().rev() {
+                    self.pending.push_front(key);
+                }
+            }
+            Action::Search { forward } => {
+                let prefix = if forward { "/" } else { "?" };
+                let pattern = self.prompt(prefix).await?;
+                // An empty pattern searches for the last one again.
+                if pattern.is_empty() {
+                    if let Some(search) = self.search.as_mut() {
+                        search.forward = forward;
+                    }
+                } else {
+                    match search::compile(&pattern, self.settings.ignore_case) {
+                        Ok(regex) => {
+                            self.search = Some(Search {
+                                pattern,
+                                regex,
+                                forward,
+                            })
+                        }
+                        Err(err) => {
+                            self.show(&err.to_string()).await?;
+                            return Ok(false);
+                        }
+                    }
+                }
+                self.search_again(false, 1);
+            }
+            Action::SearchAgain { reverse, count } => self.search_again(reverse, count),
+            Action::SearchWord { forward } => {
+                let Some(word) = search::word_at(&self.lines[row], column) else {
+                    self.status = Some("No string under cursor".into());
+                    return Ok(false);
+                };
+                self.cursor.1 = word.start;
+                let pattern = search::word_pattern(&self.lines[row][word]);
+                self.search = Some(Search {
+                    regex: search::compile(&pattern, self.settings.ignore_case)?,
+                    pattern,
+                    forward,
+                });
+                self.search_again(false, 1);
+            }
+            Action::RepeatSubstitute => {
+                if let Some(substitution) = &self.last_substitution {
+                    // Repeat the last substitution on this line, without its flags.
+                    let substitution = Substitution {
+                        global: false,
+                        confirm: false,
+                        ..substitution.clone()
+                    };
+                    let ignore_case = substitution
+                        .ignore_case
+                        .unwrap_or(self.settings.ignore_case);
+                    let regex = search::compile(&substitution.pattern, ignore_case)?;
+                    let (count, _) = self.substitute((row, row), &regex, &substitution).await?;
+                    if count == 0 {
+                        self.status = Some(format!("Pattern not found: {}", substitution.pattern));
+                    }
+                }
+            }
+            Action::Window { key, count } => match self.window_command(key, count) {
+                Ok(quit) => return Ok(quit),
+                Err(err) => self.status = Some(err.to_string()),
+            },
+            Action::Ex => {
+                // Commands typed while selecting act on the selected lines.
+                let range = if visual.is_some() { "'<,'>" } else { "" };
+                let command = self.prompt(&format!(":{range}")).await?;
+                return self.ex_command(&format!("{range}{command}")).await;
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl<'a> Editor<'a> {
+    /// An editor for a process, with no files open yet.
+    fn new(process: &'a Process, stdin: InputStream, (height, width): (usize, usize)) -> Self {
+        Self {
+            process,
+            stdin,
+            stdout: process.stdout.clone(),
+            height,
+            width,
+            file: None,
+            lines: Vec::new(),
+            highlighter: Highlighter::default(),
+            undo: UndoTree::new(&[]),
+            cursor: (0, 0),
+            offset: 0,
+            buffers: Vec::new(),
+            buffer: 0,
+            windows: vec![Window {
+                buffer: 0,
+                cursor: (0, 0),
+                offset: 0,
+            }],
+            window: 0,
+            layout: Layout::Window,
+            arguments: Vec::new(),
+            argument: 0,
+            mode: Mode::Normal,
+            reset: true,
+            normal: Normal::default(),
+            readline: Readline::new(NullHistory),
+            search: None,
+            highlight_search: false,
+            last_substitution: None,
+            status: None,
+            pending: VecDeque::new(),
+            visual: None,
+            settings: Settings::default(),
+            output: String::new(),
+            global: None,
+            typed: 0,
+        }
+    }
+
+    /// Edit files until told to quit.
+    async fn run(&mut self, files: Vec<String>) -> Result<()> {
+        self.arguments = files;
+        for file in &self.arguments.clone() {
+            self.open(file).await?;
+        }
+        if self.buffers.is_empty() {
+            self.buffers.push(Buffer::new(None, Vec::new()));
+        }
+        self.load_buffer(0);
+
+        loop {
+            // Each command, or everything typed in insert mode, is undone at once.
+            if self.mode == Mode::Normal {
+                self.undo.commit(&self.lines);
+            }
+
+            self.draw().await?;
+            let key = match self.pending.pop_front() {
+                Some(key) => key,
+                None => Key::read(&mut self.stdin).await?,
+            };
+            if self.mode == Mode::Insert {
+                self.insert_key(key);
+            } else if self.normal_key(key).await? {
+                break;
+            }
+
+            self.typed += 1;
+            if self.mode == Mode::Normal || self.typed >= self.settings.update_count {
+                self.update_swap();
+            }
+        }
+
+        for index in 0..self.buffers.len() {
+            self.remove_swap(index);
+        }
+
+        self.stdout.write_all(&AnsiCode::Clear.to_bytes())?;
+        Ok(())
+    }
+}
+
+pub async fn vi(process: &Process) -> Result<ExitCode> {
+    let options = Options::try_parse_from(&process.args)?;
+
+    let mut stdin = process.stdin.clone();
+    stdin.set_mode(InputMode::Char).await?;
+
+    let size = (
+        utils::js_term_get_screen_height(),
+        utils::get_screen_width(),
+    );
+    Editor::new(process, stdin, size).run(options.files).await?;
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    pub(super) use crate::programs::common::testing::{contents, file_system};
+    use crate::programs::common::testing;
+    use vfs::VfsPath;
+
+    /// Edit files in a file system, typing `keys` on a screen 24 rows by 80 columns. Returns
+    /// what the editor ended with, which is an error if it was still running when the keys ran
+    /// out, and everything it wrote to the screen.
+    pub(super) async fn edit(cwd: &VfsPath, files: &[&str], keys: &str) -> (Result<()>, String) {
+        let files = files.iter().map(|file| file.to_string()).collect();
+        testing::run(cwd, &[], keys, async |process: &mut Process| {
+            let stdin = process.stdin.clone();
+            Editor::new(process, stdin, (24, 80)).run(files).await
+        })
+        .await
+    }
+}
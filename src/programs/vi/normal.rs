@@ -0,0 +1,1863 @@
+//! Normal mode commands.
+//!
+//! Commands follow vi's grammar: an optional count, then a motion, a simple command, or an
+//! operator followed by what it acts on. That's a motion, a text object like `iw`, or the
+//! operator again for whole lines, as in `dd`.
+use super::{
+    display,
+    marks::Marks,
+    registers::{Register, Registers},
+};
+pub use crate::programs::common::unicode::{next_grapheme as next, prev_grapheme as prev};
+use crate::{
+    programs::common::{
+        keys::Key,
+        line_motions::{self, class, last_char},
+        unicode::{self, grapheme_start, grapheme_width},
+    },
+    ControlChar,
+};
+use ascii::AsciiChar;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+pub use visual::{Selection, Visual};
+
+mod visual;
+
+/// A row and a byte index into that row.
+pub type Position = (usize, usize);
+
+/// What's shown of the lines, for motions that depend on the screen.
+#[derive(Clone, Debug)]
+pub struct Screen {
+    /// The rows shown in full.
+    pub rows: Range<usize>,
+    /// How many columns of text fit across, which longer lines are wrapped at.
+    pub width: usize,
+    pub tabstop: usize,
+}
+
+/// How many spaces `>` and `<` shift lines by.
+const SHIFT_WIDTH: usize = 4;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Motion {
+    Left,
+    Right,
+    /// Backspace and space, which move onto other lines.
+    PreviousChar,
+    NextChar,
+    Up,
+    Down,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    /// Start of the next word, or WORD if `big`.
+    WordForward(bool),
+    /// Start of the previous word.
+    WordBackward(bool),
+    /// End of the word.
+    WordEnd(bool),
+    /// Find a character in the line with f, F, t or T.
+    Find {
+        c: char,
+        forward: bool,
+        till: bool,
+    },
+    /// Repeat the last find, in the opposite direction if `reverse`.
+    RepeatFind {
+        reverse: bool,
+    },
+    /// The bracket matching the one under or after the cursor.
+    MatchingBracket,
+    /// The line given by the count, or the last line with `G` or the first with `gg`.
+    GoToLine {
+        last: bool,
+    },
+    ScreenTop,
+    ScreenBottom,
+    /// Down or up a row of the screen with `gj` and `gk`, which is part of a line if it wraps.
+    RowDown,
+    RowUp,
+    /// The start or end of the row of the screen with `g0` and `g$`.
+    RowStart,
+    RowEnd,
+    /// A mark's line with `'`, or its exact position with `` ` ``.
+    Mark {
+        name: char,
+        exact: bool,
+    },
+}
+
+impl Motion {
+    /// If moving like this is a jump, which is remembered in the jump list.
+    fn is_jump(self) -> bool {
+        matches!(
+            self,
+            Self::MatchingBracket
+                | Self::GoToLine { .. }
+                | Self::ScreenTop
+                | Self::ScreenBottom
+                | Self::Mark { .. }
+        )
+    }
+}
+
+/// Which text a motion covers, when an operator is applied to it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Extent {
+    /// Up to but not including the target.
+    Exclusive,
+    /// Up to and including the target.
+    Inclusive,
+    /// Every line from the cursor's to the target's.
+    Linewise,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum TextObject {
+    Word { big: bool },
+    Quote(char),
+    Brackets(char, char),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent,
+    ToggleCase,
+    Lowercase,
+    Uppercase,
+}
+
+/// What an operator acts on.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Target {
+    Motion(Motion),
+    /// A text object, including the space or delimiters around it if `around`.
+    Object {
+        object: TextObject,
+        around: bool,
+    },
+    /// Whole lines, when the operator is doubled.
+    Line,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Insert {
+    Before,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Command {
+    Move(Motion),
+    Operate(Operator, Target),
+    Insert(Insert),
+    Put {
+        before: bool,
+    },
+    Replace(char),
+    ToggleCase,
+    Join,
+    Undo,
+    Redo,
+    UndoLine,
+    Repeat,
+    Search {
+        forward: bool,
+    },
+    SearchAgain {
+        reverse: bool,
+    },
+    SearchWord {
+        forward: bool,
+    },
+    RepeatSubstitute,
+    Ex,
+    Visual(Selection),
+    SetMark(char),
+    /// Go back or forward through the jump list.
+    JumpBack,
+    JumpForward,
+    /// A window command, typed after Ctrl-W.
+    Window(char),
+}
+
+impl Command {
+    /// If this command changes text, so `.` should repeat it.
+    fn is_change(&self) -> bool {
+        matches!(
+            self,
+            Self::Operate(operator, _) if *operator != Operator::Yank
+        ) || matches!(
+            self,
+            Self::Insert(_) | Self::Put { .. } | Self::Replace(_) | Self::ToggleCase | Self::Join
+        )
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct ParsedCommand {
+    count: Option<usize>,
+    /// The register named with `"`.
+    register: Option<char>,
+    command: Command,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+enum Parse<T = ParsedCommand> {
+    /// More keys are needed.
+    Incomplete,
+    Invalid,
+    Done(T),
+}
+
+/// What the editor needs to do after a command.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    /// The command is done, or couldn't be done.
+    Done,
+    /// Switch to insert mode.
+    Insert,
+    Undo {
+        count: usize,
+    },
+    Redo {
+        count: usize,
+    },
+    UndoLine,
+    /// Replay the keys of the last change.
+    Repeat {
+        count: Option<usize>,
+    },
+    /// Prompt for a pattern to search for.
+    Search {
+        forward: bool,
+    },
+    SearchAgain {
+        reverse: bool,
+        count: usize,
+    },
+    /// Search for the word under the cursor.
+    SearchWord {
+        forward: bool,
+    },
+    RepeatSubstitute,
+    /// Prompt for an ex command.
+    Ex,
+    /// Start selecting text, or switch to another kind of selection.
+    Visual(Selection),
+    /// Stop selecting text.
+    Normal,
+    /// Split, close or move between windows.
+    Window {
+        key: char,
+        count: Option<usize>,
+    },
+}
+
+/// The character a key stands for in normal mode.
+fn normal_char(key: Key) -> Option<char> {
+    Some(match key {
+        Key::Char(c) if c == ControlChar::A => '0',
+        Key::Char(c) if c == ControlChar::E => '$',
+        Key::Char(c) => c,
+        Key::Left => 'h',
+        Key::Right => 'l',
+        Key::Up => 'k',
+        Key::Down => 'j',
+        Key::Meta(_) | Key::Escape | Key::BackTab => return None,
+    })
+}
+
+/// Read a count, if there is one.
+fn parse_count(chars: &[char], position: &mut usize) -> Option<usize> {
+    let start = *position;
+    while let Some(c) = chars.get(*position) {
+        // Zero on its own is a motion.
+        if !c.is_ascii_digit() || (*c == '0' && *position == start) {
+            break;
+        }
+        *position += 1;
+    }
+    chars[start..*position]
+        .iter()
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+/// Read a register name given with `"`, if there is one. Returns `Err(true)` if more keys are
+/// needed, or `Err(false)` if the name is invalid.
+fn parse_register(chars: &[char], position: &mut usize) -> Result<Option<char>, bool> {
+    if chars.get(*position) != Some(&'"') {
+        return Ok(None);
+    }
+    let name = *chars.get(*position + 1).ok_or(true)?;
+    if !Registers::is_valid(name) {
+        return Err(false);
+    }
+    *position += 2;
+    Ok(Some(name))
+}
+
+/// Read a motion. Returns `Err(true)` if more keys are needed, or `Err(false)` if invalid.
+fn parse_motion(chars: &[char], position: &mut usize) -> Result<Motion, bool> {
+    let c = *chars.get(*position).ok_or(true)?;
+    *position += 1;
+    Ok(match c {
+        'h' => Motion::Left,
+        'l' => Motion::Right,
+        'j' => Motion::Down,
+        'k' => Motion::Up,
+        ' ' => Motion::NextChar,
+        c if c == AsciiChar::BackSpace => Motion::PreviousChar,
+        '0' => Motion::LineStart,
+        '^' => Motion::FirstNonBlank,
+        '$' => Motion::LineEnd,
+        'w' | 'W' => Motion::WordForward(c == 'W'),
+        'b' | 'B' => Motion::WordBackward(c == 'B'),
+        'e' | 'E' => Motion::WordEnd(c == 'E'),
+        'f' | 'F' | 't' | 'T' => {
+            let target = *chars.get(*position).ok_or(true)?;
+            *position += 1;
+            Motion::Find {
+                c: target,
+                forward: c.is_lowercase(),
+                till: c.eq_ignore_ascii_case(&'t'),
+            }
+        }
+        ';' | ',' => Motion::RepeatFind { reverse: c == ',' },
+        '%' => Motion::MatchingBracket,
+        'G' => Motion::GoToLine { last: true },
+        'g' => match chars.get(*position) {
+            Some(&next @ ('g' | 'j' | 'k' | '0' | '$')) => {
+                *position += 1;
+                match next {
+                    'g' => Motion::GoToLine { last: false },
+                    'j' => Motion::RowDown,
+                    'k' => Motion::RowUp,
+                    '0' => Motion::RowStart,
+                    _ => Motion::RowEnd,
+                }
+            }
+            Some(_) => return Err(false),
+            None => return Err(true),
+        },
+        'H' => Motion::ScreenTop,
+        'L' => Motion::ScreenBottom,
+        '\'' | '`' => {
+            let name = *chars.get(*position).ok_or(true)?;
+            *position += 1;
+            Motion::Mark {
+                name,
+                exact: c == '`',
+            }
+        }
+        _ => return Err(false),
+    })
+}
+
+/// Read a text object's type, after `i` or `a`.
+fn parse_object(c: char) -> Option<TextObject> {
+    Some(match c {
+        'w' | 'W' => TextObject::Word { big: c == 'W' },
+        '"' | '\'' | '`' => TextObject::Quote(c),
+        '(' | ')' | 'b' => TextObject::Brackets('(', ')'),
+        '[' | ']' => TextObject::Brackets('[', ']'),
+        '{' | '}' | 'B' => TextObject::Brackets('{', '}'),
+        '<' | '>' => TextObject::Brackets('<', '>'),
+        _ => return None,
+    })
+}
+
+/// Parse a normal mode command from the keys typed so far.
+fn parse(keys: &[Key]) -> Parse {
+    let Some(chars) = keys
+        .iter()
+        .map(|key| normal_char(*key))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Parse::Invalid;
+    };
+
+    let mut position = 0;
+    let mut count = parse_count(&chars, &mut position);
+    let register = match parse_register(&chars, &mut position) {
+        Ok(register) => register,
+        Err(true) => return Parse::Incomplete,
+        Err(false) => return Parse::Invalid,
+    };
+    if let Some(register_count) = parse_count(&chars, &mut position) {
+        count = Some(count.unwrap_or(1) * register_count);
+    }
+    let Some(&c) = chars.get(position) else {
+        return Parse::Incomplete;
+    };
+
+    // Operators, which may be two keys long.
+    let operator = match (c, chars.get(position + 1)) {
+        ('d', _) => Some((Operator::Delete, 1)),
+        ('c', _) => Some((Operator::Change, 1)),
+        ('y', _) => Some((Operator::Yank, 1)),
+        ('>', _) => Some((Operator::Indent, 1)),
+        ('<', _) => Some((Operator::Outdent, 1)),
+        ('g', Some('~')) => Some((Operator::ToggleCase, 2)),
+        ('g', Some('u')) => Some((Operator::Lowercase, 2)),
+        ('g', Some('U')) => Some((Operator::Uppercase, 2)),
+        ('g', None) => return Parse::Incomplete,
+        _ => None,
+    };
+
+    let command = if let Some((operator, length)) = operator {
+        let name = &chars[position..position + length];
+        position += length;
+        if let Some(motion_count) = parse_count(&chars, &mut position) {
+            count = Some(count.unwrap_or(1) * motion_count);
+        }
+
+        // Doubling the operator, or its last key, acts on whole lines.
+        let rest = &chars[position..];
+        if rest.starts_with(name) || rest.first() == name.last() {
+            Command::Operate(operator, Target::Line)
+        } else if name.starts_with(rest) {
+            return Parse::Incomplete;
+        } else if let Some(&kind @ ('i' | 'a')) = rest.first() {
+            match rest.get(1).map(|c| parse_object(*c)) {
+                Some(Some(object)) => Command::Operate(
+                    operator,
+                    Target::Object {
+                        object,
+                        around: kind == 'a',
+                    },
+                ),
+                Some(None) => return Parse::Invalid,
+                None => return Parse::Incomplete,
+            }
+        } else {
+            match parse_motion(&chars, &mut position) {
+                Ok(motion) => Command::Operate(operator, Target::Motion(motion)),
+                Err(true) => return Parse::Incomplete,
+                Err(false) => return Parse::Invalid,
+            }
+        }
+    } else {
+        match parse_motion(&chars, &mut position) {
+            Ok(motion) => Command::Move(motion),
+            Err(true) => return Parse::Incomplete,
+            Err(false) => match c {
+                'i' => Command::Insert(Insert::Before),
+                'a' => Command::Insert(Insert::After),
+                'I' => Command::Insert(Insert::LineStart),
+                'A' => Command::Insert(Insert::LineEnd),
+                'o' => Command::Insert(Insert::LineBelow),
+                'O' => Command::Insert(Insert::LineAbove),
+                'x' => Command::Operate(Operator::Delete, Target::Motion(Motion::Right)),
+                'X' => Command::Operate(Operator::Delete, Target::Motion(Motion::Left)),
+                'D' => Command::Operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+                'C' => Command::Operate(Operator::Change, Target::Motion(Motion::LineEnd)),
+                's' => Command::Operate(Operator::Change, Target::Motion(Motion::Right)),
+                'S' => Command::Operate(Operator::Change, Target::Line),
+                'Y' => Command::Operate(Operator::Yank, Target::Line),
+                'p' | 'P' => Command::Put { before: c == 'P' },
+                'r' => match chars.get(position) {
+                    Some(&c) => Command::Replace(c),
+                    None => return Parse::Incomplete,
+                },
+                '~' => Command::ToggleCase,
+                'J' => Command::Join,
+                'u' => Command::Undo,
+                c if c == ControlChar::R => Command::Redo,
+                'U' => Command::UndoLine,
+                '.' => Command::Repeat,
+                '/' | '?' => Command::Search { forward: c == '/' },
+                'n' | 'N' => Command::SearchAgain { reverse: c == 'N' },
+                '*' | '#' => Command::SearchWord { forward: c == '*' },
+                '&' => Command::RepeatSubstitute,
+                ':' => Command::Ex,
+                'v' => Command::Visual(Selection::Chars),
+                'V' => Command::Visual(Selection::Lines),
+                c if c == ControlChar::V => Command::Visual(Selection::Block),
+                'm' => match chars.get(position) {
+                    Some(&name) if Marks::is_valid(name) => Command::SetMark(name),
+                    Some(_) => return Parse::Invalid,
+                    None => return Parse::Incomplete,
+                },
+                c if c == ControlChar::O => Command::JumpBack,
+                c if c == ControlChar::I => Command::JumpForward,
+                // Control keys after Ctrl-W work like letters, so Ctrl-W Ctrl-W is Ctrl-W w.
+                c if c == ControlChar::W => match chars.get(position) {
+                    Some(&c @ '\u{1}'..='\u{1a}') => Command::Window((c as u8 + b'a' - 1) as char),
+                    Some(&c) => Command::Window(c),
+                    None => return Parse::Incomplete,
+                },
+                _ => return Parse::Invalid,
+            },
+        }
+    };
+
+    Parse::Done(ParsedCommand {
+        count,
+        register,
+        command,
+    })
+}
+
+/// Leave the counts out of a command's keys, so it can be repeated with a new one. Counts come
+/// before and after a register name, and after an operator.
+fn strip_counts(keys: &[Key]) -> Vec<Key> {
+    let mut stripped = Vec::new();
+    let mut rest = keys;
+    let skip_count = |rest: &mut &[Key]| {
+        // Zero on its own is a motion.
+        if !matches!(rest.first(), Some(Key::Char('1'..='9'))) {
+            return;
+        }
+        while let Some(Key::Char('0'..='9')) = rest.first() {
+            *rest = &rest[1..];
+        }
+    };
+    let mut take = |rest: &mut &[Key], length: usize| {
+        let length = std::cmp::min(length, rest.len());
+        stripped.extend_from_slice(&rest[..length]);
+        *rest = &rest[length..];
+    };
+
+    skip_count(&mut rest);
+    if rest.first() == Some(&Key::Char('"')) {
+        take(&mut rest, 2);
+        skip_count(&mut rest);
+    }
+    let operator = match rest {
+        [Key::Char('d' | 'c' | 'y' | '>' | '<'), ..] => 1,
+        [Key::Char('g'), Key::Char('~' | 'u' | 'U'), ..] => 2,
+        _ => 0,
+    };
+    take(&mut rest, operator);
+    skip_count(&mut rest);
+    stripped.extend_from_slice(rest);
+    stripped
+}
+
+/// Where the cursor can go in a line, which is on a character, at most its last one, rather
+/// than partway through one.
+pub fn clamp(line: &str, column: usize) -> usize {
+    grapheme_start(line, std::cmp::min(column, last_char(line)))
+}
+
+/// Where the character shown at `column` of a line starts, or the end of the line if it's
+/// shorter than that.
+fn byte_index(line: &str, column: usize) -> usize {
+    let mut end = 0;
+    (line.grapheme_indices(true))
+        .find(|(_, grapheme)| {
+            end += grapheme_width(grapheme);
+            end > column
+        })
+        .map_or(line.len(), |(index, _)| index)
+}
+
+/// Where the first non-blank character of a line is.
+pub fn first_non_blank(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// The class of the character at a position, where the end of a line counts as a blank.
+fn class_at(lines: &[String], (row, column): Position, big: bool) -> u8 {
+    lines[row][column..]
+        .chars()
+        .next()
+        .map_or(0, |c| class(c, big))
+}
+
+/// Step to the next character, treating the end of each line as a character of its own.
+fn step_forward(lines: &[String], (row, column): Position) -> Option<Position> {
+    if column < lines[row].len() {
+        Some((row, next(&lines[row], column)))
+    } else if row + 1 < lines.len() {
+        Some((row + 1, 0))
+    } else {
+        None
+    }
+}
+
+/// Step to the previous character, treating the end of each line as a character of its own.
+fn step_backward(lines: &[String], (row, column): Position) -> Option<Position> {
+    if column > 0 {
+        Some((row, prev(&lines[row], column)))
+    } else if row > 0 {
+        Some((row - 1, lines[row - 1].len()))
+    } else {
+        None
+    }
+}
+
+/// Empty lines count as words of their own.
+fn is_empty_line(lines: &[String], (row, _): Position) -> bool {
+    lines[row].is_empty()
+}
+
+/// Find the bracket matching the one at `position`.
+fn matching_bracket(lines: &[String], position: Position) -> Option<Position> {
+    let c = lines[position.0][position.1..].chars().next()?;
+    let (open, close, forward) = match c {
+        '(' => ('(', ')', true),
+        '[' => ('[', ']', true),
+        '{' => ('{', '}', true),
+        ')' => ('(', ')', false),
+        ']' => ('[', ']', false),
+        '}' => ('{', '}', false),
+        _ => return None,
+    };
+    find_unmatched(lines, position, open, close, forward)
+}
+
+/// Find the first `close` after `position` (or `open` before it, if not `forward`) that isn't
+/// part of a nested pair.
+fn find_unmatched(
+    lines: &[String],
+    mut position: Position,
+    open: char,
+    close: char,
+    forward: bool,
+) -> Option<Position> {
+    let mut depth = 0;
+    loop {
+        position = if forward {
+            step_forward(lines, position)?
+        } else {
+            step_backward(lines, position)?
+        };
+        let c = lines[position.0][position.1..].chars().next();
+        if c == Some(if forward { open } else { close }) {
+            depth += 1;
+        } else if c == Some(if forward { close } else { open }) {
+            if depth == 0 {
+                return Some(position);
+            }
+            depth -= 1;
+        }
+    }
+}
+
+/// Text a command acts on.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Span {
+    /// From a position up to, but not including, another.
+    Chars(Position, Position),
+    /// Whole lines, from one row to another inclusive.
+    Lines(usize, usize),
+    /// A rectangle, from one row to another inclusive, and from one character column up to
+    /// but not including another.
+    Block {
+        rows: (usize, usize),
+        columns: (usize, usize),
+    },
+}
+
+/// The part of each row that a span covers.
+fn ranges(lines: &[String], span: Span) -> Vec<(usize, Range<usize>)> {
+    match span {
+        Span::Lines(start, end) => (start..=end)
+            .map(|row| (row, 0..lines[row].len()))
+            .collect(),
+        Span::Chars(start, end) => (start.0..=end.0)
+            .map(|row| {
+                let from = if row == start.0 { start.1 } else { 0 };
+                let to = if row == end.0 {
+                    end.1
+                } else {
+                    lines[row].len()
+                };
+                (row, from..to)
+            })
+            .collect(),
+        Span::Block {
+            rows: (first, last),
+            columns: (left, right),
+        } => (first..=last)
+            .map(|row| {
+                let line = &lines[row];
+                (row, byte_index(line, left)..byte_index(line, right))
+            })
+            .collect(),
+    }
+}
+
+/// Get the text in a span.
+fn text(lines: &[String], span: Span) -> Register {
+    match span {
+        Span::Block { .. } => Register::Block(
+            ranges(lines, span)
+                .into_iter()
+                .map(|(row, range)| lines[row][range].to_string())
+                .collect(),
+        ),
+        Span::Lines(start, end) => Register::Lines(lines[start..=end].to_vec()),
+        Span::Chars(start, end) if start.0 == end.0 => {
+            Register::Text(lines[start.0][start.1..end.1].to_string())
+        }
+        Span::Chars(start, end) => {
+            let mut text = lines[start.0][start.1..].to_string();
+            for line in &lines[start.0 + 1..end.0] {
+                text.push('\n');
+                text.push_str(line);
+            }
+            text.push('\n');
+            text.push_str(&lines[end.0][..end.1]);
+            Register::Text(text)
+        }
+    }
+}
+
+/// Remove the text in a span.
+fn delete(lines: &mut Vec<String>, span: Span) {
+    match span {
+        Span::Lines(start, end) => {
+            lines.drain(start..=end);
+        }
+        Span::Chars(start, end) => {
+            let rest = lines[end.0][end.1..].to_string();
+            lines[start.0].replace_range(start.1.., &rest);
+            lines.drain(start.0 + 1..=end.0);
+        }
+        Span::Block { .. } => {
+            for (row, range) in ranges(lines, span) {
+                lines[row].replace_range(range, "");
+            }
+        }
+    }
+}
+
+/// Insert text at a position, returning where it ends.
+fn insert_text(lines: &mut Vec<String>, (row, column): Position, text: &str) -> Position {
+    let rest = lines[row].split_off(column);
+    let mut inserted = text.split('\n');
+    lines[row].push_str(inserted.next().unwrap_or_default());
+    let mut end = (row, lines[row].len());
+    for (index, line) in inserted.enumerate() {
+        lines.insert(row + index + 1, line.to_string());
+        end = (row + index + 1, line.len());
+    }
+    lines[end.0].push_str(&rest);
+    end
+}
+
+/// Change the case of text with `convert`, which is given each character.
+fn convert_case(lines: &mut [String], span: Span, convert: impl Fn(char) -> String) {
+    for (row, range) in ranges(lines, span) {
+        let converted: String = lines[row][range.clone()].chars().map(&convert).collect();
+        lines[row].replace_range(range, &converted);
+    }
+}
+
+fn toggle_case(c: char) -> String {
+    if c.is_uppercase() {
+        c.to_lowercase().collect()
+    } else {
+        c.to_uppercase().collect()
+    }
+}
+
+/// Keeps track of normal mode commands being typed, what's needed to repeat them, and the
+/// registers and marks they use.
+#[derive(Default)]
+pub struct Normal {
+    pub registers: Registers,
+    pub marks: Marks,
+    /// Keys of a command still being typed.
+    keys: Vec<Key>,
+    last_find: Option<Motion>,
+    /// Keys of the last change, without its count, and the count.
+    last_change: (Vec<Key>, Option<usize>),
+    /// Keys of a change still being made in insert mode.
+    recording: Option<(Vec<Key>, Option<usize>)>,
+    /// A block being changed, whose other rows get what's typed into the first.
+    block_insert: Option<BlockInsert>,
+}
+
+/// Where text typed after changing a block goes.
+struct BlockInsert {
+    rows: (usize, usize),
+    /// The character column text is inserted at.
+    column: usize,
+    /// How many lines there were, and how long the first row was, before typing.
+    lines: usize,
+    length: usize,
+}
+
+impl Normal {
+    /// Record a key typed in insert mode, in case it's part of a change.
+    pub fn record(&mut self, key: Key) {
+        if let Some((keys, _)) = self.recording.as_mut() {
+            keys.push(key);
+        }
+    }
+
+    /// Finish recording a change made in insert mode. If a block was changed, what was typed
+    /// is copied to the rest of its rows.
+    pub fn finish_insert(&mut self, lines: &mut [String]) {
+        if let Some((mut keys, count)) = self.recording.take() {
+            keys.push(Key::Escape);
+            self.last_change = (keys, count);
+        }
+
+        let Some(block) = self.block_insert.take() else {
+            return;
+        };
+        let (first, last) = block.rows;
+        // Only text typed on the first row is copied, not new lines.
+        if lines.len() != block.lines || lines[first].len() <= block.length {
+            return;
+        }
+        let start = byte_index(&lines[first], block.column);
+        let typed = lines[first][start..start + lines[first].len() - block.length].to_string();
+        for line in &mut lines[first + 1..=last] {
+            // Skip lines too short to reach the block.
+            if unicode::width(line) >= block.column {
+                line.insert_str(byte_index(line, block.column), &typed);
+            }
+        }
+    }
+
+    /// The keys to replay to repeat the last change, with a new count if given.
+    pub fn repeat_keys(&self, count: Option<usize>) -> Vec<Key> {
+        let (keys, last_count) = &self.last_change;
+        let count = count.or(*last_count);
+        count
+            .map(|count| count.to_string())
+            .unwrap_or_default()
+            .chars()
+            .map(Key::Char)
+            .chain(keys.iter().copied())
+            .collect()
+    }
+
+    /// Handle a key, running the command if it's complete.
+    pub fn handle_key(
+        &mut self,
+        key: Key,
+        lines: &mut Vec<String>,
+        cursor: &mut Position,
+        screen: Screen,
+    ) -> Option<Action> {
+        self.keys.push(key);
+        let command = match parse(&self.keys) {
+            Parse::Incomplete => return None,
+            Parse::Invalid => {
+                self.keys.clear();
+                return None;
+            }
+            Parse::Done(command) => command,
+        };
+
+        // Remember how to repeat this, leaving out the count so `.` can be given a new one.
+        let keys = strip_counts(&std::mem::take(&mut self.keys));
+        if command.command.is_change() {
+            self.last_change = (keys.clone(), command.count);
+        }
+
+        let action = self.execute(command, lines, cursor, screen);
+        if action == Action::Insert {
+            self.recording = Some((keys, command.count));
+        } else {
+            cursor.0 = std::cmp::min(cursor.0, lines.len() - 1);
+            cursor.1 = clamp(&lines[cursor.0], cursor.1);
+        }
+        Some(action)
+    }
+
+    /// Find where a motion goes from `cursor`, and how much text it covers.
+    fn motion_target(
+        &mut self,
+        motion: Motion,
+        count: Option<usize>,
+        lines: &[String],
+        cursor: Position,
+        screen: &Screen,
+    ) -> Option<(Position, Extent)> {
+        let (row, column) = cursor;
+        let line = &lines[row];
+        let times = count.unwrap_or(1);
+        let last_row = lines.len() - 1;
+        let target = match motion {
+            Motion::Left => {
+                let mut target = column;
+                for _ in 0..times {
+                    target = prev(line, target);
+                }
+                return (target != column).then_some(((row, target), Extent::Exclusive));
+            }
+            Motion::Right => {
+                let mut target = column;
+                for _ in 0..times {
+                    target = next(line, target);
+                }
+                return (target != column).then_some(((row, target), Extent::Exclusive));
+            }
+            Motion::PreviousChar | Motion::NextChar => {
+                let mut target = cursor;
+                for _ in 0..times {
+                    target = if motion == Motion::NextChar {
+                        // Skip the end of the line, which the cursor can't be on.
+                        let next = step_forward(lines, target)?;
+                        if next.1 == lines[next.0].len() && !lines[next.0].is_empty() {
+                            step_forward(lines, next)?
+                        } else {
+                            next
+                        }
+                    } else {
+                        let previous = step_backward(lines, target)?;
+                        (
+                            previous.0,
+                            std::cmp::min(previous.1, last_char(&lines[previous.0])),
+                        )
+                    };
+                }
+                return Some((target, Extent::Exclusive));
+            }
+            // Keep to the same column of the screen, whatever the characters before it are.
+            Motion::Up | Motion::Down => {
+                let target = if motion == Motion::Up {
+                    row.checked_sub(times)?
+                } else {
+                    Some(row + times).filter(|&target| target <= last_row)?
+                };
+                let column = unicode::column(line, column, screen.tabstop);
+                let index =
+                    display::index_at(&lines[target], (0, column), usize::MAX, screen.tabstop);
+                return Some(((target, index), Extent::Linewise));
+            }
+            Motion::RowDown | Motion::RowUp => {
+                let (width, tabstop) = (screen.width, screen.tabstop);
+                let (mut target, column) = display::position(line, column, width, tabstop);
+                let mut target_row = row;
+                for _ in 0..times {
+                    if motion == Motion::RowDown {
+                        if target + 1 < display::height(&lines[target_row], width, tabstop) {
+                            target += 1;
+                        } else if target_row < last_row {
+                            (target_row, target) = (target_row + 1, 0);
+                        } else {
+                            return None;
+                        }
+                    } else if target > 0 {
+                        target -= 1;
+                    } else {
+                        target_row = target_row.checked_sub(1)?;
+                        target = display::height(&lines[target_row], width, tabstop) - 1;
+                    }
+                }
+                let line = &lines[target_row];
+                let index = display::index_at(line, (target, column), width, tabstop);
+                return Some(((target_row, index), Extent::Exclusive));
+            }
+            Motion::RowStart | Motion::RowEnd => {
+                let (width, tabstop) = (screen.width, screen.tabstop);
+                let (target, _) = display::position(line, column, width, tabstop);
+                return Some(if motion == Motion::RowStart {
+                    let index = display::index_at(line, (target, 0), width, tabstop);
+                    ((row, index), Extent::Exclusive)
+                } else {
+                    let index = display::index_at(line, (target, width - 1), width, tabstop);
+                    ((row, index), Extent::Inclusive)
+                });
+            }
+            Motion::LineStart => return Some(((row, 0), Extent::Exclusive)),
+            Motion::FirstNonBlank => {
+                return Some(((row, first_non_blank(line)), Extent::Exclusive))
+            }
+            Motion::LineEnd => {
+                let target = std::cmp::min(row + times - 1, last_row);
+                return Some(((target, last_char(&lines[target])), Extent::Inclusive));
+            }
+            Motion::WordForward(big) => {
+                let mut target = cursor;
+                for _ in 0..times {
+                    let start = target;
+                    let class = class_at(lines, target, big);
+                    while class != 0 && class_at(lines, target, big) == class {
+                        let Some(next) = step_forward(lines, target) else {
+                            break;
+                        };
+                        target = next;
+                    }
+                    while class_at(lines, target, big) == 0
+                        && !(is_empty_line(lines, target) && target != start)
+                    {
+                        let Some(next) = step_forward(lines, target) else {
+                            break;
+                        };
+                        target = next;
+                    }
+                }
+                (target, Extent::Exclusive)
+            }
+            Motion::WordBackward(big) => {
+                let mut target = cursor;
+                for _ in 0..times {
+                    target = step_backward(lines, target)?;
+                    while class_at(lines, target, big) == 0 && !is_empty_line(lines, target) {
+                        target = step_backward(lines, target)?;
+                    }
+                    let class = class_at(lines, target, big);
+                    while let Some(previous) = step_backward(lines, target) {
+                        if class == 0 || class_at(lines, previous, big) != class {
+                            break;
+                        }
+                        target = previous;
+                    }
+                }
+                (target, Extent::Exclusive)
+            }
+            Motion::WordEnd(big) => {
+                let mut target = cursor;
+                for _ in 0..times {
+                    target = step_forward(lines, target)?;
+                    while class_at(lines, target, big) == 0 {
+                        target = step_forward(lines, target)?;
+                    }
+                    let class = class_at(lines, target, big);
+                    while let Some(next) = step_forward(lines, target) {
+                        if class_at(lines, next, big) != class {
+                            break;
+                        }
+                        target = next;
+                    }
+                }
+                (target, Extent::Inclusive)
+            }
+            Motion::Find { c, forward, till } => {
+                self.last_find = Some(motion);
+                let target = line_motions::find(line, column, c, forward, till, times)?;
+                let extent = if forward {
+                    Extent::Inclusive
+                } else {
+                    Extent::Exclusive
+                };
+                ((row, target), extent)
+            }
+            Motion::RepeatFind { reverse } => {
+                let Some(Motion::Find { c, forward, till }) = self.last_find else {
+                    return None;
+                };
+                let forward = forward != reverse;
+                let from = line_motions::repeat_from(line, column, forward, till);
+                let target = line_motions::find(line, from, c, forward, till, times)?;
+                let extent = if forward {
+                    Extent::Inclusive
+                } else {
+                    Extent::Exclusive
+                };
+                ((row, target), extent)
+            }
+            Motion::MatchingBracket => {
+                let start = line[column..].find(['(', ')', '[', ']', '{', '}'])?;
+                (
+                    matching_bracket(lines, (row, column + start))?,
+                    Extent::Inclusive,
+                )
+            }
+            Motion::GoToLine { last } => {
+                let target = match count {
+                    Some(count) => std::cmp::min(count.saturating_sub(1), last_row),
+                    None if last => last_row,
+                    None => 0,
+                };
+                ((target, first_non_blank(&lines[target])), Extent::Linewise)
+            }
+            Motion::ScreenTop | Motion::ScreenBottom => {
+                let target = if motion == Motion::ScreenTop {
+                    screen.rows.start
+                } else {
+                    screen.rows.end.saturating_sub(1)
+                };
+                let target = std::cmp::min(target, last_row);
+                ((target, first_non_blank(&lines[target])), Extent::Linewise)
+            }
+            Motion::Mark { name, exact } => {
+                // The mark may be past the end of lines that have since been deleted.
+                let (row, column) = self.marks.get(name)?;
+                let row = std::cmp::min(row, last_row);
+                let line = &lines[row];
+                if exact {
+                    let column = (0..=std::cmp::min(column, line.len()))
+                        .rev()
+                        .find(|&index| line.is_char_boundary(index))
+                        .unwrap_or_default();
+                    ((row, column), Extent::Exclusive)
+                } else {
+                    ((row, first_non_blank(line)), Extent::Linewise)
+                }
+            }
+        };
+        Some(target)
+    }
+
+    /// Find the text a text object covers.
+    fn object_span(
+        lines: &[String],
+        (row, column): Position,
+        object: TextObject,
+        around: bool,
+    ) -> Option<Span> {
+        let line = &lines[row];
+        match object {
+            TextObject::Word { big } => {
+                let class_at = |index: usize| line[index..].chars().next().map(|c| class(c, big));
+                let class = class_at(column)?;
+                let extend_back = |mut index: usize, class| {
+                    while index > 0 && class_at(prev(line, index)) == Some(class) {
+                        index = prev(line, index);
+                    }
+                    index
+                };
+                let extend_forward = |mut index: usize, class| {
+                    while class_at(index) == Some(class) {
+                        index = next(line, index);
+                    }
+                    index
+                };
+                let mut start = extend_back(column, class);
+                let mut end = extend_forward(column, class);
+                if around {
+                    if class == 0 {
+                        // Blanks and the word after them.
+                        if let Some(next_class) = class_at(end) {
+                            end = extend_forward(end, next_class);
+                        }
+                    } else if class_at(end) == Some(0) {
+                        end = extend_forward(end, 0);
+                    } else if start > 0 && class_at(prev(line, start)) == Some(0) {
+                        start = extend_back(start, 0);
+                    }
+                }
+                Some(Span::Chars((row, start), (row, end)))
+            }
+            TextObject::Quote(quote) => {
+                let mut quotes = Vec::new();
+                let mut escaped = false;
+                for (index, c) in line.char_indices() {
+                    if c == quote && !escaped {
+                        quotes.push(index);
+                    }
+                    escaped = c == '\\' && !escaped;
+                }
+                let (open, close) = quotes
+                    .chunks_exact(2)
+                    .map(|pair| (pair[0], pair[1]))
+                    .find(|(_, close)| *close >= column)?;
+                if !around {
+                    return Some(Span::Chars((row, open + 1), (row, close)));
+                }
+                let mut start = open;
+                let mut end = close + 1;
+                let trailing = line[end..].len() - line[end..].trim_start().len();
+                if trailing > 0 {
+                    end += trailing;
+                } else {
+                    start = line[..start].trim_end().len();
+                }
+                Some(Span::Chars((row, start), (row, end)))
+            }
+            TextObject::Brackets(open, close) => {
+                let at_cursor = line[column..].chars().next();
+                let start = if at_cursor == Some(open) {
+                    (row, column)
+                } else {
+                    find_unmatched(lines, (row, column), open, close, false)?
+                };
+                let end = find_unmatched(lines, start, open, close, true)?;
+                Some(if around {
+                    Span::Chars(start, (end.0, next(&lines[end.0], end.1)))
+                } else {
+                    Span::Chars((start.0, next(&lines[start.0], start.1)), end)
+                })
+            }
+        }
+    }
+
+    /// Move the cursor with a motion, remembering where it was if that's a jump.
+    fn move_cursor(
+        &mut self,
+        motion: Motion,
+        count: Option<usize>,
+        lines: &[String],
+        cursor: &mut Position,
+        screen: &Screen,
+    ) {
+        if let Some((target, _)) = self.motion_target(motion, count, lines, *cursor, screen) {
+            if motion.is_jump() && target != *cursor {
+                self.marks.jump(*cursor);
+            }
+            *cursor = target;
+        }
+    }
+
+    fn execute(
+        &mut self,
+        command: ParsedCommand,
+        lines: &mut Vec<String>,
+        cursor: &mut Position,
+        screen: Screen,
+    ) -> Action {
+        let count = command.count;
+        let times = count.unwrap_or(1);
+        let (row, column) = *cursor;
+        match command.command {
+            Command::Move(motion) => self.move_cursor(motion, count, lines, cursor, &screen),
+            Command::Operate(operator, target) => {
+                let Some(span) = self.span(operator, target, count, lines, *cursor, &screen) else {
+                    return Action::Done;
+                };
+                let registers = &mut self.registers;
+                return Self::operate(operator, span, lines, cursor, registers, command.register);
+            }
+            Command::Insert(insert) => {
+                let line = &lines[row];
+                *cursor = match insert {
+                    Insert::Before => *cursor,
+                    Insert::After => (row, next(line, column)),
+                    Insert::LineStart => (row, first_non_blank(line)),
+                    Insert::LineEnd => (row, line.len()),
+                    Insert::LineBelow => {
+                        lines.insert(row + 1, String::new());
+                        (row + 1, 0)
+                    }
+                    Insert::LineAbove => {
+                        lines.insert(row, String::new());
+                        (row, 0)
+                    }
+                };
+                return Action::Insert;
+            }
+            Command::Put { before } => match self.registers.get(command.register).cloned() {
+                None => return Action::Done,
+                Some(Register::Lines(pasted)) => {
+                    let at = if before { row } else { row + 1 };
+                    for _ in 0..times {
+                        lines.splice(at..at, pasted.iter().cloned());
+                    }
+                    *cursor = (at, first_non_blank(&lines[at]));
+                }
+                Some(Register::Text(pasted)) => {
+                    if pasted.is_empty() {
+                        return Action::Done;
+                    }
+                    let at = if before || lines[row].is_empty() {
+                        column
+                    } else {
+                        next(&lines[row], column)
+                    };
+                    let end = insert_text(lines, (row, at), &pasted.repeat(times));
+                    // The cursor ends up on the last character put, unless it spans lines.
+                    *cursor = if end.0 == row {
+                        (row, prev(&lines[row], end.1))
+                    } else {
+                        (row, at)
+                    };
+                }
+                Some(Register::Block(pasted)) => {
+                    let line = &lines[row];
+                    let at = if before || line.is_empty() {
+                        column
+                    } else {
+                        next(line, column)
+                    };
+                    let at = unicode::width(&line[..at]);
+                    for (index, piece) in pasted.iter().enumerate() {
+                        if row + index == lines.len() {
+                            lines.push(String::new());
+                        }
+                        // Pad short lines so each piece lines up.
+                        let line = &mut lines[row + index];
+                        let length = unicode::width(line);
+                        if length < at {
+                            line.push_str(&" ".repeat(at - length));
+                        }
+                        line.insert_str(byte_index(line, at), &piece.repeat(times));
+                    }
+                    *cursor = (row, byte_index(&lines[row], at));
+                }
+            },
+            Command::Replace(c) => {
+                let line = &mut lines[row];
+                let Some(end) = (0..times)
+                    .try_fold(column, |end, _| (end < line.len()).then(|| next(line, end)))
+                else {
+                    return Action::Done;
+                };
+                let replacement = c.to_string().repeat(times);
+                line.replace_range(column..end, &replacement);
+                cursor.1 = prev(line, column + replacement.len());
+            }
+            Command::ToggleCase => {
+                let line = &lines[row];
+                let end = (0..times).fold(column, |end, _| next(line, end));
+                let after = line.len() - end;
+                convert_case(lines, Span::Chars(*cursor, (row, end)), toggle_case);
+                // Move past the changed characters, which may have changed length.
+                cursor.1 = lines[row].len() - after;
+            }
+            Command::Join => {
+                for _ in 0..std::cmp::max(times, 2) - 1 {
+                    if row + 1 >= lines.len() {
+                        break;
+                    }
+                    let joined = lines.remove(row + 1);
+                    let joined = joined.trim_start();
+                    let line = &mut lines[row];
+                    let trimmed = line.trim_end().len();
+                    line.truncate(trimmed);
+                    cursor.1 = line.len();
+                    if !joined.is_empty() && !line.is_empty() && !joined.starts_with(')') {
+                        line.push(' ');
+                    }
+                    line.push_str(joined);
+                }
+            }
+            Command::Undo => return Action::Undo { count: times },
+            Command::Redo => return Action::Redo { count: times },
+            Command::UndoLine => return Action::UndoLine,
+            Command::Repeat => return Action::Repeat { count },
+            Command::Search { forward } => return Action::Search { forward },
+            Command::SearchAgain { reverse } => {
+                return Action::SearchAgain {
+                    reverse,
+                    count: times,
+                }
+            }
+            Command::SearchWord { forward } => return Action::SearchWord { forward },
+            Command::RepeatSubstitute => return Action::RepeatSubstitute,
+            Command::Ex => return Action::Ex,
+            Command::Visual(selection) => return Action::Visual(selection),
+            Command::SetMark(name) => self.marks.set(name, *cursor),
+            Command::JumpBack => {
+                if let Some(position) = self.marks.back(*cursor, times) {
+                    *cursor = position;
+                }
+            }
+            Command::JumpForward => {
+                if let Some(position) = self.marks.forward(times) {
+                    *cursor = position;
+                }
+            }
+            Command::Window(key) => return Action::Window { key, count },
+        }
+        Action::Done
+    }
+
+    /// Find the text an operator acts on.
+    fn span(
+        &mut self,
+        operator: Operator,
+        target: Target,
+        count: Option<usize>,
+        lines: &[String],
+        cursor: Position,
+        screen: &Screen,
+    ) -> Option<Span> {
+        let motion = match target {
+            Target::Line => {
+                let end = std::cmp::min(cursor.0 + count.unwrap_or(1) - 1, lines.len() - 1);
+                return Some(Span::Lines(cursor.0, end));
+            }
+            Target::Object { object, around } => {
+                return Self::object_span(lines, cursor, object, around)
+            }
+            Target::Motion(motion) => motion,
+        };
+
+        // `cw` changes to the end of the word, leaving the space after it.
+        let motion = match (operator, motion) {
+            (Operator::Change, Motion::WordForward(big)) if class_at(lines, cursor, big) != 0 => {
+                Motion::WordEnd(big)
+            }
+            _ => motion,
+        };
+        let (target, extent) = self.motion_target(motion, count, lines, cursor, screen)?;
+        let (start, mut end) = if target < cursor {
+            (target, cursor)
+        } else {
+            (cursor, target)
+        };
+
+        Some(match extent {
+            Extent::Linewise => Span::Lines(start.0, end.0),
+            _ if matches!(operator, Operator::Indent | Operator::Outdent) => {
+                Span::Lines(start.0, end.0)
+            }
+            Extent::Inclusive => Span::Chars(start, (end.0, next(&lines[end.0], end.1))),
+            Extent::Exclusive => {
+                // An operator stops at the end of a line rather than taking in the
+                // indentation of the next one, so `dw` on the last word of a line leaves the
+                // line break.
+                if end.0 > start.0 && end.1 <= first_non_blank(&lines[end.0]) {
+                    end = (end.0 - 1, lines[end.0 - 1].len());
+                }
+                Span::Chars(start, end)
+            }
+        })
+    }
+
+    /// Apply an operator to a span of text, keeping what's deleted or yanked in `register`.
+    fn operate(
+        operator: Operator,
+        span: Span,
+        lines: &mut Vec<String>,
+        cursor: &mut Position,
+        registers: &mut Registers,
+        register: Option<char>,
+    ) -> Action {
+        let start = match span {
+            Span::Lines(start, _) => (start, first_non_blank(&lines[start])),
+            Span::Chars(start, _) => start,
+            Span::Block {
+                rows: (first, _),
+                columns: (left, _),
+            } => (first, byte_index(&lines[first], left)),
+        };
+        match operator {
+            Operator::Delete | Operator::Change => {
+                registers.delete(register, text(lines, span));
+                delete(lines, span);
+                if operator == Operator::Change {
+                    *cursor = match span {
+                        Span::Lines(start, _) => {
+                            lines.insert(start, String::new());
+                            (start, 0)
+                        }
+                        _ => start,
+                    };
+                    return Action::Insert;
+                }
+                if lines.is_empty() {
+                    lines.push(String::new());
+                }
+                *cursor = match span {
+                    Span::Lines(start, _) => {
+                        let row = std::cmp::min(start, lines.len() - 1);
+                        (row, first_non_blank(&lines[row]))
+                    }
+                    _ => start,
+                };
+            }
+            Operator::Yank => {
+                registers.yank(register, text(lines, span));
+                if start.0 != cursor.0 || !matches!(span, Span::Lines(..)) {
+                    *cursor = match span {
+                        Span::Lines(start, _) => (start, cursor.1),
+                        _ => start,
+                    };
+                }
+            }
+            Operator::Indent | Operator::Outdent => {
+                let (Span::Lines(first, last)
+                | Span::Chars((first, _), (last, _))
+                | Span::Block {
+                    rows: (first, last),
+                    ..
+                }) = span;
+                for line in &mut lines[first..=last] {
+                    if operator == Operator::Indent {
+                        if !line.is_empty() {
+                            line.insert_str(0, &" ".repeat(SHIFT_WIDTH));
+                        }
+                    } else if line.starts_with('\t') {
+                        line.remove(0);
+                    } else {
+                        let spaces = line.len() - line.trim_start_matches(' ').len();
+                        line.drain(0..std::cmp::min(spaces, SHIFT_WIDTH));
+                    }
+                }
+                *cursor = (first, first_non_blank(&lines[first]));
+            }
+            Operator::ToggleCase | Operator::Lowercase | Operator::Uppercase => {
+                convert_case(lines, span, |c| match operator {
+                    Operator::Lowercase => c.to_lowercase().collect(),
+                    Operator::Uppercase => c.to_uppercase().collect(),
+                    _ => toggle_case(c),
+                });
+                *cursor = start;
+            }
+        }
+        Action::Done
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keys(input: &str) -> Vec<Key> {
+        input.chars().map(Key::Char).collect()
+    }
+
+    /// A screen showing the first ten lines, wrapped at ten columns.
+    pub(super) fn screen() -> Screen {
+        Screen {
+            rows: 0..10,
+            width: 10,
+            tabstop: 8,
+        }
+    }
+
+    /// Run normal mode commands on some text, returning the text and where the cursor ends up.
+    fn run(text: &str, cursor: Position, input: &str) -> (String, Position) {
+        run_with_registers(text, cursor, input).0
+    }
+
+    fn run_with_registers(
+        text: &str,
+        cursor: Position,
+        input: &str,
+    ) -> ((String, Position), Normal) {
+        let mut normal = Normal::default();
+        let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+        let mut cursor = cursor;
+        for key in keys(input) {
+            normal.handle_key(key, &mut lines, &mut cursor, screen());
+        }
+        ((lines.join("\n"), cursor), normal)
+    }
+
+    #[test]
+    fn parsing() {
+        let done = |count, command| {
+            Parse::Done(ParsedCommand {
+                count,
+                register: None,
+                command,
+            })
+        };
+        let operate = |operator, motion| Command::Operate(operator, Target::Motion(motion));
+        assert_eq!(
+            parse(&keys("3dd")),
+            done(Some(3), Command::Operate(Operator::Delete, Target::Line))
+        );
+        assert_eq!(
+            parse(&keys("2d3w")),
+            done(
+                Some(6),
+                operate(Operator::Delete, Motion::WordForward(false))
+            )
+        );
+        assert_eq!(
+            parse(&keys("ci\"")),
+            done(
+                None,
+                Command::Operate(
+                    Operator::Change,
+                    Target::Object {
+                        object: TextObject::Quote('"'),
+                        around: false
+                    }
+                )
+            )
+        );
+        assert_eq!(
+            parse(&keys("g~~")),
+            done(None, Command::Operate(Operator::ToggleCase, Target::Line))
+        );
+        assert_eq!(
+            parse(&keys("gUU")),
+            done(None, Command::Operate(Operator::Uppercase, Target::Line))
+        );
+        assert_eq!(
+            parse(&keys("gg")),
+            done(None, Command::Move(Motion::GoToLine { last: false }))
+        );
+        assert_eq!(
+            parse(&keys("0")),
+            done(None, Command::Move(Motion::LineStart))
+        );
+        assert_eq!(
+            parse(&keys("2\"A3yy")),
+            Parse::Done(ParsedCommand {
+                count: Some(6),
+                register: Some('A'),
+                command: Command::Operate(Operator::Yank, Target::Line)
+            })
+        );
+        assert_eq!(
+            parse(&keys("2\x17\x17")),
+            done(Some(2), Command::Window('w'))
+        );
+        assert_eq!(
+            parse(&[Key::Char('\x17'), Key::Left]),
+            done(None, Command::Window('h'))
+        );
+        assert_eq!(parse(&keys("\x17")), Parse::Incomplete);
+        assert_eq!(parse(&keys("\"")), Parse::Incomplete);
+        assert_eq!(parse(&keys("\"!")), Parse::Invalid);
+        assert_eq!(parse(&keys("m")), Parse::Incomplete);
+        assert_eq!(parse(&keys("10")), Parse::Incomplete);
+        assert_eq!(parse(&keys("d")), Parse::Incomplete);
+        assert_eq!(parse(&keys("g")), Parse::Incomplete);
+        assert_eq!(parse(&keys("g~")), Parse::Incomplete);
+        assert_eq!(parse(&keys("di")), Parse::Incomplete);
+        assert_eq!(parse(&keys("dt")), Parse::Incomplete);
+        assert_eq!(parse(&keys("diq")), Parse::Invalid);
+        assert_eq!(parse(&keys("dq")), Parse::Invalid);
+        assert_eq!(parse(&[Key::Escape]), Parse::Invalid);
+    }
+
+    #[test]
+    fn motions() {
+        let text = "echo foo.bar  baz\n\n  (a (b)\n c)";
+        let moved = |cursor, input| run(text, cursor, input).1;
+        assert_eq!(moved((0, 0), "w"), (0, 5));
+        assert_eq!(moved((0, 0), "3w"), (0, 9));
+        assert_eq!(moved((0, 0), "2W"), (0, 14));
+        // Empty lines are words, and words continue on the next line.
+        assert_eq!(moved((0, 14), "w"), (1, 0));
+        assert_eq!(moved((0, 14), "2w"), (2, 2));
+        assert_eq!(moved((2, 2), "b"), (1, 0));
+        assert_eq!(moved((2, 2), "2b"), (0, 14));
+        assert_eq!(moved((0, 0), "e"), (0, 3));
+        assert_eq!(moved((0, 14), "e"), (0, 16));
+        assert_eq!(moved((0, 16), "e"), (2, 2));
+        assert_eq!(moved((0, 0), "$"), (0, 16));
+        assert_eq!(moved((0, 0), "2$"), (1, 0));
+        assert_eq!(moved((2, 7), "0"), (2, 0));
+        assert_eq!(moved((2, 7), "^"), (2, 2));
+        assert_eq!(moved((0, 0), "fb"), (0, 9));
+        assert_eq!(moved((0, 0), "fb;"), (0, 14));
+        assert_eq!(moved((0, 0), "2ta"), (0, 14));
+        assert_eq!(moved((0, 16), "Fo"), (0, 7));
+        assert_eq!(moved((0, 16), "Fo;"), (0, 6));
+        assert_eq!(moved((0, 16), "Fo;,"), (0, 7));
+        assert_eq!(moved((0, 0), "fq"), (0, 0));
+        assert_eq!(moved((2, 2), "%"), (3, 2));
+        assert_eq!(moved((2, 3), "%"), (2, 7));
+        assert_eq!(moved((3, 2), "%"), (2, 2));
+        assert_eq!(moved((0, 3), "G"), (3, 1));
+        assert_eq!(moved((3, 0), "3G"), (2, 2));
+        assert_eq!(moved((3, 0), "gg"), (0, 0));
+        // The first line wraps onto two rows of the screen, which is ten columns wide.
+        assert_eq!(moved((0, 3), "gj"), (0, 13));
+        assert_eq!(moved((0, 3), "2gj"), (1, 0));
+        assert_eq!(moved((2, 2), "2gk"), (0, 12));
+        assert_eq!(moved((0, 13), "gk"), (0, 3));
+        assert_eq!(moved((0, 3), "g$"), (0, 9));
+        assert_eq!(moved((0, 13), "g0"), (0, 10));
+        assert_eq!(moved((0, 3), "2j"), (2, 3));
+        assert_eq!(moved((0, 3), "9j"), (0, 3));
+        assert_eq!(moved((0, 16), " "), (1, 0));
+        assert_eq!(moved((1, 0), "\x08"), (0, 16));
+        assert_eq!(moved((0, 0), "L"), (3, 1));
+    }
+
+    #[test]
+    fn unicode() {
+        // A cow takes up two columns and four bytes, and the accent is part of the e before it.
+        let text = "a🐮e\u{301}b\nabcdef\n漢字";
+        let moved = |cursor, input| run(text, cursor, input).1;
+        assert_eq!(moved((0, 0), "l"), (0, 1));
+        assert_eq!(moved((0, 0), "2l"), (0, 5));
+        assert_eq!(moved((0, 0), "$"), (0, 8));
+        assert_eq!(moved((0, 8), "h"), (0, 5));
+        assert_eq!(moved((0, 0), "fb"), (0, 8));
+        // Moving up and down keeps to the same column of the screen.
+        assert_eq!(moved((0, 5), "j"), (1, 3));
+        assert_eq!(moved((1, 3), "k"), (0, 5));
+        assert_eq!(moved((1, 2), "j"), (2, 3));
+        assert_eq!(moved((1, 3), "j"), (2, 3));
+        assert_eq!(moved((2, 3), "k"), (1, 2));
+        // A cursor left partway through a character goes back to its start.
+        assert_eq!(clamp("a🐮e\u{301}b", 3), 1);
+        assert_eq!(clamp("a🐮e\u{301}b", 6), 5);
+        assert_eq!(clamp("a🐮e\u{301}b", 20), 8);
+
+        assert_eq!(run(text, (0, 1), "x").0, "ae\u{301}b\nabcdef\n漢字");
+        assert_eq!(run(text, (0, 5), "x").0, "a🐮b\nabcdef\n漢字");
+        assert_eq!(run(text, (0, 0), "3x").0, "b\nabcdef\n漢字");
+        assert_eq!(run(text, (0, 5), "rx").0, "a🐮xb\nabcdef\n漢字");
+        assert_eq!(run(text, (0, 5), "~").0, "a🐮E\u{301}b\nabcdef\n漢字");
+        assert_eq!(run(text, (2, 0), "xp").0, "a🐮e\u{301}b\nabcdef\n字漢");
+    }
+
+    #[test]
+    fn operators() {
+        let text = "one two three\nfour\nfive";
+        assert_eq!(
+            run(text, (0, 0), "dw"),
+            ("two three\nfour\nfive".into(), (0, 0))
+        );
+        assert_eq!(run(text, (0, 4), "d$"), ("one \nfour\nfive".into(), (0, 3)));
+        // Deleting words stops at the end of the line.
+        assert_eq!(
+            run(text, (0, 8), "dw"),
+            ("one two \nfour\nfive".into(), (0, 7))
+        );
+        assert_eq!(run(text, (0, 4), "2dd"), ("five".into(), (0, 0)));
+        assert_eq!(run(text, (1, 0), "3dd"), ("one two three".into(), (0, 0)));
+        assert_eq!(run(text, (2, 0), "dk"), ("one two three".into(), (0, 0)));
+        assert_eq!(
+            run(text, (0, 4), "d2w"),
+            ("one \nfour\nfive".into(), (0, 3))
+        );
+        assert_eq!(
+            run(text, (0, 4), "x"),
+            ("one wo three\nfour\nfive".into(), (0, 4))
+        );
+        assert_eq!(
+            run(text, (0, 4), "3x"),
+            ("one  three\nfour\nfive".into(), (0, 4))
+        );
+        assert_eq!(
+            run(text, (0, 4), "dtt"),
+            ("one three\nfour\nfive".into(), (0, 4))
+        );
+        assert_eq!(run(text, (0, 4), "D"), ("one \nfour\nfive".into(), (0, 3)));
+        assert_eq!(
+            run(text, (0, 0), "J"),
+            ("one two three four\nfive".into(), (0, 13))
+        );
+        assert_eq!(
+            run(text, (0, 0), "3J"),
+            ("one two three four five".into(), (0, 18))
+        );
+        assert_eq!(
+            run(text, (0, 0), "~"),
+            ("One two three\nfour\nfive".into(), (0, 1))
+        );
+        assert_eq!(
+            run(text, (0, 0), "3~"),
+            ("ONE two three\nfour\nfive".into(), (0, 3))
+        );
+        assert_eq!(
+            run(text, (0, 4), "gUiw"),
+            ("one TWO three\nfour\nfive".into(), (0, 4))
+        );
+        assert_eq!(
+            run(text, (0, 0), "2rx"),
+            ("xxe two three\nfour\nfive".into(), (0, 1))
+        );
+        assert_eq!(
+            run(text, (1, 0), ">>"),
+            ("one two three\n    four\nfive".into(), (1, 4))
+        );
+        assert_eq!(
+            run(text, (1, 0), ">j<<"),
+            ("one two three\nfour\n    five".into(), (1, 0))
+        );
+        assert_eq!(run("a\nb", (0, 0), "dj"), ("".into(), (0, 0)));
+    }
+
+    #[test]
+    fn yank_and_put() {
+        let text = "one two\nthree";
+        assert_eq!(
+            run(text, (0, 0), "yyp"),
+            ("one two\none two\nthree".into(), (1, 0))
+        );
+        assert_eq!(
+            run(text, (1, 0), "yyP"),
+            ("one two\nthree\nthree".into(), (1, 0))
+        );
+        assert_eq!(
+            run(text, (0, 0), "ywP"),
+            ("one one two\nthree".into(), (0, 3))
+        );
+        assert_eq!(run(text, (0, 0), "xp"), ("noe two\nthree".into(), (0, 1)));
+        assert_eq!(
+            run(text, (0, 0), "dd2p"),
+            ("three\none two\none two".into(), (1, 0))
+        );
+        assert_eq!(
+            run_with_registers(text, (0, 4), "yj").1.registers.get(None),
+            Some(&Register::Lines(vec!["one two".into(), "three".into()]))
+        );
+        let ((text, _), normal) = run_with_registers(text, (0, 4), "d3l");
+        assert_eq!(text, "one \nthree");
+        assert_eq!(
+            normal.registers.get(None),
+            Some(&Register::Text("two".into()))
+        );
+        assert_eq!(run("ab\ncd", (0, 1), "dlP"), ("ba\ncd".into(), (0, 0)));
+        // Named registers, and the last yank kept in "0 after deleting.
+        assert_eq!(
+            run("one two\nthree", (0, 0), "\"ayyj\"Ayy\"ap").0,
+            "one two\nthree\none two\nthree"
+        );
+        assert_eq!(run("one two\nthree", (0, 0), "yiwjdd\"0P").0, "oneone two");
+        assert_eq!(run("one two\nthree", (0, 0), "ddx\"1p").0, "hree\none two");
+
+        // Text spanning lines
+        let mut lines = vec!["ab".to_string(), "cd".to_string()];
+        assert_eq!(insert_text(&mut lines, (0, 1), "x\ny\nz"), (2, 1));
+        assert_eq!(lines, ["ax", "y", "zb", "cd"]);
+        assert_eq!(
+            super::text(&lines, Span::Chars((0, 1), (2, 1))),
+            Register::Text("x\ny\nz".into())
+        );
+    }
+
+    #[test]
+    fn marks_and_jumps() {
+        let text = "one\n  two\nthree\nfour";
+        assert_eq!(run(text, (1, 3), "majj'a"), (text.into(), (1, 2)));
+        assert_eq!(run(text, (1, 3), "majj`a"), (text.into(), (1, 3)));
+        assert_eq!(run(text, (0, 0), "jmaGd'a").0, "one");
+        assert_eq!(run(text, (0, 1), "jjmbggd`b").0, "hree\nfour");
+        // Jumps go in the jump list, and to the '' mark.
+        assert_eq!(run(text, (1, 0), "G''").1, (1, 2));
+        assert_eq!(run(text, (1, 0), "Ggg\x0f").1, (3, 0));
+        assert_eq!(run(text, (1, 0), "Ggg\x0f\x0f").1, (1, 0));
+        assert_eq!(run(text, (1, 0), "Ggg\x0f\x0f\t").1, (3, 0));
+        assert_eq!(run(text, (1, 0), "jj\x0f").1, (3, 0));
+        assert_eq!(run(text, (0, 0), "'z").1, (0, 0));
+    }
+
+    #[test]
+    fn text_objects() {
+        let text = "say \"hi there\" (to (them))";
+        assert_eq!(run(text, (0, 6), "diw").0, "say \" there\" (to (them))");
+        assert_eq!(run(text, (0, 6), "daw").0, "say \"there\" (to (them))");
+        assert_eq!(run(text, (0, 6), "di\"").0, "say \"\" (to (them))");
+        assert_eq!(run(text, (0, 0), "da\"").0, "say (to (them))");
+        assert_eq!(run(text, (0, 18), "di(").0, "say \"hi there\" ()");
+        assert_eq!(run(text, (0, 21), "da(").0, "say \"hi there\" (to )");
+        assert_eq!(run("f(a,\n  b)", (1, 2), "dib").0, "f()");
+        assert_eq!(run(text, (0, 0), "di(").0, text);
+    }
+
+    #[test]
+    fn changes() {
+        let mut normal = Normal::default();
+        let mut lines = vec!["one two".to_string()];
+        let mut cursor = (0, 0);
+        let mut type_keys = |normal: &mut Normal, input: &str| {
+            let mut action = None;
+            for key in keys(input) {
+                action = normal.handle_key(key, &mut lines, &mut cursor, screen());
+            }
+            action
+        };
+        // `cw` acts like `ce`, and then waits for text to be inserted.
+        assert_eq!(type_keys(&mut normal, "2cw"), Some(Action::Insert));
+        normal.record(Key::Char('x'));
+        normal.finish_insert(&mut []);
+        let mut expected = keys("2cwx");
+        expected.push(Key::Escape);
+        assert_eq!(normal.repeat_keys(None), expected);
+        assert_eq!(normal.repeat_keys(Some(3))[0], Key::Char('3'));
+        // Yanks and moves aren't changes
+        type_keys(&mut normal, "yyw");
+        assert_eq!(normal.repeat_keys(None)[1], Key::Char('c'));
+        type_keys(&mut normal, "x");
+        assert_eq!(normal.repeat_keys(None), keys("x"));
+        // Counts are left out, wherever they are, and replaced by their product.
+        type_keys(&mut normal, "2\"a3d1w");
+        assert_eq!(normal.repeat_keys(None), keys("6\"adw"));
+    }
+}
@@ -0,0 +1,414 @@
+//! Visual mode, where text is selected first and then acted on.
+use super::{
+    clamp, next, normal_char, parse_count, parse_motion, parse_object, parse_register, prev,
+    ranges, Action, BlockInsert, Command, Motion, Normal, Operator, Parse, ParsedCommand, Position,
+    Screen, Span, TextObject,
+};
+use crate::{
+    programs::common::{keys::Key, unicode},
+    ControlChar,
+};
+use std::ops::Range;
+
+/// How text is selected.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Selection {
+    /// From one character to another, with `v`.
+    Chars,
+    /// Whole lines, with `V`.
+    Lines,
+    /// A rectangle, with Ctrl-V.
+    Block,
+}
+
+impl Selection {
+    /// What's shown at the bottom of the screen while selecting.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Chars => "-- VISUAL --",
+            Self::Lines => "-- VISUAL LINE --",
+            Self::Block => "-- VISUAL BLOCK --",
+        }
+    }
+}
+
+/// A selection being made, from where it was started to the cursor.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Visual {
+    pub selection: Selection,
+    pub anchor: Position,
+}
+
+impl Visual {
+    pub fn new(selection: Selection, cursor: Position) -> Self {
+        Self {
+            selection,
+            anchor: cursor,
+        }
+    }
+
+    /// The first and last positions selected, in order.
+    pub fn ends(&self, cursor: Position) -> (Position, Position) {
+        if self.anchor <= cursor {
+            (self.anchor, cursor)
+        } else {
+            (cursor, self.anchor)
+        }
+    }
+
+    /// The selected text.
+    fn span(&self, lines: &[String], cursor: Position) -> Span {
+        let (start, end) = self.ends(cursor);
+        match self.selection {
+            Selection::Chars => {
+                // The last character is included, which on an empty line is the line break.
+                let end = if end.1 < lines[end.0].len() {
+                    (end.0, next(&lines[end.0], end.1))
+                } else if end.0 + 1 < lines.len() {
+                    (end.0 + 1, 0)
+                } else {
+                    end
+                };
+                Span::Chars(start, end)
+            }
+            Selection::Lines => Span::Lines(start.0, end.0),
+            Selection::Block => {
+                // The columns the characters at each corner are shown in.
+                let columns = |(row, column): Position| {
+                    let line = &lines[row];
+                    let start = unicode::width(&line[..column]);
+                    (
+                        start,
+                        start + unicode::width(&line[column..next(line, column)]).max(1),
+                    )
+                };
+                let (anchor, cursor) = (columns(self.anchor), columns(cursor));
+                Span::Block {
+                    rows: (start.0, end.0),
+                    columns: (
+                        std::cmp::min(anchor.0, cursor.0),
+                        std::cmp::max(anchor.1, cursor.1),
+                    ),
+                }
+            }
+        }
+    }
+
+    /// The part of a row that's selected, if any, for highlighting it.
+    pub fn selected(&self, lines: &[String], cursor: Position, row: usize) -> Option<Range<usize>> {
+        ranges(lines, self.span(lines, cursor))
+            .into_iter()
+            .find(|(selected, _)| *selected == row)
+            .map(|(_, range)| range)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum VisualCommand {
+    Move(Motion),
+    /// Select a text object.
+    Object {
+        object: TextObject,
+        around: bool,
+    },
+    /// Apply an operator to the selection, or to every line in it if `lines`.
+    Operate {
+        operator: Operator,
+        lines: bool,
+    },
+    Join,
+    /// Move the cursor to the other end of the selection.
+    SwapEnds,
+    Select(Selection),
+    Exit,
+    Search {
+        forward: bool,
+    },
+    SearchAgain {
+        reverse: bool,
+    },
+    Ex,
+}
+
+/// Parse a visual mode command from the keys typed so far.
+fn parse_visual(keys: &[Key]) -> Parse<(Option<usize>, Option<char>, VisualCommand)> {
+    if keys == [Key::Escape] {
+        return Parse::Done((None, None, VisualCommand::Exit));
+    }
+    let Some(chars) = keys
+        .iter()
+        .map(|key| normal_char(*key))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Parse::Invalid;
+    };
+
+    let mut position = 0;
+    let count = parse_count(&chars, &mut position);
+    let register = match parse_register(&chars, &mut position) {
+        Ok(register) => register,
+        Err(true) => return Parse::Incomplete,
+        Err(false) => return Parse::Invalid,
+    };
+    let Some(&c) = chars.get(position) else {
+        return Parse::Incomplete;
+    };
+    let operate = |operator, lines| VisualCommand::Operate { operator, lines };
+    let command = match parse_motion(&chars, &mut position) {
+        Ok(motion) => VisualCommand::Move(motion),
+        Err(true) => return Parse::Incomplete,
+        Err(false) => match c {
+            'i' | 'a' => match chars.get(position).map(|c| parse_object(*c)) {
+                Some(Some(object)) => VisualCommand::Object {
+                    object,
+                    around: c == 'a',
+                },
+                Some(None) => return Parse::Invalid,
+                None => return Parse::Incomplete,
+            },
+            'd' | 'x' => operate(Operator::Delete, false),
+            'X' | 'D' => operate(Operator::Delete, true),
+            'y' => operate(Operator::Yank, false),
+            'Y' => operate(Operator::Yank, true),
+            'c' | 's' => operate(Operator::Change, false),
+            'C' | 'S' | 'R' => operate(Operator::Change, true),
+            '>' => operate(Operator::Indent, true),
+            '<' => operate(Operator::Outdent, true),
+            '~' => operate(Operator::ToggleCase, false),
+            'u' => operate(Operator::Lowercase, false),
+            'U' => operate(Operator::Uppercase, false),
+            'J' => VisualCommand::Join,
+            'o' => VisualCommand::SwapEnds,
+            'v' => VisualCommand::Select(Selection::Chars),
+            'V' => VisualCommand::Select(Selection::Lines),
+            c if c == ControlChar::V => VisualCommand::Select(Selection::Block),
+            '/' | '?' => VisualCommand::Search { forward: c == '/' },
+            'n' | 'N' => VisualCommand::SearchAgain { reverse: c == 'N' },
+            ':' => VisualCommand::Ex,
+            _ => return Parse::Invalid,
+        },
+    };
+    Parse::Done((count, register, command))
+}
+
+impl Normal {
+    /// Handle a key typed while selecting text, running the command if it's complete.
+    /// Commands that act on the selection return `Action::Normal` or `Action::Insert`, after
+    /// which it's no longer selected.
+    pub fn handle_visual_key(
+        &mut self,
+        key: Key,
+        lines: &mut Vec<String>,
+        cursor: &mut Position,
+        visual: &mut Visual,
+        screen: Screen,
+    ) -> Option<Action> {
+        self.keys.push(key);
+        let (count, register, command) = match parse_visual(&self.keys) {
+            Parse::Incomplete => return None,
+            Parse::Invalid => {
+                self.keys.clear();
+                return None;
+            }
+            Parse::Done(command) => command,
+        };
+        self.keys.clear();
+
+        let times = count.unwrap_or(1);
+        let action = match command {
+            VisualCommand::Move(motion) => {
+                self.move_cursor(motion, count, lines, cursor, &screen);
+                Action::Done
+            }
+            VisualCommand::Object { object, around } => {
+                if let Some(Span::Chars(start, end)) =
+                    Self::object_span(lines, *cursor, object, around)
+                {
+                    if start < end {
+                        visual.anchor = start;
+                        *cursor = (end.0, prev(&lines[end.0], end.1));
+                    }
+                }
+                Action::Done
+            }
+            VisualCommand::Operate {
+                operator,
+                lines: whole,
+            } => {
+                let span = if whole {
+                    let ((first, _), (last, _)) = visual.ends(*cursor);
+                    Span::Lines(first, last)
+                } else {
+                    visual.span(lines, *cursor)
+                };
+                let repeat = match operator {
+                    Operator::Indent | Operator::Outdent => times,
+                    _ => 1,
+                };
+                let mut action = Action::Normal;
+                for _ in 0..repeat {
+                    let registers = &mut self.registers;
+                    action = Self::operate(operator, span, lines, cursor, registers, register);
+                }
+                if action == Action::Insert {
+                    if let Span::Block {
+                        rows,
+                        columns: (column, _),
+                    } = span
+                    {
+                        self.block_insert = Some(BlockInsert {
+                            rows,
+                            column,
+                            lines: lines.len(),
+                            length: lines[rows.0].len(),
+                        });
+                    }
+                    return Some(Action::Insert);
+                }
+                Action::Normal
+            }
+            VisualCommand::Join => {
+                let ((first, _), (last, _)) = visual.ends(*cursor);
+                let join = ParsedCommand {
+                    count: Some(last - first + 1),
+                    register: None,
+                    command: Command::Join,
+                };
+                *cursor = (first, 0);
+                self.execute(join, lines, cursor, screen);
+                Action::Normal
+            }
+            VisualCommand::SwapEnds => {
+                std::mem::swap(&mut visual.anchor, cursor);
+                Action::Done
+            }
+            VisualCommand::Select(selection) if selection == visual.selection => Action::Normal,
+            VisualCommand::Select(selection) => Action::Visual(selection),
+            VisualCommand::Exit => Action::Normal,
+            VisualCommand::Search { forward } => Action::Search { forward },
+            VisualCommand::SearchAgain { reverse } => Action::SearchAgain {
+                reverse,
+                count: times,
+            },
+            VisualCommand::Ex => Action::Ex,
+        };
+
+        cursor.0 = std::cmp::min(cursor.0, lines.len() - 1);
+        cursor.1 = clamp(&lines[cursor.0], cursor.1);
+        Some(action)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{test::screen, Register};
+    use super::*;
+
+    /// Run commands on some text, starting and stopping visual mode as the editor would.
+    /// Returns the text, where the cursor ends up, and what's in the unnamed register.
+    fn run(text: &str, cursor: Position, input: &str) -> ((String, Position), Option<Register>) {
+        let mut normal = Normal::default();
+        let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+        let mut cursor = cursor;
+        let mut visual: Option<Visual> = None;
+        for c in input.chars() {
+            let key = if c == '\x1b' {
+                Key::Escape
+            } else {
+                Key::Char(c)
+            };
+            let action = match visual.as_mut() {
+                Some(visual) => {
+                    normal.handle_visual_key(key, &mut lines, &mut cursor, visual, screen())
+                }
+                None => normal.handle_key(key, &mut lines, &mut cursor, screen()),
+            };
+            match action {
+                Some(Action::Visual(selection)) => match visual.as_mut() {
+                    Some(visual) => visual.selection = selection,
+                    None => visual = Some(Visual::new(selection, cursor)),
+                },
+                Some(Action::Normal) => visual = None,
+                // Insert mode isn't simulated, so stop there.
+                Some(Action::Insert) => break,
+                _ => {}
+            }
+        }
+        let register = normal.registers.get(None).cloned();
+        ((lines.join("\n"), cursor), register)
+    }
+
+    #[test]
+    fn selections() {
+        let text = "one two\nthree\nfour";
+        assert_eq!(run(text, (0, 1), "vjd").0, ("oree\nfour".into(), (0, 1)));
+        assert_eq!(
+            run(text, (0, 4), "vey").1,
+            Some(Register::Text("two".into()))
+        );
+        assert_eq!(
+            run(text, (0, 4), "ve\"by\"bP").0 .0,
+            "one twotwo\nthree\nfour"
+        );
+        assert_eq!(run(text, (1, 2), "Vjd").0, ("one two".into(), (0, 0)));
+        assert_eq!(
+            run(text, (0, 0), "Vj>").0 .0,
+            "    one two\n    three\nfour"
+        );
+        assert_eq!(run(text, (0, 4), "viwU").0 .0, "one TWO\nthree\nfour");
+        assert_eq!(run(text, (0, 1), "vllohd").0 .0, "two\nthree\nfour");
+        // Switching between kinds of selection, and back out.
+        assert_eq!(run(text, (0, 0), "vVd").0 .0, "three\nfour");
+        assert_eq!(run(text, (0, 0), "vvx").0 .0, "ne two\nthree\nfour");
+        assert_eq!(run(text, (0, 0), "vl\x1bd").0 .0, "one two\nthree\nfour");
+        assert_eq!(run(text, (0, 0), "VjJ").0 .0, "one two three\nfour");
+        // An empty line's line break is selected with it.
+        assert_eq!(run("a\n\nb", (1, 0), "vd").0 .0, "a\nb");
+    }
+
+    #[test]
+    fn blocks() {
+        let text = "abcd\nefgh\nij";
+        let ((deleted, cursor), register) = run(text, (0, 1), "\x16jld");
+        assert_eq!((deleted.as_str(), cursor), ("ad\neh\nij", (0, 1)));
+        assert_eq!(
+            register,
+            Some(Register::Block(vec!["bc".into(), "fg".into()]))
+        );
+        // Short lines are padded when a block is put.
+        assert_eq!(
+            run(text, (0, 1), "\x16jlyjjp").0 .0,
+            "abcd\nefgh\nijbc\n  fg"
+        );
+        // The cursor can't go past the end of a short line, which narrows the block.
+        assert_eq!(run(text, (0, 2), "\x16jj~").0 .0, "aBCd\neFGh\niJ");
+        // Blocks are columns of the screen, which wide characters take two of.
+        assert_eq!(run("a漢b\nabcd", (0, 1), "\x16jd").0 .0, "ab\nad");
+
+        // Text typed after changing a block goes on each of its rows.
+        let mut normal = Normal::default();
+        let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+        let mut cursor = (0, 1);
+        let mut visual = Visual::new(Selection::Block, cursor);
+        for c in "jc".chars() {
+            normal.handle_visual_key(Key::Char(c), &mut lines, &mut cursor, &mut visual, screen());
+        }
+        assert_eq!(cursor, (0, 1));
+        lines[0].insert_str(1, "XY");
+        normal.finish_insert(&mut lines);
+        assert_eq!(lines, ["aXYcd", "eXYgh", "ij"]);
+    }
+
+    #[test]
+    fn highlighting() {
+        let lines: Vec<String> = ["one two", "three", "four"].map(String::from).to_vec();
+        let visual = Visual::new(Selection::Chars, (0, 4));
+        assert_eq!(visual.selected(&lines, (1, 1), 0), Some(4..7));
+        assert_eq!(visual.selected(&lines, (1, 1), 1), Some(0..2));
+        assert_eq!(visual.selected(&lines, (1, 1), 2), None);
+        let visual = Visual::new(Selection::Block, (0, 1));
+        assert_eq!(visual.selected(&lines, (2, 2), 1), Some(1..3));
+        let visual = Visual::new(Selection::Lines, (2, 1));
+        assert_eq!(visual.selected(&lines, (1, 0), 1), Some(0..5));
+    }
+}
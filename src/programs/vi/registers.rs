@@ -0,0 +1,187 @@
+//! Registers, which hold text that's been yanked or deleted so it can be put elsewhere.
+//!
+//! Like vi, `"0` holds the last yank and `"1` to `"9` the last few deletes of whole lines, with
+//! smaller deletes in `"-`. `"a` to `"z` are only written when named, and naming them in
+//! uppercase adds to what they hold. `"_` throws text away.
+use std::collections::BTreeMap;
+
+/// Text that was yanked or deleted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Register {
+    /// Whole lines, which are put above or below the cursor's line.
+    Lines(Vec<String>),
+    /// Part of a line, or lines separated by newlines.
+    Text(String),
+    /// A rectangle from visual block mode, which is put in the same column of each line.
+    Block(Vec<String>),
+}
+
+impl Default for Register {
+    fn default() -> Self {
+        Self::Text(String::new())
+    }
+}
+
+impl Register {
+    fn into_lines(self) -> Vec<String> {
+        match self {
+            Self::Lines(lines) | Self::Block(lines) => lines,
+            Self::Text(text) => text.split('\n').map(String::from).collect(),
+        }
+    }
+
+    /// Add text to the end of the register. Adding lines to text makes it lines.
+    fn append(&mut self, text: Register) {
+        *self = match (std::mem::take(self), text) {
+            (Self::Text(mut old), Self::Text(new)) => {
+                old.push_str(&new);
+                Self::Text(old)
+            }
+            (Self::Block(mut old), Self::Block(new)) => {
+                old.extend(new);
+                Self::Block(old)
+            }
+            (old, new) => {
+                let mut lines = old.into_lines();
+                lines.extend(new.into_lines());
+                Self::Lines(lines)
+            }
+        };
+    }
+
+    /// A line describing the register for `:registers`: its type, name, then its contents with
+    /// line breaks shown as `^J`.
+    pub fn describe(&self, name: char) -> String {
+        let (kind, contents) = match self {
+            Self::Lines(lines) => ('l', format!("{}^J", lines.join("^J"))),
+            Self::Text(text) => ('c', text.replace('\n', "^J")),
+            Self::Block(lines) => ('b', lines.join("^J")),
+        };
+        format!("  {kind}  \"{name}   {contents}")
+    }
+}
+
+/// All the registers, by name.
+#[derive(Default)]
+pub struct Registers {
+    registers: BTreeMap<char, Register>,
+    /// The register `""` refers to, which is the one last written.
+    unnamed: Option<char>,
+}
+
+impl Registers {
+    /// Check if a register can be named with `"`.
+    pub fn is_valid(name: char) -> bool {
+        name.is_ascii_alphanumeric() || matches!(name, '"' | '-' | '_')
+    }
+
+    /// Get what a register holds, or the unnamed register if there's no name.
+    pub fn get(&self, name: Option<char>) -> Option<&Register> {
+        let name = match name {
+            None | Some('"') => self.unnamed?,
+            Some(name) => name.to_ascii_lowercase(),
+        };
+        self.registers.get(&name)
+    }
+
+    /// Store yanked text, in `"0` if no register is named.
+    pub fn yank(&mut self, name: Option<char>, text: Register) {
+        match name {
+            None | Some('"') => self.store('0', text),
+            Some(name) => self.store(name, text),
+        }
+    }
+
+    /// Store deleted text. If no register is named, whole lines or text with line breaks go in
+    /// `"1`, moving older deletes up to `"9`, and anything smaller goes in `"-`.
+    pub fn delete(&mut self, name: Option<char>, text: Register) {
+        match name {
+            None | Some('"') => {
+                if matches!(&text, Register::Text(text) if !text.contains('\n')) {
+                    self.store('-', text);
+                    return;
+                }
+                for digit in ('1'..='8').rev() {
+                    if let Some(register) = self.registers.remove(&digit) {
+                        let next = char::from(digit as u8 + 1);
+                        self.registers.insert(next, register);
+                    }
+                }
+                self.store('1', text);
+            }
+            Some(name) => self.store(name, text),
+        }
+    }
+
+    fn store(&mut self, name: char, text: Register) {
+        if name == '_' {
+            return;
+        }
+        let lowercase = name.to_ascii_lowercase();
+        match self.registers.get_mut(&lowercase) {
+            Some(register) if name.is_ascii_uppercase() => register.append(text),
+            _ => {
+                self.registers.insert(lowercase, text);
+            }
+        }
+        self.unnamed = Some(lowercase);
+    }
+
+    /// Every register that holds something, with the unnamed register first.
+    pub fn list(&self) -> impl Iterator<Item = (char, &Register)> {
+        let unnamed = self.get(None).map(|register| ('"', register));
+        unnamed.into_iter().chain(
+            self.registers
+                .iter()
+                .map(|(name, register)| (*name, register)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(text: &str) -> Register {
+        Register::Text(text.into())
+    }
+
+    #[test]
+    fn registers() {
+        let mut registers = Registers::default();
+        assert_eq!(registers.get(None), None);
+        registers.yank(None, text("yanked"));
+        registers.delete(None, text("x"));
+        registers.delete(None, Register::Lines(vec!["one".into()]));
+        registers.delete(None, text("a\nb"));
+        assert_eq!(registers.get(Some('0')), Some(&text("yanked")));
+        assert_eq!(registers.get(Some('-')), Some(&text("x")));
+        assert_eq!(registers.get(Some('1')), Some(&text("a\nb")));
+        assert_eq!(
+            registers.get(Some('2')),
+            Some(&Register::Lines(vec!["one".into()]))
+        );
+        assert_eq!(registers.get(None), Some(&text("a\nb")));
+
+        // Named registers, which uppercase names add to.
+        registers.yank(Some('a'), text("foo"));
+        registers.yank(Some('A'), text("bar"));
+        assert_eq!(registers.get(Some('a')), Some(&text("foobar")));
+        registers.delete(Some('A'), Register::Lines(vec!["baz".into()]));
+        assert_eq!(
+            registers.get(Some('A')),
+            Some(&Register::Lines(vec!["foobar".into(), "baz".into()]))
+        );
+        assert_eq!(registers.get(Some('0')), Some(&text("yanked")));
+        registers.delete(Some('_'), text("gone"));
+        assert_eq!(registers.get(Some('_')), None);
+        assert_eq!(registers.get(None), registers.get(Some('a')));
+
+        let names: String = registers.list().map(|(name, _)| name).collect();
+        assert_eq!(names, "\"-012a");
+        assert_eq!(
+            registers.get(Some('a')).unwrap().describe('a'),
+            "  l  \"a   foobar^Jbaz^J"
+        );
+    }
+}
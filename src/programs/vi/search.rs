@@ -0,0 +1,291 @@
+//! Searching and substituting with regular expressions.
+//!
+//! Patterns use the `regex` crate's syntax, plus vi's `\<` and `\>` for the start and end of a
+//! word.
+use crate::programs::common::{color_picker::Color, highlight::Highlight};
+use anyhow::{bail, Result};
+use regex::{Captures, Regex};
+use std::ops::Range;
+
+/// Compile a search pattern.
+pub fn compile(pattern: &str, ignore_case: bool) -> Result<Regex> {
+    let pattern = pattern.replace("\\<", "\\b").replace("\\>", "\\b");
+    let pattern = if ignore_case {
+        format!("(?i){pattern}")
+    } else {
+        pattern
+    };
+    Ok(Regex::new(&pattern)?)
+}
+
+/// A pattern matching `word` as a whole word, for `*` and `#`.
+pub fn word_pattern(word: &str) -> String {
+    format!("\\<{}\\>", regex::escape(word))
+}
+
+/// Find the word under the cursor, or the next one after it, for `*` and `#`.
+pub fn word_at(line: &str, column: usize) -> Option<Range<usize>> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut start = None;
+    for (index, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (is_word(c), start) {
+            (true, None) => start = Some(index),
+            (false, Some(word_start)) => {
+                if index > column {
+                    return Some(word_start..index);
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Find the next match after the cursor, or the previous one before it if not `forward`,
+/// wrapping around the end of the file. Returns where the match starts, and if the search
+/// wrapped.
+pub fn find(
+    lines: &[String],
+    regex: &Regex,
+    (row, column): (usize, usize),
+    forward: bool,
+) -> Option<((usize, usize), bool)> {
+    let starts = |row: usize| regex.find_iter(&lines[row]).map(|found| found.start());
+    if forward {
+        if let Some(start) = starts(row).find(|start| *start > column) {
+            return Some(((row, start), false));
+        }
+        for next in (row + 1..lines.len()).chain(0..=row) {
+            if let Some(start) = starts(next).next() {
+                return Some(((next, start), next <= row));
+            }
+        }
+    } else {
+        if let Some(start) = starts(row).filter(|start| *start < column).last() {
+            return Some(((row, start), false));
+        }
+        for next in (0..row).rev().chain((row..lines.len()).rev()) {
+            if let Some(start) = starts(next).last() {
+                return Some(((next, start), next >= row));
+            }
+        }
+    }
+    None
+}
+
+/// Highlight every match in a line.
+pub fn highlight_matches(line: &str, regex: &Regex) -> Vec<Highlight> {
+    regex
+        .find_iter(line)
+        .filter(|found| !found.is_empty())
+        .map(|found| (found.range(), Color::Reverse))
+        .collect()
+}
+
+/// The arguments of a `:s` command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Substitution {
+    /// The pattern, or empty to use the last search pattern.
+    pub pattern: String,
+    pub replacement: String,
+    /// Replace every match on a line, rather than just the first.
+    pub global: bool,
+    /// Whether case is ignored, or `None` to follow the `ignorecase` option.
+    pub ignore_case: Option<bool>,
+    /// Ask before each replacement.
+    pub confirm: bool,
+}
+
+impl Substitution {
+    /// Parse the arguments of a `:s` command, like `/pattern/replacement/flags`. Any
+    /// punctuation can be used instead of `/`.
+    pub fn parse(arguments: &str) -> Result<Self> {
+        let mut chars = arguments.chars();
+        let Some(delimiter) = chars.next() else {
+            bail!("Missing pattern");
+        };
+        if delimiter.is_alphanumeric() || delimiter.is_whitespace() || delimiter == '\\' {
+            bail!("Invalid delimiter: {delimiter}");
+        }
+
+        // Split on unescaped delimiters, keeping escapes of anything else.
+        let mut parts = vec![String::new()];
+        while let Some(c) = chars.next() {
+            if c == delimiter && parts.len() < 3 {
+                parts.push(String::new());
+                continue;
+            }
+            let part = parts.last_mut().expect("BUG: parts can't be empty");
+            if c == '\\' {
+                match chars.next() {
+                    Some(c) if c == delimiter => part.push(c),
+                    Some(c) => {
+                        part.push('\\');
+                        part.push(c);
+                    }
+                    None => part.push('\\'),
+                }
+            } else {
+                part.push(c);
+            }
+        }
+
+        let mut parts = parts.into_iter();
+        let pattern = parts.next().unwrap_or_default();
+        let replacement = parts.next().unwrap_or_default();
+        let mut substitution = Self {
+            pattern,
+            replacement,
+            global: false,
+            ignore_case: None,
+            confirm: false,
+        };
+        for flag in parts.next().unwrap_or_default().chars() {
+            match flag {
+                'g' => substitution.global = true,
+                'i' => substitution.ignore_case = Some(true),
+                'I' => substitution.ignore_case = Some(false),
+                'c' => substitution.confirm = true,
+                _ => bail!("Trailing characters: {flag}"),
+            }
+        }
+        Ok(substitution)
+    }
+}
+
+/// Translate a vi replacement string, where `&` and `\1` stand for what was matched, to the
+/// `regex` crate's syntax.
+pub fn expand_replacement(replacement: &str) -> String {
+    let mut expanded = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => expanded.push_str("${0}"),
+            '$' => expanded.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => expanded.push_str(&format!("${{{digit}}}")),
+                Some('t') => expanded.push('\t'),
+                Some('$') => expanded.push_str("$$"),
+                Some(c) => expanded.push(c),
+                None => expanded.push('\\'),
+            },
+            c => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+/// The matches in a line that a substitution would replace.
+pub fn substitution_targets(line: &str, regex: &Regex, global: bool) -> Vec<Range<usize>> {
+    regex
+        .find_iter(line)
+        .take(if global { usize::MAX } else { 1 })
+        .map(|found| found.range())
+        .collect()
+}
+
+/// Replace matches in a line with an expanded `replacement`. Only matches that `accept`
+/// agrees to are replaced. Returns `None` if nothing was.
+pub fn substitute(
+    line: &str,
+    regex: &Regex,
+    replacement: &str,
+    global: bool,
+    mut accept: impl FnMut(Range<usize>) -> bool,
+) -> Option<String> {
+    let mut replaced = false;
+    let mut position = 0;
+    let mut result = String::new();
+    let captures: Vec<Captures> = regex
+        .captures_iter(line)
+        .take(if global { usize::MAX } else { 1 })
+        .collect();
+    for capture in captures {
+        let found = capture.get(0).expect("BUG: captures always have a match");
+        if !accept(found.range()) {
+            continue;
+        }
+        result.push_str(&line[position..found.start()]);
+        capture.expand(replacement, &mut result);
+        position = found.end();
+        replaced = true;
+    }
+    result.push_str(&line[position..]);
+    replaced.then_some(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn searching() {
+        let lines: Vec<String> = ["foo bar", "baz", "bar foo"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let regex = compile("foo", false).unwrap();
+        assert_eq!(find(&lines, &regex, (0, 0), true), Some(((2, 4), false)));
+        assert_eq!(find(&lines, &regex, (2, 4), true), Some(((0, 0), true)));
+        assert_eq!(find(&lines, &regex, (2, 4), false), Some(((0, 0), false)));
+        assert_eq!(find(&lines, &regex, (0, 0), false), Some(((2, 4), true)));
+        // A single match is found again after wrapping around.
+        let regex = compile("baz", false).unwrap();
+        assert_eq!(find(&lines, &regex, (1, 0), true), Some(((1, 0), true)));
+        assert_eq!(find(&lines, &regex, (1, 0), false), Some(((1, 0), true)));
+        assert_eq!(
+            find(&lines, &compile("qux", false).unwrap(), (0, 0), true),
+            None
+        );
+        // Whole words
+        assert_eq!(word_at("foo.bar baz", 4), Some(4..7));
+        assert_eq!(word_at("foo.bar baz", 3), Some(4..7));
+        assert_eq!(word_at("foo ", 3), None);
+        let regex = compile(&word_pattern("ba"), false).unwrap();
+        assert_eq!(find(&lines, &regex, (0, 0), true), None);
+        let regex = compile("BAR", true).unwrap();
+        assert_eq!(
+            highlight_matches(&lines[2], &regex),
+            vec![(0..3, Color::Reverse)]
+        );
+    }
+
+    #[test]
+    fn substitution() {
+        assert_eq!(
+            Substitution::parse("#a\\#b#c/d#gc").unwrap(),
+            Substitution {
+                pattern: "a#b".into(),
+                replacement: "c/d".into(),
+                global: true,
+                ignore_case: None,
+                confirm: true,
+            }
+        );
+        let substitution = Substitution::parse("/x").unwrap();
+        assert_eq!(
+            (
+                substitution.pattern.as_str(),
+                substitution.replacement.as_str()
+            ),
+            ("x", "")
+        );
+        assert!(Substitution::parse("/a/b/z").is_err());
+        assert!(Substitution::parse("").is_err());
+
+        let regex = compile("(o+)", false).unwrap();
+        let replacement = expand_replacement("<&\\1$>");
+        let substitute = |line, global| substitute(line, &regex, &replacement, global, |_| true);
+        assert_eq!(substitute("foo boo", false).unwrap(), "f<oooo$> boo");
+        assert_eq!(substitute("foo boo", true).unwrap(), "f<oooo$> b<oooo$>");
+        assert_eq!(substitute("bar", true), None);
+        // Only accepted matches are replaced.
+        let mut accepted = [false, true].into_iter();
+        assert_eq!(
+            super::substitute("foo boo", &regex, "0", true, |_| accepted.next().unwrap()),
+            Some("foo b0".into())
+        );
+        assert_eq!(substitution_targets("foo boo", &regex, false), vec![1..3]);
+    }
+}
@@ -0,0 +1,150 @@
+//! Options changed with `:set`.
+use crate::programs::common::unicode;
+use anyhow::{bail, Result};
+
+/// The options that can be set, and their values.
+pub struct Settings {
+    /// Show line numbers.
+    pub number: bool,
+    /// Show line numbers counted from the cursor's line.
+    pub relative_number: bool,
+    /// How many columns a tab takes up.
+    pub tabstop: usize,
+    /// Search patterns ignore case.
+    pub ignore_case: bool,
+    /// Keep changes that haven't been saved in a swap file.
+    pub swap_file: bool,
+    /// How many keys can be typed in insert mode before the swap file is brought up to date.
+    pub update_count: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            number: false,
+            relative_number: false,
+            tabstop: unicode::TAB_SIZE,
+            ignore_case: false,
+            swap_file: true,
+            update_count: 200,
+        }
+    }
+}
+
+impl Settings {
+    /// Find an option that's on or off, by its name or abbreviation.
+    fn flag(&mut self, name: &str) -> Option<(&'static str, &mut bool)> {
+        match name {
+            "number" | "nu" => Some(("number", &mut self.number)),
+            "relativenumber" | "rnu" => Some(("relativenumber", &mut self.relative_number)),
+            "ignorecase" | "ic" => Some(("ignorecase", &mut self.ignore_case)),
+            "swapfile" | "swf" => Some(("swapfile", &mut self.swap_file)),
+            _ => None,
+        }
+    }
+
+    /// Find an option that has a number, by its name or abbreviation.
+    fn value(&mut self, name: &str) -> Option<(&'static str, &mut usize)> {
+        match name {
+            "tabstop" | "ts" => Some(("tabstop", &mut self.tabstop)),
+            "updatecount" | "uc" => Some(("updatecount", &mut self.update_count)),
+            _ => None,
+        }
+    }
+
+    /// Apply an argument of `:set`, like `number`, `nonumber`, `invnumber`, `number!`,
+    /// `tabstop=4` or `ts?`. Returns what the option is set to if asked.
+    pub fn set(&mut self, argument: &str) -> Result<Option<String>> {
+        if let Some((name, value)) = argument.split_once('=') {
+            let Some((_, option)) = self.value(name) else {
+                bail!("Unknown option: {name}");
+            };
+            match value.parse() {
+                Ok(0) | Err(_) => bail!("Argument must be positive: {argument}"),
+                Ok(value) => *option = value,
+            }
+            return Ok(None);
+        }
+
+        let query = argument.strip_suffix('?');
+        let name = query.unwrap_or(argument);
+        if let Some((name, value)) = self.value(name) {
+            return Ok(Some(format!("{name}={value}")));
+        }
+        if query.is_some() {
+            let Some((name, value)) = self.flag(name) else {
+                bail!("Unknown option: {name}");
+            };
+            return Ok(Some(format!("{}{name}", if *value { "" } else { "no" })));
+        }
+
+        let toggle = argument
+            .strip_prefix("inv")
+            .or_else(|| argument.strip_suffix('!'));
+        if let Some((_, value)) = toggle.and_then(|name| self.flag(name)) {
+            *value = !*value;
+        } else if let Some((_, value)) =
+            argument.strip_prefix("no").and_then(|name| self.flag(name))
+        {
+            *value = false;
+        } else if let Some((_, value)) = self.flag(argument) {
+            *value = true;
+        } else {
+            bail!("Unknown option: {argument}");
+        }
+        Ok(None)
+    }
+
+    /// Every option and what it's set to, for `:set all`.
+    pub fn list(&mut self) -> String {
+        [
+            "ignorecase",
+            "number",
+            "relativenumber",
+            "swapfile",
+            "tabstop",
+            "updatecount",
+        ]
+        .into_iter()
+        .filter_map(|name| self.set(&format!("{name}?")).ok().flatten())
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn settings() {
+        let mut settings = Settings::default();
+        settings.set("nu").unwrap();
+        settings.set("ts=4").unwrap();
+        settings.set("invic").unwrap();
+        assert!(settings.number && settings.ignore_case);
+        assert_eq!(settings.tabstop, 4);
+        settings.set("nonumber").unwrap();
+        settings.set("ignorecase!").unwrap();
+        assert!(!settings.number && !settings.ignore_case);
+
+        assert_eq!(settings.set("ts?").unwrap().as_deref(), Some("tabstop=4"));
+        assert_eq!(
+            settings.set("tabstop").unwrap().as_deref(),
+            Some("tabstop=4")
+        );
+        assert_eq!(settings.set("nu?").unwrap().as_deref(), Some("nonumber"));
+        settings.set("rnu").unwrap();
+        settings.set("noswf").unwrap();
+        settings.set("uc=50").unwrap();
+        assert_eq!(
+            settings.list(),
+            "noignorecase\nnonumber\nrelativenumber\nnoswapfile\ntabstop=4\nupdatecount=50"
+        );
+
+        assert!(settings.set("ts=0").is_err());
+        assert!(settings.set("nu=2").is_err());
+        assert!(settings.set("bogus").is_err());
+        assert!(settings.set("notabstop").is_err());
+    }
+}
@@ -0,0 +1,246 @@
+//! Swap files, which keep a copy of changes that haven't been saved yet, so they can be
+//! recovered if the editor is stopped before they are.
+//!
+//! A buffer gets a swap file, `.name.swp` beside its file, once it has changes. It's kept up to
+//! date after each command, and every `updatecount` keys typed in insert mode, and removed when
+//! the editor quits.
+use super::{read_file, Editor};
+use crate::{programs::common::keys::Key, AnsiCode};
+use anyhow::Result;
+use std::io::Write;
+
+/// The swap file kept for a buffer.
+#[derive(Default)]
+pub enum Swap {
+    /// There isn't one yet.
+    #[default]
+    None,
+    /// It's been written at `path`, holding `lines`.
+    Written { path: String, lines: Vec<String> },
+    /// Another swap file was there when the file was opened, and has been left alone.
+    Ignored,
+}
+
+/// What to do about a swap file found when opening a file.
+enum Choice {
+    Recover,
+    Delete,
+    Ignore,
+}
+
+/// Where the swap file for a file goes: `.name.swp` in the same directory.
+pub fn swap_path(file: &str) -> String {
+    match file.rsplit_once('/') {
+        Some((directory, name)) => format!("{directory}/.{name}.swp"),
+        None => format!(".{file}.swp"),
+    }
+}
+
+impl Editor<'_> {
+    /// Look for a swap file left behind for a file being opened, and ask what to do about it.
+    /// Returns the lines to edit, which are the swap file's if it's recovered, and the swap
+    /// file the buffer starts with.
+    pub(super) async fn check_swap(
+        &mut self,
+        file: &str,
+        lines: Vec<String>,
+    ) -> Result<(Vec<String>, Swap)> {
+        let path = swap_path(file);
+        if !self.settings.swap_file {
+            return Ok((lines, Swap::None));
+        }
+        let Some(swapped) = read_file(self.process, &path)? else {
+            return Ok((lines, Swap::None));
+        };
+
+        match self.ask_about_swap(file, &path).await? {
+            Choice::Recover => {
+                self.status = Some(format!(
+                    "Recovered \"{file}\" from \"{path}\", :w to save it"
+                ));
+                let swap = Swap::Written {
+                    path,
+                    lines: swapped.clone(),
+                };
+                Ok((swapped, swap))
+            }
+            Choice::Delete => {
+                self.process.get_path(&path)?.remove_file()?;
+                Ok((lines, Swap::None))
+            }
+            Choice::Ignore => Ok((lines, Swap::Ignored)),
+        }
+    }
+
+    async fn ask_about_swap(&mut self, file: &str, path: &str) -> Result<Choice> {
+        self.reset = true;
+        self.stdout.write_all(&AnsiCode::Clear.to_bytes())?;
+        self.stdout.write_all(
+            format!(
+                "Found a swap file by the name \"{path}\"\n\
+                 It holds changes to \"{file}\" that may not have been saved, from when it was \
+                 last edited.\n\n\
+                 [R]ecover the changes, [D]elete the swap file or [I]gnore it: "
+            )
+            .as_bytes(),
+        )?;
+        self.stdout.flush()?;
+        loop {
+            match Key::read(&mut self.stdin).await? {
+                Key::Char('r' | 'R') => return Ok(Choice::Recover),
+                Key::Char('d' | 'D') => return Ok(Choice::Delete),
+                Key::Char('i' | 'I') | Key::Escape => return Ok(Choice::Ignore),
+                _ => {}
+            }
+        }
+    }
+
+    /// Bring the current buffer's swap file up to date, creating it if the buffer has changes
+    /// that haven't been saved.
+    pub(super) fn update_swap(&mut self) {
+        self.typed = 0;
+        let path = match (&self.file, self.settings.swap_file) {
+            (Some(file), true) => Some(swap_path(file)),
+            _ => None,
+        };
+        let changed = self.undo.modified() || self.lines != self.undo.current();
+        let swap = &self.buffers[self.buffer].swap;
+        match (swap, &path) {
+            (Swap::Ignored, _) => return,
+            (Swap::None, _) if !changed => return,
+            (Swap::None, None) => return,
+            (
+                Swap::Written {
+                    path: written,
+                    lines,
+                },
+                Some(path),
+            ) if written == path && *lines == self.lines => return,
+            _ => {}
+        }
+
+        // The file could have been renamed, or swap files turned off.
+        self.remove_swap(self.buffer);
+        let Some(path) = path else {
+            return;
+        };
+        let contents = self.lines.join("\n") + "\n";
+        let written = self
+            .process
+            .get_path(&path)
+            .and_then(|path| Ok(path.create_file()?.write_all(contents.as_bytes())?));
+        match written {
+            Ok(()) => {
+                self.buffers[self.buffer].swap = Swap::Written {
+                    path,
+                    lines: self.lines.clone(),
+                }
+            }
+            Err(err) => self.status = Some(format!("Unable to write swap file \"{path}\": {err}")),
+        }
+    }
+
+    /// Remove a buffer's swap file, if it has one.
+    pub(super) fn remove_swap(&mut self, index: usize) {
+        let swap = std::mem::take(&mut self.buffers[index].swap);
+        if let Swap::Written { path, .. } = swap {
+            if let Ok(path) = self.process.get_path(&path) {
+                // It's fine if it's already gone.
+                let _ = path.remove_file();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{super::test::*, *};
+    use vfs::VfsPath;
+
+    /// A file system with a file, and a swap file left behind for it by an editor that was
+    /// stopped after changing it.
+    async fn stopped() -> VfsPath {
+        let cwd = file_system(&[("notes", "one\ntwo\n")]);
+        let (result, _) = edit(&cwd, &["notes"], "ddAx\x1b\x1b").await;
+        assert!(result.is_err());
+        assert_eq!(contents(&cwd, ".notes.swp").as_deref(), Some("twox\n"));
+        assert_eq!(contents(&cwd, "notes").as_deref(), Some("one\ntwo\n"));
+        cwd
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(swap_path("notes.txt"), ".notes.txt.swp");
+        assert_eq!(swap_path("/home/user/todo"), "/home/user/.todo.swp");
+        assert_eq!(swap_path("../a/b.rs"), "../a/.b.rs.swp");
+    }
+
+    #[futures_test::test]
+    async fn recovering() {
+        let cwd = stopped().await;
+        let (result, screen) = edit(&cwd, &["notes"], "r:wq\n").await;
+        result.unwrap();
+        assert!(screen.contains("Found a swap file by the name \".notes.swp\""));
+        assert_eq!(contents(&cwd, "notes").as_deref(), Some("twox\n"));
+        assert_eq!(contents(&cwd, ".notes.swp"), None);
+
+        // The recovered changes can be undone.
+        let cwd = stopped().await;
+        edit(&cwd, &["notes"], "Ru:wq\n").await.0.unwrap();
+        assert_eq!(contents(&cwd, "notes").as_deref(), Some("one\ntwo\n"));
+        assert_eq!(contents(&cwd, ".notes.swp"), None);
+    }
+
+    #[futures_test::test]
+    async fn deleting() {
+        let cwd = stopped().await;
+        edit(&cwd, &["notes"], "d:q\n").await.0.unwrap();
+        assert_eq!(contents(&cwd, "notes").as_deref(), Some("one\ntwo\n"));
+        assert_eq!(contents(&cwd, ".notes.swp"), None);
+    }
+
+    #[futures_test::test]
+    async fn ignoring() {
+        // Other keys are ignored until one of the choices is made.
+        let cwd = stopped().await;
+        edit(&cwd, &["notes"], "xI:q\n").await.0.unwrap();
+        assert_eq!(contents(&cwd, ".notes.swp").as_deref(), Some("twox\n"));
+
+        // An ignored swap file is left alone, even with changes.
+        edit(&cwd, &["notes"], "\x1b\x1bdd:wq\n").await.0.unwrap();
+        assert_eq!(contents(&cwd, "notes").as_deref(), Some("two\n"));
+        assert_eq!(contents(&cwd, ".notes.swp").as_deref(), Some("twox\n"));
+    }
+
+    #[futures_test::test]
+    async fn updating() {
+        let cwd = file_system(&[("notes", "one\n")]);
+
+        // There's no swap file until there are changes.
+        let (result, _) = edit(&cwd, &["notes"], "jk").await;
+        assert!(result.is_err());
+        assert_eq!(contents(&cwd, ".notes.swp"), None);
+
+        // It's kept up to date while typing every `updatecount` keys.
+        let (result, _) = edit(&cwd, &["notes"], ":set updatecount=3\nAabcd").await;
+        assert!(result.is_err());
+        assert_eq!(contents(&cwd, ".notes.swp").as_deref(), Some("oneab\n"));
+        cwd.join(".notes.swp").unwrap().remove_file().unwrap();
+
+        // Saving doesn't remove it, but quitting does.
+        let (result, _) = edit(&cwd, &["notes"], "x:w\n").await;
+        assert!(result.is_err());
+        assert_eq!(contents(&cwd, "notes").as_deref(), Some("ne\n"));
+        assert_eq!(contents(&cwd, ".notes.swp").as_deref(), Some("ne\n"));
+        cwd.join(".notes.swp").unwrap().remove_file().unwrap();
+
+        edit(&cwd, &["notes"], "x:q!\n").await.0.unwrap();
+        assert_eq!(contents(&cwd, "notes").as_deref(), Some("ne\n"));
+        assert_eq!(contents(&cwd, ".notes.swp"), None);
+
+        // Not with swap files turned off.
+        let (result, _) = edit(&cwd, &["notes"], ":set noswapfile\nx").await;
+        assert!(result.is_err());
+        assert_eq!(contents(&cwd, ".notes.swp"), None);
+    }
+}
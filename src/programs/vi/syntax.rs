@@ -0,0 +1,654 @@
+//! Syntax highlighting, chosen by a file's name or the `#!` line at its start.
+//!
+//! Lines are highlighted one at a time, given the state left at the end of the line before,
+//! like being inside a block comment. Each line's highlights are kept until it changes, or the
+//! state it starts in does, so drawing the screen again only highlights lines that changed.
+use super::Editor;
+use crate::programs::{
+    common::{
+        color_picker::Color,
+        highlight::{self, Highlight},
+    },
+    theme::THEMES_DIR,
+};
+
+/// What a syntax needs to know about the lines before one to highlight it, like how deeply
+/// nested in block comments it is. Each syntax decides what its states mean, and files start
+/// in state zero.
+pub type State = usize;
+
+/// A language that can be highlighted.
+pub trait Syntax: Send + Sync {
+    /// Highlight a line that starts in `state`, returning the state at the end of it.
+    fn highlight(&self, line: &str, state: State) -> (Vec<Highlight>, State);
+}
+
+/// Find the syntax for a file from its absolute path, or the `#!` line at its start.
+pub fn detect(path: Option<&str>, first_line: &str) -> Option<Box<dyn Syntax>> {
+    if let Some(interpreter) = first_line.strip_prefix("#!") {
+        // Scripts run with `env` name the interpreter after it.
+        let mut words = interpreter.split_whitespace();
+        let mut name = words.next()?.rsplit('/').next()?;
+        if name == "env" {
+            name = words.next()?;
+        }
+        return match name {
+            "sh" | "bash" | "dash" | "zsh" => Some(Box::new(Shell)),
+            _ => None,
+        };
+    }
+
+    let path = path?;
+    if path.starts_with(&format!("{THEMES_DIR}/")) {
+        return Some(Box::new(Css));
+    }
+    let (_, extension) = path.rsplit('/').next()?.rsplit_once('.')?;
+    match extension {
+        "sh" => Some(Box::new(Shell)),
+        "rs" => Some(Box::new(Rust)),
+        "md" | "markdown" => Some(Box::new(Markdown)),
+        "css" => Some(Box::new(Css)),
+        _ => None,
+    }
+}
+
+/// A line as it was highlighted.
+struct Line {
+    text: String,
+    start: State,
+    end: State,
+    highlights: Vec<Highlight>,
+}
+
+/// The highlights of a buffer's lines, which are worked out as they're shown.
+#[derive(Default)]
+pub struct Highlighter {
+    syntax: Option<Box<dyn Syntax>>,
+    /// The file and first line the syntax was chosen for.
+    chosen_for: Option<(Option<String>, String)>,
+    lines: Vec<Line>,
+}
+
+impl Highlighter {
+    /// Highlight any lines before `end` that changed since they were last highlighted. The
+    /// syntax is chosen again if the file was renamed or its `#!` line changed, with `path`
+    /// finding the absolute path of a file.
+    pub fn update(
+        &mut self,
+        file: Option<&str>,
+        lines: &[String],
+        end: usize,
+        path: impl Fn(&str) -> Option<String>,
+    ) {
+        let first_line = lines.first().map_or("", String::as_str);
+        let changed = match &self.chosen_for {
+            Some((old_file, old_line)) => {
+                old_file.as_deref() != file
+                    || (old_line != first_line
+                        && (old_line.starts_with("#!") || first_line.starts_with("#!")))
+            }
+            None => true,
+        };
+        if changed {
+            self.syntax = detect(file.and_then(path).as_deref(), first_line);
+            self.lines.clear();
+        }
+        self.chosen_for = Some((file.map(String::from), first_line.into()));
+
+        let Some(syntax) = &self.syntax else {
+            return;
+        };
+        self.lines.truncate(lines.len());
+        let mut state = 0;
+        for (row, text) in lines[..std::cmp::min(end, lines.len())].iter().enumerate() {
+            let current = self.lines.get(row);
+            if current.is_none_or(|line| line.start != state || line.text != *text) {
+                let (highlights, end) = syntax.highlight(text, state);
+                let line = Line {
+                    text: text.clone(),
+                    start: state,
+                    end,
+                    highlights,
+                };
+                if row < self.lines.len() {
+                    self.lines[row] = line;
+                } else {
+                    self.lines.push(line);
+                }
+            }
+            state = self.lines[row].end;
+        }
+    }
+
+    /// The highlights of a line, if it's been highlighted since it last changed.
+    pub fn get(&self, lines: &[String], row: usize) -> &[Highlight] {
+        match self.lines.get(row) {
+            Some(line) if line.text == lines[row] => &line.highlights,
+            _ => &[],
+        }
+    }
+}
+
+impl Editor<'_> {
+    /// The highlighter for a buffer's lines.
+    pub(super) fn buffer_highlighter(&self, index: usize) -> &Highlighter {
+        if index == self.buffer {
+            &self.highlighter
+        } else {
+            &self.buffers[index].highlighter
+        }
+    }
+
+    /// Highlight the lines each window could show that aren't already.
+    pub(super) fn update_highlighters(&mut self) {
+        for (index, rect) in self.window_rects().into_iter().enumerate() {
+            let (buffer, offset) = if index == self.window {
+                (self.buffer, self.offset)
+            } else {
+                (self.windows[index].buffer, self.windows[index].offset)
+            };
+            // Every line takes up at least a row.
+            let end = offset + rect.height;
+            let process = self.process;
+            let path = |file: &str| Some(process.get_path(file).ok()?.as_str().to_owned());
+            if buffer == self.buffer {
+                (self.highlighter).update(self.file.as_deref(), &self.lines, end, path);
+            } else {
+                let buffer = &mut self.buffers[buffer];
+                (buffer.highlighter).update(buffer.file.as_deref(), &buffer.lines, end, path);
+            }
+        }
+    }
+}
+
+/// Shell scripts, which are in the state of the quote of a string left open, if any.
+struct Shell;
+
+impl Syntax for Shell {
+    fn highlight(&self, line: &str, state: State) -> (Vec<Highlight>, State) {
+        let quote = char::from_u32(state as u32).filter(|_| state != 0);
+        let (highlights, quote) = highlight::highlight_shell_from(line, quote, |_| true);
+        (highlights, quote.map_or(0, |quote| quote as State))
+    }
+}
+
+/// The length of the identifier or number at the start of some text.
+fn word_length(text: &str) -> usize {
+    text.find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(text.len())
+}
+
+/// Rust, which is in the state of how deeply nested in block comments it is, or `STRING`
+/// inside a string.
+struct Rust;
+
+const STRING: State = State::MAX;
+
+const RUST_KEYWORDS: [&str; 40] = [
+    "as",
+    "async",
+    "await",
+    "break",
+    "const",
+    "continue",
+    "crate",
+    "dyn",
+    "else",
+    "enum",
+    "extern",
+    "false",
+    "fn",
+    "for",
+    "if",
+    "impl",
+    "in",
+    "let",
+    "loop",
+    "match",
+    "mod",
+    "move",
+    "mut",
+    "pub",
+    "ref",
+    "return",
+    "self",
+    "Self",
+    "static",
+    "struct",
+    "super",
+    "trait",
+    "true",
+    "type",
+    "unsafe",
+    "use",
+    "where",
+    "while",
+    "yield",
+    "macro_rules",
+];
+
+impl Syntax for Rust {
+    fn highlight(&self, line: &str, state: State) -> (Vec<Highlight>, State) {
+        let mut highlights = Vec::new();
+        let mut index = 0;
+        let mut depth = 0;
+        if state == STRING {
+            let Some(end) = highlight::string_end(line, '"') else {
+                return (vec![(0..line.len(), Color::Yellow)], STRING);
+            };
+            highlights.push((0..end, Color::Yellow));
+            index = end;
+        } else {
+            depth = state;
+        }
+        // Where the block comment being read started.
+        let mut comment = (depth > 0).then_some(0);
+
+        while let Some(c) = line[index..].chars().next() {
+            let rest = &line[index..];
+            if let Some(start) = comment {
+                if rest.starts_with("*/") {
+                    depth -= 1;
+                    index += 2;
+                    if depth == 0 {
+                        highlights.push((start..index, Color::Blue));
+                        comment = None;
+                    }
+                } else if rest.starts_with("/*") {
+                    depth += 1;
+                    index += 2;
+                } else {
+                    index += c.len_utf8();
+                }
+                continue;
+            }
+
+            let (length, color) = if rest.starts_with("//") {
+                (rest.len(), Some(Color::Blue))
+            } else if rest.starts_with("/*") {
+                comment = Some(index);
+                depth = 1;
+                (2, None)
+            } else if c == '"' {
+                match highlight::string_end(&rest[1..], '"') {
+                    Some(end) => (end + 1, Some(Color::Yellow)),
+                    None => {
+                        highlights.push((index..line.len(), Color::Yellow));
+                        return (highlights, STRING);
+                    }
+                }
+            } else if c == '\'' {
+                // A character, rather than a lifetime like `'a`.
+                let length = match rest[1..].chars().next() {
+                    Some('\\') => rest
+                        .get(3..)
+                        .and_then(|rest| rest.find('\''))
+                        .map(|end| end + 4),
+                    Some(c) => rest[1 + c.len_utf8()..]
+                        .starts_with('\'')
+                        .then_some(c.len_utf8() + 2),
+                    None => None,
+                };
+                match length {
+                    Some(length) => (length, Some(Color::Yellow)),
+                    None => (1, None),
+                }
+            } else if c.is_ascii_digit() {
+                let mut length = word_length(rest);
+                // The fraction of a float, but not a range like `0..2`.
+                let fraction = &rest[length..];
+                if fraction.starts_with('.')
+                    && fraction[1..].starts_with(|c: char| c.is_ascii_digit())
+                {
+                    length += 1 + word_length(&fraction[1..]);
+                }
+                (length, Some(Color::Cyan))
+            } else if c.is_alphabetic() || c == '_' {
+                let length = word_length(rest);
+                let word = &rest[..length];
+                if rest[length..].starts_with('!') {
+                    (length + 1, Some(Color::Cyan))
+                } else if RUST_KEYWORDS.contains(&word) {
+                    (length, Some(Color::Magenta))
+                } else if c.is_uppercase() {
+                    (length, Some(Color::Green))
+                } else {
+                    (length, None)
+                }
+            } else if rest.starts_with("#[") || rest.starts_with("#![") {
+                let length = rest.find(']').map_or(rest.len(), |end| end + 1);
+                (length, Some(Color::Cyan))
+            } else {
+                (c.len_utf8(), None)
+            };
+            if let Some(color) = color {
+                highlights.push((index..index + length, color));
+            }
+            index += length;
+        }
+
+        if let Some(start) = comment {
+            highlights.push((start..line.len(), Color::Blue));
+        }
+        (highlights, depth)
+    }
+}
+
+/// Markdown, which is in state `CODE` inside a fenced code block.
+struct Markdown;
+
+const CODE: State = 1;
+
+impl Syntax for Markdown {
+    fn highlight(&self, line: &str, state: State) -> (Vec<Highlight>, State) {
+        let text = line.trim_start();
+        let indent = line.len() - text.len();
+        let whole = |color| vec![(0..line.len(), color)];
+        if text.starts_with("```") || text.starts_with("~~~") {
+            return (whole(Color::Blue), CODE - state);
+        } else if state == CODE {
+            return (whole(Color::Green), CODE);
+        } else if text.starts_with('#') {
+            return (whole(Color::Magenta), 0);
+        }
+
+        let mut highlights = Vec::new();
+        let mut index = indent;
+        if text.starts_with('>') {
+            highlights.push((index..index + 1, Color::Blue));
+            index += 1;
+        }
+        // List items.
+        let digits = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(0);
+        let marker = if text.starts_with(['-', '*', '+']) {
+            1
+        } else if digits > 0 && text[digits..].starts_with('.') {
+            digits + 1
+        } else {
+            0
+        };
+        if marker > 0 && text[marker..].starts_with(' ') {
+            highlights.push((index..index + marker, Color::Cyan));
+            index += marker;
+        }
+
+        while let Some(c) = line[index..].chars().next() {
+            let rest = &line[index..];
+            let after_word = line[..index].ends_with(|c: char| c.is_alphanumeric());
+            // Links have their text and where they go in different colors.
+            let link = (c == '[')
+                .then(|| rest.find("]("))
+                .flatten()
+                .and_then(|middle| Some((middle + 1, middle + rest[middle..].find(')')? + 1)));
+            if let Some((middle, end)) = link {
+                highlights.push((index..index + middle, Color::Cyan));
+                highlights.push((index + middle..index + end, Color::Blue));
+                index += end;
+                continue;
+            }
+
+            let span = match c {
+                '\\' => rest[1..].chars().next().map(|c| (c.len_utf8() + 1, None)),
+                '`' => rest[1..].find('`').map(|end| (end + 2, Some(Color::Green))),
+                '*' | '_' if !after_word => {
+                    // Strong emphasis is closed by the same two characters.
+                    let delimiter = if rest[1..].starts_with(c) { 2 } else { 1 };
+                    let closing = &rest[..delimiter];
+                    (rest[delimiter..].find(closing))
+                        .filter(|&end| end > 0)
+                        .map(|end| (end + 2 * delimiter, Some(Color::Yellow)))
+                }
+                _ => None,
+            };
+            let (length, color) = span.unwrap_or((c.len_utf8(), None));
+            if let Some(color) = color {
+                highlights.push((index..index + length, color));
+            }
+            index += length;
+        }
+        (highlights, 0)
+    }
+}
+
+/// CSS, as used by themes. States are made up of `COMMENT` inside a comment, and `BLOCK`
+/// between the braces of a rule.
+struct Css;
+
+const COMMENT: State = 1;
+const BLOCK: State = 2;
+
+impl Syntax for Css {
+    fn highlight(&self, line: &str, state: State) -> (Vec<Highlight>, State) {
+        let mut highlights = Vec::new();
+        let mut in_block = state & BLOCK != 0;
+        let mut comment = (state & COMMENT != 0).then_some(0);
+        // Values come after a property's name, up to the end of the declaration.
+        let mut in_value = false;
+        let mut index = 0;
+
+        while let Some(c) = line[index..].chars().next() {
+            let rest = &line[index..];
+            if let Some(start) = comment {
+                let Some(end) = rest.find("*/") else {
+                    break;
+                };
+                index += end + 2;
+                highlights.push((start..index, Color::Blue));
+                comment = None;
+                continue;
+            }
+
+            let length = css_word_length(rest);
+            let color = match c {
+                '/' if rest.starts_with("/*") => {
+                    comment = Some(index);
+                    index += 2;
+                    continue;
+                }
+                '{' => {
+                    in_block = true;
+                    None
+                }
+                '}' | ';' => {
+                    in_block &= c == ';';
+                    in_value = false;
+                    None
+                }
+                ':' if in_block => {
+                    in_value = true;
+                    None
+                }
+                '"' | '\'' => {
+                    let end =
+                        highlight::string_end(&rest[1..], c).map_or(rest.len(), |end| end + 1);
+                    highlights.push((index..index + end, Color::Yellow));
+                    index += end;
+                    continue;
+                }
+                _ if length == 0 => None,
+                _ if !in_block => Some(Color::Green),
+                _ if !in_value => Some(Color::Cyan),
+                '#' | '0'..='9' | '.' => Some(Color::Magenta),
+                _ => None,
+            };
+            let length = std::cmp::max(length, c.len_utf8());
+            if let Some(color) = color {
+                highlights.push((index..index + length, color));
+            }
+            index += length;
+        }
+
+        if let Some(start) = comment {
+            highlights.push((start..line.len(), Color::Blue));
+        }
+        let state = if in_block { BLOCK } else { 0 };
+        (
+            highlights,
+            state | if comment.is_some() { COMMENT } else { 0 },
+        )
+    }
+}
+
+/// The length of a selector, property name or value at the start of some CSS.
+fn css_word_length(text: &str) -> usize {
+    (text.char_indices())
+        .find(|&(index, c)| {
+            c.is_whitespace() || "{};:,\"'".contains(c) || text[index..].starts_with("/*")
+        })
+        .map_or(text.len(), |(index, _)| index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Highlight lines one after another, as the text of each highlighted part.
+    fn highlight(syntax: &dyn Syntax, text: &str) -> Vec<Vec<(String, Color)>> {
+        let mut state = 0;
+        text.lines()
+            .map(|line| {
+                let (highlights, end) = syntax.highlight(line, state);
+                state = end;
+                (highlights.into_iter())
+                    .map(|(range, color)| (line[range].to_string(), color))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn parts(parts: &[(&str, Color)]) -> Vec<(String, Color)> {
+        (parts.iter())
+            .map(|(text, color)| (text.to_string(), *color))
+            .collect()
+    }
+
+    #[test]
+    fn detection() {
+        let name = |path, line| {
+            detect(path, line).map(|syntax| {
+                let (highlights, _) = syntax.highlight("# x", 0);
+                highlights.first().map(|(_, color)| *color)
+            })
+        };
+        // Shell and Markdown can be told apart by how they highlight a comment or heading.
+        assert_eq!(name(Some("/root/a.sh"), ""), Some(Some(Color::Blue)));
+        assert_eq!(name(None, "#!sh"), Some(Some(Color::Blue)));
+        assert_eq!(
+            name(Some("/a.txt"), "#!/usr/bin/env bash"),
+            Some(Some(Color::Blue))
+        );
+        assert_eq!(name(Some("/README.md"), ""), Some(Some(Color::Magenta)));
+        assert!(name(Some("/usr/share/theme/themes/blue"), "").is_some());
+        assert!(name(Some("/src/main.rs"), "").is_some());
+        assert!(name(Some("/a.txt"), "").is_none());
+        assert!(name(Some("/a.sh"), "#!python").is_none());
+        assert!(name(None, "").is_none());
+    }
+
+    #[test]
+    fn rust() {
+        let lines = highlight(
+            &Rust,
+            "fn main() { /* a /* b */\nc */ let x = 'a';\nprintln!(\"{x}\n\"); // hi",
+        );
+        assert_eq!(
+            lines,
+            vec![
+                parts(&[("fn", Color::Magenta), ("/* a /* b */", Color::Blue)]),
+                parts(&[
+                    ("c */", Color::Blue),
+                    ("let", Color::Magenta),
+                    ("'a'", Color::Yellow)
+                ]),
+                parts(&[("println!", Color::Cyan), ("\"{x}", Color::Yellow)]),
+                parts(&[("\"", Color::Yellow), ("// hi", Color::Blue)]),
+            ]
+        );
+        assert_eq!(
+            highlight(&Rust, "#[test]\nfn f<'a>(s: &'a Str) -> u8 { 0x1f }"),
+            vec![
+                parts(&[("#[test]", Color::Cyan)]),
+                parts(&[
+                    ("fn", Color::Magenta),
+                    ("Str", Color::Green),
+                    ("0x1f", Color::Cyan)
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown() {
+        let lines = highlight(
+            &Markdown,
+            "# Title\n- a `b` **c** [d](e)\n```\n# not a title\n```\nsnake_case_name",
+        );
+        assert_eq!(
+            lines,
+            vec![
+                parts(&[("# Title", Color::Magenta)]),
+                parts(&[
+                    ("-", Color::Cyan),
+                    ("`b`", Color::Green),
+                    ("**c**", Color::Yellow),
+                    ("[d]", Color::Cyan),
+                    ("(e)", Color::Blue),
+                ]),
+                parts(&[("```", Color::Blue)]),
+                parts(&[("# not a title", Color::Green)]),
+                parts(&[("```", Color::Blue)]),
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn css() {
+        let lines = highlight(
+            &Css,
+            "#terminal, .a {\n    color: #0f0; /* green\n */ opacity: 0.5\n}",
+        );
+        assert_eq!(
+            lines,
+            vec![
+                parts(&[("#terminal", Color::Green), (".a", Color::Green)]),
+                parts(&[
+                    ("color", Color::Cyan),
+                    ("#0f0", Color::Magenta),
+                    ("/* green", Color::Blue)
+                ]),
+                parts(&[
+                    (" */", Color::Blue),
+                    ("opacity", Color::Cyan),
+                    ("0.5", Color::Magenta)
+                ]),
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn incremental() {
+        let mut highlighter = Highlighter::default();
+        let path = |file: &str| Some(file.to_string());
+        let mut lines: Vec<String> = ["/*", "a", "*/", "b"].map(String::from).to_vec();
+        highlighter.update(Some("/a.rs"), &lines, 10, path);
+        assert_eq!(highlighter.get(&lines, 3), &[]);
+        assert_eq!(highlighter.get(&lines, 1), &[(0..1, Color::Blue)]);
+
+        // Changing a line highlights the ones after it again if their state changed.
+        lines[0] = "//".into();
+        assert_eq!(highlighter.get(&lines, 0), &[]);
+        highlighter.update(Some("/a.rs"), &lines, 10, path);
+        assert_eq!(highlighter.get(&lines, 1), &[]);
+
+        // Only lines before the end are highlighted.
+        lines[3] = "B".into();
+        highlighter.update(Some("/a.rs"), &lines, 3, path);
+        assert_eq!(highlighter.get(&lines, 3), &[]);
+
+        // The syntax is chosen again when the file is renamed.
+        highlighter.update(Some("/a.txt"), &lines, 10, path);
+        assert_eq!(highlighter.get(&lines, 0), &[]);
+    }
+}
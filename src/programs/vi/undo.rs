@@ -0,0 +1,194 @@
+//! Undo history for the editor.
+//!
+//! Every change makes a new state of the file. Undoing and then changing something starts a new
+//! branch rather than throwing away what was undone, so `:earlier` and `:later` can still reach
+//! every state, in the order they were made.
+
+struct State {
+    lines: Vec<String>,
+    parent: Option<usize>,
+    /// The branch most recently made or redone from this state.
+    child: Option<usize>,
+}
+
+/// A tree of the states a file has been in.
+pub struct UndoTree {
+    /// States in the order they were made, so an index is a change number.
+    states: Vec<State>,
+    current: usize,
+    /// The state last written to disk.
+    saved: Option<usize>,
+    /// A line that has been changed by the last few changes, and what it held before them.
+    line: Option<(usize, String)>,
+}
+
+impl UndoTree {
+    /// Start the history with the file's original contents, which are saved.
+    pub fn new(lines: &[String]) -> Self {
+        Self {
+            states: vec![State {
+                lines: lines.to_vec(),
+                parent: None,
+                child: None,
+            }],
+            current: 0,
+            saved: Some(0),
+            line: None,
+        }
+    }
+
+    /// Record the lines as a new state, if they've changed since the current one.
+    pub fn commit(&mut self, lines: &[String]) {
+        let previous = &self.states[self.current].lines;
+        if previous == lines {
+            return;
+        }
+
+        // Keep track of a line being changed, for `U`.
+        let mut changed = (0..std::cmp::max(previous.len(), lines.len()))
+            .filter(|&row| previous.get(row) != lines.get(row));
+        match (changed.next(), changed.next()) {
+            (Some(row), None) if previous.len() == lines.len() => {
+                if self.line.as_ref().map(|(line, _)| *line) != Some(row) {
+                    self.line = Some((row, previous[row].clone()));
+                }
+            }
+            _ => self.line = None,
+        }
+
+        let index = self.states.len();
+        self.states.push(State {
+            lines: lines.to_vec(),
+            parent: Some(self.current),
+            child: None,
+        });
+        self.states[self.current].child = Some(index);
+        self.current = index;
+    }
+
+    /// The lines as of the current state.
+    pub fn current(&self) -> &[String] {
+        &self.states[self.current].lines
+    }
+
+    /// Go back to the state before the current one.
+    pub fn undo(&mut self) -> Option<&[String]> {
+        let parent = self.states[self.current].parent?;
+        self.states[parent].child = Some(self.current);
+        Some(self.go_to(parent))
+    }
+
+    /// Go forward to the state last undone from this one.
+    pub fn redo(&mut self) -> Option<&[String]> {
+        let child = self.states[self.current].child?;
+        Some(self.go_to(child))
+    }
+
+    /// Go back `count` changes in time, which may be on another branch.
+    pub fn earlier(&mut self, count: usize) -> &[String] {
+        self.go_to(self.current.saturating_sub(count))
+    }
+
+    /// Go forward `count` changes in time.
+    pub fn later(&mut self, count: usize) -> &[String] {
+        self.go_to(std::cmp::min(self.current + count, self.states.len() - 1))
+    }
+
+    /// Go to the state just after change `number`, or the original contents for change 0.
+    pub fn go_to_change(&mut self, number: usize) -> Option<&[String]> {
+        (number < self.states.len()).then(|| self.go_to(number))
+    }
+
+    /// If `row` is the line last changed, swap it back to how it was before changing it.
+    /// Undoing this is a change like any other, and can itself be undone with `U`.
+    pub fn undo_line(&mut self, row: usize, line: &mut String) -> bool {
+        match self.line.as_mut() {
+            Some((changed, original)) if *changed == row => {
+                std::mem::swap(original, line);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Note that the current state has been written to disk.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.current);
+    }
+
+    /// If there are changes that haven't been written to disk.
+    pub fn modified(&self) -> bool {
+        self.saved != Some(self.current)
+    }
+
+    fn go_to(&mut self, index: usize) -> &[String] {
+        self.current = index;
+        self.line = None;
+        &self.states[index].lines
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut tree = UndoTree::new(&lines("a"));
+        assert!(!tree.modified());
+        tree.commit(&lines("a b"));
+        tree.commit(&lines("a b"));
+        tree.commit(&lines("a b c"));
+        assert!(tree.modified());
+        assert_eq!(tree.undo().unwrap(), lines("a b"));
+        assert_eq!(tree.undo().unwrap(), lines("a"));
+        assert!(tree.undo().is_none());
+        assert!(!tree.modified());
+        assert_eq!(tree.redo().unwrap(), lines("a b"));
+        assert_eq!(tree.redo().unwrap(), lines("a b c"));
+        assert!(tree.redo().is_none());
+        tree.mark_saved();
+        assert!(!tree.modified());
+    }
+
+    #[test]
+    fn branches() {
+        let mut tree = UndoTree::new(&lines("a"));
+        tree.commit(&lines("a b"));
+        tree.undo();
+        tree.commit(&lines("a c"));
+        // Redo follows the newest branch, and earlier/later go by time.
+        assert_eq!(tree.undo().unwrap(), lines("a"));
+        assert_eq!(tree.redo().unwrap(), lines("a c"));
+        assert_eq!(tree.earlier(1), lines("a b"));
+        assert_eq!(tree.earlier(5), lines("a"));
+        assert_eq!(tree.later(2), lines("a c"));
+        assert_eq!(tree.go_to_change(1).unwrap(), lines("a b"));
+        assert!(tree.go_to_change(3).is_none());
+        // Undoing from the old branch goes back through it.
+        assert_eq!(tree.redo(), None);
+        assert_eq!(tree.undo().unwrap(), lines("a"));
+        assert_eq!(tree.redo().unwrap(), lines("a b"));
+    }
+
+    #[test]
+    fn undo_line() {
+        let mut tree = UndoTree::new(&lines("a b"));
+        tree.commit(&lines("ax b"));
+        tree.commit(&lines("axy b"));
+        let mut line = String::from("axy");
+        assert!(!tree.undo_line(1, &mut line));
+        assert!(tree.undo_line(0, &mut line));
+        assert_eq!(line, "a");
+        tree.commit(&lines("a b"));
+        assert!(tree.undo_line(0, &mut line));
+        assert_eq!(line, "axy");
+        // Changing other lines forgets about it.
+        tree.commit(&lines("axy b c"));
+        assert!(!tree.undo_line(0, &mut line));
+    }
+}
@@ -0,0 +1,676 @@
+//! Buffers, which hold the files being edited, and the windows they're shown in.
+//!
+//! The current window's cursor and the current buffer's lines are kept in the editor itself,
+//! and put back here when switching to another window or buffer.
+use super::{
+    marks::Marks, normal::Position, read_file, swap::Swap, syntax::Highlighter, undo::UndoTree,
+    Editor,
+};
+use anyhow::{bail, Result};
+
+/// A file being edited, or text that hasn't been saved anywhere yet.
+pub struct Buffer {
+    pub file: Option<String>,
+    pub lines: Vec<String>,
+    pub highlighter: Highlighter,
+    pub undo: UndoTree,
+    pub marks: Marks,
+    /// Where the cursor was left, for when the buffer is shown again.
+    pub cursor: Position,
+    /// Its swap file, which stays here even while it's the current buffer.
+    pub swap: Swap,
+}
+
+impl Buffer {
+    pub fn new(file: Option<String>, mut lines: Vec<String>) -> Self {
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        Self {
+            file,
+            undo: UndoTree::new(&lines),
+            lines,
+            highlighter: Highlighter::default(),
+            marks: Marks::default(),
+            cursor: (0, 0),
+            swap: Swap::None,
+        }
+    }
+}
+
+/// A view of a buffer.
+#[derive(Clone, Copy)]
+pub struct Window {
+    /// The index of the buffer shown.
+    pub buffer: usize,
+    pub cursor: Position,
+    /// The first row shown.
+    pub offset: usize,
+}
+
+/// Part of the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub top: usize,
+    pub left: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+/// How windows are arranged on the screen. Windows are numbered in the order they appear in
+/// the tree, which is from the top left to the bottom right.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+    Window,
+    /// Windows above each other, or side by side if `vertical`.
+    Split {
+        vertical: bool,
+        children: Vec<Layout>,
+    },
+}
+
+impl Layout {
+    /// Where each window is on the screen, including its status line.
+    pub fn rects(&self, rect: Rect) -> Vec<Rect> {
+        let mut rects = Vec::new();
+        self.collect_rects(rect, &mut rects);
+        rects
+    }
+
+    fn collect_rects(&self, rect: Rect, rects: &mut Vec<Rect>) {
+        let Self::Split { vertical, children } = self else {
+            rects.push(rect);
+            return;
+        };
+        let count = children.len();
+        if *vertical {
+            // Leave a column between windows for a separator.
+            let space = rect.width.saturating_sub(count - 1);
+            let mut left = rect.left;
+            for (index, child) in children.iter().enumerate() {
+                let width = space / count + usize::from(index < space % count);
+                child.collect_rects(
+                    Rect {
+                        left,
+                        width,
+                        ..rect
+                    },
+                    rects,
+                );
+                left += width + 1;
+            }
+        } else {
+            let mut top = rect.top;
+            for (index, child) in children.iter().enumerate() {
+                let height = rect.height / count + usize::from(index < rect.height % count);
+                child.collect_rects(
+                    Rect {
+                        top,
+                        height,
+                        ..rect
+                    },
+                    rects,
+                );
+                top += height;
+            }
+        }
+    }
+
+    /// Split a window in two, with the new window numbered `index` and the old one after it.
+    pub fn split(&mut self, index: usize, vertical: bool) {
+        self.split_at(&mut { index }, vertical);
+    }
+
+    /// Split the window `index` windows into this part of the layout, counting down as
+    /// windows are passed. Returns true once it's been split.
+    fn split_at(&mut self, index: &mut usize, vertical: bool) -> bool {
+        match self {
+            Self::Window if *index == 0 => {
+                *self = Self::Split {
+                    vertical,
+                    children: vec![Self::Window, Self::Window],
+                };
+                true
+            }
+            Self::Window => {
+                *index -= 1;
+                false
+            }
+            Self::Split {
+                vertical: same,
+                children,
+            } => {
+                for position in 0..children.len() {
+                    // Windows in a split the same way get a sibling rather than splitting.
+                    if *same == vertical && *index == 0 && children[position] == Self::Window {
+                        children.insert(position, Self::Window);
+                        return true;
+                    }
+                    if children[position].split_at(index, vertical) {
+                        return true;
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    /// Remove a window, which mustn't be the only one.
+    pub fn close(&mut self, index: usize) {
+        self.close_at(&mut { index });
+    }
+
+    fn close_at(&mut self, index: &mut usize) -> bool {
+        let Self::Split { vertical, children } = self else {
+            return false;
+        };
+        for position in 0..children.len() {
+            let closed = if children[position] == Self::Window {
+                if *index == 0 {
+                    children.remove(position);
+                    true
+                } else {
+                    *index -= 1;
+                    false
+                }
+            } else {
+                children[position].close_at(index)
+            };
+            if !closed {
+                continue;
+            }
+
+            // A split left with one part was replaced by it, which is merged into this split
+            // if it's split the same way.
+            if let Some(Self::Split {
+                vertical: same,
+                children: grandchildren,
+            }) = children.get(position)
+            {
+                if same == vertical {
+                    let grandchildren = grandchildren.clone();
+                    children.splice(position..=position, grandchildren);
+                }
+            }
+            if children.len() == 1 {
+                *self = children.remove(0);
+            }
+            return true;
+        }
+        false
+    }
+}
+
+/// How far a position is from a span of the screen, or zero if it's in it.
+fn distance(position: usize, start: usize, length: usize) -> usize {
+    if position < start {
+        start - position
+    } else {
+        (position + 1).saturating_sub(start + length)
+    }
+}
+
+/// Find the window next to `current` in the direction of `h`, `j`, `k` or `l`, preferring the
+/// one beside the cursor at `(row, column)` on the screen.
+pub fn neighbour(
+    rects: &[Rect],
+    current: usize,
+    direction: char,
+    (row, column): (usize, usize),
+) -> Option<usize> {
+    let from = rects[current];
+    rects
+        .iter()
+        .enumerate()
+        .filter(|(_, rect)| match direction {
+            'h' => rect.left + rect.width + 1 == from.left,
+            'l' => from.left + from.width + 1 == rect.left,
+            'k' => rect.top + rect.height == from.top,
+            'j' => from.top + from.height == rect.top,
+            _ => false,
+        })
+        .min_by_key(|(_, rect)| match direction {
+            'h' | 'l' => distance(row, rect.top, rect.height),
+            _ => distance(column, rect.left, rect.width + 1),
+        })
+        .map(|(index, _)| index)
+}
+
+impl Editor<'_> {
+    /// Where each window is on the screen, including its status line.
+    pub(super) fn window_rects(&self) -> Vec<Rect> {
+        self.layout.rects(Rect {
+            top: 0,
+            left: 0,
+            height: self.height,
+            width: self.width,
+        })
+    }
+
+    /// The part of a window's space its text is shown in, which is above its status line.
+    pub(super) fn text_rect(&self, rect: Rect) -> Rect {
+        Rect {
+            height: rect.height - 1,
+            ..rect
+        }
+    }
+
+    /// Where the current window's text is shown.
+    pub(super) fn view(&self) -> Rect {
+        self.text_rect(self.window_rects()[self.window])
+    }
+
+    /// The lines of a buffer.
+    pub(super) fn buffer_lines(&self, index: usize) -> &[String] {
+        if index == self.buffer {
+            &self.lines
+        } else {
+            &self.buffers[index].lines
+        }
+    }
+
+    /// The file a buffer is saved to.
+    pub(super) fn buffer_file(&self, index: usize) -> Option<&str> {
+        if index == self.buffer {
+            self.file.as_deref()
+        } else {
+            self.buffers[index].file.as_deref()
+        }
+    }
+
+    /// The name a buffer is shown with.
+    pub(super) fn buffer_name(&self, index: usize) -> &str {
+        self.buffer_file(index).unwrap_or("[No Name]")
+    }
+
+    /// If a buffer has changed since it was last saved, including by what's being typed.
+    pub(super) fn buffer_modified(&self, index: usize) -> bool {
+        if index == self.buffer {
+            self.undo.modified() || self.lines != self.undo.current()
+        } else {
+            self.buffers[index].undo.modified()
+        }
+    }
+
+    /// Put the current buffer back with the others.
+    fn store_buffer(&mut self) {
+        let buffer = &mut self.buffers[self.buffer];
+        buffer.lines = std::mem::take(&mut self.lines);
+        buffer.highlighter = std::mem::take(&mut self.highlighter);
+        buffer.undo = std::mem::replace(&mut self.undo, UndoTree::new(&[]));
+        buffer.marks = std::mem::take(&mut self.normal.marks);
+        buffer.file = self.file.take();
+        buffer.cursor = self.cursor;
+    }
+
+    /// Take a buffer from the others to make it the current one.
+    pub(super) fn load_buffer(&mut self, index: usize) {
+        let buffer = &mut self.buffers[index];
+        self.lines = std::mem::take(&mut buffer.lines);
+        self.highlighter = std::mem::take(&mut buffer.highlighter);
+        self.undo = std::mem::replace(&mut buffer.undo, UndoTree::new(&[]));
+        self.normal.marks = std::mem::take(&mut buffer.marks);
+        self.file = buffer.file.take();
+        self.buffer = index;
+    }
+
+    /// Check the current buffer can be left for another, which it can't if it has changes
+    /// that aren't saved or shown in another window, unless forced.
+    pub(super) fn check_leave(&self, force: bool) -> Result<()> {
+        let shown = (self.windows.iter().enumerate())
+            .any(|(index, window)| index != self.window && window.buffer == self.buffer);
+        if self.undo.modified() && !force && !shown {
+            bail!("No write since last change (add ! to override)");
+        }
+        Ok(())
+    }
+
+    /// Show another buffer in the current window.
+    pub(super) fn switch_buffer(&mut self, index: usize) {
+        if index != self.buffer {
+            self.store_buffer();
+            self.load_buffer(index);
+            self.cursor = self.buffers[index].cursor;
+            self.offset = 0;
+            self.windows[self.window].buffer = index;
+        }
+        self.visual = None;
+        // There's nothing left for `:g` to do in the old buffer.
+        self.global = None;
+        self.reset = true;
+    }
+
+    /// Make another window the current one.
+    pub(super) fn switch_window(&mut self, index: usize) {
+        let window = &mut self.windows[self.window];
+        window.cursor = self.cursor;
+        window.offset = self.offset;
+        let window = self.windows[index];
+        if window.buffer != self.buffer {
+            self.store_buffer();
+            self.load_buffer(window.buffer);
+        }
+        self.window = index;
+        // The buffer could have been changed in another window.
+        let row = std::cmp::min(window.cursor.0, self.lines.len() - 1);
+        self.cursor = (row, window.cursor.1);
+        self.offset = std::cmp::min(window.offset, row);
+        self.visual = None;
+        self.reset = true;
+    }
+
+    /// Find the buffer for a file, opening it if it isn't open yet, and recovering it from a
+    /// swap file if one was left behind and that's what's wanted.
+    pub(super) async fn open(&mut self, file: &str) -> Result<usize> {
+        let open = (0..self.buffers.len()).find(|&index| self.buffer_file(index) == Some(file));
+        if let Some(index) = open {
+            return Ok(index);
+        }
+        let lines = read_file(self.process, file)?;
+        self.status = Some(match &lines {
+            Some(lines) => format!("\"{file}\" {}L", lines.len()),
+            None => format!("\"{file}\" [New]"),
+        });
+        let lines = lines.unwrap_or_default();
+        let (recovered, swap) = self.check_swap(file, lines.clone()).await?;
+        let mut buffer = Buffer::new(Some(file.into()), lines);
+        // Recovered changes haven't been saved, and can be undone.
+        if recovered != buffer.lines && !recovered.is_empty() {
+            buffer.undo.commit(&recovered);
+            buffer.lines = recovered;
+        }
+        buffer.swap = swap;
+        self.buffers.push(buffer);
+        Ok(self.buffers.len() - 1)
+    }
+
+    /// Split the current window, showing a buffer in the new one, which becomes current.
+    pub(super) fn split(&mut self, vertical: bool, buffer: usize) -> Result<()> {
+        let mut layout = self.layout.clone();
+        layout.split(self.window, vertical);
+        let rects = layout.rects(Rect {
+            top: 0,
+            left: 0,
+            height: self.height,
+            width: self.width,
+        });
+        // Each window needs a row for text and one for its status line.
+        if rects.iter().any(|rect| rect.height < 2 || rect.width < 1) {
+            bail!("Not enough room");
+        }
+
+        self.layout = layout;
+        let window = Window {
+            buffer: self.buffer,
+            cursor: self.cursor,
+            offset: self.offset,
+        };
+        self.windows.insert(self.window, window);
+        // The old window is after the new one now.
+        self.window += 1;
+        self.switch_window(self.window - 1);
+        self.switch_buffer(buffer);
+        Ok(())
+    }
+
+    /// Close a window, which mustn't be the only one.
+    pub(super) fn close_window(&mut self, index: usize) {
+        if index == self.window {
+            let next = if index + 1 < self.windows.len() {
+                index + 1
+            } else {
+                index - 1
+            };
+            self.switch_window(next);
+        }
+        self.windows.remove(index);
+        self.layout.close(index);
+        if self.window > index {
+            self.window -= 1;
+        }
+        self.reset = true;
+    }
+
+    /// Quit the current window, or the editor if it's the last one. Returns true if the editor
+    /// should quit.
+    pub(super) fn quit(&mut self, force: bool) -> Result<bool> {
+        if self.windows.len() > 1 {
+            self.close_window(self.window);
+            return Ok(false);
+        }
+        self.quit_all(force)
+    }
+
+    /// Quit the editor, unless a buffer has changes that haven't been saved. Returns true if
+    /// the editor should quit.
+    pub(super) fn quit_all(&mut self, force: bool) -> Result<bool> {
+        if force {
+            return Ok(true);
+        }
+        if self.undo.modified() {
+            bail!("No write since last change (add ! to override)");
+        }
+        let modified = (0..self.buffers.len()).find(|&index| self.buffer_modified(index));
+        if let Some(index) = modified {
+            bail!(
+                "No write since last change for buffer \"{}\" (add ! to override)",
+                self.buffer_name(index)
+            );
+        }
+        Ok(true)
+    }
+
+    /// Handle a window command, typed after Ctrl-W. Returns true if the editor should quit.
+    pub(super) fn window_command(&mut self, key: char, count: Option<usize>) -> Result<bool> {
+        let last = self.windows.len() - 1;
+        match key {
+            's' | 'S' => self.split(false, self.buffer)?,
+            'v' => self.split(true, self.buffer)?,
+            'n' => {
+                self.buffers.push(Buffer::new(None, Vec::new()));
+                self.split(false, self.buffers.len() - 1)?;
+            }
+            // With a count, go to that window.
+            'w' | 'W' | 'p' if count.is_some() => {
+                let index = count.unwrap_or_default().saturating_sub(1);
+                self.switch_window(std::cmp::min(index, last));
+            }
+            'w' => self.switch_window(if self.window == last {
+                0
+            } else {
+                self.window + 1
+            }),
+            'W' | 'p' => self.switch_window(self.window.checked_sub(1).unwrap_or(last)),
+            't' => self.switch_window(0),
+            'b' => self.switch_window(last),
+            'h' | 'j' | 'k' | 'l' => {
+                let rects = self.window_rects();
+                let rect = rects[self.window];
+                let top = self.row_top(self.window, rect, self.cursor.0);
+                let screen = (rect.top + top.unwrap_or_default(), rect.left);
+                for _ in 0..count.unwrap_or(1) {
+                    if let Some(index) = neighbour(&rects, self.window, key, screen) {
+                        self.switch_window(index);
+                    }
+                }
+            }
+            'c' if self.windows.len() == 1 => bail!("Cannot close last window"),
+            'c' => self.close_window(self.window),
+            'q' => return self.quit(false),
+            'o' => {
+                for index in (0..self.windows.len()).rev() {
+                    if index != self.window {
+                        self.close_window(index);
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{super::test::*, *};
+
+    const SCREEN: Rect = Rect {
+        top: 0,
+        left: 0,
+        height: 21,
+        width: 80,
+    };
+
+    fn rect(top: usize, left: usize, height: usize, width: usize) -> Rect {
+        Rect {
+            top,
+            left,
+            height,
+            width,
+        }
+    }
+
+    #[test]
+    fn layout() {
+        let mut layout = Layout::Window;
+        assert_eq!(layout.rects(SCREEN), vec![SCREEN]);
+
+        layout.split(0, false);
+        layout.split(0, false);
+        assert_eq!(
+            layout,
+            Layout::Split {
+                vertical: false,
+                children: vec![Layout::Window, Layout::Window, Layout::Window],
+            }
+        );
+        assert_eq!(
+            layout.rects(SCREEN),
+            vec![rect(0, 0, 7, 80), rect(7, 0, 7, 80), rect(14, 0, 7, 80)]
+        );
+
+        // Splitting the other way nests a split, with a column between the windows.
+        layout.split(1, true);
+        assert_eq!(
+            layout.rects(SCREEN),
+            vec![
+                rect(0, 0, 7, 80),
+                rect(7, 0, 7, 40),
+                rect(7, 41, 7, 39),
+                rect(14, 0, 7, 80),
+            ]
+        );
+
+        // Closing windows collapses splits that are left with one part.
+        layout.close(2);
+        assert_eq!(layout.rects(SCREEN).len(), 3);
+        assert_eq!(layout.rects(SCREEN)[1], rect(7, 0, 7, 80));
+        layout.close(0);
+        layout.close(0);
+        assert_eq!(layout, Layout::Window);
+
+        // A split merges into its parent if they're split the same way.
+        layout.split(0, true);
+        layout.split(1, false);
+        layout.split(1, true);
+        layout.close(3);
+        assert_eq!(
+            layout,
+            Layout::Split {
+                vertical: true,
+                children: vec![Layout::Window, Layout::Window, Layout::Window],
+            }
+        );
+    }
+
+    #[test]
+    fn neighbours() {
+        // One window on the left, and two on the right.
+        let rects = [
+            rect(0, 0, 20, 40),
+            rect(0, 41, 10, 39),
+            rect(10, 41, 10, 39),
+        ];
+        assert_eq!(neighbour(&rects, 0, 'l', (15, 5)), Some(2));
+        assert_eq!(neighbour(&rects, 0, 'l', (2, 5)), Some(1));
+        assert_eq!(neighbour(&rects, 0, 'h', (2, 5)), None);
+        assert_eq!(neighbour(&rects, 1, 'j', (2, 50)), Some(2));
+        assert_eq!(neighbour(&rects, 2, 'k', (12, 50)), Some(1));
+        assert_eq!(neighbour(&rects, 2, 'h', (12, 50)), Some(0));
+        assert_eq!(neighbour(&rects, 2, 'j', (12, 50)), None);
+    }
+
+    #[futures_test::test]
+    async fn arguments() {
+        let cwd = file_system(&[("a", "alpha\n"), ("b", "beta\n")]);
+        let keys = ":prev\n :n\n:n\n x:prev\n :w\n:prev\nx:wq\n";
+        let (result, screen) = edit(&cwd, &["a", "b"], keys).await;
+        result.unwrap();
+        assert!(screen.contains("Cannot go before first file"));
+        assert!(screen.contains("Cannot go beyond last file"));
+        assert!(screen.contains("No write since last change (add ! to override)"));
+        assert_eq!(contents(&cwd, "a").as_deref(), Some("lpha\n"));
+        assert_eq!(contents(&cwd, "b").as_deref(), Some("eta\n"));
+
+        // Files that don't exist yet are new buffers, and are created when written.
+        let (result, screen) = edit(&cwd, &["a", "new"], ":n\niNew\x1b\x1b:wq\n").await;
+        result.unwrap();
+        assert!(screen.contains("\"new\" [New]"));
+        assert_eq!(contents(&cwd, "new").as_deref(), Some("New\n"));
+
+        // Without any files, there's nothing to go through.
+        let (result, screen) = edit(&cwd, &[], ":n\n :prev\n :q\n").await;
+        result.unwrap();
+        assert!(screen.contains("There is no argument list"));
+        assert!(screen.contains("Cannot go before first file"));
+    }
+
+    #[futures_test::test]
+    async fn buffers() {
+        let cwd = file_system(&[("one", "1\n"), ("two", "2\n")]);
+        let keys = ":ls\n :b 3\n :b 0\n :b x\n :b o\n :b tw\nx:w\n:bn\nx:wq\n";
+        let (result, screen) = edit(&cwd, &["one", "two"], keys).await;
+        result.unwrap();
+        assert!(screen.contains("  1 %a   \"one\"                          line 1"));
+        assert!(screen.contains("  2  h   \"two\"                          line 1"));
+        assert!(screen.contains("Buffer 3 does not exist"));
+        assert!(screen.contains("Buffer 0 does not exist"));
+        assert!(screen.contains("No matching buffer for x"));
+        assert!(screen.contains("More than one match for o"));
+        assert_eq!(contents(&cwd, "one").as_deref(), Some("\n"));
+        assert_eq!(contents(&cwd, "two").as_deref(), Some("\n"));
+
+        // Buffers with changes keep the editor from quitting.
+        let cwd = file_system(&[("one", "1\n"), ("two", "2\n")]);
+        let keys = ":b 2\nx:b 1\n :b! 1\n:q\n :b 2\n:w\n:q\n";
+        let (result, screen) = edit(&cwd, &["one", "two"], keys).await;
+        result.unwrap();
+        assert!(screen.contains("No write since last change for buffer \"two\""));
+    }
+
+    #[futures_test::test]
+    async fn windows() {
+        let cwd = file_system(&[("a", "alpha\n"), ("b", "beta\n")]);
+
+        // Both windows show the same buffer, so one can be closed with changes.
+        let keys = ":split\nx\x17j:q\n:q\n :wq\n";
+        let (result, screen) = edit(&cwd, &["a"], keys).await;
+        result.unwrap();
+        assert!(screen.contains("No write since last change (add ! to override)"));
+        assert_eq!(contents(&cwd, "a").as_deref(), Some("lpha\n"));
+
+        // The new window is on the left, and Ctrl-W moves between them.
+        let keys = ":vsplit b\n\x17lx\x17hxx:w\n\x17c\x17c:wq\n";
+        let (result, screen) = edit(&cwd, &["a"], keys).await;
+        result.unwrap();
+        assert!(screen.contains("Cannot close last window"));
+        assert_eq!(contents(&cwd, "a").as_deref(), Some("pha\n"));
+        assert_eq!(contents(&cwd, "b").as_deref(), Some("ta\n"));
+
+        // Each window needs a row of text and a status line.
+        let keys = "\x17s".repeat(12) + ":qa\n";
+        let (result, screen) = edit(&cwd, &["a"], &keys).await;
+        result.unwrap();
+        assert!(screen.contains("Not enough room"));
+    }
+}
@@ -9,7 +9,7 @@ use std::io::Write;
 
 /// Print line, word, and byte counts for a file.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// Print the byte count.
     #[arg(short = 'c', long)]
     bytes: bool,
@@ -23,7 +23,7 @@ struct Options {
     files: Vec<String>,
 }
 
-async fn wc_inner<'a>(
+async fn wc_inner(
     stream: &mut InputStream,
     out: &mut OutputStream,
     options: &Options,
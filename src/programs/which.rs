@@ -1,15 +1,16 @@
 use crate::process::{ExitCode, Process};
 use anyhow::{bail, Result};
-use clap::Parser;
+use clap::{Parser, ValueHint};
 use futures::io::AsyncWriteExt;
 
 /// Locate a command.
 #[derive(Parser)]
-struct Options {
+pub(super) struct Options {
     /// Print all matching commands.
     #[arg(short)]
     all: bool,
     /// The command to locate.
+    #[arg(value_hint = ValueHint::CommandName)]
     command: String,
 }
 
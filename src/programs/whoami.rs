@@ -5,7 +5,7 @@ use futures::io::AsyncWriteExt;
 
 /// Prints the current user.
 #[derive(Parser)]
-struct Options {}
+pub(super) struct Options {}
 
 pub async fn whoami(process: &mut Process) -> Result<ExitCode> {
     let _options = Options::try_parse_from(process.args.iter())?;
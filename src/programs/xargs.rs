@@ -0,0 +1,76 @@
+use crate::{
+    process::{ExitCode, Process},
+    programs,
+};
+use anyhow::Result;
+use clap::{Parser, ValueHint};
+use futures::AsyncReadExt;
+use std::io::Write;
+
+/// Run a command with arguments read from standard input.
+///
+/// Arguments are separated by whitespace, or with -0, by NUL characters like those printed by
+/// find -print0.
+#[derive(Parser)]
+pub(super) struct Options {
+    /// Arguments are separated by NUL characters.
+    #[arg(short = '0', long)]
+    null: bool,
+    /// Pass at most this many arguments each time the command is run.
+    #[arg(short = 'n', long, value_name = "MAX-ARGS")]
+    max_args: Option<usize>,
+    /// Don't run the command if there are no arguments.
+    #[arg(short = 'r', long)]
+    no_run_if_empty: bool,
+    /// The command to run, and arguments to pass before those read. Defaults to echo.
+    #[arg(trailing_var_arg = true, value_hint = ValueHint::CommandName)]
+    command: Vec<String>,
+}
+
+pub async fn xargs(process: &mut Process) -> Result<ExitCode> {
+    let mut options = Options::try_parse_from(process.args.iter())?;
+    if options.command.is_empty() {
+        options.command.push("echo".into());
+    }
+
+    let mut input = String::new();
+    process.stdin.read_to_string(&mut input).await?;
+    let arguments: Vec<String> = if options.null {
+        input
+            .split('\0')
+            .filter(|argument| !argument.is_empty())
+            .map(String::from)
+            .collect()
+    } else {
+        input.split_whitespace().map(String::from).collect()
+    };
+    if arguments.is_empty() && options.no_run_if_empty {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let max_args = options.max_args.unwrap_or(usize::MAX).max(1);
+    let mut chunks: Vec<&[String]> = arguments.chunks(max_args).collect();
+    if chunks.is_empty() {
+        chunks.push(&[]);
+    }
+    let mut code = ExitCode::SUCCESS;
+    for chunk in chunks {
+        let mut child = process.clone();
+        child.args = options.command.clone();
+        child.args.extend_from_slice(chunk);
+        // Boxed, because the command could be xargs again.
+        match Box::pin(programs::exec_program(&mut child, &options.command[0])).await? {
+            Some(status) if status.is_failure() => code = ExitCode::from(123),
+            Some(_) => {}
+            None => {
+                writeln!(
+                    process.stderr,
+                    "xargs: {}: No such file or directory",
+                    options.command[0]
+                )?;
+                return Ok(ExitCode::from(127));
+            }
+        }
+    }
+    Ok(code)
+}
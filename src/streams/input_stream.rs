@@ -121,10 +121,21 @@ impl InputStream {
         )
     }
 
+    /// Read one UTF-8 encoded character, or U+FFFD if the bytes aren't valid UTF-8.
     pub async fn get_char(&mut self) -> Result<char> {
-        let mut buffer = [0; 1];
-        self.read_exact(&mut buffer).await?;
-        Ok(buffer[0] as char)
+        let mut buffer = [0; 4];
+        self.read_exact(&mut buffer[..1]).await?;
+        let length = match buffer[0] {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        self.read_exact(&mut buffer[1..length]).await?;
+        Ok(std::str::from_utf8(&buffer[..length])
+            .ok()
+            .and_then(|c| c.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER))
     }
 
     pub async fn get_line(&mut self) -> Result<String> {
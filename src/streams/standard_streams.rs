@@ -73,7 +73,7 @@ impl FusedStream for KeyboardTerminalReader {
 fn unix_term_escape(src: &str) -> String {
     let mut string = String::with_capacity(src.len());
     for c in src.chars() {
-        if c as u8 <= 0x1F && c != '\t' && c != '\n' {
+        if (c as u32) <= 0x1F && c != '\t' && c != '\n' {
             string.push('^');
             string.push((c as u8 + 0x40) as char);
         } else {
@@ -123,17 +123,23 @@ impl KeyboardTerminalReader {
                 return;
             }
 
-            if key.len() == 1 {
+            if key.chars().count() == 1 {
                 // Send control characters.
-                if e.ctrl_key() {
+                if e.ctrl_key() && key.is_ascii() {
                     let c = key.chars().next().unwrap().to_ascii_uppercase();
                     let c = c as u8;
-                    // Allow 'R' and 'I' for refresh and inspector
-                    if c > b'@' && c <= b'Z' && c != b'R' && c != b'I' {
+                    // Allow 'R' and 'I' for refresh and inspector, unless a program wants raw
+                    // keypresses (e.g. for ^R history search)
+                    let reserved = c == b'I' || (c == b'R' && mode == InputMode::Line);
+                    if c > b'@' && c <= b'Z' && !reserved {
                         e.prevent_default();
                         let mut ctrl_char = String::new();
                         ctrl_char.push((c - b'@') as char);
                         echo(mode, &ctrl_char, &mut cbuffer);
+                    // ^_ is also typed as ^/
+                    } else if c == b'_' || c == b'/' {
+                        e.prevent_default();
+                        echo(mode, &AsciiChar::US.to_string(), &mut cbuffer);
                     }
                 // Send metakey characters.
                 } else if e.alt_key() {
@@ -149,7 +155,11 @@ impl KeyboardTerminalReader {
                 }
             } else if key == "Tab" {
                 e.prevent_default();
-                echo(mode, "\t", &mut cbuffer);
+                if e.shift_key() && mode == InputMode::Char {
+                    echo(mode, "\x1b[Z", &mut cbuffer);
+                } else {
+                    echo(mode, "\t", &mut cbuffer);
+                }
             } else if key == "ArrowLeft" {
                 echo(mode, &AnsiCode::CursorLeft.to_string(), &mut cbuffer);
             } else if key == "ArrowRight" {
@@ -173,10 +183,15 @@ impl KeyboardTerminalReader {
             } else if key == "Backspace" {
                 if mode == InputMode::Line && !cbuffer.is_empty() {
                     utils::js_term_backspace();
-                    cbuffer.pop();
+                    // Remove the whole of the last character, however many bytes it took.
+                    let start = cbuffer.iter().rposition(|&byte| byte & 0xc0 != 0x80);
+                    cbuffer.truncate(start.unwrap_or(0));
                 }
 
                 if mode == InputMode::Char {
+                    if e.alt_key() {
+                        cbuffer.push(AsciiChar::ESC.as_byte())
+                    }
                     cbuffer.push(AsciiChar::BackSpace.as_byte())
                 }
             }
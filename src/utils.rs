@@ -28,6 +28,40 @@ extern "C" {
     pub fn js_term_backspace();
     pub fn js_term_clear();
     pub fn js_term_get_screen_height() -> usize;
+    pub fn js_term_get_screen_width() -> usize;
+}
+
+/// Get the width of the terminal in characters.
+///
+/// Outside the browser (i.e. in tests) there is no terminal, so assume a common default.
+pub fn get_screen_width() -> usize {
+    if cfg!(target_arch = "wasm32") {
+        js_term_get_screen_width()
+    } else {
+        80
+    }
+}
+
+/// Get the time in seconds since the Unix epoch.
+pub fn now() -> i64 {
+    if cfg!(target_arch = "wasm32") {
+        (js_sys::Date::now() / 1000.0) as i64
+    } else {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs() as i64)
+    }
+}
+
+/// Get how many seconds the local time zone is ahead of UTC.
+///
+/// Outside the browser, assume UTC.
+pub fn utc_offset() -> i64 {
+    if cfg!(target_arch = "wasm32") {
+        -(js_sys::Date::new_0().get_timezone_offset() as i64) * 60
+    } else {
+        0
+    }
 }
 
 #[allow(unused)]
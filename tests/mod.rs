@@ -84,6 +84,134 @@ async fn integration_test_inner(tx: UnboundedSender<Command>) -> Result<()> {
     tester.run("echo -n hello;echo ' world'")?;
     tester.expect("hello world")?;
 
+    // Completion specifications
+    tester.run("complete -W 'a b' foo bar; complete -r foo; complete")?;
+    tester.expect("complete -W 'a b' bar")?;
+
+    // Pagers pass text through when there's no terminal to page on
+    tester.run("echo paged | less -N")?;
+    tester.expect("paged")?;
+    tester.run("/usr/bin/help | head -n 1")?;
+    tester.expect("To see available commands, press <tab>.")?;
+
+    // Listing files
+    tester.run("mkdir listing; mkdir listing/sub; touch listing/b listing/a listing/.hidden")?;
+    tester.run("ls listing")?;
+    tester.expect("a")?;
+    tester.expect("b")?;
+    tester.expect("sub")?;
+    tester.run("ls -ar listing")?;
+    tester.expect("sub")?;
+    tester.expect("b")?;
+    tester.expect("a")?;
+    tester.expect(".hidden")?;
+    tester.expect("..")?;
+    tester.expect(".")?;
+    tester.run("ls -d listing/sub listing/a")?;
+    tester.expect("listing/a")?;
+    tester.expect("listing/sub")?;
+    tester.run("ls -R listing/sub listing/b")?;
+    tester.expect("listing/b")?;
+    tester.expect("")?;
+    tester.expect("listing/sub:")?;
+    tester.run("ls listing/nothing || echo failed")?;
+    tester.expect("ls: cannot access 'listing/nothing': No such file or directory")?;
+    tester.expect("failed")?;
+
+    // Finding files
+    tester.run("find listing -name a -o -name 'b'")?;
+    tester.expect("listing/a")?;
+    tester.expect("listing/b")?;
+    tester.run("find listing -type d")?;
+    tester.expect("listing")?;
+    tester.expect("listing/sub")?;
+    tester.run("find listing -maxdepth 1 -mindepth 1 ! ( -name '.*' -o -type d )")?;
+    tester.expect("listing/a")?;
+    tester.expect("listing/b")?;
+    tester.run("find listing -name a -exec echo found {} \\;")?;
+    tester.expect("found listing/a")?;
+    tester.run("find listing -type f -exec echo {} +")?;
+    tester.expect("listing/.hidden listing/a listing/b")?;
+    tester.run("find listing -name b -print0 | xargs -0 echo got")?;
+    tester.expect("got listing/b")?;
+    tester.run("echo hello > listing/a; find listing -size +4c")?;
+    tester.expect("listing/a")?;
+    tester.run("find listing/sub -delete; find listing/sub || echo deleted")?;
+    tester.expect("find: 'listing/sub': No such file or directory")?;
+    tester.expect("deleted")?;
+
+    // Searching files
+    tester.run("echo 'one\ntwo\nthree\nfour\nfive' > listing/b")?;
+    tester.run("grep -n t listing/b")?;
+    tester.expect("2:two")?;
+    tester.expect("3:three")?;
+    tester.run("grep -c o listing/a listing/b")?;
+    tester.expect("listing/a:1")?;
+    tester.expect("listing/b:3")?;
+    tester.run("grep -rl hello listing")?;
+    tester.expect("listing/a")?;
+    tester.run("grep -ow 'f[a-z]*' listing/b")?;
+    tester.expect("four")?;
+    tester.expect("five")?;
+    tester.run("grep -E -e 'one|five' -A1 listing/b")?;
+    tester.expect("one")?;
+    tester.expect("two")?;
+    tester.expect("--")?;
+    tester.expect("five")?;
+    tester.run("grep -q six listing/b || echo none")?;
+    tester.expect("none")?;
+    tester.run("grep -q two listing/b && echo some")?;
+    tester.expect("some")?;
+
+    // Editing streams
+    tester.run("sed -n '2,/^f/{s/o/0/g;p}' listing/b")?;
+    tester.expect("tw0")?;
+    tester.expect("three")?;
+    tester.expect("f0ur")?;
+    tester.run("echo 'a b' | sed -E -e 's/(.) (.)/\\2 \\1/' -e '$a done'")?;
+    tester.expect("b a")?;
+    tester.expect("done")?;
+    tester.run("sed -i '1!G;h;$!d' listing/b; head -n 2 listing/b")?;
+    tester.expect("five")?;
+    tester.expect("four")?;
+    tester.run("sed q listing/nothing listing/a || echo failed")?;
+    tester.expect("sed: can't read listing/nothing: No such file or directory")?;
+    tester.expect("hello")?;
+    tester.expect("failed")?;
+
+    // Sorting
+    tester.run("echo 'b 10\na 9\nc 9' > listing/c; sort -k2,2n -k1,1r listing/c")?;
+    tester.expect("c 9")?;
+    tester.expect("a 9")?;
+    tester.expect("b 10")?;
+    tester.run("sort -C listing/c || echo unsorted")?;
+    tester.expect("unsorted")?;
+    tester.run("sort -o listing/b listing/b; sort -c listing/b && head -n 1 listing/b")?;
+    tester.expect("five")?;
+    tester.run("echo 'x:2\ny:1' | sort -t : -k 2")?;
+    tester.expect("y:1")?;
+    tester.expect("x:2")?;
+    tester.run("find listing -type f -print0 | sort -rz | xargs -0 echo")?;
+    tester.expect("listing/c listing/b listing/a listing/.hidden")?;
+
+    // Manual pages are plain text when there's no terminal to page on
+    tester.run("man -k concatenate")?;
+    tester.expect("cat (1) - concatenate files")?;
+    tester.run("man 5 cowfile | head -n 4")?;
+    tester.expect(
+        "COWFILE(5)                                                            COWFILE(5)",
+    )?;
+    tester.expect("")?;
+    tester.expect("NAME")?;
+    tester.expect("       cowfile - pictures of cows and other speakers")?;
+    tester.run("man cd | head -n 4 | tail -n 1")?;
+    tester.expect("       builtins - commands built into the shell")?;
+
+    // Editing mode
+    tester.run("set -o vi; set -o")?;
+    tester.expect("emacs           off")?;
+    tester.expect("vi              on")?;
+
     Ok(())
 }
 
@@ -97,6 +225,7 @@ async fn integration_test() -> Result<()> {
 
     let mut shell = Process {
         stdin: stdin.clone(),
+        terminal: stdin.clone(),
         stdout: stdout.clone(),
         stderr: stdout.clone(),
         env: Default::default(),
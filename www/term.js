@@ -29,6 +29,51 @@ let esc_sequence = null;
 let cursorx = 0;
 let cursory = null;
 
+// How many columns a character takes up: none for combining marks and the like, two for wide
+// characters such as emoji and CJK, and one for anything else.
+function char_width(c) {
+    if (/^[\p{M}\u200b-\u200f\ufe00-\ufe0f]$/u.test(c)) {
+        return 0;
+    }
+    if (/^[\p{Emoji_Presentation}\u1100-\u115f\u2e80-\u303e\u3041-\u33ff\u3400-\u4dbf\u4e00-\u9fff\ua000-\ua4cf\uac00-\ud7a3\uf900-\ufaff\ufe30-\ufe4f\uff00-\uff60\uffe0-\uffe6\u{20000}-\u{3fffd}]$/u.test(c)) {
+        return 2;
+    }
+    return 1;
+}
+
+// Each character of some text with its width. Emoji joined onto another with a zero width
+// joiner are shown as one, so they take up no more room.
+function* char_widths(text) {
+    let joined = false;
+    for (const c of text) {
+        yield [c, joined ? 0 : char_width(c)];
+        joined = c === "\u200d";
+    }
+}
+
+function text_width(text) {
+    let width = 0;
+    for (const [_, w] of char_widths(text)) {
+        width += w;
+    }
+    return width;
+}
+
+// Where in some text the character shown at column `x` starts, or the one after it if `x` is in
+// the middle of a wide character.
+function index_at_column(text, x) {
+    let width = 0;
+    let index = 0;
+    for (const [c, w] of char_widths(text)) {
+        if (width >= x && w > 0) {
+            break;
+        }
+        width += w;
+        index += c.length;
+    }
+    return index;
+}
+
 function get_pos_in_line(line, x) {
     let adj_span = null;
     let position = 0;
@@ -37,10 +82,10 @@ function get_pos_in_line(line, x) {
         if (child.id === cursor.id) {
             continue;
         }
-        const next_position = position + child.textContent.length;
+        const next_position = position + text_width(child.textContent);
         if (next_position >= x) {
-            const pos = x - position;
             const content = child.textContent;
+            const pos = index_at_column(content, x - position);
             // Split spans
             // Can be optimized, probably.
             child.textContent = content.substr(0, pos);
@@ -171,6 +216,14 @@ function js_term_write(str) {
                     style += "ct-magenta";
                 } else if (fg === "36") {
                     style += "ct-cyan";
+                } else if (fg === "2") {
+                    style += "ct-dim";
+                } else if (fg === "7") {
+                    style += "ct-reverse";
+                } else if (fg === "1") {
+                    style += "ct-bold";
+                } else if (fg === "4") {
+                    style += "ct-underline";
                 } else if (fg === "0") {
                     style = "ct-normal";
                 }
@@ -280,8 +333,10 @@ function write_to_line(line, str) {
     focus.className = style;
     line.insertBefore(focus, adj_span);
 
-    for (let i = 0; i < str.length; i++) {
-        const c = str[i];
+    let joined = false;
+    for (let i = 0; i < str.length; ) {
+        const c = String.fromCodePoint(str.codePointAt(i));
+        i += c.length;
         if (c == '\n') {
             let new_line;
             if (cursory === null) {
@@ -292,7 +347,7 @@ function write_to_line(line, str) {
                 new_line = current_line();
             }
             cursorx = 0;
-            write_to_line(new_line, str.substr(i + 1));
+            write_to_line(new_line, str.substr(i));
             return;
         }
         if (c == '\b') {
@@ -301,20 +356,30 @@ function write_to_line(line, str) {
             }
             continue;
         }
+        const width = joined ? 0 : char_width(c);
+        joined = c === "\u200d";
         focus.textContent += c;
-        cursorx += 1
-        while (adj_span?.textContent === "") {
-            let temp = adj_span;
-            adj_span = adj_span.nextSibling;
-            if (adj_span?.id === cursor.id) {
+        cursorx += width;
+        // Write over as many columns as the character takes up.
+        let remaining = width;
+        while (remaining > 0) {
+            while (adj_span?.textContent === "") {
+                let temp = adj_span;
                 adj_span = adj_span.nextSibling;
+                if (adj_span?.id === cursor.id) {
+                    adj_span = adj_span.nextSibling;
+                }
+                line.removeChild(temp);
             }
-            line.removeChild(temp);
-        }
-        if (adj_span === null) {
-            continue;
+            if (adj_span === null) {
+                break;
+            }
+            const text = adj_span.textContent;
+            const index = index_at_column(text, remaining);
+            remaining -= text_width(text.substr(0, index));
+            // Half of a wide character that was written over leaves a space behind.
+            adj_span.textContent = " ".repeat(Math.max(0, -remaining)) + text.substr(index);
         }
-        adj_span.textContent = adj_span.textContent.substr(1);
     }
 }
 
@@ -337,14 +402,13 @@ function js_term_backspace() {
     if (latest_div === cursor) {
         latest_div = latest_div.previousSibling;
     }
-    const text = latest_div.textContent
-    if (text !== "") {
-        latest_div.textContent = text.substr(0, text.length - 1);
-    }
+    const chars = Array.from(latest_div.textContent);
+    const removed = chars.pop();
+    latest_div.textContent = chars.join("");
     if (latest_div.textContent === "") {
         latest_div.remove();
     }
-    move_cursor(cursorx - 1, cursory);
+    move_cursor(cursorx - (removed === undefined ? 1 : char_width(removed)), cursory);
 }
 
 // Not even remotely accurate, but at least it's usually less than the
@@ -358,3 +422,20 @@ function js_term_get_screen_height() {
     const lines = Math.round((terminal.offsetHeight / rem) * 0.5);
     return lines;
 }
+
+// Number of characters that fit on one line of the terminal.
+function js_term_get_screen_width() {
+    // Measure a run of characters in the terminal's font.
+    const probe = document.createElement("span");
+    probe.style.position = "absolute";
+    probe.style.visibility = "hidden";
+    probe.textContent = "M".repeat(100);
+    terminal.appendChild(probe);
+    const char_width = probe.getBoundingClientRect().width / 100;
+    probe.remove();
+
+    if (!char_width) {
+        return 80;
+    }
+    return Math.max(1, Math.floor(terminal.clientWidth / char_width));
+}